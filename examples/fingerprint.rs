@@ -1,6 +1,6 @@
 use osshkeys::error::OsshResult;
-use osshkeys::keys::FingerprintHash;
-use osshkeys::{PublicKey, PublicParts};
+use osshkeys::keys::{Fingerprint, FingerprintHash};
+use osshkeys::PublicKey;
 use std::env;
 use std::fmt::Display;
 use std::fs;
@@ -11,10 +11,7 @@ fn print_fingerprint<P: Display + AsRef<Path>>(path: P) -> OsshResult<()> {
     match fs::read_to_string(path) {
         Ok(s) => {
             let pubkey = PublicKey::from_keystr(&s)?;
-            println!(
-                "SHA256:{}",
-                hex::encode(pubkey.fingerprint(FingerprintHash::SHA256)?)
-            );
+            println!("{}", Fingerprint::of(&pubkey, FingerprintHash::SHA256)?);
         }
         Err(e) => {
             println!("{}", e);
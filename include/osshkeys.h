@@ -0,0 +1,198 @@
+#ifndef OSSHKEYS_H
+#define OSSHKEYS_H
+
+/* This file is generated by cbindgen, do not edit it manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The fingerprint hash algorithm
+typedef enum OsshHash {
+  OSSH_HASH_MD5,
+  OSSH_HASH_SHA256,
+  OSSH_HASH_SHA384,
+  OSSH_HASH_SHA512,
+} OsshHash;
+
+// The private key format of [`ossh_keypair_serialize()`]
+typedef enum OsshKeyFormat {
+  // The OpenSSH private key format, encrypted with AES-256-CTR if a passphrase is given
+  OSSH_KEY_FORMAT_OPENSSH,
+  // The traditional OpenSSL PEM format
+  OSSH_KEY_FORMAT_PEM,
+  // The PKCS#8 format
+  OSSH_KEY_FORMAT_PKCS8,
+} OsshKeyFormat;
+
+// The key type of [`ossh_keypair_generate()`]
+typedef enum OsshKeyType {
+  OSSH_KEY_TYPE_RSA,
+  OSSH_KEY_TYPE_DSA,
+  OSSH_KEY_TYPE_ECDSA,
+  OSSH_KEY_TYPE_ED25519,
+} OsshKeyType;
+
+// The result of the C API functions
+typedef enum OsshStatus {
+  OSSH_STATUS_OK = 0,
+  // A null pointer or an invalid string is passed
+  OSSH_STATUS_INVALID_ARGUMENT,
+  // The key is malformed
+  OSSH_STATUS_INVALID_KEY,
+  // The passphrase is incorrect or missing
+  OSSH_STATUS_INCORRECT_PASS,
+  // The key type or the cipher is not supported
+  OSSH_STATUS_UNSUPPORTED,
+  // An error from OpenSSL
+  OSSH_STATUS_CRYPTO,
+  // An I/O or formatting error
+  OSSH_STATUS_IO,
+  // The function panicked
+  OSSH_STATUS_PANIC,
+  OSSH_STATUS_UNKNOWN,
+} OsshStatus;
+
+// General key pair type
+//
+// This is a type to make it easy to store different types of key pair in the container.
+// Each can contain one of the types supported in this crate.
+//
+// Key pair is the so-called "private key" which contains both public and private parts of an asymmetry key.
+typedef struct OsshKeyPair OsshKeyPair;
+
+// General public key type
+//
+// This is a type to make it easy to store different types of public key in the container.
+// Each can contain one of the types supported in this crate.
+//
+// Public key is usually stored in the `.pub` file when generating the key.
+typedef struct OsshPublicKey OsshPublicKey;
+
+// Get the message of the last error on the current thread
+//
+// Return null if there is no error. The string is valid until the next failed call on the same thread.
+const char *ossh_last_error(void);
+
+// Generate a new key pair
+//
+// Set `bits` to 0 to use the default key size.
+//
+// # Safety
+// `out` must be a valid pointer to store the key pair.
+enum OsshStatus ossh_keypair_generate(enum OsshKeyType keytype,
+                                      size_t bits,
+                                      struct OsshKeyPair **out);
+
+// Parse a private key in the OpenSSH, PEM or PKCS#8 format
+//
+// # Safety
+// `keystr` must be a valid C string, `passphrase` must be null or a valid C string,
+// and `out` must be a valid pointer to store the key pair.
+enum OsshStatus ossh_keypair_from_keystr(const char *keystr,
+                                         const char *passphrase,
+                                         struct OsshKeyPair **out);
+
+// Get the public key of the key pair
+//
+// # Safety
+// `keypair` must be a valid key pair, and `out` must be a valid pointer to store the public key.
+enum OsshStatus ossh_keypair_public_key(const struct OsshKeyPair *keypair,
+                                        struct OsshPublicKey **out);
+
+// Get the fingerprint of the key pair, such as `SHA256:...`
+//
+// # Safety
+// `keypair` must be a valid key pair, and `out` must be a valid pointer to store the string.
+// The string must be freed by [`ossh_string_free()`].
+enum OsshStatus ossh_keypair_fingerprint(const struct OsshKeyPair *keypair,
+                                         enum OsshHash hash,
+                                         char **out);
+
+// Sign the data with the key pair
+//
+// # Safety
+// `keypair` must be a valid key pair, `data` must point to `len` readable bytes,
+// and `sig`, `sig_len` must be valid pointers to store the signature.
+// The signature must be freed by [`ossh_bytes_free()`].
+enum OsshStatus ossh_keypair_sign(const struct OsshKeyPair *keypair,
+                                  const uint8_t *data,
+                                  size_t len,
+                                  uint8_t **sig,
+                                  size_t *sig_len);
+
+// Serialize the private key
+//
+// Pass a null or an empty `passphrase` to serialize without encryption.
+//
+// # Safety
+// `keypair` must be a valid key pair, `passphrase` must be null or a valid C string,
+// and `out` must be a valid pointer to store the string.
+// The string must be freed by [`ossh_string_free()`].
+enum OsshStatus ossh_keypair_serialize(const struct OsshKeyPair *keypair,
+                                       enum OsshKeyFormat format,
+                                       const char *passphrase,
+                                       char **out);
+
+// Free the key pair, the private key is wiped
+//
+// # Safety
+// `keypair` must be null or a key pair returned by this library, which has not been freed.
+void ossh_keypair_free(struct OsshKeyPair *keypair);
+
+// Parse a public key in the OpenSSH or PEM format
+//
+// # Safety
+// `keystr` must be a valid C string, and `out` must be a valid pointer to store the public key.
+enum OsshStatus ossh_publickey_from_keystr(const char *keystr, struct OsshPublicKey **out);
+
+// Serialize the public key in the OpenSSH format
+//
+// # Safety
+// `pubkey` must be a valid public key, and `out` must be a valid pointer to store the string.
+// The string must be freed by [`ossh_string_free()`].
+enum OsshStatus ossh_publickey_serialize(const struct OsshPublicKey *pubkey, char **out);
+
+// Get the fingerprint of the public key, such as `SHA256:...`
+//
+// # Safety
+// `pubkey` must be a valid public key, and `out` must be a valid pointer to store the string.
+// The string must be freed by [`ossh_string_free()`].
+enum OsshStatus ossh_publickey_fingerprint(const struct OsshPublicKey *pubkey,
+                                           enum OsshHash hash,
+                                           char **out);
+
+// Verify the signature of the data
+//
+// # Safety
+// `pubkey` must be a valid public key, `data` must point to `len` readable bytes,
+// `sig` must point to `sig_len` readable bytes, and `valid` must be a valid pointer to store the result.
+enum OsshStatus ossh_publickey_verify(const struct OsshPublicKey *pubkey,
+                                      const uint8_t *data,
+                                      size_t len,
+                                      const uint8_t *sig,
+                                      size_t sig_len,
+                                      bool *valid);
+
+// Free the public key
+//
+// # Safety
+// `pubkey` must be null or a public key returned by this library, which has not been freed.
+void ossh_publickey_free(struct OsshPublicKey *pubkey);
+
+// Free the string returned by this library, the content is wiped
+//
+// # Safety
+// `s` must be null or a string returned by this library, which has not been freed.
+void ossh_string_free(char *s);
+
+// Free the bytes returned by this library
+//
+// # Safety
+// `p` must be null or the bytes returned by this library with the same `len`, which have not been freed.
+void ossh_bytes_free(uint8_t *p,
+                     size_t len);
+
+#endif /* OSSHKEYS_H */
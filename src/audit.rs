@@ -0,0 +1,1060 @@
+use crate::error::*;
+use crate::format::ossh_privkey::ossh_priv_publickey;
+use crate::keys::cert::{unix_time, Certificate, CERT_FOREVER};
+use crate::keys::{Fingerprint, FingerprintHash, Key, KeyPair, KeyType, PublicKey, PublicParts};
+use crate::known_hosts::KnownHostEntry;
+use crate::policy::Policy;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "rayon")]
+use rayon::ThreadPoolBuilder;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The options of auditing the keys
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditOptions {
+    /// The hash algorithm of the fingerprints
+    pub hash: FingerprintHash,
+    /// Check the type and the size of the keys with the policy
+    pub policy: Option<Policy>,
+    /// The number of the worker threads, `0` to use the global thread pool of rayon
+    pub threads: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl Default for AuditOptions {
+    fn default() -> Self {
+        AuditOptions {
+            hash: FingerprintHash::SHA256,
+            policy: None,
+            threads: 0,
+        }
+    }
+}
+
+/// The audit result of a key
+#[cfg(feature = "rayon")]
+#[derive(Debug)]
+pub struct KeyAudit {
+    /// The index of the key in the slice, or the line number (starting from 1) in the authorized_keys file
+    pub index: usize,
+    /// The fingerprint of the key, or the error of parsing the line of the authorized_keys file
+    pub fingerprint: OsshResult<Fingerprint>,
+    /// The result of checking the key with the policy, `None` if there is no policy or the key failed to parse
+    pub policy: Option<OsshResult<()>>,
+}
+
+#[cfg(feature = "rayon")]
+impl KeyAudit {
+    fn new(index: usize, key: OsshResult<&PublicKey>, options: &AuditOptions) -> Self {
+        match key {
+            Ok(key) => KeyAudit {
+                index,
+                fingerprint: Fingerprint::of(key, options.hash),
+                policy: options
+                    .policy
+                    .as_ref()
+                    .map(|policy| policy.check_key(key.keytype(), key.size())),
+            },
+            Err(err) => KeyAudit {
+                index,
+                fingerprint: Err(err),
+                policy: None,
+            },
+        }
+    }
+}
+
+/// Compute the fingerprints of the keys in parallel
+///
+/// The fingerprints are in the same order as the keys.
+#[cfg(feature = "rayon")]
+pub fn fingerprint_keys(keys: &[PublicKey], hash: FingerprintHash) -> Vec<OsshResult<Fingerprint>> {
+    parallel_map(keys, 0, |key| Fingerprint::of(key, hash))
+}
+
+/// Compute the fingerprints of the keys and check them with the policy in parallel
+///
+/// The results are in the same order as the keys.
+#[cfg(feature = "rayon")]
+pub fn audit_keys(keys: &[PublicKey], options: &AuditOptions) -> Vec<KeyAudit> {
+    let indexed: Vec<_> = keys.iter().enumerate().collect();
+    parallel_map(&indexed, options.threads, |&(index, key)| {
+        KeyAudit::new(index, Ok(key), options)
+    })
+}
+
+/// Parse the keys of the authorized_keys file, then compute their fingerprints and check them with the policy in parallel
+///
+/// The empty lines and the comments are skipped, and a line which fails to parse is reported
+/// in [`KeyAudit::fingerprint`] without stopping the others.
+#[cfg(feature = "rayon")]
+pub fn audit_authorized_keys(s: &str, options: &AuditOptions) -> Vec<KeyAudit> {
+    let lines: Vec<_> = s
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    parallel_map(
+        &lines,
+        options.threads,
+        |&(index, line)| match PublicKey::from_keystr(line) {
+            Ok(key) => KeyAudit::new(index, Ok(&key), options),
+            Err(err) => KeyAudit::new(index, Err(err), options),
+        },
+    )
+}
+
+/// The options of [`lint_authorized_keys()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintOptions {
+    /// The maximum length of a line in bytes, 8192 by default like the line buffer of the older sshd
+    pub max_line_length: usize,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        LintOptions {
+            max_line_length: 8192,
+        }
+    }
+}
+
+/// A problem of an authorized_keys line found by [`lint_authorized_keys()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LintIssue {
+    /// The line can't be parsed, with the reason
+    SyntaxError(ErrorKind),
+    /// The key is already on the earlier line
+    DuplicateKey(usize),
+    /// The option is unknown to sshd
+    UnknownOption(String),
+    /// The option has a missing, unexpected or unquoted value
+    MalformedOption(String),
+    /// The option is given more than once, but sshd only accepts it once
+    DuplicateOption(String),
+    /// The option and its negation are both given
+    ConflictingOptions(String, String),
+    /// The line is longer than [`LintOptions::max_line_length`], with its length
+    LineTooLong(usize),
+    /// The key type is deprecated and refused by the default configuration of sshd, such as `ssh-dss`
+    DeprecatedKeyType(String),
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LintIssue::SyntaxError(kind) => write!(f, "syntax error: {}", kind),
+            LintIssue::DuplicateKey(line) => write!(f, "duplicate of the key on line {}", line),
+            LintIssue::UnknownOption(name) => write!(f, "unknown option {}", name),
+            LintIssue::MalformedOption(name) => write!(f, "malformed option {}", name),
+            LintIssue::DuplicateOption(name) => write!(f, "duplicate option {}", name),
+            LintIssue::ConflictingOptions(a, b) => write!(f, "conflicting options {} and {}", a, b),
+            LintIssue::LineTooLong(len) => write!(f, "line too long ({} bytes)", len),
+            LintIssue::DeprecatedKeyType(name) => write!(f, "deprecated key type {}", name),
+        }
+    }
+}
+
+/// A problem found by [`lint_authorized_keys()`] with its line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// The line number starting from 1
+    pub line: usize,
+    pub issue: LintIssue,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.issue)
+    }
+}
+
+/// The options of sshd without a value
+const FLAG_OPTIONS: &[&str] = &[
+    "agent-forwarding",
+    "cert-authority",
+    "no-agent-forwarding",
+    "no-port-forwarding",
+    "no-pty",
+    "no-touch-required",
+    "no-user-rc",
+    "no-x11-forwarding",
+    "port-forwarding",
+    "pty",
+    "restrict",
+    "user-rc",
+    "verify-required",
+    "x11-forwarding",
+];
+/// The options of sshd with a quoted value
+const VALUE_OPTIONS: &[&str] = &[
+    "command",
+    "environment",
+    "expiry-time",
+    "from",
+    "permitlisten",
+    "permitopen",
+    "principals",
+    "tunnel",
+];
+/// The options which sshd refuses to be given twice
+const SINGLE_OPTIONS: &[&str] = &["command", "principals", "tunnel"];
+
+/// Check the authorized_keys file before deploying it, and report the problems in the order of the lines
+///
+/// The syntax errors, the duplicate keys, the unknown, malformed, duplicate and conflicting options,
+/// the oversized lines and the deprecated key types are reported. The empty lines and the comments are skipped.
+pub fn lint_authorized_keys(s: &str, options: &LintOptions) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    let mut blobs = HashMap::new();
+    for (i, line) in s.lines().enumerate() {
+        let mut report = |issue| findings.push(LintFinding { line: i + 1, issue });
+        if line.len() > options.max_line_length {
+            report(LintIssue::LineTooLong(line.len()));
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let key = match PublicKey::from_keystr(line) {
+            Ok(key) => key,
+            Err(err) => {
+                report(LintIssue::SyntaxError(err.kind()));
+                continue;
+            }
+        };
+        for issue in lint_options(key.options()) {
+            report(issue);
+        }
+        #[cfg(feature = "dsa")]
+        if key.keytype() == KeyType::DSA {
+            report(LintIssue::DeprecatedKeyType(key.keyname().to_owned()));
+        }
+        match key.blob() {
+            Ok(blob) => {
+                if let Some(first) = blobs.get(&blob) {
+                    report(LintIssue::DuplicateKey(*first));
+                } else {
+                    blobs.insert(blob, i + 1);
+                }
+            }
+            Err(err) => report(LintIssue::SyntaxError(err.kind())),
+        }
+    }
+    findings
+}
+
+/// Check the comma separated options of an authorized_keys line
+fn lint_options(options: &str) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    for option in split_options(options) {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name.to_ascii_lowercase(), Some(value)),
+            None => (option.to_ascii_lowercase(), None),
+        };
+        let quoted = value.map_or(false, |v| {
+            v.len() >= 2 && v.starts_with('"') && v.ends_with('"')
+        });
+        if FLAG_OPTIONS.contains(&name.as_str()) {
+            if value.is_some() {
+                issues.push(LintIssue::MalformedOption(name.clone()));
+            }
+        } else if VALUE_OPTIONS.contains(&name.as_str()) {
+            if !quoted {
+                issues.push(LintIssue::MalformedOption(name.clone()));
+            }
+        } else {
+            issues.push(LintIssue::UnknownOption(name));
+            continue;
+        }
+        if SINGLE_OPTIONS.contains(&name.as_str()) && names.contains(&name) {
+            issues.push(LintIssue::DuplicateOption(name.clone()));
+        }
+        names.push(name);
+    }
+    for name in &names {
+        if let Some(negated) = name.strip_prefix("no-") {
+            if names.iter().any(|n| n == negated) {
+                issues.push(LintIssue::ConflictingOptions(
+                    negated.to_owned(),
+                    name.clone(),
+                ));
+            }
+        }
+    }
+    issues
+}
+
+/// Split the options at the commas outside the quotes
+fn split_options(options: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    if options.is_empty() {
+        return parts;
+    }
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in options.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&options[start..]);
+    parts
+}
+
+/// The strength class of a key from its type and size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyStrength {
+    /// Broken or refused by OpenSSH, such as DSA and RSA under 1024 bits
+    Broken,
+    /// Under 112 bits of security, such as RSA under 2048 bits
+    Weak,
+    /// 112 bits of security, such as RSA under 3072 bits
+    Acceptable,
+    /// At least 128 bits of security, such as Ed25519, EcDSA and RSA of 3072 bits or more
+    Strong,
+}
+
+impl KeyStrength {
+    /// Classify the key type with the size in bits
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    pub fn of(keytype: KeyType, bits: usize) -> Self {
+        match keytype {
+            #[cfg(feature = "dsa")]
+            KeyType::DSA => KeyStrength::Broken,
+            #[cfg(feature = "rsa")]
+            KeyType::RSA if bits < 1024 => KeyStrength::Broken,
+            #[cfg(feature = "rsa")]
+            KeyType::RSA if bits < 2048 => KeyStrength::Weak,
+            #[cfg(feature = "rsa")]
+            KeyType::RSA if bits < 3072 => KeyStrength::Acceptable,
+            #[cfg(feature = "rsa")]
+            KeyType::RSA => KeyStrength::Strong,
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA => KeyStrength::Strong,
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519 => KeyStrength::Strong,
+        }
+    }
+
+    /// Get the lowercase name, such as `strong`
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyStrength::Broken => "broken",
+            KeyStrength::Weak => "weak",
+            KeyStrength::Acceptable => "acceptable",
+            KeyStrength::Strong => "strong",
+        }
+    }
+}
+
+impl fmt::Display for KeyStrength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The kind of the file where a key is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeySource {
+    /// A public key file, such as `id_ed25519.pub`
+    PublicKey,
+    /// A private key file, such as `id_ed25519`
+    PrivateKey,
+    /// A certificate file, such as `id_ed25519-cert.pub`
+    Certificate,
+    /// A line of the authorized_keys file
+    AuthorizedKeys,
+    /// A line of the known_hosts file
+    KnownHosts,
+}
+
+/// A problem found on a key by the [`Scanner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// The key is [`KeyStrength::Weak`] or [`KeyStrength::Broken`]
+    WeakKey(KeyStrength),
+    /// The key is in [`ScanOptions::blacklist`]
+    Blacklisted,
+    /// The key violates [`ScanOptions::policy`], with the reason
+    PolicyViolation(String),
+    /// The certificate has expired
+    CertificateExpired,
+    /// The certificate expires within [`ScanOptions::expiry_warning`], with the seconds left
+    CertificateExpiresSoon(u64),
+    /// The certificate is not valid yet
+    CertificateNotYetValid,
+    /// The file is readable by the others for a private key, or writable by the others for the other files, with the mode
+    InsecurePermissions(u32),
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Finding::WeakKey(strength) => write!(f, "{} key", strength),
+            Finding::Blacklisted => f.write_str("blacklisted key"),
+            Finding::PolicyViolation(reason) => write!(f, "policy violation: {}", reason),
+            Finding::CertificateExpired => f.write_str("certificate expired"),
+            Finding::CertificateExpiresSoon(secs) => {
+                write!(f, "certificate expires in {} days", secs / 86400)
+            }
+            Finding::CertificateNotYetValid => f.write_str("certificate not yet valid"),
+            Finding::InsecurePermissions(mode) => write!(f, "insecure permissions {:04o}", mode),
+        }
+    }
+}
+
+/// The report of a key found by the [`Scanner`]
+#[derive(Debug, Clone)]
+pub struct KeyReport {
+    /// The file containing the key
+    pub path: PathBuf,
+    /// The line number (starting from 1) of the authorized_keys and known_hosts files
+    pub line: Option<usize>,
+    pub source: KeySource,
+    pub keytype: KeyType,
+    /// The size of the key in bits
+    pub size: usize,
+    pub strength: KeyStrength,
+    pub fingerprint: Fingerprint,
+    pub comment: String,
+    /// The end of the validity period of the certificate in seconds since the Unix epoch
+    pub valid_before: Option<u64>,
+    /// The problems of the key, empty if it passes all the checks
+    pub findings: Vec<Finding>,
+}
+
+impl fmt::Display for KeyReport {
+    /// Write the report in one line, like `path:line 256 SHA256:... comment (ED25519) strong: finding, ...`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, " {} {}", self.size, self.fingerprint)?;
+        if !self.comment.is_empty() {
+            write!(f, " {}", self.comment)?;
+        }
+        write!(f, " ({:?}) {}", self.keytype, self.strength)?;
+        for (i, finding) in self.findings.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { ", " })?;
+            write!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+/// A file or a line which the [`Scanner`] failed to read
+#[derive(Debug)]
+pub struct ScanError {
+    pub path: PathBuf,
+    /// The line number (starting from 1) of the authorized_keys and known_hosts files
+    pub line: Option<usize>,
+    pub error: Error,
+}
+
+/// The result of a scan
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// The keys in the order they were found
+    pub keys: Vec<KeyReport>,
+    /// The files and the lines which failed to read, without stopping the scan
+    pub errors: Vec<ScanError>,
+}
+
+impl ScanReport {
+    /// Check if any key has a finding or any file failed to read
+    pub fn has_problems(&self) -> bool {
+        !self.errors.is_empty() || self.keys.iter().any(|key| !key.findings.is_empty())
+    }
+}
+
+/// The options of the [`Scanner`]
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// The hash algorithm of the fingerprints in the reports
+    pub hash: FingerprintHash,
+    /// Check the type and the size of the keys with the policy
+    pub policy: Option<Policy>,
+    /// The fingerprints of the compromised or retired keys
+    pub blacklist: Vec<Fingerprint>,
+    /// Report the certificates expiring within the seconds
+    pub expiry_warning: u64,
+    /// Scan the subdirectories
+    pub recursive: bool,
+    /// The time to check the certificates in seconds since the Unix epoch, `None` for the current time
+    pub time: Option<u64>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            hash: FingerprintHash::SHA256,
+            policy: None,
+            blacklist: Vec::new(),
+            expiry_warning: 30 * 86400,
+            recursive: false,
+            time: None,
+        }
+    }
+}
+
+/// Walk the directories and the key files, and report the findings of each key for the key rotation and the compliance audits
+///
+/// The files are recognized by their names and contents:
+/// - `authorized_keys*` and `*known_hosts*` are read line by line.
+/// - `*-cert.pub` is a certificate, and the other `*.pub` files are public keys.
+/// - The files beginning with `-----BEGIN` and containing a `PRIVATE KEY` are private keys.
+///   The public key of an encrypted OpenSSH private key is read without the passphrase,
+///   and the other encrypted private keys are reported as the errors.
+///
+/// The other files in the directories are skipped, and the symbolic links to the directories are not followed.
+pub struct Scanner {
+    options: ScanOptions,
+    report: ScanReport,
+}
+
+impl Scanner {
+    pub fn new(options: ScanOptions) -> Self {
+        Scanner {
+            options,
+            report: ScanReport::default(),
+        }
+    }
+
+    /// Scan the file or the directory
+    ///
+    /// Only fail if the path itself can't be read, the errors of the files inside are collected into the report.
+    pub fn scan_path<P: AsRef<Path>>(&mut self, path: P) -> OsshResult<()> {
+        let path = path.as_ref();
+        if fs::metadata(path)?.is_dir() {
+            self.scan_dir(path)
+        } else {
+            self.scan_file(path, FileKind::detect(path), false);
+            Ok(())
+        }
+    }
+
+    /// Scan the file as an authorized_keys file whatever its name is
+    pub fn scan_authorized_keys<P: AsRef<Path>>(&mut self, path: P) {
+        self.scan_file(path.as_ref(), FileKind::AuthorizedKeys, false);
+    }
+
+    /// Scan the file as a known_hosts file whatever its name is
+    pub fn scan_known_hosts<P: AsRef<Path>>(&mut self, path: P) {
+        self.scan_file(path.as_ref(), FileKind::KnownHosts, false);
+    }
+
+    /// Get the report so far
+    pub fn report(&self) -> &ScanReport {
+        &self.report
+    }
+
+    /// Finish the scan and take the report
+    pub fn finish(self) -> ScanReport {
+        self.report
+    }
+
+    fn scan_dir(&mut self, dir: &Path) -> OsshResult<()> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for path in entries {
+            match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => {
+                    if self.options.recursive {
+                        if let Err(error) = self.scan_dir(&path) {
+                            self.error(&path, None, error);
+                        }
+                    }
+                }
+                // Follow the symbolic links to the files, but not to the directories
+                Ok(_) if path.is_file() => self.scan_file(&path, FileKind::detect(&path), true),
+                Ok(_) => {}
+                Err(error) => self.error(&path, None, error.into()),
+            }
+        }
+        Ok(())
+    }
+
+    // The files found in the directories are skipped silently if they are not keys
+    fn scan_file(&mut self, path: &Path, kind: FileKind, in_dir: bool) {
+        let content = match fs::read(path).map(String::from_utf8) {
+            Ok(Ok(content)) => content,
+            Ok(Err(_)) if in_dir && kind == FileKind::Unknown => return,
+            Ok(Err(error)) => {
+                return self.error(
+                    path,
+                    None,
+                    Error::with_error(ErrorKind::InvalidFormat, error),
+                )
+            }
+            Err(error) => return self.error(path, None, error.into()),
+        };
+        let kind = match kind {
+            FileKind::Unknown => FileKind::sniff(&content),
+            kind => kind,
+        };
+        let mode = file_mode(path);
+        match kind {
+            FileKind::AuthorizedKeys | FileKind::KnownHosts => {
+                for (i, line) in content.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let (source, key) = if kind == FileKind::AuthorizedKeys {
+                        (KeySource::AuthorizedKeys, PublicKey::from_keystr(line))
+                    } else {
+                        let key = KnownHostEntry::parse(line).and_then(|entry| {
+                            let mut key = PublicKey::from_blob(&entry.key().to_blob()?)?;
+                            key.set_comment(entry.key().comment());
+                            Ok(key)
+                        });
+                        (KeySource::KnownHosts, key)
+                    };
+                    match key {
+                        Ok(key) => self.add_key(path, Some(i + 1), source, &key, None, mode),
+                        Err(error) => self.error(path, Some(i + 1), error),
+                    }
+                }
+            }
+            FileKind::PublicKey => match PublicKey::from_keystr(&content) {
+                Ok(key) => self.add_key(path, None, KeySource::PublicKey, &key, None, mode),
+                Err(error) => self.error(path, None, error),
+            },
+            FileKind::Certificate => match Certificate::from_keystr(&content) {
+                Ok(cert) => self.add_key(
+                    path,
+                    None,
+                    KeySource::Certificate,
+                    cert.public_key(),
+                    Some(&cert),
+                    mode,
+                ),
+                Err(error) => self.error(path, None, error),
+            },
+            FileKind::PrivateKey => match read_private_publickey(&content) {
+                Ok(key) => self.add_key(path, None, KeySource::PrivateKey, &key, None, mode),
+                Err(error) => self.error(path, None, error),
+            },
+            // Neither the name nor the content looks like a key
+            FileKind::Unknown if in_dir => {}
+            FileKind::Unknown => self.error(path, None, ErrorKind::InvalidFormat.into()),
+        }
+    }
+
+    fn add_key(
+        &mut self,
+        path: &Path,
+        line: Option<usize>,
+        source: KeySource,
+        key: &PublicKey,
+        cert: Option<&Certificate>,
+        mode: Option<u32>,
+    ) {
+        let fingerprint = match Fingerprint::of(key, self.options.hash) {
+            Ok(fingerprint) => fingerprint,
+            Err(error) => return self.error(path, line, error),
+        };
+        let keytype = key.keytype();
+        let size = key.size();
+        let strength = KeyStrength::of(keytype, size);
+
+        let mut findings = Vec::new();
+        if strength < KeyStrength::Acceptable {
+            findings.push(Finding::WeakKey(strength));
+        }
+        if self.options.blacklist.iter().any(|fp| fp.matches(key)) {
+            findings.push(Finding::Blacklisted);
+        }
+        if let Some(policy) = &self.options.policy {
+            if let Err(error) = policy.check_key(keytype, size) {
+                findings.push(Finding::PolicyViolation(match error.kind() {
+                    ErrorKind::PolicyViolation(reason) => reason,
+                    kind => kind.to_string(),
+                }));
+            }
+        }
+        if let Some(cert) = cert {
+            let now = self.options.time.unwrap_or_else(unix_time);
+            if now < cert.valid_after() {
+                findings.push(Finding::CertificateNotYetValid);
+            } else if now >= cert.valid_before() {
+                findings.push(Finding::CertificateExpired);
+            } else if cert.valid_before() != CERT_FOREVER
+                && cert.valid_before() - now <= self.options.expiry_warning
+            {
+                findings.push(Finding::CertificateExpiresSoon(cert.valid_before() - now));
+            }
+        }
+        if let Some(mode) = mode {
+            let mask = if source == KeySource::PrivateKey {
+                0o077
+            } else {
+                0o022
+            };
+            if mode & mask != 0 {
+                findings.push(Finding::InsecurePermissions(mode));
+            }
+        }
+
+        self.report.keys.push(KeyReport {
+            path: path.to_owned(),
+            line,
+            source,
+            keytype,
+            size,
+            strength,
+            fingerprint,
+            comment: cert.map_or(key.comment(), |c| c.comment()).to_owned(),
+            valid_before: cert.map(|c| c.valid_before()),
+            findings,
+        });
+    }
+
+    fn error(&mut self, path: &Path, line: Option<usize>, error: Error) {
+        self.report.errors.push(ScanError {
+            path: path.to_owned(),
+            line,
+            error,
+        });
+    }
+}
+
+/// A key listed by [`fingerprint_file()`]
+#[derive(Debug, Clone)]
+pub struct KeyFingerprint {
+    /// The line number (starting from 1) of the authorized_keys and known_hosts files
+    pub line: Option<usize>,
+    pub source: KeySource,
+    pub keytype: KeyType,
+    /// The size of the key in bits
+    pub size: usize,
+    /// The fingerprints in the order of the hash algorithms
+    pub fingerprints: Vec<Fingerprint>,
+    /// The comment of the key, or the host names of the known_hosts entry
+    pub comment: String,
+}
+
+impl KeyFingerprint {
+    fn new(
+        line: Option<usize>,
+        source: KeySource,
+        key: &PublicKey,
+        comment: &str,
+        hashes: &[FingerprintHash],
+    ) -> OsshResult<Self> {
+        Ok(KeyFingerprint {
+            line,
+            source,
+            keytype: key.keytype(),
+            size: key.size(),
+            fingerprints: hashes
+                .iter()
+                .map(|&hash| Fingerprint::of(key, hash))
+                .collect::<OsshResult<_>>()?,
+            comment: comment.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for KeyFingerprint {
+    /// Write a line for each fingerprint like `ssh-keygen -l`, such as `256 SHA256:... comment (ED25519)`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let comment = if self.comment.is_empty() {
+            "no comment"
+        } else {
+            &self.comment
+        };
+        let cert = if self.source == KeySource::Certificate {
+            "-CERT"
+        } else {
+            ""
+        };
+        for (i, fingerprint) in self.fingerprints.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(
+                f,
+                "{} {} {} ({:?}{})",
+                self.size, fingerprint, comment, self.keytype, cert
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// List the keys of a file with their fingerprints, like `ssh-keygen -l -f`
+///
+/// The file can be a private key, a public key, a certificate, an authorized_keys or a known_hosts file,
+/// which is recognized like the [`Scanner`] does. The public key of an encrypted OpenSSH private key is read without the passphrase.
+/// The files listing the keys by line are read without stopping at the lines which are not keys,
+/// and fail with [`ErrorKind::InvalidKeyFormat`] if no key is found.
+pub fn fingerprint_file<P: AsRef<Path>>(
+    path: P,
+    hashes: &[FingerprintHash],
+) -> OsshResult<Vec<KeyFingerprint>> {
+    let path = path.as_ref();
+    fingerprint_content(&fs::read_to_string(path)?, FileKind::detect(path), hashes)
+}
+
+/// List the keys in the content of a file like [`fingerprint_file()`], recognizing the file by its content only
+///
+/// The known_hosts lines are read as the authorized_keys lines, with the host names as the options.
+pub fn fingerprint_keystr(
+    content: &str,
+    hashes: &[FingerprintHash],
+) -> OsshResult<Vec<KeyFingerprint>> {
+    fingerprint_content(content, FileKind::Unknown, hashes)
+}
+
+fn fingerprint_content(
+    content: &str,
+    kind: FileKind,
+    hashes: &[FingerprintHash],
+) -> OsshResult<Vec<KeyFingerprint>> {
+    let kind = match kind {
+        FileKind::Unknown => FileKind::sniff(content),
+        kind => kind,
+    };
+    if kind == FileKind::PrivateKey {
+        let key = match KeyPair::from_keystr(content, None) {
+            Ok(keypair) => keypair.clone_public_key()?,
+            Err(_) => read_private_publickey(content)?,
+        };
+        let key = KeyFingerprint::new(None, KeySource::PrivateKey, &key, key.comment(), hashes)?;
+        return Ok(vec![key]);
+    }
+    if content
+        .trim_start()
+        .starts_with("---- BEGIN SSH2 PUBLIC KEY ----")
+    {
+        let key = PublicKey::from_keystr(content)?;
+        let key = KeyFingerprint::new(None, KeySource::PublicKey, &key, key.comment(), hashes)?;
+        return Ok(vec![key]);
+    }
+
+    let mut keys = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let key = if kind == FileKind::KnownHosts {
+            let entry = match KnownHostEntry::parse(line) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let hosts = line
+                .split_ascii_whitespace()
+                .find(|field| !field.starts_with('@'))
+                .unwrap_or_default();
+            KeyFingerprint::new(
+                Some(i + 1),
+                KeySource::KnownHosts,
+                entry.key(),
+                hosts,
+                hashes,
+            )?
+        } else if let Ok(key) = PublicKey::from_keystr(line) {
+            if kind == FileKind::AuthorizedKeys || !key.options().is_empty() {
+                let source = KeySource::AuthorizedKeys;
+                KeyFingerprint::new(Some(i + 1), source, &key, key.comment(), hashes)?
+            } else {
+                KeyFingerprint::new(None, KeySource::PublicKey, &key, key.comment(), hashes)?
+            }
+        } else if let Ok(cert) = Certificate::from_keystr(line) {
+            let source = KeySource::Certificate;
+            KeyFingerprint::new(None, source, cert.public_key(), cert.comment(), hashes)?
+        } else {
+            continue;
+        };
+        keys.push(key);
+    }
+    if keys.is_empty() {
+        return Err(ErrorKind::InvalidKeyFormat.into());
+    }
+    Ok(keys)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    AuthorizedKeys,
+    KnownHosts,
+    Certificate,
+    PublicKey,
+    PrivateKey,
+    Unknown,
+}
+
+impl FileKind {
+    fn detect(path: &Path) -> Self {
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return FileKind::Unknown,
+        };
+        if name.starts_with("authorized_keys") {
+            FileKind::AuthorizedKeys
+        } else if name.contains("known_hosts") {
+            FileKind::KnownHosts
+        } else if name.ends_with("-cert.pub") {
+            FileKind::Certificate
+        } else if name.ends_with(".pub") {
+            FileKind::PublicKey
+        } else {
+            FileKind::Unknown
+        }
+    }
+
+    fn sniff(content: &str) -> Self {
+        let content = content.trim_start();
+        if content.starts_with("-----BEGIN") && content.contains("PRIVATE KEY") {
+            FileKind::PrivateKey
+        } else {
+            FileKind::Unknown
+        }
+    }
+}
+
+fn read_private_publickey(keystr: &str) -> OsshResult<PublicKey> {
+    let pemdata = ::pem::parse(keystr.as_bytes())?;
+    if pemdata.tag() == "OPENSSH PRIVATE KEY" {
+        ossh_priv_publickey(pemdata.contents())
+    } else {
+        KeyPair::from_keystr(keystr, None)?.clone_public_key()
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .ok()
+        .map(|meta| meta.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(feature = "rayon")]
+fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    let map = || items.par_iter().map(&f).collect();
+    if threads == 0 {
+        return map();
+    }
+    // Fall back to the global pool if the dedicated one can't be created
+    match ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(map),
+        Err(_) => map(),
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "rayon",
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ed25519"
+))]
+mod test {
+    use super::*;
+    use crate::keys::PublicParts;
+
+    const ED25519_PUB: &str = include_str!("../assets/openssh_ed25519.pub");
+    const RSA_PUB: &str = include_str!("../assets/openssh_rsa.pub");
+    const DSA_PUB: &str = include_str!("../assets/openssh_dsa.pub");
+
+    #[test]
+    fn audit_authorized_keys_file() {
+        let file = format!(
+            "# comment\n\n{}command=\"echo \\\"a b\\\"\",no-pty {}  from=\"10.0.0.?\" {}invalid line\n",
+            ED25519_PUB, RSA_PUB, DSA_PUB
+        );
+        let options = AuditOptions {
+            policy: Some(Policy::openssh()),
+            threads: 2,
+            ..Default::default()
+        };
+        let audits = audit_authorized_keys(&file, &options);
+        assert_eq!(
+            audits.iter().map(|a| a.index).collect::<Vec<_>>(),
+            [3, 4, 5, 6]
+        );
+        for (audit, keystr) in audits.iter().zip([ED25519_PUB, RSA_PUB, DSA_PUB]) {
+            let key = PublicKey::from_keystr(keystr).unwrap();
+            assert!(audit.fingerprint.as_ref().unwrap().matches(&key));
+        }
+        assert!(audits[0].policy.as_ref().unwrap().is_ok());
+        assert!(audits[1].policy.as_ref().unwrap().is_ok());
+        assert!(matches!(
+            audits[2]
+                .policy
+                .as_ref()
+                .unwrap()
+                .as_ref()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::PolicyViolation(_)
+        ));
+        assert!(audits[3].fingerprint.is_err());
+        assert!(audits[3].policy.is_none());
+    }
+
+    #[test]
+    fn audit_keys_in_order() {
+        let keys: Vec<_> = [ED25519_PUB, RSA_PUB, DSA_PUB]
+            .iter()
+            .cycle()
+            .take(50)
+            .map(|s| PublicKey::from_keystr(s).unwrap())
+            .collect();
+        let fingerprints = fingerprint_keys(&keys, FingerprintHash::SHA256);
+        for threads in [0, 1, 3, 64] {
+            let options = AuditOptions {
+                threads,
+                ..Default::default()
+            };
+            let audits = audit_keys(&keys, &options);
+            assert_eq!(audits.len(), keys.len());
+            for (i, (audit, key)) in audits.iter().zip(&keys).enumerate() {
+                assert_eq!(audit.index, i);
+                assert_eq!(
+                    audit.fingerprint.as_ref().unwrap().digest(),
+                    key.fingerprint(FingerprintHash::SHA256).unwrap()
+                );
+                assert_eq!(
+                    audit.fingerprint.as_ref().unwrap(),
+                    fingerprints[i].as_ref().unwrap()
+                );
+                assert!(audit.policy.is_none());
+            }
+        }
+        assert!(audit_keys(&[], &AuditOptions::default()).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+#![cfg_attr(
+    not(any(feature = "rsa", feature = "dsa", feature = "ecdsa")),
+    allow(dead_code)
+)]
+
+use crate::cipher::Cipher;
+use crate::error::OsshResult;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{HasPublic, PKeyRef, Private};
+use openssl::sign::{Signer, Verifier};
+
+#[cfg(feature = "openssl-cipher")]
+mod openssl_impl;
+#[cfg(feature = "rustcrypto-cipher")]
+mod rustcrypto_impl;
+
+#[cfg(not(any(feature = "rustcrypto-cipher", feature = "openssl-cipher")))]
+compile_error!("No cipher backend is selected! Please enable one cipher backend feature.");
+
+#[cfg(all(feature = "rustcrypto-cipher", feature = "openssl-cipher"))]
+compile_error!("Multiple cipher backends are selected! Only one cipher backend is supported.");
+
+/// The backend selected by the features
+#[cfg(feature = "openssl-cipher")]
+pub(crate) type Backend = openssl_impl::OpensslBackend;
+/// The backend selected by the features
+#[cfg(feature = "rustcrypto-cipher")]
+pub(crate) type Backend = rustcrypto_impl::RustCryptoBackend;
+
+/// The hash algorithms of the signatures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(not(all(feature = "rsa", feature = "ecdsa")), allow(dead_code))]
+pub(crate) enum HashAlg {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlg {
+    pub(crate) fn message_digest(self) -> MessageDigest {
+        match self {
+            HashAlg::Sha1 => MessageDigest::sha1(),
+            HashAlg::Sha256 => MessageDigest::sha256(),
+            HashAlg::Sha384 => MessageDigest::sha384(),
+            HashAlg::Sha512 => MessageDigest::sha512(),
+        }
+    }
+}
+
+/// The crypto primitives used by the ciphers and the keys
+///
+/// A new backend only implements the cipher primitives for the [`Cipher`]s other than [`Cipher::Null`],
+/// the signing falls back to OpenSSL which holds the keys in every backend.
+pub(crate) trait CryptoBackend {
+    /// The name reported by [`capabilities()`](crate::capabilities())
+    const NAME: &'static str;
+
+    /// Whether the RSA private key operations apply base blinding and constant-time modular exponentiation
+    ///
+    /// This is a static guarantee of the backend. Every backend signs with OpenSSL so far, which does both by default.
+    /// A backend with its own RSA implementation must only set this if it does the same.
+    const RSA_SIDE_CHANNEL_HARDENED: bool = true;
+
+    /// Get the length of the output buffer for `len` bytes of input
+    fn calc_buflen(len: usize, block_size: usize) -> usize;
+
+    /// Encrypt `src` into `dest` and return the length of the ciphertext
+    fn encrypt(
+        cipher: Cipher,
+        dest: &mut [u8],
+        src: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize>;
+
+    /// Decrypt `src` into `dest` and return the length of the plaintext
+    fn decrypt(
+        cipher: Cipher,
+        dest: &mut [u8],
+        src: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize>;
+
+    /// Decrypt `buf[..len]` in place and return the length of the plaintext
+    fn decrypt_in_place(
+        cipher: Cipher,
+        buf: &mut [u8],
+        len: usize,
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize>;
+
+    /// Sign the data, the hash is `None` for the algorithms hashing by themselves like Ed25519
+    fn sign(pkey: &PKeyRef<Private>, hash: Option<HashAlg>, data: &[u8]) -> OsshResult<Vec<u8>> {
+        let mut signer = match hash {
+            Some(hash) => Signer::new(hash.message_digest(), pkey)?,
+            None => Signer::new_without_digest(pkey)?,
+        };
+        Ok(signer.sign_oneshot_to_vec(data)?)
+    }
+
+    /// Verify the signature of the data
+    fn verify<T: HasPublic>(
+        pkey: &PKeyRef<T>,
+        hash: Option<HashAlg>,
+        data: &[u8],
+        sig: &[u8],
+    ) -> OsshResult<bool> {
+        let mut verifier = match hash {
+            Some(hash) => Verifier::new(hash.message_digest(), pkey)?,
+            None => Verifier::new_without_digest(pkey)?,
+        };
+        Ok(verifier.verify_oneshot(sig, data)?)
+    }
+}
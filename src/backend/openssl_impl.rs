@@ -0,0 +1,101 @@
+use super::CryptoBackend;
+use crate::cipher::Cipher;
+use crate::error::{ErrorKind, OsshResult};
+use openssl::cipher::{Cipher as EvpCipher, CipherRef};
+use openssl::cipher_ctx::CipherCtx;
+use openssl::symm::{self, Crypter, Mode};
+
+/// The backend of OpenSSL (Feature `openssl-cipher`)
+pub(crate) struct OpensslBackend;
+
+fn symm_cipher(cipher: Cipher) -> OsshResult<symm::Cipher> {
+    use Cipher::*;
+    Ok(match cipher {
+        #[cfg(feature = "aes-cbc")]
+        Aes128_Cbc => symm::Cipher::aes_128_cbc(),
+        #[cfg(feature = "aes-cbc")]
+        Aes192_Cbc => symm::Cipher::aes_192_cbc(),
+        #[cfg(feature = "aes-cbc")]
+        Aes256_Cbc => symm::Cipher::aes_256_cbc(),
+        Aes128_Ctr => symm::Cipher::aes_128_ctr(),
+        Aes192_Ctr => symm::Cipher::aes_192_ctr(),
+        Aes256_Ctr => symm::Cipher::aes_256_ctr(),
+        #[cfg(feature = "3des-cbc")]
+        TDes_Cbc => symm::Cipher::des_ede3_cbc(),
+        Null => return Err(ErrorKind::UnsupportCipher(cipher.name().to_owned()).into()),
+    })
+}
+
+fn evp_cipher(cipher: Cipher) -> OsshResult<&'static CipherRef> {
+    use Cipher::*;
+    Ok(match cipher {
+        #[cfg(feature = "aes-cbc")]
+        Aes128_Cbc => EvpCipher::aes_128_cbc(),
+        #[cfg(feature = "aes-cbc")]
+        Aes192_Cbc => EvpCipher::aes_192_cbc(),
+        #[cfg(feature = "aes-cbc")]
+        Aes256_Cbc => EvpCipher::aes_256_cbc(),
+        Aes128_Ctr => EvpCipher::aes_128_ctr(),
+        Aes192_Ctr => EvpCipher::aes_192_ctr(),
+        Aes256_Ctr => EvpCipher::aes_256_ctr(),
+        #[cfg(feature = "3des-cbc")]
+        TDes_Cbc => EvpCipher::des_ede3_cbc(),
+        Null => return Err(ErrorKind::UnsupportCipher(cipher.name().to_owned()).into()),
+    })
+}
+
+fn crypt(
+    cipher: Cipher,
+    mode: Mode,
+    dest: &mut [u8],
+    src: &[u8],
+    key: &[u8],
+    iv: &[u8],
+) -> OsshResult<usize> {
+    let mut crypt = Crypter::new(symm_cipher(cipher)?, mode, key, Some(iv))?;
+    let mut n = crypt.update(src, dest)?;
+    n += crypt.finalize(&mut dest[n..])?;
+    Ok(n)
+}
+
+impl CryptoBackend for OpensslBackend {
+    const NAME: &'static str = "openssl";
+
+    fn calc_buflen(len: usize, block_size: usize) -> usize {
+        len + block_size
+    }
+
+    fn encrypt(
+        cipher: Cipher,
+        dest: &mut [u8],
+        src: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        crypt(cipher, Mode::Encrypt, dest, src, key, iv)
+    }
+
+    fn decrypt(
+        cipher: Cipher,
+        dest: &mut [u8],
+        src: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        crypt(cipher, Mode::Decrypt, dest, src, key, iv)
+    }
+
+    fn decrypt_in_place(
+        cipher: Cipher,
+        buf: &mut [u8],
+        len: usize,
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        let mut ctx = CipherCtx::new()?;
+        ctx.decrypt_init(Some(evp_cipher(cipher)?), Some(key), Some(iv))?;
+        let mut n = ctx.cipher_update_inplace(buf, len)?;
+        n += ctx.cipher_final(&mut buf[n..])?;
+        Ok(n)
+    }
+}
@@ -0,0 +1,136 @@
+use super::CryptoBackend;
+use crate::cipher::Cipher;
+use crate::error::{ErrorKind, OsshResult};
+use aes::{Aes128, Aes192, Aes256};
+#[cfg(any(feature = "aes-cbc", feature = "3des-cbc"))]
+use cbc::{Decryptor as CbcDecryptor, Encryptor as CbcEncryptor};
+#[cfg(any(feature = "aes-cbc", feature = "3des-cbc"))]
+use cipher::{block_padding::Pkcs7, BlockCipher, BlockDecryptMut, BlockEncryptMut, KeyInit};
+use cipher::{KeyIvInit, StreamCipher};
+use ctr::Ctr128BE;
+#[cfg(feature = "3des-cbc")]
+use des::TdesEde3;
+
+type Aes128Ctr = Ctr128BE<Aes128>;
+type Aes192Ctr = Ctr128BE<Aes192>;
+type Aes256Ctr = Ctr128BE<Aes256>;
+
+/// The backend of RustCrypto (Feature `rustcrypto-cipher`)
+pub(crate) struct RustCryptoBackend;
+
+/// Call the CBC function or the CTR function with the block cipher of `$cipher`
+macro_rules! dispatch {
+    ($cipher:expr, $cbc:ident, $ctr:ident, $($arg:expr),*) => {{
+        use Cipher::*;
+        match $cipher {
+            #[cfg(feature = "aes-cbc")]
+            Aes128_Cbc => $cbc::<Aes128>($($arg),*),
+            #[cfg(feature = "aes-cbc")]
+            Aes192_Cbc => $cbc::<Aes192>($($arg),*),
+            #[cfg(feature = "aes-cbc")]
+            Aes256_Cbc => $cbc::<Aes256>($($arg),*),
+            Aes128_Ctr => $ctr::<Aes128Ctr>($($arg),*),
+            Aes192_Ctr => $ctr::<Aes192Ctr>($($arg),*),
+            Aes256_Ctr => $ctr::<Aes256Ctr>($($arg),*),
+            #[cfg(feature = "3des-cbc")]
+            TDes_Cbc => $cbc::<TdesEde3>($($arg),*),
+            Null => Err(ErrorKind::UnsupportCipher($cipher.name().to_owned()).into()),
+        }
+    }};
+}
+
+#[cfg(any(feature = "aes-cbc", feature = "3des-cbc"))]
+fn cbc_encrypt<C>(dest: &mut [u8], src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<usize>
+where
+    C: BlockCipher + BlockEncryptMut + KeyInit,
+{
+    Ok(CbcEncryptor::<C>::new_from_slices(key, iv)?
+        .encrypt_padded_b2b_mut::<Pkcs7>(src, dest)?
+        .len())
+}
+
+#[cfg(any(feature = "aes-cbc", feature = "3des-cbc"))]
+fn cbc_decrypt<C>(dest: &mut [u8], src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<usize>
+where
+    C: BlockCipher + BlockDecryptMut + KeyInit,
+{
+    Ok(CbcDecryptor::<C>::new_from_slices(key, iv)?
+        .decrypt_padded_b2b_mut::<Pkcs7>(src, dest)?
+        .len())
+}
+
+#[cfg(any(feature = "aes-cbc", feature = "3des-cbc"))]
+fn cbc_decrypt_in_place<C>(buf: &mut [u8], len: usize, key: &[u8], iv: &[u8]) -> OsshResult<usize>
+where
+    C: BlockCipher + BlockDecryptMut + KeyInit,
+{
+    Ok(CbcDecryptor::<C>::new_from_slices(key, iv)?
+        .decrypt_padded_mut::<Pkcs7>(&mut buf[..len])?
+        .len())
+}
+
+fn ctr_endecrypt<C>(dest: &mut [u8], src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<usize>
+where
+    C: StreamCipher + KeyIvInit,
+{
+    if dest.len() >= src.len() {
+        <C>::new_from_slices(key, iv)?.apply_keystream_b2b(src, &mut dest[..src.len()])?;
+        Ok(src.len())
+    } else {
+        Err(ErrorKind::InvalidLength.into())
+    }
+}
+
+fn ctr_endecrypt_in_place<C>(buf: &mut [u8], len: usize, key: &[u8], iv: &[u8]) -> OsshResult<usize>
+where
+    C: StreamCipher + KeyIvInit,
+{
+    <C>::new_from_slices(key, iv)?.try_apply_keystream(&mut buf[..len])?;
+    Ok(len)
+}
+
+impl CryptoBackend for RustCryptoBackend {
+    const NAME: &'static str = "rustcrypto";
+
+    fn calc_buflen(len: usize, block_size: usize) -> usize {
+        len + block_size - (len % block_size)
+    }
+
+    fn encrypt(
+        cipher: Cipher,
+        dest: &mut [u8],
+        src: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        dispatch!(cipher, cbc_encrypt, ctr_endecrypt, dest, src, key, iv)
+    }
+
+    fn decrypt(
+        cipher: Cipher,
+        dest: &mut [u8],
+        src: &[u8],
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        dispatch!(cipher, cbc_decrypt, ctr_endecrypt, dest, src, key, iv)
+    }
+
+    fn decrypt_in_place(
+        cipher: Cipher,
+        buf: &mut [u8],
+        len: usize,
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        dispatch!(
+            cipher,
+            cbc_decrypt_in_place,
+            ctr_endecrypt_in_place,
+            buf,
+            len,
+            key,
+            iv
+        )
+    }
+}
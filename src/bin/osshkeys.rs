@@ -0,0 +1,373 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use osshkeys::defaults::defaults;
+use osshkeys::error::{ErrorKind, OsshResult};
+use osshkeys::format::{convert_key, ConvertOptions, LineEnding, OutputFormat, OutputOptions};
+use osshkeys::keys::{Fingerprint, FingerprintHash};
+use osshkeys::{Key as _, KeyPair, KeyType, PublicKey};
+use std::fs;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+/// Generate, convert and inspect OpenSSH keys
+#[derive(Parser)]
+#[command(name = "osshkeys", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a new key pair
+    Generate {
+        /// The type of the key, such as `ed25519`, `ssh-ed25519` or `ecdsa-sha2-nistp384`
+        #[arg(short = 't', long = "type", default_value = "ed25519")]
+        keytype: KeyTypeArg,
+        /// The size of the key in bits, 0 for the default size
+        #[arg(short, long, default_value = "0")]
+        bits: usize,
+        /// The comment of the key
+        #[arg(short = 'C', long, default_value = "")]
+        comment: String,
+        /// The passphrase to encrypt the private key, leave empty for no encryption
+        #[arg(short = 'N', long, default_value = "")]
+        passphrase: String,
+        /// The private key file, the public key is written with the `.pub` suffix
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// Convert a private or public key to another format
+    Convert {
+        /// The key file
+        #[arg(short, long)]
+        file: PathBuf,
+        /// The passphrase of the private key
+        #[arg(short = 'P', long)]
+        passphrase: Option<String>,
+        /// The output format
+        #[arg(short = 'm', long, value_enum, default_value = "openssh")]
+        format: FormatArg,
+        /// The passphrase to encrypt the output, leave empty for no encryption
+        #[arg(short = 'N', long, default_value = "")]
+        new_passphrase: String,
+        /// Wrap the base64 data at this width, such as 64 or 70
+        #[arg(long)]
+        line_width: Option<usize>,
+        /// End the lines with CRLF
+        #[arg(long)]
+        crlf: bool,
+        /// The output file, print to the standard output if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show the fingerprint of a public or private key
+    Fingerprint {
+        /// The key file
+        file: PathBuf,
+        /// The passphrase of the private key
+        #[arg(short = 'P', long)]
+        passphrase: Option<String>,
+        /// The hash algorithm of the fingerprint
+        #[arg(short = 'E', long, value_enum, default_value = "sha256")]
+        hash: HashArg,
+        /// Show the randomart of the key as well
+        #[arg(short, long)]
+        visual: bool,
+    },
+    /// Show the information of a public or private key
+    Inspect {
+        /// The key file
+        file: PathBuf,
+        /// The passphrase of the private key
+        #[arg(short = 'P', long)]
+        passphrase: Option<String>,
+    },
+    /// Change the passphrase of a private key, the key is rewritten in the OpenSSH format
+    ChangePassphrase {
+        /// The private key file
+        #[arg(short, long)]
+        file: PathBuf,
+        /// The old passphrase
+        #[arg(short = 'P', long)]
+        old_passphrase: Option<String>,
+        /// The new passphrase, leave empty to remove the encryption
+        #[arg(short = 'N', long, default_value = "")]
+        new_passphrase: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    /// The OpenSSH private key format
+    Openssh,
+    /// The traditional OpenSSL PEM format
+    Pem,
+    /// The PKCS#8 format
+    Pkcs8,
+    /// The PuTTY private key format
+    Ppk,
+    /// The OpenSSH public key format
+    OpensshPublic,
+    /// The OpenSSL PEM public key format
+    PemPublic,
+    /// The SSH2 public key format (RFC 4716)
+    Rfc4716,
+}
+
+impl From<FormatArg> for OutputFormat {
+    fn from(arg: FormatArg) -> Self {
+        match arg {
+            FormatArg::Openssh => OutputFormat::Openssh,
+            FormatArg::Pem => OutputFormat::Pem,
+            FormatArg::Pkcs8 => OutputFormat::Pkcs8,
+            FormatArg::Ppk => OutputFormat::Ppk,
+            FormatArg::OpensshPublic => OutputFormat::OpensshPublic,
+            FormatArg::PemPublic => OutputFormat::PemPublic,
+            FormatArg::Rfc4716 => OutputFormat::Rfc4716,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum HashArg {
+    Md5,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl From<HashArg> for FingerprintHash {
+    fn from(arg: HashArg) -> Self {
+        match arg {
+            HashArg::Md5 => FingerprintHash::MD5,
+            HashArg::Sha256 => FingerprintHash::SHA256,
+            HashArg::Sha384 => FingerprintHash::SHA384,
+            HashArg::Sha512 => FingerprintHash::SHA512,
+        }
+    }
+}
+
+/// The key type of `-t`, with the key size implied by the curve-specific EcDSA names
+#[derive(Clone)]
+struct KeyTypeArg {
+    keytype: KeyType,
+    bits: usize,
+}
+
+impl FromStr for KeyTypeArg {
+    type Err = osshkeys::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keytype = s.parse::<KeyType>()?;
+        #[cfg(feature = "ecdsa")]
+        if keytype == KeyType::ECDSA && s != "ecdsa" {
+            let curve = s.parse::<osshkeys::keys::ecdsa::EcCurve>()?;
+            return Ok(KeyTypeArg {
+                keytype,
+                bits: curve.size(),
+            });
+        }
+        Ok(KeyTypeArg { keytype, bits: 0 })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("osshkeys: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> OsshResult<()> {
+    match command {
+        Command::Generate {
+            keytype,
+            bits,
+            comment,
+            passphrase,
+            file,
+        } => {
+            let bits = match (keytype.bits, bits) {
+                (0, bits) => bits,
+                (curve_bits, 0) => curve_bits,
+                (curve_bits, bits) if curve_bits == bits => bits,
+                // The size conflicts with the curve of the key type
+                _ => return Err(ErrorKind::InvalidKeySize.into()),
+            };
+            let mut keypair = KeyPair::generate(keytype.keytype, bits)?;
+            *keypair.comment_mut() = comment;
+            let privkey = serialize_openssh(&keypair, &passphrase)?;
+            write_file(&file, privkey.as_bytes(), 0o600)?;
+            let pubkey = format!("{}\n", keypair.serialize_publickey()?);
+            write_file(&pub_path(&file), pubkey.as_bytes(), 0o644)?;
+            println!("{}", Fingerprint::of(&keypair, FingerprintHash::SHA256)?);
+        }
+        Command::Convert {
+            file,
+            passphrase,
+            format,
+            new_passphrase,
+            line_width,
+            crlf,
+            output,
+        } => {
+            let options = ConvertOptions {
+                output: OutputOptions {
+                    line_width,
+                    line_ending: if crlf {
+                        LineEnding::CRLF
+                    } else {
+                        LineEnding::LF
+                    },
+                    trailing_newline: None,
+                },
+                ..Default::default()
+            };
+            let converted = convert_key(
+                &fs::read(&file)?,
+                passphrase.as_deref(),
+                format.into(),
+                Some(&new_passphrase),
+                &options,
+            )?;
+            match output {
+                Some(output) => write_file(&output, &converted, 0o600)?,
+                None => io::stdout().write_all(&converted)?,
+            }
+        }
+        Command::Fingerprint {
+            file,
+            passphrase,
+            hash,
+            visual,
+        } => {
+            let (pubkey, _) = read_key(&file, passphrase.as_deref())?;
+            let hash = hash.into();
+            println!(
+                "{} {} {} ({})",
+                pubkey.size(),
+                Fingerprint::of(&pubkey, hash)?,
+                pubkey.comment(),
+                pubkey.short_keyname()
+            );
+            if visual {
+                println!("{}", pubkey.randomart(hash)?);
+            }
+        }
+        Command::Inspect { file, passphrase } => {
+            let (pubkey, private) = read_key(&file, passphrase.as_deref())?;
+            println!("Type: {}", pubkey.keyname());
+            println!("Size: {}", pubkey.size());
+            println!("Comment: {}", pubkey.comment());
+            match private {
+                Some(encrypted) => println!(
+                    "Private: yes ({})",
+                    if encrypted {
+                        "encrypted"
+                    } else {
+                        "unencrypted"
+                    }
+                ),
+                None => println!("Private: no"),
+            }
+            for hash in [FingerprintHash::MD5, FingerprintHash::SHA256] {
+                println!("Fingerprint: {}", Fingerprint::of(&pubkey, hash)?);
+            }
+        }
+        Command::ChangePassphrase {
+            file,
+            old_passphrase,
+            new_passphrase,
+        } => {
+            let keypair = read_keypair(&file, old_passphrase.as_deref())?;
+            let privkey = serialize_openssh(&keypair, &new_passphrase)?;
+            replace_file(&file, privkey.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn pub_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".pub");
+    PathBuf::from(name)
+}
+
+fn read_keypair(path: &Path, passphrase: Option<&str>) -> OsshResult<KeyPair> {
+    let keystr = fs::read_to_string(path)?;
+    KeyPair::from_keystr(&keystr, passphrase)
+}
+
+/// Read a public or private key, also return if the private key is encrypted
+///
+/// If the private key is encrypted but no passphrase is given,
+/// the public key is read from the `.pub` file instead.
+fn read_key(path: &Path, passphrase: Option<&str>) -> OsshResult<(PublicKey, Option<bool>)> {
+    let keystr = fs::read_to_string(path)?;
+    if let Ok(pubkey) = PublicKey::from_keystr(&keystr) {
+        return Ok((pubkey, None));
+    }
+    match KeyPair::from_keystr(&keystr, None) {
+        Ok(keypair) => Ok((keypair.clone_public_key()?, Some(false))),
+        Err(e) if e.kind() == ErrorKind::IncorrectPass => {
+            let pubkey = match passphrase {
+                Some(passphrase) => {
+                    KeyPair::from_keystr(&keystr, Some(passphrase))?.clone_public_key()?
+                }
+                None => PublicKey::from_keystr(&fs::read_to_string(pub_path(path))?)?,
+            };
+            Ok((pubkey, Some(true)))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn serialize_openssh(keypair: &KeyPair, passphrase: &str) -> OsshResult<String> {
+    let passphrase = Some(passphrase).filter(|p| !p.is_empty());
+    keypair.serialize_openssh_with_defaults(passphrase, &defaults())
+}
+
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn write_file(path: &Path, content: &[u8], mode: u32) -> OsshResult<()> {
+    let mut fop = fs::OpenOptions::new();
+    fop.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    fop.mode(mode);
+
+    let mut f = fop.open(path)?;
+    f.write_all(content)?;
+    Ok(())
+}
+
+/// Replace the private key file without a window where it is partially written
+///
+/// The content is written to a temporary file with the permission `0600` in the same directory,
+/// synced to the disk, then renamed over the original file.
+fn replace_file(path: &Path, content: &[u8]) -> OsshResult<()> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".tmp-{}", std::process::id()));
+    let tmp = path.with_file_name(name);
+
+    let mut fop = fs::OpenOptions::new();
+    fop.write(true).create_new(true);
+    #[cfg(unix)]
+    fop.mode(0o600);
+    let result = fop.open(&tmp).and_then(|mut f| {
+        f.write_all(content)?;
+        f.sync_all()?;
+        fs::rename(&tmp, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(result?)
+}
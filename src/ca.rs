@@ -0,0 +1,399 @@
+use crate::error::*;
+use crate::hostkeys::write_key_file;
+use crate::keys::cert::{
+    format_time, unix_time, CertType, Certificate, CertificateFields, CERT_FOREVER,
+};
+use crate::keys::rsa::RsaSignature;
+use crate::keys::{Fingerprint, FingerprintHash, PublicKey, SshSigner};
+use crate::known_hosts::match_pattern_list;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The storage of the serial numbers issued by the [`Ca`]
+///
+/// The serial numbers must never be reused, so the store should persist the allocation before returning it.
+pub trait SerialStore {
+    /// Allocate the next serial number
+    fn next_serial(&mut self) -> OsshResult<u64>;
+}
+
+/// The serial numbers counted in memory, which restart when the process restarts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySerialStore {
+    next: u64,
+}
+
+impl MemorySerialStore {
+    /// Start counting from the serial number
+    pub fn starting_at(next: u64) -> Self {
+        MemorySerialStore { next }
+    }
+}
+
+impl Default for MemorySerialStore {
+    /// Start counting from 1, since 0 is the serial number of the certificates without one
+    fn default() -> Self {
+        Self::starting_at(1)
+    }
+}
+
+impl SerialStore for MemorySerialStore {
+    fn next_serial(&mut self) -> OsshResult<u64> {
+        let serial = self.next;
+        self.next = serial.checked_add(1).ok_or(ErrorKind::InvalidArgument)?;
+        Ok(serial)
+    }
+}
+
+/// The last issued serial number kept in a file, as a decimal number
+///
+/// The file is replaced atomically after each allocation. A missing file means no serial number has been issued,
+/// so the first one is 1. The file must not be shared by the processes running at the same time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSerialStore {
+    path: PathBuf,
+}
+
+impl FileSerialStore {
+    /// Keep the serial number in the file
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileSerialStore {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    /// Read the last issued serial number, which is 0 if none has been issued
+    pub fn last_serial(&self) -> OsshResult<u64> {
+        match fs::read_to_string(&self.path) {
+            Ok(s) => s
+                .trim()
+                .parse()
+                .map_err(|e| Error::with_error(ErrorKind::InvalidFormat, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl SerialStore for FileSerialStore {
+    fn next_serial(&mut self) -> OsshResult<u64> {
+        let serial = self
+            .last_serial()?
+            .checked_add(1)
+            .ok_or(ErrorKind::InvalidArgument)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        write_key_file(Path::new(&tmp), format!("{}\n", serial).as_bytes(), 0o600)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(serial)
+    }
+}
+
+/// The restrictions on the certificates issued by the [`Ca`]
+///
+/// The default policy allows everything.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IssuancePolicy {
+    /// The longest validity period in seconds, or `None` for no limit
+    ///
+    /// With a limit, the certificates valid forever are refused.
+    pub max_validity: Option<u64>,
+    /// The allowed principals, or `None` to allow any principal
+    ///
+    /// The patterns can have the `*` and `?` wildcards and the `!` negation.
+    /// With the patterns, the certificates without principals, which are valid for anyone, are refused.
+    pub allowed_principals: Option<Vec<String>>,
+    /// The allowed extension names, or `None` to allow any extension
+    pub allowed_extensions: Option<Vec<String>>,
+    /// The allowed critical option names, or `None` to allow any critical option
+    pub allowed_critical_options: Option<Vec<String>>,
+}
+
+impl IssuancePolicy {
+    /// Check the fields of a new certificate
+    ///
+    /// Fail with [`ErrorKind::PolicyViolation`] if they are not allowed.
+    pub fn check(&self, fields: &CertificateFields) -> OsshResult<()> {
+        if let Some(max) = self.max_validity {
+            if fields.valid_before < fields.valid_after
+                || fields.valid_before - fields.valid_after > max
+                || fields.valid_before == CERT_FOREVER
+            {
+                return Err(violation(format!(
+                    "the validity period is longer than {} seconds",
+                    max
+                )));
+            }
+        }
+        if let Some(allowed) = &self.allowed_principals {
+            if fields.valid_principals.is_empty() {
+                return Err(violation("the certificate has no principals".to_owned()));
+            }
+            let allowed = || allowed.iter().map(String::as_str);
+            if let Some(p) = fields
+                .valid_principals
+                .iter()
+                .find(|p| !match_pattern_list(allowed(), p))
+            {
+                return Err(violation(format!("principal {} is not allowed", p)));
+            }
+        }
+        check_names("extension", &self.allowed_extensions, &fields.extensions)?;
+        check_names(
+            "critical option",
+            &self.allowed_critical_options,
+            &fields.critical_options,
+        )
+    }
+}
+
+fn check_names(
+    what: &str,
+    allowed: &Option<Vec<String>>,
+    options: &[(String, String)],
+) -> OsshResult<()> {
+    if let Some(allowed) = allowed {
+        if let Some((name, _)) = options.iter().find(|(name, _)| !allowed.contains(name)) {
+            return Err(violation(format!("{} {} is not allowed", what, name)));
+        }
+    }
+    Ok(())
+}
+
+fn violation(reason: String) -> Error {
+    ErrorKind::PolicyViolation(reason).into()
+}
+
+/// The audit record of an issued certificate
+///
+/// It's displayed as a single line for the logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuanceRecord {
+    /// The time of the issuance in seconds since the Unix epoch
+    pub issued_at: u64,
+    pub serial: u64,
+    pub cert_type: CertType,
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    /// The SHA256 fingerprint of the certified key
+    pub key_fingerprint: Fingerprint,
+    /// The SHA256 fingerprint of the CA key
+    pub ca_fingerprint: Fingerprint,
+}
+
+impl fmt::Display for IssuanceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let valid_before = if self.valid_before == CERT_FOREVER {
+            "forever".to_owned()
+        } else {
+            format_time(self.valid_before)
+        };
+        write!(
+            f,
+            "{} issued {} certificate serial {} id \"{}\" for {} signed by {} principals \"{}\" valid from {} to {}",
+            format_time(self.issued_at),
+            self.cert_type,
+            self.serial,
+            self.key_id,
+            self.key_fingerprint,
+            self.ca_fingerprint,
+            self.principals.join(","),
+            format_time(self.valid_after),
+            valid_before
+        )
+    }
+}
+
+/// The callback receiving the audit records of the [`Ca`]
+pub type AuditFn = dyn FnMut(&IssuanceRecord) + Send;
+
+/// A small SSH certificate authority
+///
+/// The CA signs the certificates with its signer, which may be a [`KeyPair`](crate::KeyPair),
+/// a hardware key or any other [`SshSigner`]. Each certificate gets the next serial number from the store,
+/// is checked with the policy, and is reported to the audit callback after signing.
+pub struct Ca<S: SshSigner, T: SerialStore> {
+    signer: S,
+    store: T,
+    policy: IssuancePolicy,
+    rsa_signature: RsaSignature,
+    audit: Option<Box<AuditFn>>,
+}
+
+impl<S: SshSigner, T: SerialStore> Ca<S, T> {
+    /// Create the CA with the signer, the serial number store and the policy
+    ///
+    /// The RSA CA keys sign with `rsa-sha2-512`.
+    pub fn new(signer: S, store: T, policy: IssuancePolicy) -> Self {
+        Ca {
+            signer,
+            store,
+            policy,
+            rsa_signature: RsaSignature::SHA2_512,
+            audit: None,
+        }
+    }
+
+    /// Set the hash algorithm of the RSA CA signatures
+    pub fn set_rsa_signature(&mut self, rsa_signature: RsaSignature) {
+        self.rsa_signature = rsa_signature;
+    }
+
+    /// Set the callback receiving the audit record of each issued certificate
+    pub fn set_audit<F: FnMut(&IssuanceRecord) + Send + 'static>(&mut self, audit: F) {
+        self.audit = Some(Box::new(audit));
+    }
+
+    /// Get the issuance policy
+    pub fn policy(&self) -> &IssuancePolicy {
+        &self.policy
+    }
+
+    /// Get the public key of the CA, which the servers and the clients trust
+    pub fn public_key(&self) -> OsshResult<PublicKey> {
+        self.signer.clone_public_key()
+    }
+
+    /// Issue the certificate of the key
+    ///
+    /// The serial number of `fields` is replaced by the next one of the store.
+    /// Fail with [`ErrorKind::PolicyViolation`] if the policy refuses the fields, in which case no serial number is used.
+    pub fn issue(
+        &mut self,
+        key: &PublicKey,
+        fields: &CertificateFields,
+    ) -> OsshResult<Certificate> {
+        self.policy.check(fields)?;
+        let fields = CertificateFields {
+            serial: self.store.next_serial()?,
+            ..fields.clone()
+        };
+        let cert = Certificate::sign(key, &fields, &self.signer, self.rsa_signature)?;
+        if let Some(audit) = &mut self.audit {
+            audit(&IssuanceRecord {
+                issued_at: unix_time(),
+                serial: fields.serial,
+                cert_type: fields.cert_type,
+                key_id: fields.key_id,
+                principals: fields.valid_principals,
+                valid_after: fields.valid_after,
+                valid_before: fields.valid_before,
+                key_fingerprint: Fingerprint::of(key, FingerprintHash::SHA256)?,
+                ca_fingerprint: Fingerprint::of(cert.signature_key(), FingerprintHash::SHA256)?,
+            });
+        }
+        Ok(cert)
+    }
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::KeyPair;
+    #[cfg(not(feature = "fips"))]
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn ca_issue_with_policy() {
+        let ca_key = KeyPair::from_keystr(include_str!("../assets/ca_ed25519"), None).unwrap();
+        let key = PublicKey::from_keystr(include_str!("../assets/openssh_ecdsa.pub")).unwrap();
+        let policy = IssuancePolicy {
+            max_validity: Some(3600),
+            allowed_principals: Some(vec!["*.example.com".into(), "!root.example.com".into()]),
+            allowed_extensions: Some(vec!["permit-pty".into()]),
+            allowed_critical_options: Some(vec![]),
+        };
+        let mut ca = Ca::new(ca_key, MemorySerialStore::starting_at(10), policy);
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        ca.set_audit(move |record| sink.lock().unwrap().push(record.clone()));
+
+        let fields = CertificateFields {
+            serial: 12345,
+            cert_type: CertType::Host,
+            key_id: "web".into(),
+            valid_principals: vec!["web.example.com".into()],
+            valid_after: 1_700_000_000,
+            valid_before: 1_700_003_600,
+            ..Default::default()
+        };
+        let cert = ca.issue(&key, &fields).unwrap();
+        assert_eq!(cert.serial(), 10);
+        assert!(cert
+            .verify_at(
+                &[ca.public_key().unwrap()],
+                CertType::Host,
+                "web.example.com",
+                1_700_000_000
+            )
+            .unwrap());
+        assert!(cert.signature_key() == &ca.public_key().unwrap());
+        assert_eq!(ca.issue(&key, &fields).unwrap().serial(), 11);
+
+        for refused in [
+            CertificateFields {
+                valid_before: CERT_FOREVER,
+                ..fields.clone()
+            },
+            CertificateFields {
+                valid_principals: vec!["root.example.com".into()],
+                ..fields.clone()
+            },
+            CertificateFields {
+                valid_principals: vec![],
+                ..fields.clone()
+            },
+            CertificateFields {
+                extensions: vec![("permit-X11-forwarding".into(), String::new())],
+                ..fields.clone()
+            },
+            CertificateFields {
+                critical_options: vec![("force-command".into(), "true".into())],
+                ..fields.clone()
+            },
+        ] {
+            assert!(matches!(
+                ca.issue(&key, &refused).err().unwrap().kind(),
+                ErrorKind::PolicyViolation(_)
+            ));
+        }
+        assert_eq!(ca.issue(&key, &fields).unwrap().serial(), 12);
+
+        let records = records.lock().unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.serial).collect::<Vec<_>>(),
+            [10, 11, 12]
+        );
+        let line = records[0].to_string();
+        assert!(line.contains("issued host certificate serial 10 id \"web\""));
+        assert!(line.contains("valid from 2023-11-14T22:13:20 to 2023-11-14T23:13:20"));
+        assert!(line.contains(
+            &Fingerprint::of(&key, FingerprintHash::SHA256)
+                .unwrap()
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn file_serial_store() {
+        let path = std::env::temp_dir().join(format!("osshkeys-ca-serial-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut store = FileSerialStore::new(&path);
+        assert_eq!(store.last_serial().unwrap(), 0);
+        assert_eq!(store.next_serial().unwrap(), 1);
+        assert_eq!(store.next_serial().unwrap(), 2);
+        // A new store continues from the file
+        assert_eq!(FileSerialStore::new(&path).next_serial().unwrap(), 3);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "3\n");
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(store.next_serial().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use crate::backend::{Backend, CryptoBackend};
+use crate::cipher::Cipher;
+use crate::keys::ecdsa::EcCurve;
+use crate::keys::KeyType;
+
+/// The key types, the curves, the ciphers, the KDFs and the formats supported by this build
+///
+/// The lists depend on the enabled features, for example the feature `fips` removes the algorithms which are not FIPS approved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The key types compiled in, without the ones refused by the feature `fips`
+    pub key_types: Vec<KeyType>,
+    /// The elliptic curves of the ECDSA keys, empty without the feature `ecdsa`
+    pub curves: Vec<EcCurve>,
+    /// The ciphers to encrypt the OpenSSH private keys, without [`Cipher::Null`]
+    pub ciphers: Vec<Cipher>,
+    /// The KDFs of the encrypted private keys, `bcrypt` for OpenSSH and `pbkdf2` for PKCS#8
+    pub kdfs: Vec<&'static str>,
+    /// The key formats which can be parsed, like `openssh`, `pkcs8` or `rfc4716`
+    pub read_formats: Vec<&'static str>,
+    /// The key formats which can be written, like `openssh`, `pkcs8` or `ppk`
+    pub write_formats: Vec<&'static str>,
+    /// The backend of the ciphers, `openssl` or `rustcrypto`
+    pub cipher_backend: &'static str,
+    /// Whether the RSA private key operations are hardened against the timing side channels,
+    /// see [`RsaKeyPair::is_side_channel_hardened()`](crate::keys::rsa::RsaKeyPair::is_side_channel_hardened())
+    pub rsa_side_channel_hardened: bool,
+    /// The version of the linked OpenSSL library, like `OpenSSL 3.0.2 15 Mar 2022`
+    pub openssl_version: &'static str,
+    /// The optional features enabled in this build
+    pub features: Vec<&'static str>,
+}
+
+/// Get the capabilities of this build
+///
+/// ```rust
+/// use osshkeys::capabilities;
+///
+/// let caps = capabilities();
+/// assert!(caps.read_formats.contains(&"openssh"));
+/// ```
+pub fn capabilities() -> Capabilities {
+    let key_types = [
+        #[cfg(feature = "rsa")]
+        KeyType::RSA,
+        #[cfg(all(feature = "dsa", not(feature = "fips")))]
+        KeyType::DSA,
+        #[cfg(feature = "ecdsa")]
+        KeyType::ECDSA,
+        #[cfg(all(feature = "ed25519", not(feature = "fips")))]
+        KeyType::ED25519,
+    ]
+    .to_vec();
+
+    let mut ciphers = Vec::new();
+    #[cfg(feature = "aes-cbc")]
+    ciphers.extend([Cipher::Aes128_Cbc, Cipher::Aes192_Cbc, Cipher::Aes256_Cbc]);
+    ciphers.extend([Cipher::Aes128_Ctr, Cipher::Aes192_Ctr, Cipher::Aes256_Ctr]);
+    #[cfg(all(feature = "3des-cbc", not(feature = "fips")))]
+    ciphers.push(Cipher::TDes_Cbc);
+
+    let kdfs = if cfg!(feature = "fips") {
+        vec!["pbkdf2"]
+    } else {
+        vec!["bcrypt", "pbkdf2"]
+    };
+
+    let mut read_formats = vec![
+        "openssh",
+        "pem",
+        "pkcs8",
+        "openssh-public",
+        "pem-public",
+        "rfc4716",
+    ];
+    let mut write_formats = vec![
+        "openssh",
+        "pem",
+        "pkcs8",
+        "ppk",
+        "openssh-public",
+        "pem-public",
+        "rfc4716",
+    ];
+    if cfg!(feature = "openpgp") {
+        read_formats.push("openpgp");
+        write_formats.push("openpgp");
+    }
+
+    Capabilities {
+        key_types,
+        curves: if cfg!(feature = "ecdsa") {
+            vec![EcCurve::Nistp256, EcCurve::Nistp384, EcCurve::Nistp521]
+        } else {
+            Vec::new()
+        },
+        ciphers,
+        kdfs,
+        read_formats,
+        write_formats,
+        cipher_backend: Backend::NAME,
+        rsa_side_channel_hardened: Backend::RSA_SIDE_CHANNEL_HARDENED,
+        openssl_version: openssl::version::version(),
+        features: enabled_features(),
+    }
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    macro_rules! feature {
+        ($($name:literal),*) => {
+            $(
+                if cfg!(feature = $name) {
+                    features.push($name);
+                }
+            )*
+        };
+    }
+    feature!(
+        "rsa",
+        "dsa",
+        "ecdsa",
+        "ed25519",
+        "aes-cbc",
+        "3des-cbc",
+        "legacy-des",
+        "openssl-vendored",
+        "serde",
+        "tokio",
+        "cli",
+        "tracing",
+        "memsec",
+        "simd-base64",
+        "fips",
+        "openssl-provider",
+        "tpm2",
+        "keychain",
+        "pkcs11",
+        "yubikey-piv",
+        "ed25519-batch",
+        "rayon",
+        "openpgp",
+        "miette",
+        "num-bigint",
+        "full-debug",
+        "ffi",
+        "uniffi"
+    );
+    features
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_capabilities() {
+        let caps = capabilities();
+        assert_eq!(caps.curves.is_empty(), cfg!(not(feature = "ecdsa")));
+        assert!(caps.ciphers.contains(&Cipher::Aes256_Ctr));
+        assert!(!caps.ciphers.contains(&Cipher::Null));
+        assert!(caps.write_formats.contains(&"ppk"));
+        assert!(!caps.read_formats.contains(&"ppk"));
+        assert!(!caps.openssl_version.is_empty());
+        assert_eq!(caps.features.contains(&"serde"), cfg!(feature = "serde"));
+        #[cfg(all(feature = "rsa", feature = "dsa", not(feature = "fips")))]
+        assert!(caps.key_types.contains(&KeyType::RSA) && caps.key_types.contains(&KeyType::DSA));
+    }
+}
@@ -1,19 +1,24 @@
 use std::str::FromStr;
 
-use self::internal_impl::*;
+use crate::backend::{Backend, CryptoBackend};
 use crate::error::{Error as OsshError, ErrorKind, OsshResult};
+use zeroize::Zeroize;
 
 /// Indicate the algorithm used by encryption/decryption
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[allow(non_camel_case_types)]
 #[non_exhaustive]
 pub enum Cipher {
+    #[cfg(feature = "aes-cbc")]
     Aes128_Cbc,
+    #[cfg(feature = "aes-cbc")]
     Aes192_Cbc,
+    #[cfg(feature = "aes-cbc")]
     Aes256_Cbc,
     Aes128_Ctr,
     Aes192_Ctr,
     Aes256_Ctr,
+    #[cfg(feature = "3des-cbc")]
     TDes_Cbc,
     Null,
 }
@@ -38,14 +43,11 @@ impl Cipher {
         iv: &[u8],
     ) -> OsshResult<usize> {
         use Cipher::*;
+        #[cfg(all(feature = "fips", feature = "3des-cbc"))]
+        if self == TDes_Cbc {
+            crate::fips::refuse(self.name())?;
+        }
         match self {
-            Aes128_Cbc => aes128cbc_encrypt(dest, src, key, iv),
-            Aes192_Cbc => aes192cbc_encrypt(dest, src, key, iv),
-            Aes256_Cbc => aes256cbc_encrypt(dest, src, key, iv),
-            Aes128_Ctr => aes128ctr_encrypt(dest, src, key, iv),
-            Aes192_Ctr => aes192ctr_encrypt(dest, src, key, iv),
-            Aes256_Ctr => aes256ctr_encrypt(dest, src, key, iv),
-            TDes_Cbc => tdescbc_encrypt(dest, src, key, iv),
             Null => {
                 if dest.len() >= src.len() {
                     dest[..src.len()].clone_from_slice(src);
@@ -54,6 +56,7 @@ impl Cipher {
                     Err(ErrorKind::InvalidLength.into())
                 }
             }
+            _ => Backend::encrypt(self, dest, src, key, iv),
         }
     }
 
@@ -63,9 +66,18 @@ impl Cipher {
     /// Usually you don't need to call it directly.
     pub fn decrypt(self, src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<Vec<u8>> {
         let mut buf = vec![0; self.calc_buffer_len(src.len())];
-        let n = self.decrypt_to(&mut buf, src, key, iv)?;
-        buf.truncate(n);
-        Ok(buf)
+        match self.decrypt_to(&mut buf, src, key, iv) {
+            Ok(n) => {
+                // Wipe the decrypted padding before truncating
+                buf[n..].zeroize();
+                buf.truncate(n);
+                Ok(buf)
+            }
+            Err(e) => {
+                buf.zeroize();
+                Err(e)
+            }
+        }
     }
 
     pub fn decrypt_to(
@@ -77,13 +89,6 @@ impl Cipher {
     ) -> OsshResult<usize> {
         use Cipher::*;
         match self {
-            Aes128_Cbc => aes128cbc_decrypt(dest, src, key, iv),
-            Aes192_Cbc => aes192cbc_decrypt(dest, src, key, iv),
-            Aes256_Cbc => aes256cbc_decrypt(dest, src, key, iv),
-            Aes128_Ctr => aes128ctr_decrypt(dest, src, key, iv),
-            Aes192_Ctr => aes192ctr_decrypt(dest, src, key, iv),
-            Aes256_Ctr => aes256ctr_decrypt(dest, src, key, iv),
-            TDes_Cbc => tdescbc_decrypt(dest, src, key, iv),
             Null => {
                 if dest.len() >= src.len() {
                     dest[..src.len()].clone_from_slice(src);
@@ -92,6 +97,32 @@ impl Cipher {
                     Err(ErrorKind::InvalidLength.into())
                 }
             }
+            _ => Backend::decrypt(self, dest, src, key, iv),
+        }
+    }
+
+    /// Decrypt the data in place
+    ///
+    /// The ciphertext is `buf[..len]`, and `buf` must be at least [calc_buffer_len(len)](enum.Cipher.html#method.calc_buffer_len)
+    /// long since some backends need the room of an extra block.
+    /// Return the length of the plaintext, which is at the beginning of `buf`.
+    pub fn decrypt_in_place(
+        self,
+        buf: &mut [u8],
+        len: usize,
+        key: &[u8],
+        iv: &[u8],
+    ) -> OsshResult<usize> {
+        use Cipher::*;
+        if buf.len() < self.calc_buffer_len(len)
+            || key.len() != self.key_len()
+            || iv.len() != self.iv_len()
+        {
+            return Err(ErrorKind::InvalidLength.into());
+        }
+        match self {
+            Null => Ok(len),
+            _ => Backend::decrypt_in_place(self, buf, len, key, iv),
         }
     }
 
@@ -104,7 +135,7 @@ impl Cipher {
     /// ```
     /// # use osshkeys::cipher::Cipher;
     /// # use hex_literal::hex;
-    /// let cipher = Cipher::Aes128_Cbc;
+    /// let cipher = Cipher::Aes128_Ctr;
     /// let src = hex!("ed58042b83e18d59bde732638136ac0e");
     /// let key = hex!("2b7e151628aed2a6abf7158809cf4f3c");
     /// let iv = hex!("000102030405060708090a0b0c0d0e0f");
@@ -114,19 +145,23 @@ impl Cipher {
     /// buf.truncate(n);
     /// ```
     pub fn calc_buffer_len(self, len: usize) -> usize {
-        calc_buflen(len, self.block_size())
+        Backend::calc_buflen(len, self.block_size())
     }
 
     /// Return the required key length in bytes
     pub fn key_len(self) -> usize {
         use Cipher::*;
         match self {
+            #[cfg(feature = "aes-cbc")]
             Aes128_Cbc => 16,
+            #[cfg(feature = "aes-cbc")]
             Aes192_Cbc => 24,
+            #[cfg(feature = "aes-cbc")]
             Aes256_Cbc => 32,
             Aes128_Ctr => 16,
             Aes192_Ctr => 24,
             Aes256_Ctr => 32,
+            #[cfg(feature = "3des-cbc")]
             TDes_Cbc => 24,
             Null => 0,
         }
@@ -136,12 +171,16 @@ impl Cipher {
     pub fn iv_len(self) -> usize {
         use Cipher::*;
         match self {
+            #[cfg(feature = "aes-cbc")]
             Aes128_Cbc => 16,
+            #[cfg(feature = "aes-cbc")]
             Aes192_Cbc => 16,
+            #[cfg(feature = "aes-cbc")]
             Aes256_Cbc => 16,
             Aes128_Ctr => 16,
             Aes192_Ctr => 16,
             Aes256_Ctr => 16,
+            #[cfg(feature = "3des-cbc")]
             TDes_Cbc => 8,
             Null => 0,
         }
@@ -151,12 +190,16 @@ impl Cipher {
     pub fn block_size(self) -> usize {
         use Cipher::*;
         match self {
+            #[cfg(feature = "aes-cbc")]
             Aes128_Cbc => 16,
+            #[cfg(feature = "aes-cbc")]
             Aes192_Cbc => 16,
+            #[cfg(feature = "aes-cbc")]
             Aes256_Cbc => 16,
             Aes128_Ctr => 16,
             Aes192_Ctr => 16,
             Aes256_Ctr => 16,
+            #[cfg(feature = "3des-cbc")]
             TDes_Cbc => 8,
             Null => 8,
         }
@@ -166,12 +209,16 @@ impl Cipher {
     pub fn name(self) -> &'static str {
         use Cipher::*;
         match self {
+            #[cfg(feature = "aes-cbc")]
             Aes128_Cbc => "aes128-cbc",
+            #[cfg(feature = "aes-cbc")]
             Aes192_Cbc => "aes192-cbc",
+            #[cfg(feature = "aes-cbc")]
             Aes256_Cbc => "aes256-cbc",
             Aes128_Ctr => "aes128-ctr",
             Aes192_Ctr => "aes192-ctr",
             Aes256_Ctr => "aes256-ctr",
+            #[cfg(feature = "3des-cbc")]
             TDes_Cbc => "3des-cbc",
             Null => "none",
         }
@@ -199,350 +246,61 @@ impl FromStr for Cipher {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use Cipher::*;
         match s {
+            #[cfg(feature = "3des-cbc")]
             "3des-cbc" => Ok(TDes_Cbc),
+            #[cfg(feature = "aes-cbc")]
             "aes128-cbc" => Ok(Aes128_Cbc),
+            #[cfg(feature = "aes-cbc")]
             "aes192-cbc" => Ok(Aes192_Cbc),
+            #[cfg(feature = "aes-cbc")]
             "aes256-cbc" | "rijndael-cbc@lysator.liu.se" => Ok(Aes256_Cbc),
             "aes128-ctr" => Ok(Aes128_Ctr),
             "aes192-ctr" => Ok(Aes192_Ctr),
             "aes256-ctr" => Ok(Aes256_Ctr),
             "none" => Ok(Null),
-            _ => Err(ErrorKind::UnsupportCipher.into()),
+            _ => Err(ErrorKind::UnsupportCipher(s.to_owned()).into()),
         }
     }
 }
 
-#[cfg(not(any(feature = "rustcrypto-cipher", feature = "openssl-cipher")))]
-compile_error!("No cipher backend is selected! Please enable one cipher backend feature.");
-
-#[cfg(all(feature = "rustcrypto-cipher", feature = "openssl-cipher"))]
-compile_error!("Multiple cipher backends are selected! Only one cipher backend is supported.");
-
-#[cfg(feature = "rustcrypto-cipher")]
-mod internal_impl {
-    use aes::{Aes128, Aes192, Aes256};
-    use cbc::{Decryptor as CbcDecryptor, Encryptor as CbcEncryptor};
-    use cipher::{
-        block_padding::Pkcs7, BlockCipher, BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit,
-        StreamCipher,
-    };
-    use ctr::Ctr128BE;
-    use des::TdesEde3;
-
-    use crate::error::{ErrorKind, OsshResult};
-
-    type Aes128Ctr = Ctr128BE<Aes128>;
-    type Aes192Ctr = Ctr128BE<Aes192>;
-    type Aes256Ctr = Ctr128BE<Aes256>;
-
-    pub fn calc_buflen(len: usize, block_size: usize) -> usize {
-        let addi = block_size - (len % block_size);
-        len + addi
-    }
-
-    fn cbc_encrypt<C>(dest: &mut [u8], src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<usize>
-    where
-        C: BlockCipher + BlockEncryptMut + KeyInit,
-    {
-        Ok(CbcEncryptor::<C>::new_from_slices(key, iv)?
-            .encrypt_padded_b2b_mut::<Pkcs7>(src, dest)?
-            .len())
-    }
-
-    fn cbc_decrypt<C>(dest: &mut [u8], src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<usize>
-    where
-        C: BlockCipher + BlockDecryptMut + KeyInit,
-    {
-        Ok(CbcDecryptor::<C>::new_from_slices(key, iv)?
-            .decrypt_padded_b2b_mut::<Pkcs7>(src, dest)?
-            .len())
-    }
-
-    fn ctr_endecrypt<C>(dest: &mut [u8], src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<usize>
-    where
-        C: StreamCipher + KeyIvInit,
-    {
-        if dest.len() >= src.len() {
-            <C>::new_from_slices(key, iv)?.apply_keystream_b2b(src, &mut dest[..src.len()])?;
-            Ok(src.len())
-        } else {
-            Err(ErrorKind::InvalidLength.into())
+#[cfg(all(test, not(feature = "fips")))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cipher_decrypt_in_place() {
+        let ciphers = [
+            #[cfg(feature = "aes-cbc")]
+            Cipher::Aes128_Cbc,
+            #[cfg(feature = "aes-cbc")]
+            Cipher::Aes192_Cbc,
+            #[cfg(feature = "aes-cbc")]
+            Cipher::Aes256_Cbc,
+            Cipher::Aes128_Ctr,
+            Cipher::Aes192_Ctr,
+            Cipher::Aes256_Ctr,
+            #[cfg(feature = "3des-cbc")]
+            Cipher::TDes_Cbc,
+            Cipher::Null,
+        ];
+        let plain: Vec<u8> = (0..64).collect();
+        for cipher in ciphers {
+            let key = vec![0x42; cipher.key_len()];
+            let iv = vec![0x24; cipher.iv_len()];
+            let encrypted = cipher.encrypt(&plain, &key, &iv).unwrap();
+
+            let mut buf = vec![0; cipher.calc_buffer_len(encrypted.len())];
+            buf[..encrypted.len()].copy_from_slice(&encrypted);
+            let n = cipher
+                .decrypt_in_place(&mut buf, encrypted.len(), &key, &iv)
+                .unwrap();
+            assert_eq!(&buf[..n], &plain[..], "{}", cipher.name());
+
+            // The buffer must have the room of the backend
+            let mut short = encrypted.clone();
+            assert!(cipher
+                .decrypt_in_place(&mut short, encrypted.len(), &key, &iv)
+                .is_err());
         }
     }
-
-    pub fn aes128cbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_encrypt::<Aes128>(dest, src, key, iv)
-    }
-    pub fn aes128cbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_decrypt::<Aes128>(dest, src, key, iv)
-    }
-
-    pub fn aes192cbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_encrypt::<Aes192>(dest, src, key, iv)
-    }
-    pub fn aes192cbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_decrypt::<Aes192>(dest, src, key, iv)
-    }
-
-    pub fn aes256cbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_encrypt::<Aes256>(dest, src, key, iv)
-    }
-    pub fn aes256cbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_decrypt::<Aes256>(dest, src, key, iv)
-    }
-
-    pub fn tdescbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_encrypt::<TdesEde3>(dest, src, key, iv)
-    }
-    pub fn tdescbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        cbc_decrypt::<TdesEde3>(dest, src, key, iv)
-    }
-
-    pub fn aes128ctr_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        ctr_endecrypt::<Aes128Ctr>(dest, src, key, iv)
-    }
-    pub fn aes128ctr_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        ctr_endecrypt::<Aes128Ctr>(dest, src, key, iv)
-    }
-
-    pub fn aes192ctr_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        ctr_endecrypt::<Aes192Ctr>(dest, src, key, iv)
-    }
-    pub fn aes192ctr_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        ctr_endecrypt::<Aes192Ctr>(dest, src, key, iv)
-    }
-
-    pub fn aes256ctr_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        ctr_endecrypt::<Aes256Ctr>(dest, src, key, iv)
-    }
-    pub fn aes256ctr_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        ctr_endecrypt::<Aes256Ctr>(dest, src, key, iv)
-    }
-}
-
-#[cfg(feature = "openssl-cipher")]
-mod internal_impl {
-    use openssl::symm::{Cipher, Crypter, Mode};
-
-    use crate::error::OsshResult;
-
-    fn openssl_encrypt(
-        cipher: Cipher,
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        let mut crypt = Crypter::new(cipher, Mode::Encrypt, key, Some(iv))?;
-        let mut n = crypt.update(src, dest)?;
-        n += crypt.finalize(&mut dest[n..])?;
-        Ok(n)
-    }
-
-    fn openssl_decrypt(
-        cipher: Cipher,
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        let mut crypt = Crypter::new(cipher, Mode::Decrypt, key, Some(iv))?;
-        let mut n = crypt.update(src, dest)?;
-        n += crypt.finalize(&mut dest[n..])?;
-        Ok(n)
-    }
-
-    pub fn calc_buflen(len: usize, block_size: usize) -> usize {
-        let bs = block_size;
-        len + bs
-    }
-
-    pub fn aes128cbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::aes_128_cbc(), dest, src, key, iv)
-    }
-    pub fn aes128cbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::aes_128_cbc(), dest, src, key, iv)
-    }
-
-    pub fn aes192cbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::aes_192_cbc(), dest, src, key, iv)
-    }
-    pub fn aes192cbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::aes_192_cbc(), dest, src, key, iv)
-    }
-
-    pub fn aes256cbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::aes_256_cbc(), dest, src, key, iv)
-    }
-    pub fn aes256cbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::aes_256_cbc(), dest, src, key, iv)
-    }
-
-    pub fn tdescbc_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::des_ede3_cbc(), dest, src, key, iv)
-    }
-    pub fn tdescbc_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::des_ede3_cbc(), dest, src, key, iv)
-    }
-
-    pub fn aes128ctr_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::aes_128_ctr(), dest, src, key, iv)
-    }
-    pub fn aes128ctr_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::aes_128_ctr(), dest, src, key, iv)
-    }
-
-    pub fn aes192ctr_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::aes_192_ctr(), dest, src, key, iv)
-    }
-    pub fn aes192ctr_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::aes_192_ctr(), dest, src, key, iv)
-    }
-
-    pub fn aes256ctr_encrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_encrypt(Cipher::aes_256_ctr(), dest, src, key, iv)
-    }
-    pub fn aes256ctr_decrypt(
-        dest: &mut [u8],
-        src: &[u8],
-        key: &[u8],
-        iv: &[u8],
-    ) -> OsshResult<usize> {
-        openssl_decrypt(Cipher::aes_256_ctr(), dest, src, key, iv)
-    }
 }
@@ -0,0 +1,154 @@
+use crate::cipher::Cipher;
+use crate::error::*;
+use crate::format::ossh_privkey::DEFAULT_ROUNDS;
+use crate::keys::ecdsa::EcCurve;
+use crate::keys::{KeyPair, KeyType};
+use std::sync::RwLock;
+
+static DEFAULTS: RwLock<Option<Defaults>> = RwLock::new(None);
+
+/// The defaults of the key generation and the private key encryption
+///
+/// The process-wide defaults set by [`set_defaults()`] are used by [`KeyPair::generate()`] when the key size is `0`,
+/// and by the OpenSSH private keys encrypted without explicit KDF rounds.
+/// The defaults can also be passed per call to [`KeyPair::generate_with_defaults()`] and
+/// [`KeyPair::serialize_openssh_with_defaults()`].
+///
+/// ```rust
+/// # #[cfg(not(feature = "fips"))] {
+/// use osshkeys::cipher::Cipher;
+/// use osshkeys::defaults::Defaults;
+///
+/// let defaults = Defaults {
+///     rsa_bits: 4096,
+///     cipher: Cipher::Aes256_Ctr,
+///     kdf_rounds: 64,
+///     ..Defaults::default()
+/// };
+/// let keypair = defaults.generate().unwrap();
+/// let keystr = keypair
+///     .serialize_openssh_with_defaults(Some("passphrase"), &defaults)
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Defaults {
+    /// The key type generated by [`Defaults::generate()`], Ed25519 unless it is compiled out or refused by the feature `fips`
+    pub key_type: KeyType,
+    /// The size in bits of the RSA keys, 2048 bits by default
+    pub rsa_bits: usize,
+    /// The curve of the EcDSA keys, NIST P-256 by default
+    pub ecdsa_curve: EcCurve,
+    /// The cipher of the encrypted OpenSSH private keys, AES-256-CTR by default
+    pub cipher: Cipher,
+    /// The bcrypt KDF rounds of the encrypted OpenSSH private keys, 16 by default
+    pub kdf_rounds: u32,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        let key_types = [
+            #[cfg(all(feature = "ed25519", not(feature = "fips")))]
+            KeyType::ED25519,
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA,
+            #[cfg(feature = "rsa")]
+            KeyType::RSA,
+            #[cfg(feature = "dsa")]
+            KeyType::DSA,
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519,
+        ];
+        Defaults {
+            key_type: key_types[0],
+            rsa_bits: 2048,
+            ecdsa_curve: EcCurve::Nistp256,
+            cipher: Cipher::Aes256_Ctr,
+            kdf_rounds: DEFAULT_ROUNDS,
+        }
+    }
+}
+
+impl Defaults {
+    /// Get the size in bits of the generated keys of the type
+    pub fn key_bits(&self, keytype: KeyType) -> usize {
+        match keytype {
+            #[cfg(feature = "rsa")]
+            KeyType::RSA => self.rsa_bits,
+            #[cfg(feature = "dsa")]
+            KeyType::DSA => 1024,
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA => self.ecdsa_curve.size(),
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519 => 256,
+        }
+    }
+
+    /// Generate a key of the default type and size
+    pub fn generate(&self) -> OsshResult<KeyPair> {
+        KeyPair::generate_with_defaults(self.key_type, 0, self)
+    }
+}
+
+/// Replace the process-wide defaults
+pub fn set_defaults(defaults: Defaults) {
+    *DEFAULTS.write().unwrap() = Some(defaults);
+}
+
+/// Get the process-wide defaults, see [`set_defaults()`]
+pub fn defaults() -> Defaults {
+    DEFAULTS.read().unwrap().clone().unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    #[cfg(not(feature = "fips"))]
+    use crate::format::ossh_privkey::{ossh_priv_ciphername, ossh_priv_kdf_rounds};
+    use crate::keys::Key;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::PublicParts;
+
+    #[test]
+    fn defaults_generate() {
+        let defaults = Defaults {
+            key_type: KeyType::ECDSA,
+            ecdsa_curve: EcCurve::Nistp384,
+            ..Defaults::default()
+        };
+        let keypair = defaults.generate().unwrap();
+        assert_eq!(keypair.keytype(), KeyType::ECDSA);
+        assert_eq!(keypair.size(), 384);
+
+        let keypair = KeyPair::generate_with_defaults(KeyType::ECDSA, 521, &defaults).unwrap();
+        assert_eq!(keypair.size(), 521);
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn defaults_serialize() {
+        let defaults = Defaults {
+            cipher: Cipher::Aes128_Ctr,
+            kdf_rounds: 4,
+            ..Defaults::default()
+        };
+        let keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let keystr = keypair
+            .serialize_openssh_with_defaults(Some("12345678"), &defaults)
+            .unwrap();
+        let pemdata = ::pem::parse(&keystr).unwrap();
+        assert_eq!(
+            ossh_priv_ciphername(pemdata.contents()).unwrap(),
+            "aes128-ctr"
+        );
+        assert_eq!(ossh_priv_kdf_rounds(pemdata.contents()).unwrap(), Some(4));
+        let parsed = KeyPair::from_keystr(&keystr, Some("12345678")).unwrap();
+        assert_eq!(parsed.blob().unwrap(), keypair.blob().unwrap());
+
+        let keystr = keypair
+            .serialize_openssh_with_defaults(None, &defaults)
+            .unwrap();
+        let pemdata = ::pem::parse(&keystr).unwrap();
+        assert_eq!(ossh_priv_ciphername(pemdata.contents()).unwrap(), "none");
+    }
+}
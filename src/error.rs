@@ -1,6 +1,7 @@
-use backtrace::Backtrace;
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::error::Error as StdError;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::ops::Range;
 
 /// The [Result](https://doc.rust-lang.org/std/result/enum.Result.html) alias of this crate
 pub type OsshResult<T> = Result<T, Error>;
@@ -9,7 +10,11 @@ pub type OsshResult<T> = Result<T, Error>;
 pub struct Error {
     kind: ErrorKind,
     inner: Option<Box<dyn StdError + Send + Sync + 'static>>,
-    bt: Backtrace,
+    position: Option<ErrorPosition>,
+    // Boxed like the backtrace, since most errors have no span
+    span: Option<Box<TextSpan>>,
+    // Boxed to keep the error small, and only allocated when it is captured
+    bt: Option<Box<Backtrace>>,
 }
 
 impl Error {
@@ -18,7 +23,9 @@ impl Error {
         Error {
             kind,
             inner: None,
-            bt: Backtrace::new(),
+            position: None,
+            span: None,
+            bt: capture_backtrace(),
         }
     }
 
@@ -27,17 +34,68 @@ impl Error {
         Error {
             kind,
             inner: Some(err.into()),
-            bt: Backtrace::new(),
+            position: None,
+            span: None,
+            bt: capture_backtrace(),
         }
     }
 
+    /// Attach the position where the parsing error occurred
+    ///
+    /// The innermost position is kept if the error already has one.
+    pub(crate) fn at(mut self, section: &'static str, field: &'static str, offset: usize) -> Self {
+        if self.position.is_none() {
+            self.position = Some(ErrorPosition {
+                section,
+                field,
+                offset,
+            });
+        }
+        self
+    }
+
+    /// Attach the span of the key text which caused the error
+    ///
+    /// The innermost span is kept if the error already has one.
+    pub(crate) fn spanned(mut self, offset: usize, len: usize, label: &'static str) -> Self {
+        if self.span.is_none() {
+            self.span = Some(Box::new(TextSpan { offset, len, label }));
+        }
+        self
+    }
+
     /// Get the kind of the error
     pub fn kind(&self) -> ErrorKind {
-        self.kind
+        self.kind.clone()
+    }
+
+    /// Get the position in the key data where the parsing error occurred, if it is known
+    pub fn position(&self) -> Option<&ErrorPosition> {
+        self.position.as_ref()
     }
 
+    /// Get the span of the key text which caused the error, if it is known
+    ///
+    /// The span is only known for the errors in the text, like an invalid base64 line or an unexpected PEM tag.
+    pub fn span(&self) -> Option<&TextSpan> {
+        self.span.as_deref()
+    }
+
+    /// Get the backtrace captured when the error was created
+    ///
+    /// The backtrace is only captured if it is enabled by the `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` environment variables, see [`Backtrace::capture()`].
     pub fn backtrace(&self) -> &Backtrace {
-        &self.bt
+        static DISABLED: Backtrace = Backtrace::disabled();
+        self.bt.as_deref().unwrap_or(&DISABLED)
+    }
+}
+
+fn capture_backtrace() -> Option<Box<Backtrace>> {
+    let bt = Backtrace::capture();
+    match bt.status() {
+        BacktraceStatus::Captured => Some(Box::new(bt)),
+        _ => None,
     }
 }
 
@@ -45,10 +103,18 @@ impl Debug for Error {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         writeln!(f, "OsshError {{")?;
         write!(f, "Kind: {:?} => \"{}\"", self.kind, self.kind)?;
+        if let Some(position) = &self.position {
+            write!(f, "\nPosition: {}", position)?;
+        }
+        if let Some(span) = &self.span {
+            write!(f, "\nSpan: {} (bytes {:?})", span.label, span.range())?;
+        }
         if let Some(cause) = &self.inner {
             write!(f, "\nCaused: {:?}", cause)?;
         }
-        write!(f, "\nBackTrace: \n{:?}", self.bt)?;
+        if let Some(bt) = &self.bt {
+            write!(f, "\nBackTrace: \n{}", bt)?;
+        }
         write!(f, "\n}}")
     }
 }
@@ -56,6 +122,9 @@ impl Debug for Error {
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         write!(f, "{}", self.kind)?;
+        if let Some(position) = &self.position {
+            write!(f, " at {}", position)?;
+        }
         if let Some(cause) = &self.inner {
             write!(f, "; Caused by: {}", cause)?;
         }
@@ -78,6 +147,91 @@ impl StdError for Error {
     }
 }
 
+/// The position in the key data where a parsing error occurred
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorPosition {
+    section: &'static str,
+    field: &'static str,
+    offset: usize,
+}
+
+impl ErrorPosition {
+    /// Get the section of the key data, such as `header`, `publickey` or `privatekey`
+    pub fn section(&self) -> &str {
+        self.section
+    }
+
+    /// Get the name of the field being parsed, such as `ciphername` or `n`
+    pub fn field(&self) -> &str {
+        self.field
+    }
+
+    /// Get the byte offset of the field from the beginning of the section
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Display for ErrorPosition {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "field '{}' (offset {}) in section '{}'",
+            self.field, self.offset, self.section
+        )
+    }
+}
+
+/// The span of the key text where a parsing error occurred
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    offset: usize,
+    len: usize,
+    label: &'static str,
+}
+
+impl TextSpan {
+    /// Get the byte range of the span in the parsed text
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+
+    /// Get the description of the spanned text, such as `invalid base64`
+    pub fn label(&self) -> &str {
+        self.label
+    }
+}
+
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let kind = format!("{:?}", self.kind);
+        let name = kind.split('(').next().unwrap_or_default();
+        Some(Box::new(format!("osshkeys::{}", name)))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let help = match self.kind {
+            ErrorKind::IncorrectPass | ErrorKind::MacMismatch => "check the passphrase of the key",
+            ErrorKind::Base64Error => "the key data must be base64 encoded",
+            ErrorKind::InvalidPemFormat => {
+                "the key must be enclosed in the -----BEGIN and -----END lines"
+            }
+            _ => return None,
+        };
+        Some(Box::new(help))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let span = self.span.as_ref()?;
+        Some(Box::new(std::iter::once(miette::LabeledSpan::new(
+            Some(span.label.to_owned()),
+            span.offset,
+            span.len,
+        ))))
+    }
+}
+
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Self {
         Self::from_kind(kind)
@@ -99,6 +253,17 @@ impl From<openssl::error::ErrorStack> for Error {
         Self::with_error(ErrorKind::OpenSslError, err)
     }
 }
+#[cfg(feature = "keychain")]
+impl From<keyring::Error> for Error {
+    fn from(err: keyring::Error) -> Self {
+        use keyring::Error::*;
+        let kind = match err {
+            BadEncoding(_) | TooLong(..) | Invalid(..) => ErrorKind::InvalidArgument,
+            _ => ErrorKind::IOError,
+        };
+        Self::with_error(kind, err)
+    }
+}
 impl From<ed25519_dalek::SignatureError> for Error {
     fn from(err: ed25519_dalek::SignatureError) -> Self {
         Self::with_error(ErrorKind::Ed25519Error, err)
@@ -109,6 +274,12 @@ impl From<base64::DecodeError> for Error {
         Self::with_error(ErrorKind::Base64Error, err)
     }
 }
+#[cfg(feature = "simd-base64")]
+impl From<base64_simd::Error> for Error {
+    fn from(err: base64_simd::Error) -> Self {
+        Self::with_error(ErrorKind::Base64Error, err)
+    }
+}
 impl From<bcrypt_pbkdf::Error> for Error {
     fn from(err: bcrypt_pbkdf::Error) -> Self {
         use bcrypt_pbkdf::Error::*;
@@ -121,25 +292,25 @@ impl From<bcrypt_pbkdf::Error> for Error {
     }
 }
 
-#[cfg(feature = "rustcrypto-cipher")]
+#[cfg(any(feature = "rustcrypto-cipher", feature = "legacy-des"))]
 impl From<cipher::InvalidLength> for Error {
     fn from(err: cipher::InvalidLength) -> Self {
         Self::with_error(ErrorKind::InvalidKeyIvLength, err)
     }
 }
-#[cfg(feature = "rustcrypto-cipher")]
+#[cfg(any(feature = "rustcrypto-cipher", feature = "legacy-des"))]
 impl From<cipher::inout::PadError> for Error {
     fn from(err: cipher::inout::PadError) -> Self {
         Self::with_error(ErrorKind::InvalidLength, err)
     }
 }
-#[cfg(feature = "rustcrypto-cipher")]
+#[cfg(any(feature = "rustcrypto-cipher", feature = "legacy-des"))]
 impl From<cipher::StreamCipherError> for Error {
     fn from(err: cipher::StreamCipherError) -> Self {
         Self::with_error(ErrorKind::InvalidLength, err)
     }
 }
-#[cfg(feature = "rustcrypto-cipher")]
+#[cfg(any(feature = "rustcrypto-cipher", feature = "legacy-des"))]
 impl From<cipher::block_padding::UnpadError> for Error {
     fn from(err: cipher::block_padding::UnpadError) -> Self {
         Self::with_error(ErrorKind::IncorrectPass, err)
@@ -158,7 +329,8 @@ impl From<std::array::TryFromSliceError> for Error {
 }
 
 /// Indicate the reason of the error
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum ErrorKind {
     /// The error is caused by OpenSSL, to get the underlying error, use [std::error::Error::source()](https://doc.rust-lang.org/std/error/trait.Error.html#method.source)
     OpenSslError,
@@ -184,25 +356,40 @@ pub enum ErrorKind {
     InvalidLength,
     /// The elliptic curve is not supported
     UnsupportCurve,
-    /// The encrypt cipher is not supported
-    UnsupportCipher,
+    /// The encrypt cipher or KDF is not supported, containing its name
+    UnsupportCipher(String),
     /// The passphrase is incorrect, can't decrypt the key
     IncorrectPass,
+    /// The authentication tag of an AEAD cipher doesn't match, the passphrase is likely incorrect
+    ///
+    /// Unlike [`ErrorKind::IncorrectPass`] from the mismatched check integers of the CBC and CTR modes,
+    /// the tag covers the whole encrypted data, so the data may also be corrupted.
+    MacMismatch,
     /// The key type is not the desired one
     TypeNotMatch,
-    /// The key type is not supported
-    UnsupportType,
+    /// The key type is not supported, containing the algorithm name or the PEM tag
+    UnsupportType(String),
     /// The key file's PEM part is invalid
     InvalidPemFormat,
     /// The key or IV length can't meet the cipher's requirement
     InvalidKeyIvLength,
+    /// The operation is cancelled by the caller
+    Cancelled,
+    /// The legacy algorithm is refused, containing the algorithm name such as `ssh-rsa` or `ssh-dss`
+    LegacyAlgorithm(String),
+    /// The key, the encryption or the signature is not allowed by the policy, containing the reason
+    PolicyViolation(String),
+    /// The algorithm or the operation is not FIPS approved, containing its name (Feature `fips`)
+    FipsNotApproved(String),
+    /// The PKCS#11 module returned an error, containing the `CK_RV` code (Feature `pkcs11`)
+    Pkcs11Error(u64),
     /// Something shouldn't happen but it DID happen...
     Unknown,
 }
 
 impl ErrorKind {
     /// Get the description of the kind
-    pub fn description(self) -> &'static str {
+    pub fn description(&self) -> &'static str {
         use ErrorKind::*;
 
         match self {
@@ -218,12 +405,18 @@ impl ErrorKind {
             InvalidKeySize => "Invalid Key Size",
             InvalidLength => "Invalid Length",
             UnsupportCurve => "Unsupported Elliptic Curve",
-            UnsupportCipher => "Unsupported Cipher",
+            UnsupportCipher(_) => "Unsupported Cipher",
             IncorrectPass => "Incorrect Passphrase",
+            MacMismatch => "MAC Mismatch",
             TypeNotMatch => "Key Type Not Match",
-            UnsupportType => "Unsupported Key Type",
+            UnsupportType(_) => "Unsupported Key Type",
             InvalidPemFormat => "Invalid PEM Format",
             InvalidKeyIvLength => "Invalid Key/IV Length",
+            Cancelled => "Operation Cancelled",
+            LegacyAlgorithm(_) => "Legacy Algorithm Refused",
+            PolicyViolation(_) => "Policy Violation",
+            FipsNotApproved(_) => "Not FIPS Approved",
+            Pkcs11Error(_) => "PKCS#11 Error",
             Unknown => "Unknown Error",
         }
     }
@@ -231,6 +424,17 @@ impl ErrorKind {
 
 impl Display for ErrorKind {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}", self.description())
+        write!(f, "{}", self.description())?;
+        match self {
+            ErrorKind::UnsupportCipher(name)
+            | ErrorKind::UnsupportType(name)
+            | ErrorKind::LegacyAlgorithm(name)
+            | ErrorKind::PolicyViolation(name)
+            | ErrorKind::FipsNotApproved(name) => {
+                write!(f, " \"{}\"", name)
+            }
+            ErrorKind::Pkcs11Error(rv) => write!(f, " 0x{:08X}", rv),
+            _ => Ok(()),
+        }
     }
 }
@@ -0,0 +1,541 @@
+use crate::defaults::defaults;
+use crate::error::*;
+use crate::keys::{
+    Fingerprint, FingerprintHash, KeyPair, KeyType, PrivateParts, PublicKey, PublicParts,
+};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use zeroize::Zeroize;
+
+/// The result of the C API functions
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsshStatus {
+    Ok = 0,
+    /// A null pointer or an invalid string is passed
+    InvalidArgument,
+    /// The key is malformed
+    InvalidKey,
+    /// The passphrase is incorrect or missing
+    IncorrectPass,
+    /// The key type or the cipher is not supported
+    Unsupported,
+    /// An error from OpenSSL
+    Crypto,
+    /// An I/O or formatting error
+    Io,
+    /// The function panicked
+    Panic,
+    Unknown,
+}
+
+impl From<ErrorKind> for OsshStatus {
+    fn from(kind: ErrorKind) -> Self {
+        use ErrorKind::*;
+        match kind {
+            InvalidArgument => OsshStatus::InvalidArgument,
+            InvalidKeyFormat | InvalidFormat | InvalidKey | InvalidKeySize | InvalidLength
+            | InvalidPemFormat | InvalidKeyIvLength | TypeNotMatch => OsshStatus::InvalidKey,
+            IncorrectPass | MacMismatch => OsshStatus::IncorrectPass,
+            UnsupportCurve | UnsupportCipher(_) | UnsupportType(_) | LegacyAlgorithm(_)
+            | PolicyViolation(_) | FipsNotApproved(_) => OsshStatus::Unsupported,
+            OpenSslError | Pkcs11Error(_) => OsshStatus::Crypto,
+            IOError | FmtError => OsshStatus::Io,
+            _ => OsshStatus::Unknown,
+        }
+    }
+}
+
+/// The key type of [`ossh_keypair_generate()`]
+#[repr(C)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsshKeyType {
+    RSA,
+    DSA,
+    ECDSA,
+    ED25519,
+}
+
+/// Fail with [`ErrorKind::UnsupportType`] if the key type is compiled out by its feature
+impl TryFrom<OsshKeyType> for KeyType {
+    type Error = Error;
+    fn try_from(keytype: OsshKeyType) -> Result<Self, Self::Error> {
+        match keytype {
+            #[cfg(feature = "rsa")]
+            OsshKeyType::RSA => Ok(KeyType::RSA),
+            #[cfg(feature = "dsa")]
+            OsshKeyType::DSA => Ok(KeyType::DSA),
+            #[cfg(feature = "ecdsa")]
+            OsshKeyType::ECDSA => Ok(KeyType::ECDSA),
+            #[cfg(feature = "ed25519")]
+            OsshKeyType::ED25519 => Ok(KeyType::ED25519),
+            #[allow(unreachable_patterns)]
+            _ => Err(ErrorKind::UnsupportType(format!("{:?}", keytype)).into()),
+        }
+    }
+}
+
+/// The private key format of [`ossh_keypair_serialize()`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsshKeyFormat {
+    /// The OpenSSH private key format, encrypted with AES-256-CTR if a passphrase is given
+    Openssh,
+    /// The traditional OpenSSL PEM format
+    Pem,
+    /// The PKCS#8 format
+    Pkcs8,
+}
+
+/// The fingerprint hash algorithm
+#[repr(C)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsshHash {
+    MD5,
+    SHA256,
+    SHA384,
+    SHA512,
+}
+
+impl From<OsshHash> for FingerprintHash {
+    fn from(hash: OsshHash) -> Self {
+        match hash {
+            OsshHash::MD5 => FingerprintHash::MD5,
+            OsshHash::SHA256 => FingerprintHash::SHA256,
+            OsshHash::SHA384 => FingerprintHash::SHA384,
+            OsshHash::SHA512 => FingerprintHash::SHA512,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Run the function body, record the error and catch the panic
+fn ffi_call<F: FnOnce() -> OsshResult<()>>(f: F) -> OsshStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => OsshStatus::Ok,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            e.kind().into()
+        }
+        Err(_) => {
+            set_last_error("osshkeys panicked".to_string());
+            OsshStatus::Panic
+        }
+    }
+}
+
+fn invalid_arg() -> Error {
+    Error::from_kind(ErrorKind::InvalidArgument)
+}
+
+unsafe fn ref_arg<'a, T>(p: *const T) -> OsshResult<&'a T> {
+    p.as_ref().ok_or_else(invalid_arg)
+}
+
+unsafe fn out_arg<'a, T>(p: *mut T) -> OsshResult<&'a mut T> {
+    p.as_mut().ok_or_else(invalid_arg)
+}
+
+unsafe fn str_arg<'a>(p: *const c_char) -> OsshResult<Option<&'a str>> {
+    if p.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(p)
+        .to_str()
+        .map(Some)
+        .map_err(|e| Error::with_error(ErrorKind::InvalidArgument, e))
+}
+
+unsafe fn bytes_arg<'a>(p: *const u8, len: usize) -> OsshResult<&'a [u8]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if p.is_null() {
+        return Err(invalid_arg());
+    }
+    Ok(slice::from_raw_parts(p, len))
+}
+
+fn into_c_string(s: String) -> OsshResult<*mut c_char> {
+    CString::new(s)
+        .map(CString::into_raw)
+        .map_err(|e| Error::with_error(ErrorKind::InvalidArgument, e))
+}
+
+/// Get the message of the last error on the current thread
+///
+/// Return null if there is no error. The string is valid until the next failed call on the same thread.
+#[no_mangle]
+pub extern "C" fn ossh_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Generate a new key pair
+///
+/// Set `bits` to 0 to use the default key size.
+///
+/// # Safety
+/// `out` must be a valid pointer to store the key pair.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_generate(
+    keytype: OsshKeyType,
+    bits: usize,
+    out: *mut *mut KeyPair,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        *out = Box::into_raw(Box::new(KeyPair::generate(keytype.try_into()?, bits)?));
+        Ok(())
+    })
+}
+
+/// Parse a private key in the OpenSSH, PEM or PKCS#8 format
+///
+/// # Safety
+/// `keystr` must be a valid C string, `passphrase` must be null or a valid C string,
+/// and `out` must be a valid pointer to store the key pair.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_from_keystr(
+    keystr: *const c_char,
+    passphrase: *const c_char,
+    out: *mut *mut KeyPair,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        let keystr = str_arg(keystr)?.ok_or_else(invalid_arg)?;
+        let keypair = KeyPair::from_keystr(keystr, str_arg(passphrase)?)?;
+        *out = Box::into_raw(Box::new(keypair));
+        Ok(())
+    })
+}
+
+/// Get the public key of the key pair
+///
+/// # Safety
+/// `keypair` must be a valid key pair, and `out` must be a valid pointer to store the public key.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_public_key(
+    keypair: *const KeyPair,
+    out: *mut *mut PublicKey,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        *out = Box::into_raw(Box::new(ref_arg(keypair)?.clone_public_key()?));
+        Ok(())
+    })
+}
+
+/// Get the fingerprint of the key pair, such as `SHA256:...`
+///
+/// # Safety
+/// `keypair` must be a valid key pair, and `out` must be a valid pointer to store the string.
+/// The string must be freed by [`ossh_string_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_fingerprint(
+    keypair: *const KeyPair,
+    hash: OsshHash,
+    out: *mut *mut c_char,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        *out = into_c_string(Fingerprint::of(ref_arg(keypair)?, hash.into())?.to_string())?;
+        Ok(())
+    })
+}
+
+/// Sign the data with the key pair
+///
+/// # Safety
+/// `keypair` must be a valid key pair, `data` must point to `len` readable bytes,
+/// and `sig`, `sig_len` must be valid pointers to store the signature.
+/// The signature must be freed by [`ossh_bytes_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_sign(
+    keypair: *const KeyPair,
+    data: *const u8,
+    len: usize,
+    sig: *mut *mut u8,
+    sig_len: *mut usize,
+) -> OsshStatus {
+    ffi_call(|| {
+        let sig = out_arg(sig)?;
+        let sig_len = out_arg(sig_len)?;
+        let signature = ref_arg(keypair)?.sign(bytes_arg(data, len)?)?;
+        *sig_len = signature.len();
+        *sig = Box::into_raw(signature.into_boxed_slice()) as *mut u8;
+        Ok(())
+    })
+}
+
+/// Serialize the private key
+///
+/// Pass a null or an empty `passphrase` to serialize without encryption.
+///
+/// # Safety
+/// `keypair` must be a valid key pair, `passphrase` must be null or a valid C string,
+/// and `out` must be a valid pointer to store the string.
+/// The string must be freed by [`ossh_string_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_serialize(
+    keypair: *const KeyPair,
+    format: OsshKeyFormat,
+    passphrase: *const c_char,
+    out: *mut *mut c_char,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        let keypair = ref_arg(keypair)?;
+        let passphrase = str_arg(passphrase)?.filter(|p| !p.is_empty());
+        let keystr = match format {
+            OsshKeyFormat::Openssh => {
+                keypair.serialize_openssh_with_defaults(passphrase, &defaults())?
+            }
+            OsshKeyFormat::Pem => keypair.serialize_pem(passphrase)?,
+            OsshKeyFormat::Pkcs8 => keypair.serialize_pkcs8(passphrase)?,
+        };
+        *out = into_c_string(keystr)?;
+        Ok(())
+    })
+}
+
+/// Free the key pair, the private key is wiped
+///
+/// # Safety
+/// `keypair` must be null or a key pair returned by this library, which has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_keypair_free(keypair: *mut KeyPair) {
+    if !keypair.is_null() {
+        drop(Box::from_raw(keypair));
+    }
+}
+
+/// Parse a public key in the OpenSSH or PEM format
+///
+/// # Safety
+/// `keystr` must be a valid C string, and `out` must be a valid pointer to store the public key.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_publickey_from_keystr(
+    keystr: *const c_char,
+    out: *mut *mut PublicKey,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        let keystr = str_arg(keystr)?.ok_or_else(invalid_arg)?;
+        *out = Box::into_raw(Box::new(PublicKey::from_keystr(keystr)?));
+        Ok(())
+    })
+}
+
+/// Serialize the public key in the OpenSSH format
+///
+/// # Safety
+/// `pubkey` must be a valid public key, and `out` must be a valid pointer to store the string.
+/// The string must be freed by [`ossh_string_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn ossh_publickey_serialize(
+    pubkey: *const PublicKey,
+    out: *mut *mut c_char,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        *out = into_c_string(ref_arg(pubkey)?.serialize()?)?;
+        Ok(())
+    })
+}
+
+/// Get the fingerprint of the public key, such as `SHA256:...`
+///
+/// # Safety
+/// `pubkey` must be a valid public key, and `out` must be a valid pointer to store the string.
+/// The string must be freed by [`ossh_string_free()`].
+#[no_mangle]
+pub unsafe extern "C" fn ossh_publickey_fingerprint(
+    pubkey: *const PublicKey,
+    hash: OsshHash,
+    out: *mut *mut c_char,
+) -> OsshStatus {
+    ffi_call(|| {
+        let out = out_arg(out)?;
+        *out = into_c_string(Fingerprint::of(ref_arg(pubkey)?, hash.into())?.to_string())?;
+        Ok(())
+    })
+}
+
+/// Verify the signature of the data
+///
+/// # Safety
+/// `pubkey` must be a valid public key, `data` must point to `len` readable bytes,
+/// `sig` must point to `sig_len` readable bytes, and `valid` must be a valid pointer to store the result.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_publickey_verify(
+    pubkey: *const PublicKey,
+    data: *const u8,
+    len: usize,
+    sig: *const u8,
+    sig_len: usize,
+    valid: *mut bool,
+) -> OsshStatus {
+    ffi_call(|| {
+        let valid = out_arg(valid)?;
+        *valid = ref_arg(pubkey)?.verify(bytes_arg(data, len)?, bytes_arg(sig, sig_len)?)?;
+        Ok(())
+    })
+}
+
+/// Free the public key
+///
+/// # Safety
+/// `pubkey` must be null or a public key returned by this library, which has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_publickey_free(pubkey: *mut PublicKey) {
+    if !pubkey.is_null() {
+        drop(Box::from_raw(pubkey));
+    }
+}
+
+/// Free the string returned by this library, the content is wiped
+///
+/// # Safety
+/// `s` must be null or a string returned by this library, which has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        CString::from_raw(s).into_bytes().zeroize();
+    }
+}
+
+/// Free the bytes returned by this library
+///
+/// # Safety
+/// `p` must be null or the bytes returned by this library with the same `len`, which have not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ossh_bytes_free(p: *mut u8, len: usize) {
+    if !p.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(p, len)));
+    }
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+
+    const ED25519_KEY: &str = include_str!("../assets/openssh_ed25519_enc");
+    const DATA: &[u8] = b"8Kn9PPQV";
+
+    #[test]
+    fn ffi_keypair_roundtrip() {
+        unsafe {
+            let keystr = CString::new(ED25519_KEY).unwrap();
+            let pass = CString::new("12345678").unwrap();
+            let mut keypair = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_from_keystr(keystr.as_ptr(), ptr::null(), &mut keypair),
+                OsshStatus::IncorrectPass
+            );
+            assert!(!ossh_last_error().is_null());
+            assert_eq!(
+                ossh_keypair_from_keystr(keystr.as_ptr(), pass.as_ptr(), &mut keypair),
+                OsshStatus::Ok
+            );
+
+            let mut fp = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_fingerprint(keypair, OsshHash::SHA256, &mut fp),
+                OsshStatus::Ok
+            );
+            let fingerprint = CStr::from_ptr(fp).to_str().unwrap().to_string();
+            assert!(fingerprint.starts_with("SHA256:"));
+            ossh_string_free(fp);
+
+            let mut pubkey = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_public_key(keypair, &mut pubkey),
+                OsshStatus::Ok
+            );
+            let mut pubstr = ptr::null_mut();
+            assert_eq!(
+                ossh_publickey_serialize(pubkey, &mut pubstr),
+                OsshStatus::Ok
+            );
+            let mut pubkey2 = ptr::null_mut();
+            assert_eq!(
+                ossh_publickey_from_keystr(pubstr, &mut pubkey2),
+                OsshStatus::Ok
+            );
+            assert!(*pubkey == *pubkey2);
+            ossh_string_free(pubstr);
+
+            let (mut sig, mut sig_len) = (ptr::null_mut(), 0);
+            assert_eq!(
+                ossh_keypair_sign(keypair, DATA.as_ptr(), DATA.len(), &mut sig, &mut sig_len),
+                OsshStatus::Ok
+            );
+            let mut valid = false;
+            assert_eq!(
+                ossh_publickey_verify(pubkey2, DATA.as_ptr(), DATA.len(), sig, sig_len, &mut valid),
+                OsshStatus::Ok
+            );
+            assert!(valid);
+            ossh_bytes_free(sig, sig_len);
+
+            let mut serialized = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_serialize(
+                    keypair,
+                    OsshKeyFormat::Openssh,
+                    ptr::null(),
+                    &mut serialized
+                ),
+                OsshStatus::Ok
+            );
+            let mut keypair2 = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_from_keystr(serialized, ptr::null(), &mut keypair2),
+                OsshStatus::Ok
+            );
+            assert!((*keypair2).matches_public(&*pubkey));
+            ossh_string_free(serialized);
+
+            ossh_keypair_free(keypair);
+            ossh_keypair_free(keypair2);
+            ossh_publickey_free(pubkey);
+            ossh_publickey_free(pubkey2);
+        }
+    }
+
+    #[test]
+    fn ffi_generate_invalid_argument() {
+        unsafe {
+            assert_eq!(
+                ossh_keypair_generate(OsshKeyType::ECDSA, 0, ptr::null_mut()),
+                OsshStatus::InvalidArgument
+            );
+            let mut keypair = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_generate(OsshKeyType::ECDSA, 0, &mut keypair),
+                OsshStatus::Ok
+            );
+            let mut out = ptr::null_mut();
+            assert_eq!(
+                ossh_keypair_serialize(keypair, OsshKeyFormat::Pkcs8, ptr::null(), &mut out),
+                OsshStatus::Ok
+            );
+            ossh_string_free(out);
+            ossh_keypair_free(keypair);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::error::*;
+use openssl::provider::Provider;
+
+/// The minimum size in bits of the RSA keys generated in the FIPS mode
+pub const FIPS_MIN_RSA_SIZE: usize = 2048;
+
+/// Load the OpenSSL FIPS provider and disable the fallback to the default provider
+///
+/// The provider is unloaded when the returned value is dropped, so keep it alive while using this crate.
+/// This requires OpenSSL 3 with the FIPS module installed and configured.
+pub fn load_openssl_fips_provider() -> OsshResult<Provider> {
+    Ok(Provider::try_load(None, "fips", false)?)
+}
+
+/// Fail with [`ErrorKind::FipsNotApproved`] for the algorithm or the operation which is not FIPS approved
+pub(crate) fn refuse(name: &str) -> OsshResult<()> {
+    Err(ErrorKind::FipsNotApproved(name.to_owned()).into())
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    feature = "3des-cbc"
+))]
+mod test {
+    use crate::cipher::Cipher;
+    use crate::error::ErrorKind;
+    use crate::keys::rsa::RsaSignature;
+    use crate::keys::*;
+    use rand::rngs::OsRng;
+
+    fn assert_refused<T>(result: crate::error::OsshResult<T>) {
+        assert!(matches!(
+            result.map(|_| ()).unwrap_err().kind(),
+            ErrorKind::FipsNotApproved(_)
+        ));
+    }
+
+    #[test]
+    fn fips_refused_operations() {
+        assert_refused(KeyPair::generate(KeyType::ED25519, 0));
+        assert_refused(KeyPair::generate(KeyType::DSA, 0));
+        assert_refused(KeyPair::generate(KeyType::RSA, 1024));
+        assert_refused(KeyPair::generate_with_rng(KeyType::ECDSA, 256, &mut OsRng));
+        assert_refused(Cipher::TDes_Cbc.encrypt(&[0; 8], &[0; 24], &[0; 8]));
+
+        let keypair = KeyPair::generate(KeyType::RSA, 2048).unwrap();
+        assert_refused(keypair.sign_ssh_signature_with(b"data", Some(RsaSignature::SHA1)));
+        let sig = keypair
+            .sign_ssh_signature_with(b"data", Some(RsaSignature::SHA2_256))
+            .unwrap();
+        assert!(keypair
+            .clone_public_key()
+            .unwrap()
+            .verify_ssh_signature(b"data", &sig)
+            .unwrap());
+        assert_refused(keypair.serialize_openssh(Some("12345678"), Cipher::Aes256_Ctr));
+        assert!(keypair.serialize_pkcs8(Some("12345678")).is_ok());
+
+        let ed25519 = std::fs::read_to_string("assets/openssh_ed25519").unwrap();
+        let ed25519 = KeyPair::from_keystr(&ed25519, None).unwrap();
+        assert_refused(ed25519.sign(b"data"));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::error::*;
+use ::base64::engine::general_purpose::STANDARD;
+#[cfg(not(feature = "simd-base64"))]
+use ::base64::engine::general_purpose::STANDARD_NO_PAD;
+use ::base64::read::DecoderReader;
+#[cfg(not(feature = "simd-base64"))]
+use ::base64::Engine;
+#[cfg(feature = "simd-base64")]
+use base64_simd::AsOut;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read};
+use zeroize::Zeroizing;
+
+// A multiple of 3 bytes, so only the last chunk can be padded
+const ENCODE_CHUNK_LEN: usize = 768;
+
+/// Encode the data in the standard base64 alphabet with the padding
+pub fn encode<T: AsRef<[u8]>>(data: T) -> String {
+    #[cfg(feature = "simd-base64")]
+    return base64_simd::STANDARD.encode_to_string(data);
+    #[cfg(not(feature = "simd-base64"))]
+    STANDARD.encode(data)
+}
+
+/// Encode the data in the standard base64 alphabet without the padding, like the SHA256 fingerprints
+pub fn encode_unpadded<T: AsRef<[u8]>>(data: T) -> String {
+    #[cfg(feature = "simd-base64")]
+    return base64_simd::STANDARD_NO_PAD.encode_to_string(data);
+    #[cfg(not(feature = "simd-base64"))]
+    STANDARD_NO_PAD.encode(data)
+}
+
+/// Decode the padded base64 data
+///
+/// The ASCII whitespaces, such as the line breaks and the indents of the pasted keys, are ignored.
+pub fn decode<T: AsRef<[u8]>>(data: T) -> OsshResult<Vec<u8>> {
+    let data = strip_whitespace(data.as_ref());
+    #[cfg(feature = "simd-base64")]
+    return Ok(base64_simd::STANDARD.decode_to_vec(data)?);
+    #[cfg(not(feature = "simd-base64"))]
+    Ok(STANDARD.decode(data)?)
+}
+
+/// Decode the unpadded base64 data, ignoring the ASCII whitespaces
+pub fn decode_unpadded<T: AsRef<[u8]>>(data: T) -> OsshResult<Vec<u8>> {
+    let data = strip_whitespace(data.as_ref());
+    #[cfg(feature = "simd-base64")]
+    return Ok(base64_simd::STANDARD_NO_PAD.decode_to_vec(data)?);
+    #[cfg(not(feature = "simd-base64"))]
+    Ok(STANDARD_NO_PAD.decode(data)?)
+}
+
+/// Write the padded base64 encoding of the data into the sink, wrapping the lines at `line_width` if it isn't 0
+///
+/// The lines are separated by `\n` without the trailing one. The data is encoded in small chunks,
+/// so the whole encoded string is never allocated, and the chunk buffer is wiped since the data may be a private key.
+pub fn write_encoded<W: fmt::Write + ?Sized>(
+    out: &mut W,
+    data: &[u8],
+    line_width: usize,
+) -> fmt::Result {
+    let mut buf = Zeroizing::new([0u8; ENCODE_CHUNK_LEN / 3 * 4]);
+    let mut column = 0;
+    for chunk in data.chunks(ENCODE_CHUNK_LEN) {
+        #[cfg(feature = "simd-base64")]
+        let encoded = base64_simd::STANDARD.encode_as_str(chunk, buf.as_mut_slice().as_out());
+        #[cfg(not(feature = "simd-base64"))]
+        let encoded = {
+            let len = STANDARD
+                .encode_slice(chunk, &mut *buf)
+                .map_err(|_| fmt::Error)?;
+            // The base64 string only contains the ASCII characters
+            std::str::from_utf8(&buf[..len]).unwrap()
+        };
+        let mut encoded: &str = encoded;
+        while !encoded.is_empty() {
+            if line_width == 0 {
+                out.write_str(encoded)?;
+                break;
+            }
+            if column == line_width {
+                out.write_char('\n')?;
+                column = 0;
+            }
+            let (line, rest) = encoded.split_at(encoded.len().min(line_width - column));
+            out.write_str(line)?;
+            column += line.len();
+            encoded = rest;
+        }
+    }
+    Ok(())
+}
+
+fn strip_whitespace(data: &[u8]) -> Cow<'_, [u8]> {
+    if data.iter().any(u8::is_ascii_whitespace) {
+        Cow::Owned(
+            data.iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect(),
+        )
+    } else {
+        Cow::Borrowed(data)
+    }
+}
+
+/// Decode the padded base64 data from a reader without loading the whole input into the memory
+///
+/// The ASCII whitespaces are ignored like [`decode()`], and the invalid data is reported as an I/O error.
+/// The streaming decoder doesn't use the SIMD instructions of the `simd-base64` feature.
+pub struct Base64Decoder<R: Read> {
+    inner: DecoderReader<'static, ::base64::engine::GeneralPurpose, WhitespaceFilter<R>>,
+}
+
+impl<R: Read> Base64Decoder<R> {
+    /// Decode the base64 data read from the reader
+    pub fn new(reader: R) -> Self {
+        Base64Decoder {
+            inner: DecoderReader::new(WhitespaceFilter(reader), &STANDARD),
+        }
+    }
+
+    /// Get the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().0
+    }
+}
+
+impl<R: Read> Read for Base64Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+struct WhitespaceFilter<R>(R);
+
+impl<R: Read> Read for WhitespaceFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let len = self.0.read(buf)?;
+            if len == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..len {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+            // Keep reading if the whole chunk is whitespaces, since 0 means the end of the input
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base64_write_encoded() {
+        for len in [0, 1, 2, 3, 52, 53, 767, 768, 769, 2000] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let encoded = encode(&data);
+            let mut written = String::new();
+            write_encoded(&mut written, &data, 0).unwrap();
+            assert_eq!(written, encoded);
+            for width in [64, 70] {
+                let mut written = String::new();
+                write_encoded(&mut written, &data, width).unwrap();
+                assert_eq!(written.replace('\n', ""), encoded);
+                assert!(written.lines().all(|l| l.len() <= width));
+                assert_eq!(written.lines().count(), (encoded.len() + width - 1) / width);
+            }
+        }
+    }
+
+    #[test]
+    fn base64_whitespace() {
+        let data: Vec<u8> = (0..=255).collect();
+        let encoded = encode(&data);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(70)
+            .map(|line| format!("  {}\r\n", std::str::from_utf8(line).unwrap()))
+            .collect::<String>();
+        assert_eq!(decode(&wrapped).unwrap(), data);
+        assert_eq!(decode_unpadded(encode_unpadded(&data)).unwrap(), data);
+        assert!(decode(encode_unpadded(&data)).is_err());
+        assert_eq!(decode("AAA*").unwrap_err().kind(), ErrorKind::Base64Error);
+
+        let mut decoded = Vec::new();
+        Base64Decoder::new(wrapped.as_bytes())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        assert!(Base64Decoder::new(&b"AAA*"[..])
+            .read_to_end(&mut Vec::new())
+            .is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::cipher::Cipher;
+use crate::defaults::defaults;
+use crate::error::*;
+use crate::format::registry::find_key_encoder;
+use crate::format::OutputOptions;
+use crate::keys::{KeyPair, PublicKey};
+use std::fmt;
+use std::str::FromStr;
+
+/// The output format of [`convert_key()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// The OpenSSH private key format
+    Openssh,
+    /// The traditional OpenSSL PEM private key format
+    Pem,
+    /// The PKCS#8 private key format
+    Pkcs8,
+    /// The PuTTY private key format version 2
+    Ppk,
+    /// The OpenSSH public key format
+    OpensshPublic,
+    /// The OpenSSL PEM public key format
+    PemPublic,
+    /// The SSH2 public key file format (RFC 4716)
+    Rfc4716,
+}
+
+impl OutputFormat {
+    /// Parse from the format name, like `openssh` or `pem-public`
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "openssh" => Some(OutputFormat::Openssh),
+            "pem" => Some(OutputFormat::Pem),
+            "pkcs8" => Some(OutputFormat::Pkcs8),
+            "ppk" => Some(OutputFormat::Ppk),
+            "openssh-public" => Some(OutputFormat::OpensshPublic),
+            "pem-public" => Some(OutputFormat::PemPublic),
+            "rfc4716" => Some(OutputFormat::Rfc4716),
+            _ => None,
+        }
+    }
+
+    /// The format name accepted by [`convert_key_by_name()`]
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Openssh => "openssh",
+            OutputFormat::Pem => "pem",
+            OutputFormat::Pkcs8 => "pkcs8",
+            OutputFormat::Ppk => "ppk",
+            OutputFormat::OpensshPublic => "openssh-public",
+            OutputFormat::PemPublic => "pem-public",
+            OutputFormat::Rfc4716 => "rfc4716",
+        }
+    }
+
+    /// Indicate if the format contains the private key
+    pub fn is_private(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Openssh | OutputFormat::Pem | OutputFormat::Pkcs8 | OutputFormat::Ppk
+        )
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s).ok_or_else(|| ErrorKind::UnsupportType(s.to_owned()).into())
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The options of [`convert_key()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertOptions {
+    /// The cipher used to encrypt the OpenSSH private key, default to the cipher of [`defaults()`]
+    pub cipher: Cipher,
+    /// Replace the comment of the key
+    pub comment: Option<String>,
+    /// The line width, the line ending and the trailing newline of the output
+    pub output: OutputOptions,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            cipher: defaults().cipher,
+            comment: None,
+            output: OutputOptions::default(),
+        }
+    }
+}
+
+/// Convert a key file to another format
+///
+/// The input can be any private key or public key format supported by
+/// [`KeyPair::from_keystr()`] and [`PublicKey::from_keystr()`], and the format is detected automatically.
+/// The `passphrase` decrypts the input, and the `new_passphrase` encrypts the output if it is not empty.
+///
+/// A public key can only be converted to the public key formats,
+/// and the PuTTY private key format can only be written but not read.
+pub fn convert_key(
+    input: &[u8],
+    passphrase: Option<&str>,
+    format: OutputFormat,
+    new_passphrase: Option<&str>,
+    options: &ConvertOptions,
+) -> OsshResult<Vec<u8>> {
+    let new_passphrase = new_passphrase.filter(|p| !p.is_empty());
+    let output = match read_input(input, passphrase, format.is_private(), options)? {
+        InputKey::Public(pubkey) => serialize_public(&pubkey, format)?,
+        InputKey::Private(keypair) => match format {
+            OutputFormat::Openssh => match new_passphrase {
+                Some(_) => keypair.serialize_openssh(new_passphrase, options.cipher)?,
+                None => keypair.serialize_openssh(None, Cipher::Null)?,
+            },
+            OutputFormat::Pem => keypair.serialize_pem(new_passphrase)?,
+            OutputFormat::Pkcs8 => keypair.serialize_pkcs8(new_passphrase)?,
+            OutputFormat::Ppk => keypair.serialize_ppk(new_passphrase)?,
+            _ => serialize_public(&keypair.clone_public_key()?, format)?,
+        },
+    };
+    Ok(options.output.apply(&output)?.into_bytes())
+}
+
+/// Convert a key file like [`convert_key()`], to the format of this crate or registered by [`register_key_encoder()`] with the name
+///
+/// The names of the formats of this crate are listed by [`OutputFormat::name()`], and they can't be replaced by the encoders.
+/// The output of the encoders is returned as is, without applying the [`OutputOptions`].
+/// Fail with [`ErrorKind::UnsupportType`] if there is no format of the name.
+///
+/// [`register_key_encoder()`]: crate::format::register_key_encoder
+pub fn convert_key_by_name(
+    input: &[u8],
+    passphrase: Option<&str>,
+    format: &str,
+    new_passphrase: Option<&str>,
+    options: &ConvertOptions,
+) -> OsshResult<Vec<u8>> {
+    if let Some(format) = OutputFormat::from_name(format) {
+        return convert_key(input, passphrase, format, new_passphrase, options);
+    }
+    let encoder =
+        find_key_encoder(format).ok_or_else(|| ErrorKind::UnsupportType(format.to_owned()))?;
+    let new_passphrase = new_passphrase.filter(|p| !p.is_empty());
+    match read_input(input, passphrase, encoder.is_private(), options)? {
+        InputKey::Public(pubkey) => encoder.encode_public(&pubkey),
+        InputKey::Private(keypair) => encoder.encode_keypair(&keypair, new_passphrase),
+    }
+}
+
+enum InputKey {
+    Private(KeyPair),
+    Public(PublicKey),
+}
+
+/// Parse the input key of the conversion, and replace its comment
+fn read_input(
+    input: &[u8],
+    passphrase: Option<&str>,
+    private_output: bool,
+    options: &ConvertOptions,
+) -> OsshResult<InputKey> {
+    let keystr = std::str::from_utf8(input).map_err(|_| ErrorKind::InvalidKeyFormat)?;
+    if keystr.trim_start().starts_with("PuTTY-User-Key-File-") {
+        return Err(ErrorKind::UnsupportType("PuTTY private key".to_owned()).into());
+    }
+
+    if !keystr.contains("PRIVATE KEY-----") {
+        if private_output {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        let mut pubkey = PublicKey::from_keystr(keystr)?;
+        if let Some(comment) = &options.comment {
+            *pubkey.comment_mut() = comment.clone();
+        }
+        return Ok(InputKey::Public(pubkey));
+    }
+
+    let mut keypair = KeyPair::from_keystr(keystr, passphrase)?;
+    if let Some(comment) = &options.comment {
+        *keypair.comment_mut() = comment.clone();
+    }
+    Ok(InputKey::Private(keypair))
+}
+
+fn serialize_public(pubkey: &PublicKey, format: OutputFormat) -> OsshResult<String> {
+    match format {
+        OutputFormat::OpensshPublic => pubkey.serialize(),
+        OutputFormat::PemPublic => pubkey.serialize_pem(),
+        OutputFormat::Rfc4716 => pubkey.serialize_rfc4716(),
+        _ => Err(ErrorKind::InvalidArgument.into()),
+    }
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "aes-cbc"))]
+mod test {
+    use super::*;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::PublicParts;
+
+    #[cfg(not(feature = "fips"))]
+    const ECDSA_KEY: &[u8] = include_bytes!("../../assets/openssh_ecdsa_enc");
+    const ECDSA_PUB: &[u8] = include_bytes!("../../assets/openssh_ecdsa_enc.pub");
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn convert_private_formats() {
+        let options = ConvertOptions::default();
+        let pubkey = PublicKey::from_keystr(std::str::from_utf8(ECDSA_PUB).unwrap()).unwrap();
+        for format in [
+            OutputFormat::Openssh,
+            OutputFormat::Pem,
+            OutputFormat::Pkcs8,
+        ] {
+            let converted = convert_key(
+                ECDSA_KEY,
+                Some("12345678"),
+                format,
+                Some("87654321"),
+                &options,
+            )
+            .unwrap();
+            let back = convert_key(
+                &converted,
+                Some("87654321"),
+                OutputFormat::Openssh,
+                None,
+                &options,
+            )
+            .unwrap();
+            let keypair = KeyPair::from_keystr(std::str::from_utf8(&back).unwrap(), None).unwrap();
+            assert!(keypair.matches_public(&pubkey));
+        }
+
+        let ppk = convert_key(
+            ECDSA_KEY,
+            Some("12345678"),
+            OutputFormat::Ppk,
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(ppk.starts_with(b"PuTTY-User-Key-File-2: ecdsa-sha2-nistp384\n"));
+        assert!(convert_key(&ppk, None, OutputFormat::Openssh, None, &options).is_err());
+    }
+
+    #[test]
+    fn output_format_names() {
+        for format in [
+            OutputFormat::Openssh,
+            OutputFormat::Pem,
+            OutputFormat::Pkcs8,
+            OutputFormat::Ppk,
+            OutputFormat::OpensshPublic,
+            OutputFormat::PemPublic,
+            OutputFormat::Rfc4716,
+        ] {
+            assert_eq!(format.name().parse::<OutputFormat>().unwrap(), format);
+        }
+        let err = "der".parse::<OutputFormat>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnsupportType("der".to_owned()));
+
+        let converted =
+            convert_key_by_name(ECDSA_PUB, None, "rfc4716", None, &ConvertOptions::default())
+                .unwrap();
+        assert!(converted.starts_with(b"---- BEGIN SSH2 PUBLIC KEY ----"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn convert_public_formats() {
+        let options = ConvertOptions {
+            comment: Some("converted".to_owned()),
+            ..Default::default()
+        };
+        let rfc4716 = convert_key(ECDSA_PUB, None, OutputFormat::Rfc4716, None, &options).unwrap();
+        let openssh =
+            convert_key(&rfc4716, None, OutputFormat::OpensshPublic, None, &options).unwrap();
+        let pubkey = PublicKey::from_keystr(std::str::from_utf8(&openssh).unwrap()).unwrap();
+        assert_eq!(pubkey.comment(), "converted");
+        assert_eq!(
+            pubkey.blob().unwrap(),
+            PublicKey::from_keystr(std::str::from_utf8(ECDSA_PUB).unwrap())
+                .unwrap()
+                .blob()
+                .unwrap()
+        );
+
+        let err = convert_key(ECDSA_PUB, None, OutputFormat::Pem, None, &options).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArgument);
+        let from_private = convert_key(
+            ECDSA_KEY,
+            Some("12345678"),
+            OutputFormat::PemPublic,
+            None,
+            &options,
+        )
+        .unwrap();
+        assert!(from_private.starts_with(b"-----BEGIN PUBLIC KEY-----"));
+    }
+}
@@ -1,77 +1,191 @@
 use crate::error::*;
-use crate::keys::{dsa::*, ecdsa::*, ed25519::*, rsa::*, PublicKey, PublicParts};
-use crate::sshbuf::{SshReadExt, SshWriteExt};
-use base64::prelude::*;
+use crate::format::base64;
+#[cfg(feature = "dsa")]
+use crate::keys::dsa::*;
+#[cfg(feature = "ecdsa")]
+use crate::keys::ecdsa::*;
+#[cfg(feature = "ed25519")]
+use crate::keys::ed25519::*;
+#[cfg(feature = "rsa")]
+use crate::keys::rsa::*;
+use crate::keys::{KeyType, PublicKey, PublicParts};
+use crate::sshbuf::{SshReader, SshWriteExt};
+#[cfg(feature = "ed25519")]
 use ed25519_dalek::VerifyingKey as Ed25519PubKey;
+#[cfg(feature = "ed25519")]
 use ed25519_dalek::PUBLIC_KEY_LENGTH;
+#[cfg(feature = "ecdsa")]
 use openssl::bn::BigNumContext;
+#[cfg(feature = "dsa")]
 use openssl::dsa::DsaRef;
+#[cfg(feature = "ecdsa")]
 use openssl::ec::{EcKeyRef, PointConversionForm};
+#[cfg(any(feature = "rsa", feature = "dsa", feature = "ecdsa"))]
 use openssl::pkey::{HasParams, HasPublic};
+#[cfg(feature = "rsa")]
 use openssl::rsa::RsaRef;
-use std::fmt::Write as _;
+use std::fmt;
 use std::io;
 use std::str::FromStr;
 
-pub fn parse_ossh_pubkey(keystr: &str) -> OsshResult<PublicKey> {
-    let key_split: Vec<&str> = keystr.split_ascii_whitespace().collect();
-    if key_split.len() < 2 || key_split.len() > 3 {
+const SECTION_PUBKEY: &str = "publickey";
+
+/// Split the one-line OpenSSH key string into the key name, the base64 blob and the comment
+///
+/// The comment is the rest of the line, which can contain whitespaces.
+pub(crate) fn split_ossh_keystr(keystr: &str) -> OsshResult<(&str, &str, &str)> {
+    fn next_field(s: &str) -> (&str, &str) {
+        match s.find(|c: char| c.is_ascii_whitespace()) {
+            Some(i) => (&s[..i], s[i..].trim_start()),
+            None => (s, ""),
+        }
+    }
+    let (keyname, rest) = next_field(keystr.trim());
+    let (blob, comment) = next_field(rest);
+    if blob.is_empty() {
         return Err(ErrorKind::InvalidKeyFormat.into());
     }
-    let blob = BASE64_STANDARD.decode(key_split[1])?;
-    let mut pubkey: PublicKey = match key_split[0] {
+    Ok((keyname, blob, comment))
+}
+
+/// Split the authorized_keys options before the key name, such as `from="10.0.0.?",no-pty`
+///
+/// The options may contain the quoted whitespaces. Return the options and the rest of the line,
+/// or no options if the line doesn't start with them.
+pub(crate) fn split_ossh_options(line: &str) -> (&str, &str) {
+    fn is_keyname(s: &str) -> bool {
+        let keyname = s.split_ascii_whitespace().next().unwrap_or_default();
+        KeyType::from_str(keyname).is_ok()
+    }
+    let line = line.trim();
+    if is_keyname(line) {
+        return ("", line);
+    }
+
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ' ' | '\t' if !quoted => {
+                let rest = line[i..].trim_start();
+                // Keep the unknown key name to report it
+                return if is_keyname(rest) {
+                    (&line[..i], rest)
+                } else {
+                    ("", line)
+                };
+            }
+            _ => {}
+        }
+    }
+    ("", line)
+}
+
+pub fn parse_ossh_pubkey(keystr: &str) -> OsshResult<PublicKey> {
+    let text = keystr;
+    // The fields are the slices of the text
+    let span_of = |field: &str| field.as_ptr() as usize - text.as_ptr() as usize;
+    let (options, keystr) = split_ossh_options(keystr);
+    let (keyname, blob, comment) = split_ossh_keystr(keystr)?;
+    let blob =
+        base64::decode(blob).map_err(|e| e.spanned(span_of(blob), blob.len(), "invalid base64"))?;
+    let mut pubkey: PublicKey = match keyname {
+        #[cfg(feature = "rsa")]
         RSA_NAME | RSA_SHA256_NAME | RSA_SHA512_NAME => {
             let mut rsa = decode_rsa_pubkey(&blob)?;
-            rsa.set_sign_type(RsaSignature::from_name(key_split[0]).unwrap());
+            rsa.set_sign_type(RsaSignature::from_name(keyname).unwrap());
             rsa.into()
         }
+        #[cfg(feature = "dsa")]
         DSA_NAME => decode_dsa_pubkey(&blob)?.into(),
+        #[cfg(feature = "ecdsa")]
         NIST_P256_NAME => decode_ecdsa_pubkey(&blob, Some(EcCurve::Nistp256))?.into(),
+        #[cfg(feature = "ecdsa")]
         NIST_P384_NAME => decode_ecdsa_pubkey(&blob, Some(EcCurve::Nistp384))?.into(),
+        #[cfg(feature = "ecdsa")]
         NIST_P521_NAME => decode_ecdsa_pubkey(&blob, Some(EcCurve::Nistp521))?.into(),
+        #[cfg(feature = "ed25519")]
         ED25519_NAME => decode_ed25519_pubkey(&blob)?.into(),
-        _ => return Err(ErrorKind::UnsupportType.into()),
+        _ => {
+            return Err(
+                Error::from(ErrorKind::UnsupportType(keyname.to_owned())).spanned(
+                    span_of(keyname),
+                    keyname.len(),
+                    "unknown key type",
+                ),
+            )
+        }
     };
-    if key_split.len() == 3 {
-        *pubkey.comment_mut() = key_split[2].to_owned();
-    }
+    *pubkey.comment_mut() = comment.to_owned();
+    pubkey.options = options.to_owned();
     Ok(pubkey)
 }
 
+/// Decode the public key from the SSH wire encoding blob
+pub fn decode_ossh_pubkey(keyblob: &[u8]) -> OsshResult<PublicKey> {
+    let keyname = SshReader::new(keyblob, SECTION_PUBKEY).read_utf8("keytype")?;
+    let pubkey = match keyname {
+        #[cfg(feature = "rsa")]
+        RSA_NAME | RSA_SHA256_NAME | RSA_SHA512_NAME => {
+            let mut rsa = decode_rsa_pubkey(keyblob)?;
+            rsa.set_sign_type(RsaSignature::from_name(keyname).unwrap());
+            rsa.into()
+        }
+        #[cfg(feature = "dsa")]
+        DSA_NAME => decode_dsa_pubkey(keyblob)?.into(),
+        #[cfg(feature = "ecdsa")]
+        NIST_P256_NAME | NIST_P384_NAME | NIST_P521_NAME => {
+            decode_ecdsa_pubkey(keyblob, EcCurve::from_name(keyname).ok())?.into()
+        }
+        #[cfg(feature = "ed25519")]
+        ED25519_NAME => decode_ed25519_pubkey(keyblob)?.into(),
+        _ => return Err(ErrorKind::UnsupportType(keyname.to_owned()).into()),
+    };
+    Ok(pubkey)
+}
+
+#[cfg(feature = "rsa")]
 pub(crate) fn decode_rsa_pubkey(keyblob: &[u8]) -> OsshResult<RsaPublicKey> {
-    let mut reader = io::Cursor::new(keyblob);
-    let keyname = reader.read_utf8()?;
+    let mut reader = SshReader::new(keyblob, SECTION_PUBKEY);
+    let keyname = reader.read_utf8("keytype")?;
     if keyname != RSA_NAME && keyname != RSA_SHA256_NAME && keyname != RSA_SHA512_NAME {
         return Err(ErrorKind::TypeNotMatch.into());
     }
-    let e = reader.read_mpint()?;
-    let n = reader.read_mpint()?;
+    let e = reader.read_mpint("e")?;
+    let n = reader.read_mpint("n")?;
+    reader.finish()?;
 
     Ok(RsaPublicKey::new(n, e)?)
 }
 
+#[cfg(feature = "dsa")]
 pub(crate) fn decode_dsa_pubkey(keyblob: &[u8]) -> OsshResult<DsaPublicKey> {
-    let mut reader = io::Cursor::new(keyblob);
-    if reader.read_utf8()? != DSA_NAME {
+    let mut reader = SshReader::new(keyblob, SECTION_PUBKEY);
+    if reader.read_utf8("keytype")? != DSA_NAME {
         return Err(ErrorKind::TypeNotMatch.into());
     }
 
-    let p = reader.read_mpint()?;
-    let q = reader.read_mpint()?;
-    let g = reader.read_mpint()?;
-    let y = reader.read_mpint()?;
+    let p = reader.read_mpint("p")?;
+    let q = reader.read_mpint("q")?;
+    let g = reader.read_mpint("g")?;
+    let y = reader.read_mpint("y")?;
+    reader.finish()?;
 
     Ok(DsaPublicKey::new(p, q, g, y)?)
 }
 
+#[cfg(feature = "ecdsa")]
 pub(crate) fn decode_ecdsa_pubkey(
     keyblob: &[u8],
     curve_hint: Option<EcCurve>,
 ) -> OsshResult<EcDsaPublicKey> {
-    let mut reader = io::Cursor::new(keyblob);
-    let curve = if reader.read_utf8()?.starts_with("ecdsa-sha2-") {
-        let ident_str = reader.read_utf8()?;
-        EcCurve::from_str(&ident_str).map_err(|_| ErrorKind::UnsupportCurve)?
+    let mut reader = SshReader::new(keyblob, SECTION_PUBKEY);
+    let curve = if reader.read_utf8("keytype")?.starts_with("ecdsa-sha2-") {
+        let ident_str = reader.read_utf8("curve")?;
+        EcCurve::from_str(ident_str).map_err(|_| ErrorKind::UnsupportCurve)?
     } else {
         return Err(ErrorKind::TypeNotMatch.into());
     };
@@ -80,40 +194,48 @@ pub(crate) fn decode_ecdsa_pubkey(
             return Err(ErrorKind::TypeNotMatch.into());
         }
     }
-    let pub_key = reader.read_string()?;
-    EcDsaPublicKey::from_bytes(curve, &pub_key)
+    let pub_key = reader.read_string("Q")?;
+    reader.finish()?;
+    EcDsaPublicKey::from_bytes(curve, pub_key)
 }
 
+#[cfg(feature = "ed25519")]
 pub(crate) fn decode_ed25519_pubkey(keyblob: &[u8]) -> OsshResult<Ed25519PublicKey> {
-    let mut reader = io::Cursor::new(keyblob);
-    if reader.read_utf8()? != ED25519_NAME {
+    let mut reader = SshReader::new(keyblob, SECTION_PUBKEY);
+    if reader.read_utf8("keytype")? != ED25519_NAME {
         return Err(ErrorKind::TypeNotMatch.into());
     }
 
-    let pub_key = reader.read_string()?;
+    let pub_key = reader.read_string("pk")?;
+    reader.finish()?;
     if pub_key.len() != PUBLIC_KEY_LENGTH {
         return Err(ErrorKind::InvalidKeySize.into());
     }
 
-    Ok(Ed25519PublicKey::new(
-        pub_key.as_slice().try_into().unwrap(),
-    )?)
+    Ok(Ed25519PublicKey::new(pub_key.try_into().unwrap())?)
 }
 
 pub fn serialize_ossh_pubkey(key: &dyn PublicParts, comment: &str) -> OsshResult<String> {
     let mut keystr = String::new();
-    write!(
-        &mut keystr,
-        "{} {}",
-        key.keyname(),
-        BASE64_STANDARD.encode(key.blob()?)
-    )?;
+    write_ossh_pubkey(&mut keystr, key, comment)?;
+    Ok(keystr)
+}
+
+/// Write the one-line OpenSSH public key into the sink, without the trailing newline
+pub fn write_ossh_pubkey<W: fmt::Write + ?Sized>(
+    out: &mut W,
+    key: &dyn PublicParts,
+    comment: &str,
+) -> OsshResult<()> {
+    write!(out, "{} ", key.keyname())?;
+    base64::write_encoded(out, &key.blob()?, 0)?;
     if !comment.is_empty() {
-        write!(&mut keystr, " {}", comment)?;
+        write!(out, " {}", comment)?;
     }
-    Ok(keystr)
+    Ok(())
 }
 
+#[cfg(feature = "rsa")]
 pub(crate) fn encode_rsa_pubkey<T: HasPublic + HasParams>(key: &RsaRef<T>) -> OsshResult<Vec<u8>> {
     let mut buf = io::Cursor::new(Vec::new());
 
@@ -124,6 +246,7 @@ pub(crate) fn encode_rsa_pubkey<T: HasPublic + HasParams>(key: &RsaRef<T>) -> Os
     Ok(buf.into_inner())
 }
 
+#[cfg(feature = "dsa")]
 pub(crate) fn encode_dsa_pubkey<T: HasPublic + HasParams>(key: &DsaRef<T>) -> OsshResult<Vec<u8>> {
     let mut buf = io::Cursor::new(Vec::new());
 
@@ -136,6 +259,7 @@ pub(crate) fn encode_dsa_pubkey<T: HasPublic + HasParams>(key: &DsaRef<T>) -> Os
     Ok(buf.into_inner())
 }
 
+#[cfg(feature = "ecdsa")]
 pub(crate) fn encode_ecdsa_pubkey<T: HasPublic + HasParams>(
     curve: EcCurve,
     key: &EcKeyRef<T>,
@@ -154,6 +278,7 @@ pub(crate) fn encode_ecdsa_pubkey<T: HasPublic + HasParams>(
     Ok(buf.into_inner())
 }
 
+#[cfg(feature = "ed25519")]
 pub(crate) fn encode_ed25519_pubkey(pub_key: &Ed25519PubKey) -> OsshResult<Vec<u8>> {
     let mut buf = io::Cursor::new(Vec::new());
 
@@ -163,7 +288,13 @@ pub(crate) fn encode_ed25519_pubkey(pub_key: &Ed25519PubKey) -> OsshResult<Vec<u
     Ok(buf.into_inner())
 }
 
-#[cfg(test)]
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
 mod test {
     use super::*;
 
@@ -173,6 +304,31 @@ mod test {
     const ECDSA_PUBKEY: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBKtcK82cEoqjiXyqPpyQAlkOQYs8LL5dDahPah5dqoaJfVHcKS5CJYBX0Ow+Dlj9xKtSQRCyJXOCEtJx+k4LUV0= osshkeys_ecdsa-test";
     const ED25519_PUBKEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIMoWBluPErgKhNja3lHEf7ie6AVzR24mPRd742xEYodC osshkeys_ed25519-test";
 
+    #[test]
+    fn publickey_decode_blob() {
+        for keystr in [
+            DSA_PUBKEY,
+            RSA_PUBKEY,
+            RSA256_PUBKEY,
+            ECDSA_PUBKEY,
+            ED25519_PUBKEY,
+        ] {
+            let key = parse_ossh_pubkey(keystr).unwrap();
+            let blob = base64::decode(keystr.split_ascii_whitespace().nth(1).unwrap()).unwrap();
+            let decoded = decode_ossh_pubkey(&blob).unwrap();
+            assert!(decoded == key);
+            assert_eq!(decoded.comment(), "");
+            assert_eq!(decoded.blob().unwrap(), blob);
+        }
+
+        let mut blob = Vec::new();
+        blob.write_utf8("ssh-foo").unwrap();
+        assert_eq!(
+            decode_ossh_pubkey(&blob).err().unwrap().kind(),
+            ErrorKind::UnsupportType("ssh-foo".to_string())
+        );
+    }
+
     #[test]
     fn dsa_publickey_parse_serialize() {
         let dsa = parse_ossh_pubkey(DSA_PUBKEY).unwrap();
@@ -212,4 +368,82 @@ mod test {
         let ed25519_string = serialize_ossh_pubkey(&ed25519, ed25519.comment()).unwrap();
         assert_eq!(&ed25519_string, ED25519_PUBKEY);
     }
+
+    #[test]
+    fn publickey_comment_whitespace() {
+        let keystr = format!("{} comment with  spaces \n", ED25519_PUBKEY);
+        let key = parse_ossh_pubkey(&keystr).unwrap();
+        assert_eq!(key.comment(), "osshkeys_ed25519-test comment with  spaces");
+        let key = parse_ossh_pubkey(ED25519_PUBKEY.rsplit_once(' ').unwrap().0).unwrap();
+        assert_eq!(key.comment(), "");
+        assert!(parse_ossh_pubkey(ED25519_NAME).is_err());
+    }
+
+    #[test]
+    fn publickey_authorized_keys_line() {
+        let line = format!(
+            "  command=\"echo \\\"a b\\\"\",no-pty\t{} from the laptop\r\n",
+            ED25519_PUBKEY
+        );
+        let key = parse_ossh_pubkey(&line).unwrap();
+        assert_eq!(key.options(), "command=\"echo \\\"a b\\\"\",no-pty");
+        assert_eq!(key.comment(), "osshkeys_ed25519-test from the laptop");
+        assert_eq!(
+            key.serialize().unwrap(),
+            format!("{} from the laptop", ED25519_PUBKEY)
+        );
+
+        let key = parse_ossh_pubkey(&format!("restrict,from=\"10.0.0.?\" {}", RSA_PUBKEY)).unwrap();
+        assert_eq!(key.options(), "restrict,from=\"10.0.0.?\"");
+        assert_eq!(key.comment(), "osshkeys_rsa-test");
+        let key = parse_ossh_pubkey(&format!("\r\n{}\r\n", ECDSA_PUBKEY)).unwrap();
+        assert_eq!(key.options(), "");
+        assert_eq!(key.comment(), "osshkeys_ecdsa-test");
+
+        assert!(parse_ossh_pubkey("no-pty ssh-foo AAAA").is_err());
+        assert!(parse_ossh_pubkey(&format!("from=\"a {}", ED25519_PUBKEY)).is_err());
+    }
+
+    #[test]
+    fn unsupported_publickey_type() {
+        let err = parse_ossh_pubkey("ssh-foo AAAA").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnsupportType("ssh-foo".to_owned()));
+        assert_eq!(err.to_string(), "Unsupported Key Type \"ssh-foo\"");
+        assert_eq!(err.span().unwrap().range(), 0..7);
+    }
+
+    #[test]
+    fn invalid_base64_span() {
+        let keystr = "no-pty ssh-ed25519 AAAA!C3 comment";
+        let err = parse_ossh_pubkey(keystr).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Base64Error);
+        let span = err.span().unwrap();
+        assert_eq!(&keystr[span.range()], "AAAA!C3");
+        assert_eq!(span.label(), "invalid base64");
+    }
+
+    #[test]
+    fn truncated_publickey_position() {
+        let mut blob = base64::decode(ED25519_PUBKEY.split(' ').nth(1).unwrap()).unwrap();
+        blob.truncate(30);
+        let keystr = format!("{} {}", ED25519_NAME, base64::encode(blob));
+        let err = parse_ossh_pubkey(&keystr).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::IOError);
+        let position = err.position().unwrap();
+        assert_eq!(position.section(), "publickey");
+        assert_eq!(position.field(), "pk");
+        assert_eq!(position.offset(), 15);
+    }
+
+    #[test]
+    fn trailing_publickey_data() {
+        let mut blob = base64::decode(ED25519_PUBKEY.split(' ').nth(1).unwrap()).unwrap();
+        let len = blob.len();
+        blob.push(0);
+        let err = decode_ossh_pubkey(&blob).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidLength);
+        let position = err.position().unwrap();
+        assert_eq!(position.field(), "trailing data");
+        assert_eq!(position.offset(), len);
+    }
 }
@@ -0,0 +1,249 @@
+use crate::error::*;
+use std::fmt;
+use std::io;
+
+/// The maximum line width of the base64 data, limited by the MIME encoding
+const MAX_LINE_WIDTH: usize = 76;
+
+/// The line ending of the serialized keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\n`, written by OpenSSH and OpenSSL on the Unix systems
+    #[default]
+    LF,
+    /// `\r\n`, expected by some Windows tools and network appliances
+    CRLF,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::LF => "\n",
+            LineEnding::CRLF => "\r\n",
+        }
+    }
+}
+
+/// The formatting options of the serialized keys
+///
+/// The default options keep the output of the serialize functions unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputOptions {
+    /// Wrap the base64 data at this width, such as `64` for the PEM formats or `70` for the OpenSSH formats
+    ///
+    /// `None` keeps the width of each format. The PuTTY keys are never rewrapped,
+    /// since the number of the lines is part of their format.
+    /// OpenSSL only reads the traditional encrypted PEM keys wrapped at 64 characters.
+    pub line_width: Option<usize>,
+    /// The line ending
+    pub line_ending: LineEnding,
+    /// End the output with a line ending or not, `None` keeps the one of each format
+    pub trailing_newline: Option<bool>,
+}
+
+impl OutputOptions {
+    /// Reformat the output of the serialize functions, such as [`KeyPair::serialize_pem()`](crate::keys::KeyPair::serialize_pem)
+    ///
+    /// The base64 data between the `BEGIN` and `END` lines of the PEM, OpenSSH and RFC 4716 formats is rewrapped,
+    /// while the armor lines and the headers are kept.
+    pub fn apply(&self, keystr: &str) -> OsshResult<String> {
+        if let Some(width) = self.line_width {
+            if width == 0 || width > MAX_LINE_WIDTH {
+                return Err(ErrorKind::InvalidArgument.into());
+            }
+        }
+        let newline = self.line_ending.as_str();
+        let trailing = self
+            .trailing_newline
+            .unwrap_or_else(|| keystr.ends_with('\n'));
+
+        let mut lines = Vec::new();
+        let mut body = String::new();
+        let mut armored = false;
+        let mut continued = false;
+        for line in keystr.lines() {
+            let line = line.trim_end_matches('\r');
+            let is_body = armored
+                && !continued
+                && !line.is_empty()
+                && line
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'=');
+            if is_body && self.line_width.is_some() {
+                body.push_str(line);
+                continue;
+            }
+            self.flush_body(&mut body, &mut lines);
+            if is_armor(line, "BEGIN") {
+                armored = true;
+            } else if is_armor(line, "END") {
+                armored = false;
+            }
+            // The RFC 4716 header lines ending with a backslash continue on the next line
+            continued = armored && line.ends_with('\\');
+            lines.push(line.to_owned());
+        }
+        self.flush_body(&mut body, &mut lines);
+
+        let mut output = lines.join(newline);
+        if trailing {
+            output.push_str(newline);
+        }
+        Ok(output)
+    }
+
+    fn flush_body(&self, body: &mut String, lines: &mut Vec<String>) {
+        if let Some(width) = self.line_width {
+            // The base64 string only contains the ASCII characters
+            lines.extend(
+                body.as_bytes()
+                    .chunks(width)
+                    .map(|line| std::str::from_utf8(line).unwrap().to_owned()),
+            );
+            body.clear();
+        }
+    }
+}
+
+/// Adapt an [`io::Write`] into the [`fmt::Write`] sink of the `write_*` functions
+///
+/// The I/O error is kept since [`fmt::Write`] can't carry it, see [`IoWriter::take_error()`].
+#[derive(Debug)]
+pub struct IoWriter<W: io::Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IoWriter<W> {
+    /// Wrap the writer
+    pub fn new(inner: W) -> Self {
+        IoWriter { inner, error: None }
+    }
+
+    /// Take the last I/O error, which makes the `write_*` functions fail with [`ErrorKind::FmtError`]
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Get the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+fn is_armor(line: &str, kind: &str) -> bool {
+    let line = line.trim_start_matches('-').trim_start();
+    line.starts_with(kind) && line.ends_with("----")
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519", feature = "aes-cbc"))]
+mod test {
+    use super::*;
+    use crate::keys::{KeyPair, PublicKey, PublicParts};
+
+    const ECDSA_KEY: &str = include_str!("../../assets/openssh_ecdsa");
+    const ED25519_PUB: &str = include_str!("../../assets/openssh_ed25519.pub");
+
+    #[test]
+    fn output_line_width() {
+        let keypair = KeyPair::from_keystr(ECDSA_KEY, None).unwrap();
+        let options = OutputOptions {
+            line_width: Some(64),
+            ..Default::default()
+        };
+        let keystr = options.apply(ECDSA_KEY).unwrap();
+        assert!(keystr.lines().all(|l| l.len() <= 64));
+        assert!(keystr.lines().any(|l| l.len() == 64));
+        assert!(keystr.ends_with("-----END OPENSSH PRIVATE KEY-----\n"));
+        let parsed = KeyPair::from_keystr(&keystr, None).unwrap();
+        assert_eq!(parsed.blob().unwrap(), keypair.blob().unwrap());
+
+        let pkcs8 = keypair.serialize_pkcs8(Some("12345678")).unwrap();
+        let options = OutputOptions {
+            line_width: Some(70),
+            ..Default::default()
+        };
+        let keystr = options.apply(&pkcs8).unwrap();
+        assert!(keystr.lines().any(|l| l.len() == 70));
+        let parsed = KeyPair::from_keystr(&keystr, Some("12345678")).unwrap();
+        assert_eq!(parsed.blob().unwrap(), keypair.blob().unwrap());
+
+        // The headers of the traditional encrypted PEM are kept
+        let pem = keypair.serialize_pem(Some("12345678")).unwrap();
+        let options = OutputOptions {
+            line_ending: LineEnding::CRLF,
+            ..Default::default()
+        };
+        let keystr = options.apply(&pem).unwrap();
+        assert!(keystr.contains("Proc-Type: 4,ENCRYPTED\r\n"));
+        assert_eq!(keystr.replace("\r\n", "\n"), pem);
+        let parsed = KeyPair::from_keystr(&keystr, Some("12345678")).unwrap();
+        assert_eq!(parsed.blob().unwrap(), keypair.blob().unwrap());
+
+        assert_eq!(OutputOptions::default().apply(&pem).unwrap(), pem);
+        for width in [0, 77] {
+            let options = OutputOptions {
+                line_width: Some(width),
+                ..Default::default()
+            };
+            assert!(options.apply(&pem).is_err());
+        }
+    }
+
+    #[test]
+    fn output_io_writer() {
+        let pubkey = PublicKey::from_keystr(ED25519_PUB).unwrap();
+        let mut writer = IoWriter::new(Vec::new());
+        for _ in 0..2 {
+            pubkey.write_openssh(&mut writer).unwrap();
+            fmt::Write::write_char(&mut writer, '\n').unwrap();
+        }
+        assert!(writer.take_error().is_none());
+        let expected = format!("{}\n", pubkey.serialize().unwrap());
+        assert_eq!(writer.into_inner(), expected.repeat(2).into_bytes());
+
+        let mut buf = [0u8; 16];
+        let mut writer = IoWriter::new(&mut buf[..]);
+        let err = pubkey.write_openssh(&mut writer).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::FmtError);
+        assert_eq!(
+            writer.take_error().unwrap().kind(),
+            io::ErrorKind::WriteZero
+        );
+    }
+
+    #[test]
+    fn output_line_ending() {
+        let mut pubkey = PublicKey::from_keystr(ED25519_PUB).unwrap();
+        pubkey
+            .set_comment("a comment which is long enough to be split into the continuation lines");
+        let options = OutputOptions {
+            line_width: Some(64),
+            line_ending: LineEnding::CRLF,
+            trailing_newline: Some(false),
+        };
+        let keystr = options.apply(&pubkey.serialize_rfc4716().unwrap()).unwrap();
+        assert!(keystr.contains("\\\r\n"));
+        assert!(keystr.ends_with("---- END SSH2 PUBLIC KEY ----"));
+        assert!(!keystr.replace("\r\n", "").contains('\n'));
+        let parsed = PublicKey::from_keystr(&keystr).unwrap();
+        assert_eq!(parsed.comment(), pubkey.comment());
+        assert_eq!(parsed.blob().unwrap(), pubkey.blob().unwrap());
+
+        let options = OutputOptions {
+            trailing_newline: Some(true),
+            ..Default::default()
+        };
+        let keystr = options.apply(&pubkey.serialize().unwrap()).unwrap();
+        assert_eq!(keystr, format!("{}\n", pubkey.serialize().unwrap()));
+    }
+}
@@ -1,53 +1,120 @@
+#![cfg_attr(not(feature = "aes-cbc"), allow(dead_code, unused_imports))]
+
 use crate::cipher::*;
 use crate::error::*;
-use crate::keys::{rsa::*, *};
+use crate::format::base64;
+use crate::format::locate_pem_error;
+use crate::format::pkcs8::parse_ed25519_pkcs8;
+#[cfg(feature = "ed25519")]
+use crate::format::pkcs8::serialize_pkcs8_privkey;
+#[cfg(feature = "rsa")]
+use crate::keys::rsa::*;
+use crate::keys::*;
 use digest::DynDigest;
-use openssl::{
-    pkey::{PKey, Public},
-    rsa::Rsa,
-};
+use openssl::pkey::PKey;
+#[cfg(feature = "rsa")]
+use openssl::{pkey::Public, rsa::Rsa};
+#[cfg(any(feature = "experimental", feature = "legacy-des"))]
 use pem::Pem as PemBlock;
-use zeroize::Zeroize;
+use rand::rngs::OsRng;
+use rand_core::CryptoRngCore;
+use std::fmt::Write;
+use zeroize::{Zeroize, Zeroizing};
 
 const MAX_KEY_LEN: usize = 64;
 
 //TODO: Not to depend on openssl to parse pem file in the future
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
 pub fn parse_pem_privkey(pem: &[u8], passphrase: Option<&str>) -> OsshResult<KeyPair> {
+    #[cfg(feature = "legacy-des")]
+    if let Some(keypair) = parse_des_pem_privkey(pem, passphrase)? {
+        return Ok(keypair);
+    }
     let pkey = if let Some(passphrase) = passphrase {
         PKey::private_key_from_pem_passphrase(pem, passphrase.as_bytes())
-            .map_err(|_| ErrorKind::IncorrectPass)?
+            .map_err(|_| Error::from(ErrorKind::IncorrectPass))
     } else {
-        PKey::private_key_from_pem(pem)?
+        PKey::private_key_from_pem(pem).map_err(Error::from)
+    };
+
+    match pkey {
+        Ok(pkey) => KeyPair::from_ossl_pkey(&pkey),
+        // Some OpenSSL versions refuse the Ed25519 keys of PKCS#8 v2
+        Err(err) => parse_ed25519_pkcs8(pem, passphrase, false).map_err(|_| err),
+    }
+}
+
+/// Decrypt the traditional PEM key encrypted with single DES, which OpenSSL 3 refuses without the legacy provider
+///
+/// Return `None` if the key isn't encrypted with `DES-CBC`.
+#[cfg(feature = "legacy-des")]
+fn parse_des_pem_privkey(pem: &[u8], passphrase: Option<&str>) -> OsshResult<Option<KeyPair>> {
+    let Ok(pemblock) = ::pem::parse(pem) else {
+        return Ok(None);
     };
+    let is_des = pemblock
+        .headers()
+        .get("DEK-Info")
+        .map_or(false, |info| info.starts_with("DES-CBC,"));
+    if !is_des {
+        return Ok(None);
+    }
+    let der = Zeroizing::new(pem_decrypt(&pemblock, passphrase.map(str::as_bytes))?);
+    let pkey = PKey::private_key_from_der(&der).map_err(|_| ErrorKind::IncorrectPass)?;
+    Ok(Some(KeyPair::from_ossl_pkey(&pkey)?))
+}
 
-    KeyPair::from_ossl_pkey(&pkey)
+/// Decrypt with single DES in CBC mode, which isn't an SSH cipher and only appears in the legacy PEM keys
+#[cfg(feature = "legacy-des")]
+fn des_cbc_decrypt(src: &[u8], key: &[u8], iv: &[u8]) -> OsshResult<Vec<u8>> {
+    use cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+
+    Ok(cbc::Decryptor::<des::Des>::new_from_slices(key, iv)?
+        .decrypt_padded_vec_mut::<Pkcs7>(src)?)
 }
 
 //TODO: Not to depend on openssl to parse pem file in the future
 pub fn stringify_pem_privkey(keypair: &KeyPair, passphrase: Option<&str>) -> OsshResult<String> {
+    stringify_pem_privkey_with_rng(keypair, passphrase, &mut OsRng)
+}
+
+#[cfg_attr(not(feature = "aes-cbc"), allow(unused_variables))]
+#[cfg_attr(
+    not(any(feature = "rsa", feature = "dsa", feature = "ecdsa")),
+    allow(unreachable_code)
+)]
+pub fn stringify_pem_privkey_with_rng<R: CryptoRngCore + ?Sized>(
+    keypair: &KeyPair,
+    passphrase: Option<&str>,
+    rng: &mut R,
+) -> OsshResult<String> {
     let pem = if let Some(passphrase) = passphrase {
+        let (tag, der): (&str, Vec<u8>) = match &keypair.key {
+            #[cfg(feature = "rsa")]
+            KeyPairType::RSA(key) => ("RSA PRIVATE KEY", key.ossl_rsa().private_key_to_der()?),
+            #[cfg(feature = "dsa")]
+            KeyPairType::DSA(key) => ("DSA PRIVATE KEY", key.ossl_dsa().private_key_to_der()?),
+            #[cfg(feature = "ecdsa")]
+            KeyPairType::ECDSA(key) => ("EC PRIVATE KEY", key.ossl_ec().private_key_to_der()?),
+            // Ed25519 keys only have the PKCS#8 form
+            #[cfg(feature = "ed25519")]
+            KeyPairType::ED25519(_) => return serialize_pkcs8_privkey(keypair, Some(passphrase)),
+        };
+        let der = Zeroizing::new(der);
         // TODO: Allow for cipher selection
-        let cipher = openssl::symm::Cipher::aes_128_cbc();
-        let passphrase = passphrase.as_bytes();
-        match &keypair.key {
-            KeyPairType::RSA(key) => key
-                .ossl_rsa()
-                .private_key_to_pem_passphrase(cipher, passphrase)?,
-            KeyPairType::DSA(key) => key
-                .ossl_dsa()
-                .private_key_to_pem_passphrase(cipher, passphrase)?,
-            KeyPairType::ECDSA(key) => key
-                .ossl_ec()
-                .private_key_to_pem_passphrase(cipher, passphrase)?,
-            KeyPairType::ED25519(key) => key
-                .ossl_pkey()?
-                .private_key_to_pem_pkcs8_passphrase(cipher, passphrase)?,
-        }
+        #[cfg(feature = "aes-cbc")]
+        return pem_encrypt(tag, &der, passphrase.as_bytes(), Cipher::Aes128_Cbc, rng);
+        #[cfg(not(feature = "aes-cbc"))]
+        return Err(ErrorKind::UnsupportCipher(String::from("aes128-cbc")).into());
     } else {
         match &keypair.key {
+            #[cfg(feature = "rsa")]
             KeyPairType::RSA(key) => key.ossl_rsa().private_key_to_pem()?,
+            #[cfg(feature = "dsa")]
             KeyPairType::DSA(key) => key.ossl_dsa().private_key_to_pem()?,
+            #[cfg(feature = "ecdsa")]
             KeyPairType::ECDSA(key) => key.ossl_ec().private_key_to_pem()?,
+            #[cfg(feature = "ed25519")]
             KeyPairType::ED25519(key) => key.ossl_pkey()?.private_key_to_pem_pkcs8()?,
         }
     };
@@ -55,22 +122,67 @@ pub fn stringify_pem_privkey(keypair: &KeyPair, passphrase: Option<&str>) -> Oss
     String::from_utf8(pem).map_err(|e| Error::with_error(ErrorKind::InvalidPemFormat, e))
 }
 
+/// Encrypt the traditional PEM key with the `Proc-Type` and `DEK-Info` headers, like OpenSSL but with the cipher backend of this crate
+///
+/// The key is derived from the passphrase and the first 8 bytes of the random IV with `EVP_BytesToKey()` of MD5.
+#[cfg(feature = "aes-cbc")]
+fn pem_encrypt<R: CryptoRngCore + ?Sized>(
+    tag: &str,
+    der: &[u8],
+    passphrase: &[u8],
+    cipher: Cipher,
+    rng: &mut R,
+) -> OsshResult<String> {
+    let algo = match cipher {
+        #[cfg(feature = "3des-cbc")]
+        Cipher::TDes_Cbc => "DES-EDE3-CBC",
+        Cipher::Aes128_Cbc => "AES-128-CBC",
+        Cipher::Aes192_Cbc => "AES-192-CBC",
+        Cipher::Aes256_Cbc => "AES-256-CBC",
+        _ => return Err(ErrorKind::UnsupportCipher(cipher.name().to_owned()).into()),
+    };
+    let mut iv = vec![0u8; cipher.iv_len()];
+    rng.fill_bytes(&mut iv);
+    let key = Zeroizing::new(openssl_kdf(
+        passphrase,
+        &iv[..8].try_into()?,
+        &mut md5::Md5::default(),
+        cipher.key_len(),
+        1,
+    )?);
+    let encrypted = cipher.encrypt(der, &key, &iv)?;
+
+    let iv: String = iv.iter().map(|b| format!("{:02X}", b)).collect();
+    let mut pem = format!(
+        "-----BEGIN {}-----\nProc-Type: 4,ENCRYPTED\nDEK-Info: {},{}\n\n",
+        tag, algo, iv
+    );
+    base64::write_encoded(&mut pem, &encrypted, 64)?;
+    writeln!(pem, "\n-----END {}-----", tag)?;
+    Ok(pem)
+}
+
 pub fn parse_pem_pubkey(pem: &[u8]) -> OsshResult<PublicKey> {
+    #[cfg(feature = "rsa")]
     if pem.starts_with(b"-----BEGIN RSA PUBLIC KEY-----") {
-        let rsa = Rsa::<Public>::public_key_from_pem_pkcs1(pem)?;
+        let rsa = Rsa::<Public>::public_key_from_pem_pkcs1(pem)
+            .map_err(|e| locate_pem_error(pem, e.into()))?;
         let rsapubkey = RsaPublicKey::from_ossl_rsa(rsa, RsaSignature::SHA1)?;
-        Ok(rsapubkey.into())
-    } else {
-        let pkey = PKey::public_key_from_pem(pem)?;
-        Ok(PublicKey::from_ossl_pkey(&pkey)?)
+        return Ok(rsapubkey.into());
     }
+    let pkey = PKey::public_key_from_pem(pem).map_err(|e| locate_pem_error(pem, e.into()))?;
+    PublicKey::from_ossl_pkey(&pkey)
 }
 
 pub fn stringify_pem_pubkey(pubkey: &PublicKey) -> OsshResult<String> {
     let pem = match &pubkey.key {
+        #[cfg(feature = "rsa")]
         PublicKeyType::RSA(key) => key.ossl_rsa().public_key_to_pem_pkcs1()?,
+        #[cfg(feature = "dsa")]
         PublicKeyType::DSA(key) => key.ossl_pkey()?.public_key_to_pem()?,
+        #[cfg(feature = "ecdsa")]
         PublicKeyType::ECDSA(key) => key.ossl_pkey()?.public_key_to_pem()?,
+        #[cfg(feature = "ed25519")]
         PublicKeyType::ED25519(key) => key.ossl_pkey()?.public_key_to_pem()?,
     };
 
@@ -78,7 +190,7 @@ pub fn stringify_pem_pubkey(pubkey: &PublicKey) -> OsshResult<String> {
 }
 
 /// Self experimental implementation for decrypting OpenSSL PEM format
-#[cfg(feature = "experimental")]
+#[cfg(any(feature = "experimental", feature = "legacy-des"))]
 #[allow(dead_code)]
 fn pem_decrypt(pemblock: &PemBlock, passphrase: Option<&[u8]>) -> OsshResult<Vec<u8>> {
     let mut encrypted = false;
@@ -91,7 +203,7 @@ fn pem_decrypt(pemblock: &PemBlock, passphrase: Option<&[u8]>) -> OsshResult<Vec
             encrypted = ("4", "ENCRYPTED") == (ver, locktype)
         }
         if !encrypted {
-            return Err(ErrorKind::UnsupportType.into());
+            return Err(ErrorKind::UnsupportType(header.to_owned()).into());
         }
     }
     if encrypted {
@@ -103,24 +215,46 @@ fn pem_decrypt(pemblock: &PemBlock, passphrase: Option<&[u8]>) -> OsshResult<Vec
             .expect("regexp should compile");
             if let Some(caps) = re.captures(header) {
                 let algo = caps.get(1).map_or("", |m| m.as_str());
-                let iv = caps.get(2).map_or("", |m| m.as_str()).as_bytes();
+                let iv = decode_hex(caps.get(2).map_or("", |m| m.as_str()))?;
+                // The salt of the KDF is the first 8 bytes of the IV
+                if iv.len() < 8 {
+                    return Err(ErrorKind::InvalidPemFormat.into());
+                }
                 if let Some(passphrase) = passphrase {
+                    #[cfg(feature = "legacy-des")]
+                    if algo == "DES-CBC" {
+                        let key = zeroize::Zeroizing::new(openssl_kdf(
+                            passphrase,
+                            &iv[..8].try_into()?,
+                            &mut md5::Md5::default(),
+                            8,
+                            1,
+                        )?);
+                        return des_cbc_decrypt(pemblock.contents(), &key, &iv);
+                    }
                     let ciph = match algo {
-                        "DES-CBC" => return Err(ErrorKind::UnsupportCipher.into()),
+                        "DES-CBC" => return Err(ErrorKind::UnsupportCipher(algo.to_owned()).into()),
+                        #[cfg(feature = "3des-cbc")]
                         "DES-EDE3-CBC" => Cipher::TDes_Cbc,
+                        #[cfg(feature = "aes-cbc")]
                         "AES-128-CBC" => Cipher::Aes128_Cbc,
+                        #[cfg(feature = "aes-cbc")]
                         "AES-192-CBC" => Cipher::Aes192_Cbc,
+                        #[cfg(feature = "aes-cbc")]
                         "AES-256-CBC" => Cipher::Aes256_Cbc,
-                        _ => return Err(ErrorKind::UnsupportCipher.into()),
+                        _ => return Err(ErrorKind::UnsupportCipher(algo.to_owned()).into()),
                     };
-                    let key = openssl_kdf(
+                    if iv.len() != ciph.iv_len() {
+                        return Err(ErrorKind::InvalidPemFormat.into());
+                    }
+                    let key = zeroize::Zeroizing::new(openssl_kdf(
                         passphrase,
-                        &iv.try_into()?,
+                        &iv[..8].try_into()?,
                         &mut md5::Md5::default(),
                         ciph.key_len(),
                         1,
-                    )?;
-                    decrypted = Some(ciph.decrypt(pemblock.contents(), &key, iv)?);
+                    )?);
+                    decrypted = Some(ciph.decrypt(pemblock.contents(), &key, &iv)?);
                 } else {
                     return Err(ErrorKind::IncorrectPass.into());
                 };
@@ -131,14 +265,25 @@ fn pem_decrypt(pemblock: &PemBlock, passphrase: Option<&[u8]>) -> OsshResult<Vec
         }
         return Err(ErrorKind::InvalidPemFormat.into());
     }
-    return Ok(pemblock.contents().to_vec());
+    Ok(pemblock.contents().to_vec())
+}
+
+#[cfg(any(feature = "experimental", feature = "legacy-des"))]
+fn decode_hex(s: &str) -> OsshResult<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return Err(ErrorKind::InvalidPemFormat.into());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| ErrorKind::InvalidPemFormat.into())
+        })
+        .collect()
 }
 
 /// Self experimental implementation for OpenSSL kdf
 ///
 /// From OpenSSL EVP_BytesToKey()
-#[cfg(feature = "experimental")]
-#[allow(dead_code)]
 fn openssl_kdf(
     data: &[u8],
     salt: &[u8; 8],
@@ -180,3 +325,62 @@ fn openssl_kdf(
     dig.zeroize();
     Ok(key)
 }
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    feature = "aes-cbc",
+    feature = "3des-cbc",
+    not(feature = "fips")
+))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pem_encrypt_traditional() {
+        let ciphers = [
+            Cipher::TDes_Cbc,
+            Cipher::Aes128_Cbc,
+            Cipher::Aes192_Cbc,
+            Cipher::Aes256_Cbc,
+        ];
+        for keytype in [KeyType::RSA, KeyType::DSA, KeyType::ECDSA] {
+            let keypair = KeyPair::generate(keytype, 0).unwrap();
+            let (tag, der) = match &keypair.key {
+                #[cfg(feature = "rsa")]
+                KeyPairType::RSA(key) => ("RSA PRIVATE KEY", key.ossl_rsa().private_key_to_der()),
+                #[cfg(feature = "dsa")]
+                KeyPairType::DSA(key) => ("DSA PRIVATE KEY", key.ossl_dsa().private_key_to_der()),
+                #[cfg(feature = "ecdsa")]
+                KeyPairType::ECDSA(key) => ("EC PRIVATE KEY", key.ossl_ec().private_key_to_der()),
+                #[cfg(feature = "ed25519")]
+                KeyPairType::ED25519(_) => unreachable!(),
+            };
+            let der = der.unwrap();
+            for cipher in ciphers {
+                let pem = pem_encrypt(tag, &der, b"12345678", cipher, &mut OsRng).unwrap();
+                assert!(pem.starts_with(&format!("-----BEGIN {}-----\n", tag)));
+                assert!(pem.lines().all(|l| l.len() <= 64));
+                // Decrypted by OpenSSL
+                let pkey =
+                    PKey::private_key_from_pem_passphrase(pem.as_bytes(), b"12345678").unwrap();
+                let parsed = KeyPair::from_ossl_pkey(&pkey).unwrap();
+                assert!(parsed.clone_public_key().unwrap() == keypair.clone_public_key().unwrap());
+                assert!(
+                    PKey::private_key_from_pem_passphrase(pem.as_bytes(), b"87654321").is_err()
+                );
+            }
+        }
+        assert!(pem_encrypt(
+            "RSA PRIVATE KEY",
+            &[0; 16],
+            b"12345678",
+            Cipher::Aes128_Ctr,
+            &mut OsRng
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::*;
+use crate::format::base64;
+use crate::keys::{KeyPair, KeyPairType, PublicParts};
+use crate::sshbuf::{SshReader, SshWriteExt};
+use hmac::{Hmac, Mac};
+use openssl::symm::{Cipher, Crypter, Mode};
+use sha1::{Digest, Sha1};
+use std::fmt;
+use zeroize::Zeroizing;
+
+const PPK_V2_HEADER: &str = "PuTTY-User-Key-File-2";
+const PPK_MAC_KEY: &[u8] = b"putty-private-key-file-mac-key";
+const PPK_CIPHER: &str = "aes256-cbc";
+const PPK_CIPHER_BLOCK: usize = 16;
+const PPK_LINE_LEN: usize = 64;
+
+/// Serialize the key pair in the PuTTY private key format version 2
+///
+/// The key is encrypted with AES-256-CBC if the passphrase is given and not empty.
+/// The version 2 format is chosen since it can be read by all the PuTTY releases.
+pub fn serialize_ppk(keypair: &KeyPair, passphrase: Option<&str>) -> OsshResult<String> {
+    let mut ppk = String::new();
+    write_ppk(&mut ppk, keypair, passphrase)?;
+    Ok(ppk)
+}
+
+/// Write the key pair in the PuTTY private key format version 2 into the sink
+///
+/// The sink receives the plaintext private key if the passphrase is not given, so wipe it after use in that case.
+pub fn write_ppk<W: fmt::Write + ?Sized>(
+    ppk: &mut W,
+    keypair: &KeyPair,
+    passphrase: Option<&str>,
+) -> OsshResult<()> {
+    let passphrase = passphrase.filter(|p| !p.is_empty());
+    let pubkey = keypair.blob()?;
+    let keyname = SshReader::new(&pubkey, "publickey").read_utf8("keytype")?;
+    let encryption = if passphrase.is_some() {
+        PPK_CIPHER
+    } else {
+        "none"
+    };
+
+    let mut privkey = Zeroizing::new(Vec::new());
+    encode_ppk_private(keypair, &mut privkey)?;
+    if passphrase.is_some() {
+        // Padded with the SHA-1 digest of the unpadded data, like PuTTY does
+        let padding = (PPK_CIPHER_BLOCK - privkey.len() % PPK_CIPHER_BLOCK) % PPK_CIPHER_BLOCK;
+        let digest = Sha1::digest(&*privkey);
+        privkey.extend_from_slice(&digest[..padding]);
+    }
+
+    let mut macdata = Zeroizing::new(Vec::new());
+    macdata.write_utf8(keyname)?;
+    macdata.write_utf8(encryption)?;
+    macdata.write_utf8(keypair.comment())?;
+    macdata.write_string(&pubkey)?;
+    macdata.write_string(&privkey)?;
+    let mut mackey = Sha1::new();
+    mackey.update(PPK_MAC_KEY);
+    mackey.update(passphrase.unwrap_or_default());
+    let mut mac = Hmac::<Sha1>::new_from_slice(&mackey.finalize())
+        .map_err(|e| Error::with_error(ErrorKind::InvalidLength, e))?;
+    mac.update(&macdata);
+    let mac = mac.finalize().into_bytes();
+
+    if let Some(passphrase) = passphrase {
+        let mut key = Zeroizing::new(Vec::with_capacity(40));
+        for i in 0..2u32 {
+            let mut hasher = Sha1::new();
+            hasher.update(i.to_be_bytes());
+            hasher.update(passphrase);
+            key.extend_from_slice(&hasher.finalize());
+        }
+        let mut crypter = Crypter::new(
+            Cipher::aes_256_cbc(),
+            Mode::Encrypt,
+            &key[..32],
+            Some(&[0u8; 16]),
+        )?;
+        crypter.pad(false);
+        let mut encrypted = Zeroizing::new(vec![0u8; privkey.len() + PPK_CIPHER_BLOCK]);
+        let mut n = crypter.update(&privkey, &mut encrypted)?;
+        n += crypter.finalize(&mut encrypted[n..])?;
+        encrypted.truncate(n);
+        privkey = encrypted;
+    }
+
+    writeln!(ppk, "{}: {}", PPK_V2_HEADER, keyname)?;
+    writeln!(ppk, "Encryption: {}", encryption)?;
+    writeln!(ppk, "Comment: {}", keypair.comment())?;
+    write_lines(ppk, "Public-Lines", &pubkey)?;
+    write_lines(ppk, "Private-Lines", &privkey)?;
+    write!(ppk, "Private-MAC: ")?;
+    for b in mac {
+        write!(ppk, "{:02x}", b)?;
+    }
+    writeln!(ppk)?;
+    Ok(())
+}
+
+fn write_lines<W: fmt::Write + ?Sized>(ppk: &mut W, name: &str, data: &[u8]) -> OsshResult<()> {
+    let encoded_len = (data.len() + 2) / 3 * 4;
+    writeln!(
+        ppk,
+        "{}: {}",
+        name,
+        (encoded_len + PPK_LINE_LEN - 1) / PPK_LINE_LEN
+    )?;
+    if !data.is_empty() {
+        base64::write_encoded(ppk, data, PPK_LINE_LEN)?;
+        writeln!(ppk)?;
+    }
+    Ok(())
+}
+
+fn encode_ppk_private(keypair: &KeyPair, buf: &mut Vec<u8>) -> OsshResult<()> {
+    match &keypair.key {
+        #[cfg(feature = "rsa")]
+        KeyPairType::RSA(rsa) => {
+            let inner = rsa.ossl_rsa();
+            buf.write_mpint(inner.d())?;
+            buf.write_mpint(inner.p().ok_or(ErrorKind::InvalidKey)?)?;
+            buf.write_mpint(inner.q().ok_or(ErrorKind::InvalidKey)?)?;
+            buf.write_mpint(inner.iqmp().ok_or(ErrorKind::InvalidKey)?)?;
+        }
+        #[cfg(feature = "dsa")]
+        KeyPairType::DSA(dsa) => {
+            buf.write_mpint(dsa.ossl_dsa().priv_key())?;
+        }
+        #[cfg(feature = "ecdsa")]
+        KeyPairType::ECDSA(ecdsa) => {
+            buf.write_mpint(ecdsa.ossl_ec().private_key())?;
+        }
+        #[cfg(feature = "ed25519")]
+        KeyPairType::ED25519(ed25519) => {
+            // PuTTY reads the seed as a little endian integer
+            let seed = Zeroizing::new(ed25519.key.to_bytes());
+            buf.write_string(&*seed)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "ed25519", not(feature = "fips")))]
+mod test {
+    use super::*;
+
+    const ED25519_KEY: &str = include_str!("../../assets/openssh_ed25519_enc");
+
+    #[test]
+    fn ppk_ed25519() {
+        let mut keypair = KeyPair::from_keystr(ED25519_KEY, Some("12345678")).unwrap();
+        *keypair.comment_mut() = "ppk-test".to_owned();
+        let ppk = serialize_ppk(&keypair, None).unwrap();
+        let lines: Vec<&str> = ppk.lines().collect();
+        assert_eq!(lines[0], "PuTTY-User-Key-File-2: ssh-ed25519");
+        assert_eq!(lines[1], "Encryption: none");
+        assert_eq!(lines[2], "Comment: ppk-test");
+        assert_eq!(lines[3], "Public-Lines: 2");
+        assert_eq!(
+            base64::decode(format!("{}{}", lines[4], lines[5])).unwrap(),
+            keypair.blob().unwrap()
+        );
+        assert_eq!(lines[6], "Private-Lines: 1");
+        assert!(lines[8].starts_with("Private-MAC: "));
+        assert_eq!(lines[8].len(), "Private-MAC: ".len() + 40);
+
+        let encrypted = serialize_ppk(&keypair, Some("87654321")).unwrap();
+        let lines: Vec<&str> = encrypted.lines().collect();
+        assert_eq!(lines[1], "Encryption: aes256-cbc");
+        assert_eq!(
+            base64::decode(lines[7]).unwrap().len() % PPK_CIPHER_BLOCK,
+            0
+        );
+        assert_ne!(lines[7], ppk.lines().nth(7).unwrap());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::error::*;
+use crate::keys::{KeyPair, PublicKey};
+use std::sync::{Arc, RwLock};
+
+/// The handler parsing the private keys of a format unknown to this crate
+///
+/// It receives the same key data and passphrase as [`parse_keystr()`](super::parse_keystr).
+pub type FormatHandlerFn = dyn Fn(&[u8], Option<&str>) -> OsshResult<KeyPair> + Send + Sync;
+
+static FORMAT_HANDLERS: RwLock<Vec<(FormatMatcher, Arc<FormatHandlerFn>)>> =
+    RwLock::new(Vec::new());
+static KEY_ENCODERS: RwLock<Vec<(String, Arc<dyn KeyEncoder>)>> = RwLock::new(Vec::new());
+
+/// The key data handled by a registered format handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FormatMatcher {
+    /// The PEM block with the tag, like `APPLIANCE PRIVATE KEY`
+    PemTag(String),
+    /// The data which is not PEM and starts with the magic bytes
+    Magic(Vec<u8>),
+}
+
+impl FormatMatcher {
+    fn matches(&self, data: &[u8], pem_tag: Option<&str>) -> bool {
+        match (self, pem_tag) {
+            (FormatMatcher::PemTag(tag), Some(pem_tag)) => tag == pem_tag,
+            (FormatMatcher::Magic(magic), None) => data.starts_with(magic),
+            _ => false,
+        }
+    }
+}
+
+/// Register the process-wide handler of a private key format for [`parse_keystr()`](super::parse_keystr)
+///
+/// The formats of this crate are always parsed by themselves, so the handlers are only called
+/// for the PEM blocks with an unknown tag and for the data which is not PEM.
+/// The handlers are tried in the order of registration, and registering the same matcher again replaces its handler.
+///
+/// ```rust
+/// # #[cfg(feature = "ed25519")] {
+/// use osshkeys::format::{parse_keystr, register_format_handler, FormatMatcher};
+/// use osshkeys::KeyPair;
+///
+/// // The appliance stores the OpenSSH private keys with its own PEM tag
+/// register_format_handler(
+///     FormatMatcher::PemTag("APPLIANCE PRIVATE KEY".to_string()),
+///     |data, passphrase| {
+///         let keystr = String::from_utf8_lossy(data).replace("APPLIANCE", "OPENSSH");
+///         KeyPair::from_keystr(&keystr, passphrase)
+///     },
+/// );
+/// # }
+/// ```
+pub fn register_format_handler<F>(matcher: FormatMatcher, handler: F)
+where
+    F: Fn(&[u8], Option<&str>) -> OsshResult<KeyPair> + Send + Sync + 'static,
+{
+    let mut handlers = FORMAT_HANDLERS.write().unwrap();
+    let handler: Arc<FormatHandlerFn> = Arc::new(handler);
+    match handlers.iter_mut().find(|(m, _)| *m == matcher) {
+        Some(entry) => entry.1 = handler,
+        None => handlers.push((matcher, handler)),
+    }
+}
+
+/// Remove the handler registered by [`register_format_handler()`], returning false if there is none
+pub fn unregister_format_handler(matcher: &FormatMatcher) -> bool {
+    let mut handlers = FORMAT_HANDLERS.write().unwrap();
+    let len = handlers.len();
+    handlers.retain(|(m, _)| m != matcher);
+    handlers.len() != len
+}
+
+/// Find the registered handler of the data, `pem_tag` is `None` if the data is not PEM
+pub(crate) fn find_format_handler(
+    data: &[u8],
+    pem_tag: Option<&str>,
+) -> Option<Arc<FormatHandlerFn>> {
+    // Release the lock before calling the handler, which may register the handlers
+    FORMAT_HANDLERS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(matcher, _)| matcher.matches(data, pem_tag))
+        .map(|(_, handler)| Arc::clone(handler))
+}
+
+/// The encoder of an output format unknown to this crate, see [`register_key_encoder()`]
+///
+/// The private key formats implement [`KeyEncoder::encode_keypair()`], and the public key formats implement
+/// [`KeyEncoder::encode_public()`], which also encodes the public part of the key pairs by default.
+pub trait KeyEncoder: Send + Sync {
+    /// Indicate if the format contains the private key, the public keys can't be converted to it
+    fn is_private(&self) -> bool;
+
+    /// Encode the key pair, the passphrase is `None` if the output shouldn't be encrypted
+    fn encode_keypair(&self, keypair: &KeyPair, passphrase: Option<&str>) -> OsshResult<Vec<u8>> {
+        let _ = passphrase;
+        self.encode_public(&keypair.clone_public_key()?)
+    }
+
+    /// Encode the public key, fail with [`ErrorKind::InvalidArgument`] by default
+    fn encode_public(&self, pubkey: &PublicKey) -> OsshResult<Vec<u8>> {
+        let _ = pubkey;
+        Err(ErrorKind::InvalidArgument.into())
+    }
+}
+
+/// Register the process-wide encoder of an output format for [`convert_key_by_name()`](super::convert_key_by_name)
+///
+/// The names of the formats of this crate always select their own encoders.
+/// Registering the same name again replaces its encoder.
+pub fn register_key_encoder<E: KeyEncoder + 'static>(name: &str, encoder: E) {
+    let mut encoders = KEY_ENCODERS.write().unwrap();
+    let encoder: Arc<dyn KeyEncoder> = Arc::new(encoder);
+    match encoders.iter_mut().find(|(n, _)| n == name) {
+        Some(entry) => entry.1 = encoder,
+        None => encoders.push((name.to_owned(), encoder)),
+    }
+}
+
+/// Remove the encoder registered by [`register_key_encoder()`], returning false if there is none
+pub fn unregister_key_encoder(name: &str) -> bool {
+    let mut encoders = KEY_ENCODERS.write().unwrap();
+    let len = encoders.len();
+    encoders.retain(|(n, _)| n != name);
+    encoders.len() != len
+}
+
+/// Find the registered encoder of the format name
+pub(crate) fn find_key_encoder(name: &str) -> Option<Arc<dyn KeyEncoder>> {
+    KEY_ENCODERS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, encoder)| Arc::clone(encoder))
+}
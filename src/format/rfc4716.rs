@@ -0,0 +1,206 @@
+use crate::error::*;
+use crate::format::base64;
+use crate::format::ossh_pubkey::decode_ossh_pubkey;
+use crate::keys::{PublicKey, PublicParts};
+use std::fmt;
+
+pub(crate) const RFC4716_BEGIN: &str = "---- BEGIN SSH2 PUBLIC KEY ----";
+const RFC4716_END: &str = "---- END SSH2 PUBLIC KEY ----";
+const RFC4716_LINE_LEN: usize = 70;
+const RFC4716_MAX_TAG_LEN: usize = 64;
+const RFC4716_MAX_VALUE_LEN: usize = 1024;
+
+/// Parse the public key in the SSH2 public key file format (RFC 4716)
+///
+/// The `Comment` header becomes the key comment, the other headers are ignored.
+pub fn parse_rfc4716_pubkey(keystr: &str) -> OsshResult<PublicKey> {
+    let mut lines = keystr.lines().map(str::trim).skip_while(|l| l.is_empty());
+    if lines.next() != Some(RFC4716_BEGIN) {
+        return Err(ErrorKind::InvalidKeyFormat.into());
+    }
+
+    let mut comment = None;
+    let mut body = String::new();
+    let mut ended = false;
+    while let Some(line) = lines.next() {
+        if line == RFC4716_END {
+            ended = true;
+            break;
+        }
+        if !line.contains(':') {
+            body.push_str(line);
+            continue;
+        }
+        if !body.is_empty() {
+            return Err(ErrorKind::InvalidKeyFormat.into());
+        }
+        // Join the header continuation lines which end with a backslash
+        let mut header = line.to_owned();
+        while header.ends_with('\\') {
+            header.pop();
+            header.push_str(lines.next().ok_or(ErrorKind::InvalidKeyFormat)?);
+        }
+        let (tag, value) = header.split_once(':').unwrap();
+        if tag.eq_ignore_ascii_case("Comment") {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            comment = Some(value.to_owned());
+        }
+    }
+    if !ended {
+        return Err(ErrorKind::InvalidKeyFormat.into());
+    }
+
+    let mut pubkey = decode_ossh_pubkey(&base64::decode(body)?)?;
+    if let Some(comment) = comment {
+        *pubkey.comment_mut() = comment;
+    }
+    Ok(pubkey)
+}
+
+/// Serialize the public key in the SSH2 public key file format (RFC 4716)
+pub fn serialize_rfc4716_pubkey(key: &PublicKey) -> OsshResult<String> {
+    serialize_rfc4716_pubkey_with_headers(key, &[])
+}
+
+/// Serialize the public key in the SSH2 public key file format (RFC 4716) with the additional headers
+///
+/// The headers, such as `Subject` or `x-...` private headers, are written in order after the `Comment` header.
+/// The `Comment` header is written from the key comment, so it cannot be given in the headers.
+/// The header tags are limited to 64 bytes of printable ASCII without colons,
+/// and the values are limited to 1024 bytes.
+pub fn serialize_rfc4716_pubkey_with_headers(
+    key: &PublicKey,
+    headers: &[(&str, &str)],
+) -> OsshResult<String> {
+    let mut keystr = String::new();
+    write_rfc4716_pubkey_with_headers(&mut keystr, key, headers)?;
+    Ok(keystr)
+}
+
+/// Write the public key in the SSH2 public key file format (RFC 4716) with the additional headers into the sink
+///
+/// See [`serialize_rfc4716_pubkey_with_headers()`] for the restrictions of the headers.
+pub fn write_rfc4716_pubkey_with_headers<W: fmt::Write + ?Sized>(
+    out: &mut W,
+    key: &PublicKey,
+    headers: &[(&str, &str)],
+) -> OsshResult<()> {
+    out.write_str(RFC4716_BEGIN)?;
+    out.write_char('\n')?;
+    if !key.comment().is_empty() {
+        write_header(out, "Comment", &format!("\"{}\"", key.comment()))?;
+    }
+    for (tag, value) in headers {
+        if tag.eq_ignore_ascii_case("Comment")
+            || tag.is_empty()
+            || tag.len() > RFC4716_MAX_TAG_LEN
+            || !tag.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+        {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        write_header(out, tag, value)?;
+    }
+    base64::write_encoded(out, &key.blob()?, RFC4716_LINE_LEN)?;
+    out.write_char('\n')?;
+    out.write_str(RFC4716_END)?;
+    out.write_char('\n')?;
+    Ok(())
+}
+
+fn write_header<W: fmt::Write + ?Sized>(out: &mut W, tag: &str, value: &str) -> OsshResult<()> {
+    if value.len() > RFC4716_MAX_VALUE_LEN || value.contains(['\r', '\n']) {
+        return Err(ErrorKind::InvalidArgument.into());
+    }
+    let header = format!("{}: {}", tag, value);
+    // The header lines are limited to 72 bytes including the continuation backslash
+    let mut chars = header.chars().peekable();
+    while chars.peek().is_some() {
+        let mut line = String::new();
+        while line.len() < RFC4716_LINE_LEN - 4 {
+            match chars.next() {
+                Some(c) => line.push(c),
+                None => break,
+            }
+        }
+        out.write_str(&line)?;
+        if chars.peek().is_some() {
+            out.write_char('\\')?;
+        }
+        out.write_char('\n')?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "ed25519"))]
+mod test {
+    use super::*;
+
+    const ED25519_PUB: &str = include_str!("../../assets/openssh_ed25519.pub");
+
+    #[test]
+    fn rfc4716_roundtrip() {
+        let mut pubkey = PublicKey::from_keystr(ED25519_PUB).unwrap();
+        *pubkey.comment_mut() =
+            "a comment which is long enough to be split into the continuation lines".to_owned();
+        let keystr = serialize_rfc4716_pubkey(&pubkey).unwrap();
+        assert!(keystr.starts_with(RFC4716_BEGIN));
+        assert!(keystr.lines().all(|l| l.len() <= 72));
+        assert!(keystr.lines().nth(1).unwrap().ends_with('\\'));
+
+        let parsed = parse_rfc4716_pubkey(&keystr).unwrap();
+        assert_eq!(parsed.comment(), pubkey.comment());
+        assert_eq!(parsed.blob().unwrap(), pubkey.blob().unwrap());
+        assert!(parse_rfc4716_pubkey(&keystr.replace(RFC4716_END, "")).is_err());
+    }
+
+    #[test]
+    fn rfc4716_headers() {
+        let mut pubkey = PublicKey::from_keystr(ED25519_PUB).unwrap();
+        pubkey.set_comment("user@host");
+        let keystr = serialize_rfc4716_pubkey_with_headers(
+            &pubkey,
+            &[("Subject", "user"), ("x-command", "ssh-keygen -e")],
+        )
+        .unwrap();
+        let mut lines = keystr.lines().skip(1);
+        assert_eq!(lines.next(), Some("Comment: \"user@host\""));
+        assert_eq!(lines.next(), Some("Subject: user"));
+        assert_eq!(lines.next(), Some("x-command: ssh-keygen -e"));
+
+        let parsed = parse_rfc4716_pubkey(&keystr).unwrap();
+        assert_eq!(parsed.comment(), "user@host");
+        assert_eq!(parsed.blob().unwrap(), pubkey.blob().unwrap());
+
+        for headers in [
+            [("Comment", "other")],
+            [("Bad Tag", "value")],
+            [("Subject", "line\nbreak")],
+        ] {
+            assert!(serialize_rfc4716_pubkey_with_headers(&pubkey, &headers).is_err());
+        }
+    }
+
+    #[test]
+    fn rfc4716_ssh_keygen() {
+        // Exported by `ssh-keygen -e -f assets/openssh_ed25519.pub`
+        let keystr = concat!(
+            "---- BEGIN SSH2 PUBLIC KEY ----\n",
+            "Comment: \"256-bit ED25519, converted by root@vm from OpenSSH\"\n",
+            "AAAAC3NzaC1lZDI1NTE5AAAAIMoWBluPErgKhNja3lHEf7ie6AVzR24mPRd742xEYodC\n",
+            "---- END SSH2 PUBLIC KEY ----\n",
+        );
+        let pubkey = PublicKey::from_keystr(keystr).unwrap();
+        assert_eq!(
+            pubkey.comment(),
+            "256-bit ED25519, converted by root@vm from OpenSSH"
+        );
+        assert_eq!(
+            pubkey.blob().unwrap(),
+            PublicKey::from_keystr(ED25519_PUB).unwrap().blob().unwrap()
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use crate::error::*;
+#[cfg(any(feature = "rsa", feature = "ecdsa", feature = "ed25519"))]
+use crate::keys::PublicKeyType;
+use crate::keys::{Fingerprint, FingerprintHash, Key, PublicKey};
+#[cfg(feature = "ecdsa")]
+use openssl::bn::{BigNum, BigNumContext};
+#[cfg(feature = "ecdsa")]
+use openssl::ec::PointConversionForm;
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::str::FromStr;
+
+/// The length of the keygrips, which are SHA-1 digests
+pub const KEYGRIP_LEN: usize = 20;
+
+// The parameters of Ed25519 in the form hashed by libgcrypt,
+// where `a` is `-1` and `b` is `-d` but only their magnitudes are hashed
+#[cfg(feature = "ed25519")]
+const ED25519_P: &[u8] = &[
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xed,
+];
+#[cfg(feature = "ed25519")]
+const ED25519_A: &[u8] = &[0x01];
+#[cfg(feature = "ed25519")]
+const ED25519_B: &[u8] = &[
+    0x2d, 0xfc, 0x93, 0x11, 0xd4, 0x90, 0x01, 0x8c, 0x73, 0x38, 0xbf, 0x86, 0x88, 0x86, 0x17, 0x67,
+    0xff, 0x8f, 0xf5, 0xb2, 0xbe, 0xbe, 0x27, 0x54, 0x8a, 0x14, 0xb2, 0x35, 0xec, 0xa6, 0x87, 0x4a,
+];
+#[cfg(feature = "ed25519")]
+const ED25519_G: &[u8] = &[
+    0x04, 0x21, 0x69, 0x36, 0xd3, 0xcd, 0x6e, 0x53, 0xfe, 0xc0, 0xa4, 0xe2, 0x31, 0xfd, 0xd6, 0xdc,
+    0x5c, 0x69, 0x2c, 0xc7, 0x60, 0x95, 0x25, 0xa7, 0xb2, 0xc9, 0x56, 0x2d, 0x60, 0x8f, 0x25, 0xd5,
+    0x1a, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x58,
+];
+#[cfg(feature = "ed25519")]
+const ED25519_N: &[u8] = &[
+    0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x14, 0xde, 0xf9, 0xde, 0xa2, 0xf7, 0x9c, 0xd6, 0x58, 0x12, 0x63, 0x1a, 0x5c, 0xf5, 0xd3, 0xed,
+];
+
+/// Hash the curve parameters and the public point like libgcrypt, skipping the cofactor
+#[cfg(any(feature = "ecdsa", feature = "ed25519"))]
+fn hash_curve(hasher: &mut Sha1, params: [(char, &[u8]); 6]) {
+    for (name, value) in params {
+        hasher.update(format!("(1:{}{}:", name, value.len()));
+        hasher.update(value);
+        hasher.update(")");
+    }
+}
+
+/// Compute the keygrip of the key, which gpg-agent uses to name the keys instead of the fingerprints
+///
+/// The keygrip is used in the sshcontrol file and as the file name in the `private-keys-v1.d` directory.
+/// Only the RSA, EcDSA and Ed25519 keys are supported, and the other keys fail with [`ErrorKind::UnsupportType`].
+#[cfg_attr(
+    not(any(feature = "rsa", feature = "ecdsa", feature = "ed25519")),
+    allow(unused_mut, unused_variables, unreachable_code)
+)]
+pub fn keygrip(key: &PublicKey) -> OsshResult<[u8; KEYGRIP_LEN]> {
+    let mut hasher = Sha1::new();
+    match &key.key {
+        #[cfg(feature = "rsa")]
+        PublicKeyType::RSA(key) => {
+            // The modulus is hashed in the signed form, with the leading zero if the high bit is set
+            let n = key.ossl_rsa().n().to_vec();
+            if n.first().map_or(false, |&b| b & 0x80 != 0) {
+                hasher.update([0]);
+            }
+            hasher.update(&n);
+        }
+        #[cfg(feature = "ecdsa")]
+        PublicKeyType::ECDSA(key) => {
+            // The parameters are hashed as the unsigned integers without the leading zeros,
+            // and the points in the uncompressed form
+            let ec = key.ossl_ec();
+            let group = ec.group();
+            let mut ctx = BigNumContext::new()?;
+            let mut p = BigNum::new()?;
+            let mut a = BigNum::new()?;
+            let mut b = BigNum::new()?;
+            let mut n = BigNum::new()?;
+            group.components_gfp(&mut p, &mut a, &mut b, &mut ctx)?;
+            group.order(&mut n, &mut ctx)?;
+            let form = PointConversionForm::UNCOMPRESSED;
+            let g = group
+                .generator_opt()
+                .ok_or(ErrorKind::InvalidKey)?
+                .to_bytes(group, form, &mut ctx)?;
+            let q = ec.public_key().to_bytes(group, form, &mut ctx)?;
+            hash_curve(
+                &mut hasher,
+                [
+                    ('p', &p.to_vec()),
+                    ('a', &a.to_vec()),
+                    ('b', &b.to_vec()),
+                    ('g', &g),
+                    ('n', &n.to_vec()),
+                    ('q', &q),
+                ],
+            );
+        }
+        #[cfg(feature = "ed25519")]
+        PublicKeyType::ED25519(key) => hash_curve(
+            &mut hasher,
+            [
+                ('p', ED25519_P),
+                ('a', ED25519_A),
+                ('b', ED25519_B),
+                ('g', ED25519_G),
+                ('n', ED25519_N),
+                ('q', &key.as_bytes()[..]),
+            ],
+        ),
+        #[allow(unreachable_patterns)]
+        _ => return Err(ErrorKind::UnsupportType(key.keyname().to_owned()).into()),
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// A key listed in the sshcontrol file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshControlEntry {
+    /// The keygrip of the key
+    pub keygrip: [u8; KEYGRIP_LEN],
+    /// The caching time of the passphrase in seconds, `0` for the default of gpg-agent
+    pub ttl: u32,
+    /// Ask for the confirmation before each use of the key
+    pub confirm: bool,
+    /// The line is prefixed with `!`, so gpg-agent doesn't offer the key
+    pub disabled: bool,
+}
+
+impl SshControlEntry {
+    fn parse(line: &str) -> OsshResult<Self> {
+        let (disabled, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line.trim_start()),
+            None => (false, line),
+        };
+        let mut fields = line.split_ascii_whitespace();
+        let hexgrip = fields.next().ok_or(ErrorKind::InvalidFormat)?;
+        if hexgrip.len() != KEYGRIP_LEN * 2 || !hexgrip.is_ascii() {
+            return Err(ErrorKind::InvalidFormat.into());
+        }
+        let mut keygrip = [0u8; KEYGRIP_LEN];
+        for (i, b) in keygrip.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hexgrip[i * 2..i * 2 + 2], 16)
+                .map_err(|_| Error::from_kind(ErrorKind::InvalidFormat))?;
+        }
+        let ttl = match fields.next() {
+            Some(ttl) => ttl
+                .parse()
+                .map_err(|_| Error::from_kind(ErrorKind::InvalidFormat))?,
+            None => 0,
+        };
+        let mut confirm = false;
+        for flag in fields {
+            match flag {
+                "confirm" => confirm = true,
+                _ => return Err(ErrorKind::InvalidFormat.into()),
+            }
+        }
+        Ok(SshControlEntry {
+            keygrip,
+            ttl,
+            confirm,
+            disabled,
+        })
+    }
+}
+
+impl fmt::Display for SshControlEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.disabled {
+            f.write_str("!")?;
+        }
+        for b in &self.keygrip {
+            write!(f, "{:02X}", b)?;
+        }
+        write!(f, " {}", self.ttl)?;
+        if self.confirm {
+            f.write_str(" confirm")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    /// A comment, an empty line or a line which isn't understood, kept as is
+    Other(String),
+    Entry(SshControlEntry),
+}
+
+/// The sshcontrol file of gpg-agent, which lists the keys offered by its ssh-agent emulation
+///
+/// gpg-agent appends a key to this file when it receives the key from `ssh-add`,
+/// and only the keys listed here without `!` are offered to ssh.
+/// The comments and the order of the lines are kept, so the file can be edited in place.
+/// The lines which can't be parsed are skipped like gpg-agent does, but also kept when writing the file back.
+#[derive(Debug, Clone, Default)]
+pub struct SshControl {
+    lines: Vec<Line>,
+}
+
+impl SshControl {
+    /// Parse the content of the sshcontrol file
+    pub fn parse(s: &str) -> OsshResult<Self> {
+        let lines = s
+            .lines()
+            .map(|line| match SshControlEntry::parse(line.trim()) {
+                Ok(entry) => Line::Entry(entry),
+                Err(_) => Line::Other(line.to_owned()),
+            })
+            .collect();
+        Ok(SshControl { lines })
+    }
+
+    /// Iterate over the listed keys, including the disabled ones
+    pub fn entries(&self) -> impl Iterator<Item = &SshControlEntry> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(entry) => Some(entry),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// Find the entry of the key
+    pub fn find(&self, key: &PublicKey) -> OsshResult<Option<&SshControlEntry>> {
+        let grip = keygrip(key)?;
+        Ok(self.entries().find(|entry| entry.keygrip == grip))
+    }
+
+    /// Add the key like gpg-agent does when it receives the key from `ssh-add`
+    ///
+    /// The entry is appended after the comment lines of the key type and its fingerprints.
+    /// If the key is already listed, even disabled, nothing is changed and `false` is returned.
+    /// The private key itself still has to be imported into gpg-agent, such as by `ssh-add`.
+    pub fn add_identity(&mut self, key: &PublicKey, ttl: u32, confirm: bool) -> OsshResult<bool> {
+        if self.find(key)?.is_some() {
+            return Ok(false);
+        }
+        let md5 = Fingerprint::of(key, FingerprintHash::MD5)?;
+        let sha256 = Fingerprint::of(key, FingerprintHash::SHA256)?;
+        let mut header = format!("# {} key", key.short_keyname());
+        if !key.comment().is_empty() {
+            header.push_str(": ");
+            header.push_str(key.comment());
+        }
+        self.lines.push(Line::Other(header));
+        self.lines
+            .push(Line::Other(format!("# Fingerprints:  {}", md5)));
+        self.lines
+            .push(Line::Other(format!("#                {}", sha256)));
+        self.lines.push(Line::Entry(SshControlEntry {
+            keygrip: keygrip(key)?,
+            ttl,
+            confirm,
+            disabled: false,
+        }));
+        Ok(true)
+    }
+}
+
+impl FromStr for SshControl {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for SshControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(line) => writeln!(f, "{}", line)?,
+                Line::Entry(entry) => writeln!(f, "{}", entry)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+mod test {
+    use super::*;
+
+    // The keygrips shown by `gpg --with-keygrip` after importing the keys
+    const ED25519_GRIP: &str = "B409D23FEF10AF7C36EE90D2611AA7E0791745A1";
+    const RSA_GRIP: &str = "824054E547F0DF49AB3E91B703D3BD737196DAD8";
+    // The keygrips computed by gcry_pk_get_keygrip() of libgcrypt, which gpg-agent uses for the keys from ssh-add
+    const NISTP256_GRIP: &str = "0766724564C0CC31AA9F83E57EA01548CD8110DC";
+    const NISTP384_PUB: &str = "ecdsa-sha2-nistp384 AAAAE2VjZHNhLXNoYTItbmlzdHAzODQAAAAIbmlzdHAzODQAAABhBDd9he/WNA1x56j7B1/7xf+o9copVkAGNpI7yO9xeZJt6DVY/p+rFaB5nVmDPUeWj26Z6y98PZLBcMO1irhFxKkaI65iyEtTb37ayy5+QNExLukyyAUAhTcSGrP4n4mHpA==";
+    const NISTP384_GRIP: &str = "6CB41B57BDCCBFDACD767AC750FDC5B7AD353AF9";
+    const NISTP521_PUB: &str = "ecdsa-sha2-nistp521 AAAAE2VjZHNhLXNoYTItbmlzdHA1MjEAAAAIbmlzdHA1MjEAAACFBAC8NaboACxCRnY3Uz1ODxjUG5z5t1LMYcsQOt0yRO1r2XaixnrMKtdSKAIqUDiguH+9Plp+DqiIe48wNhXJugZhEAATh+WPGRa82zAg16sCkOGds6FdVo1xVZWAVrlRxlT4/JQWrz1th+LC6u/PeSjow4jC3UQD7atUPmrKk5i7KlrVAQ==";
+    const NISTP521_GRIP: &str = "0230B843EF5D0D03C0ED917C8F6DD5E6A8C952EC";
+
+    fn load(name: &str) -> PublicKey {
+        let path = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name);
+        PublicKey::from_keystr(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn gpg_keygrip() {
+        let ed25519 = load("openpgp_ed25519_enc.pub");
+        let rsa = load("openpgp_rsa_enc.pub");
+        let grip = SshControlEntry::parse(ED25519_GRIP).unwrap().keygrip;
+        assert_eq!(keygrip(&ed25519).unwrap(), grip);
+        let grip = SshControlEntry::parse(RSA_GRIP).unwrap().keygrip;
+        assert_eq!(keygrip(&rsa).unwrap(), grip);
+        for (key, grip) in [
+            (load("openssh_ecdsa.pub"), NISTP256_GRIP),
+            (PublicKey::from_keystr(NISTP384_PUB).unwrap(), NISTP384_GRIP),
+            (PublicKey::from_keystr(NISTP521_PUB).unwrap(), NISTP521_GRIP),
+        ] {
+            let grip = SshControlEntry::parse(grip).unwrap().keygrip;
+            assert_eq!(keygrip(&key).unwrap(), grip);
+        }
+        assert!(keygrip(&load("openssh_dsa.pub")).is_err());
+    }
+
+    #[test]
+    fn sshcontrol_add_identity() {
+        let content = format!(
+            "# List of allowed ssh keys.\n\n!{} 600\n",
+            ED25519_GRIP.to_ascii_lowercase()
+        );
+        let mut control = SshControl::parse(&content).unwrap();
+        let ed25519 = load("openpgp_ed25519_enc.pub");
+        let rsa = load("openpgp_rsa_enc.pub");
+        let entry = control.find(&ed25519).unwrap().unwrap();
+        assert!(entry.disabled);
+        assert_eq!(entry.ttl, 600);
+        assert!(control.find(&rsa).unwrap().is_none());
+
+        assert!(!control.add_identity(&ed25519, 0, false).unwrap());
+        assert!(control.add_identity(&rsa, 0, true).unwrap());
+        let entry = control.find(&rsa).unwrap().unwrap();
+        assert!(entry.confirm && !entry.disabled);
+        let expected = format!(
+            "# List of allowed ssh keys.\n\n!{} 600\n\
+             # RSA key: openpgp:0xCDAEC245\n\
+             # Fingerprints:  {}\n\
+             #                {}\n\
+             {} 0 confirm\n",
+            ED25519_GRIP,
+            Fingerprint::of(&rsa, FingerprintHash::MD5).unwrap(),
+            Fingerprint::of(&rsa, FingerprintHash::SHA256).unwrap(),
+            RSA_GRIP
+        );
+        assert_eq!(control.to_string(), expected);
+        assert_eq!(SshControl::parse(&expected).unwrap().entries().count(), 2);
+
+        for line in [
+            "XYZ 0",
+            "B409D23FEF 0",
+            &format!("{} -1", RSA_GRIP),
+            &format!("{} 0 foo", RSA_GRIP),
+        ] {
+            let content = format!("{}\n{} 0\n", line, NISTP256_GRIP);
+            let control = SshControl::parse(&content).unwrap();
+            assert_eq!(control.entries().count(), 1);
+            assert_eq!(control.to_string(), content);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::keys::{Fingerprint, FingerprintHash, KeyPair};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// The callback receiving the usage records of the private keys
+pub type KeyUsageFn = dyn Fn(&KeyUsageRecord) + Send + Sync;
+
+static KEY_USAGE_HOOK: RwLock<Option<Arc<KeyUsageFn>>> = RwLock::new(None);
+
+thread_local! {
+    static CONTEXT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// The operation on a private key reported to the hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyOperation {
+    /// An encrypted private key is decrypted
+    Decrypt,
+    /// A signature is produced
+    Sign,
+}
+
+impl fmt::Display for KeyOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            KeyOperation::Decrypt => "decrypt",
+            KeyOperation::Sign => "sign",
+        })
+    }
+}
+
+/// The usage record of a private key
+///
+/// It's displayed as a single line for the logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyUsageRecord {
+    pub operation: KeyOperation,
+    /// The SHA256 fingerprint of the key
+    pub fingerprint: Fingerprint,
+    /// The context set by [`with_context()`] on the thread of the operation
+    pub context: Option<String>,
+}
+
+impl fmt::Display for KeyUsageRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} with {}", self.operation, self.fingerprint)?;
+        if let Some(context) = &self.context {
+            write!(f, " context \"{}\"", context)?;
+        }
+        Ok(())
+    }
+}
+
+/// Set the process-wide callback receiving the usage record of each private key operation
+///
+/// The hook is called when an encrypted key is decrypted by [`KeyPair::from_keystr()`] and the related functions
+/// or by [`Keystore::open()`](crate::keystore::Keystore::open), and when [`KeyPair`] produces a signature.
+/// It is called on the thread of the operation, so it should return quickly.
+///
+/// ```rust
+/// # #[cfg(feature = "ecdsa")] {
+/// use osshkeys::hooks::{set_key_usage_hook, with_context};
+/// use osshkeys::{KeyPair, KeyType, PrivateParts};
+///
+/// set_key_usage_hook(|record| eprintln!("{}", record));
+/// let keypair = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+/// with_context("deploy job 42", || keypair.sign(b"data")).unwrap();
+/// # }
+/// ```
+pub fn set_key_usage_hook<F: Fn(&KeyUsageRecord) + Send + Sync + 'static>(hook: F) {
+    *KEY_USAGE_HOOK.write().unwrap() = Some(Arc::new(hook));
+}
+
+/// Remove the callback set by [`set_key_usage_hook()`]
+pub fn clear_key_usage_hook() {
+    *KEY_USAGE_HOOK.write().unwrap() = None;
+}
+
+/// Run the closure with the context reported in the usage records of its operations
+///
+/// The context is restored when the closure returns, so the calls can be nested.
+pub fn with_context<R, F: FnOnce() -> R>(context: &str, f: F) -> R {
+    struct Restore(Option<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CONTEXT.with(|c| *c.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(CONTEXT.with(|c| c.borrow_mut().replace(context.to_owned())));
+    f()
+}
+
+/// Report the operation on the key to the hook if it is set
+pub(crate) fn report_key_usage(operation: KeyOperation, keypair: &KeyPair) {
+    // Release the lock before calling the hook, which may set the hook again
+    let hook = match KEY_USAGE_HOOK.read().unwrap().as_ref() {
+        Some(hook) => Arc::clone(hook),
+        None => return,
+    };
+    if let Ok(fingerprint) = Fingerprint::of(keypair, FingerprintHash::SHA256) {
+        hook(&KeyUsageRecord {
+            operation,
+            fingerprint,
+            context: CONTEXT.with(|c| c.borrow().clone()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_context() {
+        assert_eq!(CONTEXT.with(|c| c.borrow().clone()), None);
+        with_context("outer", || {
+            with_context("inner", || {
+                assert_eq!(CONTEXT.with(|c| c.borrow().clone()).unwrap(), "inner");
+            });
+            assert_eq!(CONTEXT.with(|c| c.borrow().clone()).unwrap(), "outer");
+        });
+        assert_eq!(CONTEXT.with(|c| c.borrow().clone()), None);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::cipher::Cipher;
+use crate::error::*;
+use crate::format::base64;
+use crate::keys::cert::unix_time;
+use crate::keys::rsa::RsaSignature;
+use crate::keys::{FingerprintHash, Key, KeyPair, KeyType, PublicKey, PublicParts};
+use crate::sshbuf::{SshReader, SshWriteExt};
+use sha1::{Digest, Sha1};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// The key types generated by [`generate_host_keys()`], the same set as `ssh-keygen -A`
+///
+/// The key types compiled out by the features are skipped.
+pub const HOST_KEY_TYPES: &[KeyType] = &[
+    #[cfg(feature = "rsa")]
+    KeyType::RSA,
+    #[cfg(feature = "ecdsa")]
+    KeyType::ECDSA,
+    #[cfg(feature = "ed25519")]
+    KeyType::ED25519,
+];
+
+/// The global request name which the server announces all its host keys with, used by `UpdateHostKeys`
+pub const HOSTKEYS_REQUEST: &str = "hostkeys-00@openssh.com";
+/// The global request name which the client asks the server to prove the ownership of the host keys with
+pub const HOSTKEYS_PROVE_REQUEST: &str = "hostkeys-prove-00@openssh.com";
+
+/// The suffix of the replacement host key file names generated by [`rotate_host_keys()`]
+const NEXT_SUFFIX: &str = ".next";
+const SECTION_HOSTKEYS: &str = "hostkeys";
+
+/// A generated host key and the file name it should be stored in
+pub struct HostKey {
+    /// The generated key pair
+    pub keypair: KeyPair,
+    /// The file name of the private key, such as `ssh_host_ed25519_key`
+    ///
+    /// The public key is stored in the same name with the `.pub` suffix.
+    pub filename: String,
+}
+
+/// Get the standard file name of the host key of the given type
+pub fn host_key_filename(keytype: KeyType) -> String {
+    let name = match keytype {
+        #[cfg(feature = "rsa")]
+        KeyType::RSA => "rsa",
+        #[cfg(feature = "dsa")]
+        KeyType::DSA => "dsa",
+        #[cfg(feature = "ecdsa")]
+        KeyType::ECDSA => "ecdsa",
+        #[cfg(feature = "ed25519")]
+        KeyType::ED25519 => "ed25519",
+    };
+    format!("ssh_host_{}_key", name)
+}
+
+/// Generate the standard set of host keys with the default key sizes
+///
+/// The comment of each key is set to the given one.
+pub fn generate_host_keys(comment: &str) -> OsshResult<Vec<HostKey>> {
+    HOST_KEY_TYPES
+        .iter()
+        .map(|&keytype| {
+            let mut keypair = KeyPair::generate(keytype, 0)?;
+            *keypair.comment_mut() = comment.to_owned();
+            Ok(HostKey {
+                keypair,
+                filename: host_key_filename(keytype),
+            })
+        })
+        .collect()
+}
+
+/// Generate the missing host keys in the directory, like `ssh-keygen -A` does
+///
+/// The private keys are written in the unencrypted OpenSSH format with the permission `0600`,
+/// and the public keys are written with the permission `0644`.
+/// The key types whose private key file already exists are skipped.
+///
+/// Return the paths of the newly written private keys.
+pub fn write_host_keys<P: AsRef<Path>>(dir: P, comment: &str) -> OsshResult<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let mut written = Vec::new();
+    for &keytype in HOST_KEY_TYPES.iter() {
+        let path = dir.join(host_key_filename(keytype));
+        if path.exists() {
+            continue;
+        }
+        generate_host_key_file(&path, keytype, comment)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+fn generate_host_key_file(path: &Path, keytype: KeyType, comment: &str) -> OsshResult<KeyPair> {
+    let mut keypair = KeyPair::generate(keytype, 0)?;
+    *keypair.comment_mut() = comment.to_owned();
+
+    let privkey = keypair.serialize_openssh(None, Cipher::Null)?;
+    let pubkey = keypair.serialize_publickey()?;
+    write_key_file(path, privkey.as_bytes(), 0o600)?;
+    write_key_file(
+        &pubkey_path(path),
+        format!("{}\n", pubkey).as_bytes(),
+        0o644,
+    )?;
+    Ok(keypair)
+}
+
+fn pubkey_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".pub");
+    path.with_file_name(name)
+}
+
+/// A host key which is being replaced by [`rotate_host_keys()`]
+///
+/// It stays in its path and is still offered by the server, next to its replacement `ssh_host_*_key.next`,
+/// until the clients have learned the new keys.
+pub struct RetiredHostKey {
+    /// The path of the retired private key
+    pub path: PathBuf,
+    /// The Unix time when the key was retired, which is when its replacement was generated
+    pub retired_at: u64,
+    /// The public key of the retired key
+    pub pubkey: PublicKey,
+}
+
+/// The result of [`rotate_host_keys()`]
+pub struct HostKeyRotation {
+    /// The paths of the newly generated private keys
+    pub generated: Vec<PathBuf>,
+    /// The host keys in service after the rotation
+    pub active: Vec<PublicKey>,
+    /// The host keys waiting for their replacements to take over after the grace period
+    pub retired: Vec<RetiredHostKey>,
+}
+
+impl HostKeyRotation {
+    /// Build the known_hosts lines of the active and the retired host keys for the comma-separated host patterns
+    ///
+    /// The lines of the retired keys should be removed once their grace period is over.
+    pub fn known_hosts_lines(&self, hosts: &str) -> OsshResult<Vec<String>> {
+        self.published_keys()
+            .map(|key| {
+                Ok(format!(
+                    "{} {} {}",
+                    hosts,
+                    key.keyname(),
+                    base64::encode(key.to_blob()?)
+                ))
+            })
+            .collect()
+    }
+
+    /// Build the SSHFP resource records of the active and the retired host keys, like `ssh-keygen -r`
+    pub fn sshfp_records(&self, host: &str) -> OsshResult<Vec<String>> {
+        let mut records = Vec::new();
+        for key in self.published_keys() {
+            records.extend(sshfp_records(host, key)?);
+        }
+        Ok(records)
+    }
+
+    fn published_keys(&self) -> impl Iterator<Item = &PublicKey> {
+        self.active
+            .iter()
+            .chain(self.retired.iter().map(|key| &key.pubkey))
+    }
+}
+
+/// Build the SSHFP resource records defined in [RFC 4255](https://tools.ietf.org/html/rfc4255) of the host key
+///
+/// Both the SHA-1 and the SHA-256 records are returned, in the same form as `ssh-keygen -r`.
+pub fn sshfp_records(host: &str, key: &PublicKey) -> OsshResult<Vec<String>> {
+    let algorithm = match key.keytype() {
+        #[cfg(feature = "rsa")]
+        KeyType::RSA => 1,
+        #[cfg(feature = "dsa")]
+        KeyType::DSA => 2,
+        #[cfg(feature = "ecdsa")]
+        KeyType::ECDSA => 3,
+        #[cfg(feature = "ed25519")]
+        KeyType::ED25519 => 4,
+    };
+    let blob = key.to_blob()?;
+    let digests = [
+        (1, Sha1::digest(&blob).to_vec()),
+        (2, key.fingerprint(FingerprintHash::SHA256)?),
+    ];
+    Ok(digests
+        .iter()
+        .map(|(fptype, digest)| {
+            let mut record = format!("{} IN SSHFP {} {} ", host, algorithm, fptype);
+            for b in digest {
+                let _ = write!(record, "{:02x}", b);
+            }
+            record
+        })
+        .collect())
+}
+
+/// Rotate the standard set of host keys in the directory
+///
+/// The replacement keys are generated as `ssh_host_*_key.next` with their public keys, and the existing keys are left in place,
+/// so the server keeps offering them. The server should load both, e.g. by adding the `.next` keys as the `HostKey`s of sshd,
+/// until the grace period is over, then the replacements take over by [`prune_retired_host_keys()`].
+/// The key types without an existing key are generated in their standard paths like [`write_host_keys()`].
+///
+/// Fail with [`io::ErrorKind::AlreadyExists`] if an earlier rotation is still in its grace period.
+pub fn rotate_host_keys<P: AsRef<Path>>(dir: P, comment: &str) -> OsshResult<HostKeyRotation> {
+    let dir = dir.as_ref();
+    let mut generated = Vec::new();
+    let mut active = Vec::new();
+    for &keytype in HOST_KEY_TYPES.iter() {
+        if next_path(&dir.join(host_key_filename(keytype))).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the host keys are still being rotated",
+            )
+            .into());
+        }
+    }
+    for &keytype in HOST_KEY_TYPES.iter() {
+        let mut path = dir.join(host_key_filename(keytype));
+        if path.exists() {
+            path = next_path(&path);
+        }
+        let keypair = generate_host_key_file(&path, keytype, comment)?;
+        active.push(keypair.clone_public_key()?);
+        generated.push(path);
+    }
+    Ok(HostKeyRotation {
+        generated,
+        active,
+        retired: retired_host_keys(dir)?,
+    })
+}
+
+fn next_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(NEXT_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// List the retired host keys in the directory, which have a pending replacement, sorted by the retirement time
+pub fn retired_host_keys<P: AsRef<Path>>(dir: P) -> OsshResult<Vec<RetiredHostKey>> {
+    let dir = dir.as_ref();
+    let mut retired = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let next = entry?.path();
+        let path = match next
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| name.starts_with("ssh_host_"))
+            .and_then(|name| name.strip_suffix(NEXT_SUFFIX))
+        {
+            Some(name) => dir.join(name),
+            None => continue,
+        };
+        if !path.exists() {
+            continue;
+        }
+        let retired_at = fs::metadata(&next)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let keypair = KeyPair::from_keystr(&fs::read_to_string(&path)?, None)?;
+        retired.push(RetiredHostKey {
+            path,
+            retired_at,
+            pubkey: keypair.clone_public_key()?,
+        });
+    }
+    retired.sort_by(|a, b| (a.retired_at, &a.path).cmp(&(b.retired_at, &b.path)));
+    Ok(retired)
+}
+
+/// Replace the retired host keys whose grace period is over with their `.next` keys
+///
+/// Each replacement is moved over the retired key, so the host key of the type is never missing.
+/// Return the paths of the replaced private keys.
+pub fn prune_retired_host_keys<P: AsRef<Path>>(
+    dir: P,
+    grace: Duration,
+) -> OsshResult<Vec<PathBuf>> {
+    prune_retired_host_keys_at(dir, grace, unix_time())
+}
+
+/// Replace the retired host keys like [`prune_retired_host_keys()`],
+/// with the time in seconds since the Unix epoch
+pub fn prune_retired_host_keys_at<P: AsRef<Path>>(
+    dir: P,
+    grace: Duration,
+    time: u64,
+) -> OsshResult<Vec<PathBuf>> {
+    let mut replaced = Vec::new();
+    for key in retired_host_keys(dir)? {
+        if key.retired_at.saturating_add(grace.as_secs()) > time {
+            continue;
+        }
+        let next = next_path(&key.path);
+        fs::rename(&next, &key.path)?;
+        if pubkey_path(&next).exists() {
+            fs::rename(pubkey_path(&next), pubkey_path(&key.path))?;
+        }
+        replaced.push(key.path);
+    }
+    Ok(replaced)
+}
+
+/// Encode the host keys as the payload of the `hostkeys-00@openssh.com` or `hostkeys-prove-00@openssh.com` request
+///
+/// The payload is a sequence of `string hostkey`.
+pub fn encode_hostkeys(hostkeys: &[PublicKey]) -> OsshResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    for key in hostkeys {
+        buf.write_string(&key.to_blob()?)?;
+    }
+    Ok(buf)
+}
+
+/// Decode the host keys from the payload of the `hostkeys-00@openssh.com` or `hostkeys-prove-00@openssh.com` request
+///
+/// The host keys of the unsupported types are skipped, as the clients are required to ignore them.
+pub fn decode_hostkeys(payload: &[u8]) -> OsshResult<Vec<PublicKey>> {
+    let mut reader = SshReader::new(payload, SECTION_HOSTKEYS);
+    let mut hostkeys = Vec::new();
+    while !reader.is_empty() {
+        match PublicKey::from_blob(reader.read_string("hostkey")?) {
+            Ok(key) => hostkeys.push(key),
+            Err(e) if matches!(e.kind(), ErrorKind::UnsupportType(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hostkeys)
+}
+
+/// Build the data signed to prove the ownership of the host key
+///
+/// The data is `string "hostkeys-prove-00@openssh.com", string session identifier, string hostkey`.
+pub fn hostkeys_prove_data(session_id: &[u8], hostkey: &PublicKey) -> OsshResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.write_utf8(HOSTKEYS_PROVE_REQUEST)?;
+    buf.write_string(session_id)?;
+    buf.write_string(&hostkey.to_blob()?)?;
+    Ok(buf)
+}
+
+/// Build the reply of the `hostkeys-prove-00@openssh.com` request on the server side
+///
+/// Each requested host key is signed by the matching key pair, in the order of the request.
+/// The RSA keys are signed with `rsa_signature`, which should follow the negotiated host key algorithm.
+/// Fail with [`ErrorKind::InvalidArgument`] if a requested key is not one of `keypairs`.
+pub fn prove_hostkeys(
+    keypairs: &[KeyPair],
+    session_id: &[u8],
+    request: &[u8],
+    rsa_signature: RsaSignature,
+) -> OsshResult<Vec<u8>> {
+    let mut reader = SshReader::new(request, SECTION_HOSTKEYS);
+    let mut reply = Vec::new();
+    while !reader.is_empty() {
+        let blob = reader.read_string("hostkey")?;
+        let keypair = keypairs
+            .iter()
+            .find(|keypair| keypair.blob().map_or(false, |b| b == blob))
+            .ok_or(ErrorKind::InvalidArgument)?;
+        let data = hostkeys_prove_data(session_id, &keypair.clone_public_key()?)?;
+        reply.write_string(&keypair.sign_ssh_signature_with(&data, Some(rsa_signature))?)?;
+    }
+    Ok(reply)
+}
+
+/// Verify the reply of the `hostkeys-prove-00@openssh.com` request on the client side
+///
+/// `hostkeys` are the keys sent in the request, in the same order.
+/// Return `false` if the number of the signatures doesn't match or any signature is invalid.
+pub fn verify_hostkeys_prove(
+    hostkeys: &[PublicKey],
+    session_id: &[u8],
+    reply: &[u8],
+) -> OsshResult<bool> {
+    let mut reader = SshReader::new(reply, SECTION_HOSTKEYS);
+    for key in hostkeys {
+        if reader.is_empty() {
+            return Ok(false);
+        }
+        let sig = reader.read_string("signature")?;
+        let data = hostkeys_prove_data(session_id, key)?;
+        // The signature of another key type is invalid too
+        if !matches!(key.verify_ssh_signature(&data, sig), Ok(true)) {
+            return Ok(false);
+        }
+    }
+    Ok(reader.is_empty())
+}
+
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub(crate) fn write_key_file(path: &Path, content: &[u8], mode: u32) -> OsshResult<()> {
+    let mut fop = fs::OpenOptions::new();
+    fop.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    fop.mode(mode);
+
+    let mut f = fop.open(path)?;
+    f.write_all(content)?;
+    f.sync_all()?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use crate::defaults::defaults;
+use crate::error::*;
+use crate::keys::KeyPair;
+use keyring::Entry;
+use zeroize::Zeroizing;
+
+/// An entry of the OS keychain holding a private key or a passphrase
+///
+/// The entry is identified by the service name and the account name,
+/// such as `("osshkeys", "id_ed25519")`. It is stored in the macOS Keychain,
+/// the Windows Credential Manager or the Secret Service of the Linux desktops.
+pub struct KeychainEntry {
+    entry: Entry,
+}
+
+impl KeychainEntry {
+    /// Open the entry of the default credential store
+    ///
+    /// The entry is not created until something is stored into it.
+    pub fn new(service: &str, account: &str) -> OsshResult<Self> {
+        Ok(KeychainEntry {
+            entry: Entry::new(service, account)?,
+        })
+    }
+
+    /// Wrap an entry created by `keyring`, such as one of a custom credential store
+    pub fn from_entry(entry: Entry) -> Self {
+        KeychainEntry { entry }
+    }
+
+    /// Store the key pair in the OpenSSH format, encrypted with the cipher of [`defaults()`] if a passphrase is given
+    ///
+    /// The content of the entry is replaced if it exists.
+    pub fn store_keypair(&self, keypair: &KeyPair, passphrase: Option<&str>) -> OsshResult<()> {
+        let keystr =
+            Zeroizing::new(keypair.serialize_openssh_with_defaults(passphrase, &defaults())?);
+        Ok(self.entry.set_password(&keystr)?)
+    }
+
+    /// Load the key pair stored by [`KeychainEntry::store_keypair()`]
+    ///
+    /// Return `None` if the entry doesn't exist.
+    pub fn load_keypair(&self, passphrase: Option<&str>) -> OsshResult<Option<KeyPair>> {
+        match self.load()? {
+            Some(keystr) => Ok(Some(KeyPair::from_keystr(&keystr, passphrase)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the passphrase of a key file
+    ///
+    /// The content of the entry is replaced if it exists.
+    pub fn store_passphrase(&self, passphrase: &str) -> OsshResult<()> {
+        Ok(self.entry.set_password(passphrase)?)
+    }
+
+    /// Load the passphrase stored by [`KeychainEntry::store_passphrase()`]
+    ///
+    /// Return `None` if the entry doesn't exist.
+    pub fn load_passphrase(&self) -> OsshResult<Option<Zeroizing<String>>> {
+        self.load()
+    }
+
+    /// Delete the entry from the keychain
+    ///
+    /// Return `false` if the entry doesn't exist.
+    pub fn delete(&self) -> OsshResult<bool> {
+        match self.entry.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn load(&self) -> OsshResult<Option<Zeroizing<String>>> {
+        match self.entry.get_password() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "ecdsa"))]
+mod test {
+    use super::*;
+    use crate::keys::{KeyType, PublicParts};
+    use keyring::mock::MockCredential;
+
+    fn mock_entry() -> KeychainEntry {
+        KeychainEntry::from_entry(Entry::new_with_credential(Box::new(
+            MockCredential::default(),
+        )))
+    }
+
+    #[test]
+    fn keychain_keypair() {
+        let entry = mock_entry();
+        assert!(entry.load_keypair(None).unwrap().is_none());
+
+        let keypair = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+        entry.store_keypair(&keypair, Some("12345678")).unwrap();
+        assert!(entry.load_keypair(None).is_err());
+        let loaded = entry.load_keypair(Some("12345678")).unwrap().unwrap();
+        assert_eq!(loaded.blob().unwrap(), keypair.blob().unwrap());
+
+        assert!(entry.delete().unwrap());
+        assert!(!entry.delete().unwrap());
+        assert!(entry.load_keypair(Some("12345678")).unwrap().is_none());
+    }
+
+    #[test]
+    fn keychain_passphrase() {
+        let entry = mock_entry();
+        assert!(entry.load_passphrase().unwrap().is_none());
+        entry.store_passphrase("12345678").unwrap();
+        assert_eq!(
+            entry.load_passphrase().unwrap().unwrap().as_str(),
+            "12345678"
+        );
+        entry.store_passphrase("87654321").unwrap();
+        assert_eq!(
+            entry.load_passphrase().unwrap().unwrap().as_str(),
+            "87654321"
+        );
+    }
+}
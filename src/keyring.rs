@@ -0,0 +1,317 @@
+use crate::error::*;
+use crate::keys::rsa::RsaSignature;
+use crate::keys::{KeyPair, PublicKey, PublicParts, SshSigner};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// The callback asked before each use of a key, returning `false` to refuse the use
+pub type ConfirmFn = dyn Fn(&PublicKey) -> bool + Send + Sync;
+
+/// The usage constraints of a key in the [`Keyring`], like the `-t` and `-c` options of `ssh-add`
+#[derive(Clone, Default)]
+pub struct KeyConstraints {
+    /// Remove the key after the duration since it was added, or keep it forever if `None`
+    pub lifetime: Option<Duration>,
+    /// Ask the callback before each signature, or sign without asking if `None`
+    pub confirm: Option<Arc<ConfirmFn>>,
+}
+
+/// The source of the identifiers which tell the entries of the same key apart
+static NEXT_ENTRY_ID: AtomicU64 = AtomicU64::new(0);
+
+struct KeyringEntry {
+    id: u64,
+    keypair: KeyPair,
+    blob: Vec<u8>,
+    expires_at: Option<Instant>,
+    confirm: Option<Arc<ConfirmFn>>,
+}
+
+impl KeyringEntry {
+    fn expired(&self, now: Instant) -> bool {
+        self.expires_at.map_or(false, |t| t <= now)
+    }
+}
+
+/// An in-memory set of the unlocked key pairs, which is the core of an ssh-agent
+///
+/// The keyring is cheaply cloned, and all the clones share the same keys, so it can be used across the threads.
+/// The expired keys are never used, and they are dropped on the next change of the keyring or listing of the identities.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    entries: Arc<RwLock<Vec<KeyringEntry>>>,
+}
+
+impl Keyring {
+    /// Create an empty keyring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the key pair with the constraints
+    ///
+    /// If the key is already in the keyring, it is replaced along with its constraints.
+    pub fn add(&self, keypair: KeyPair, constraints: KeyConstraints) -> OsshResult<()> {
+        let now = Instant::now();
+        let entry = KeyringEntry {
+            id: NEXT_ENTRY_ID.fetch_add(1, Ordering::Relaxed),
+            blob: keypair.blob()?,
+            keypair,
+            expires_at: constraints.lifetime.map(|lifetime| now + lifetime),
+            confirm: constraints.confirm,
+        };
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|e| e.blob != entry.blob && !e.expired(now));
+        entries.push(entry);
+        Ok(())
+    }
+
+    /// Remove the key, and return whether it was in the keyring
+    pub fn remove(&self, pubkey: &PublicKey) -> OsshResult<bool> {
+        let blob = pubkey.blob()?;
+        let mut entries = self.entries.write().unwrap();
+        let len = entries.len();
+        entries.retain(|e| e.blob != blob);
+        Ok(entries.len() != len)
+    }
+
+    /// Remove all the keys
+    pub fn clear(&self) {
+        self.entries.write().unwrap().clear();
+    }
+
+    /// List the public keys of the unexpired keys, in the order they were added
+    ///
+    /// The comments of the key pairs are kept in the public keys.
+    pub fn identities(&self) -> OsshResult<Vec<PublicKey>> {
+        let now = Instant::now();
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|e| !e.expired(now));
+        entries
+            .iter()
+            .map(|e| e.keypair.clone_public_key())
+            .collect()
+    }
+
+    /// Check if the unexpired key is in the keyring
+    pub fn contains(&self, pubkey: &PublicKey) -> bool {
+        let now = Instant::now();
+        match pubkey.blob() {
+            Ok(blob) => self
+                .entries
+                .read()
+                .unwrap()
+                .iter()
+                .any(|e| e.blob == blob && !e.expired(now)),
+            Err(_) => false,
+        }
+    }
+
+    /// Sign the data with the key like [`KeyPair::sign_ssh_signature_with()`], after checking its constraints
+    ///
+    /// Fail with [`ErrorKind::InvalidArgument`] if the key is not in the keyring or has expired,
+    /// and with [`ErrorKind::Cancelled`] if the confirm callback refuses it.
+    /// The keyring is not locked while the callback runs, so the other threads can still use it.
+    /// If the key is replaced meanwhile, the constraints of the new one are checked again before signing.
+    pub fn sign(
+        &self,
+        pubkey: &PublicKey,
+        data: &[u8],
+        rsa_signature: Option<RsaSignature>,
+    ) -> OsshResult<Vec<u8>> {
+        let blob = pubkey.blob()?;
+        loop {
+            let (id, confirm) = self.with_key(&blob, |e| (e.id, e.confirm.clone()))?;
+            if let Some(confirm) = confirm {
+                if !confirm(pubkey) {
+                    return Err(ErrorKind::Cancelled.into());
+                }
+            }
+            let signed = self.with_key(&blob, |e| {
+                (e.id == id).then(|| e.keypair.sign_ssh_signature_with(data, rsa_signature))
+            })?;
+            if let Some(sig) = signed {
+                return sig;
+            }
+        }
+    }
+
+    /// Get a signer using the key in the keyring, which can sign the certificates and the other SSH signatures
+    pub fn signer(&self, pubkey: &PublicKey) -> OsshResult<KeyringSigner> {
+        let blob = pubkey.blob()?;
+        let pubkey = self.with_key(&blob, |e| e.keypair.clone_public_key())??;
+        Ok(KeyringSigner {
+            keyring: self.clone(),
+            pubkey,
+        })
+    }
+
+    /// Get the number of the keys, including the expired ones not dropped yet
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    /// Check if there is no key
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn with_key<T>(&self, blob: &[u8], f: impl FnOnce(&KeyringEntry) -> T) -> OsshResult<T> {
+        let now = Instant::now();
+        let entries = self.entries.read().unwrap();
+        entries
+            .iter()
+            .find(|e| e.blob == blob && !e.expired(now))
+            .map(f)
+            .ok_or_else(|| ErrorKind::InvalidArgument.into())
+    }
+}
+
+/// A key in the [`Keyring`] used as an [`SshSigner`], created by [`Keyring::signer()`]
+///
+/// The constraints of the key are checked on each signature.
+pub struct KeyringSigner {
+    keyring: Keyring,
+    pubkey: PublicKey,
+}
+
+impl SshSigner for KeyringSigner {
+    fn clone_public_key(&self) -> OsshResult<PublicKey> {
+        let mut pubkey = PublicKey::from_blob(&self.pubkey.blob()?)?;
+        pubkey.set_comment(self.pubkey.comment());
+        Ok(pubkey)
+    }
+
+    fn sign_ssh_signature_with(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<RsaSignature>,
+    ) -> OsshResult<Vec<u8>> {
+        self.keyring.sign(&self.pubkey, data, rsa_signature)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+mod test {
+    use super::*;
+    use crate::keys::KeyType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    const DATA: &[u8] = b"keyring test data";
+
+    #[test]
+    fn keyring_constraints() {
+        let keyring = Keyring::new();
+        let ed25519 = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let ed25519_pub = ed25519.clone_public_key().unwrap();
+        keyring.add(ed25519, KeyConstraints::default()).unwrap();
+
+        let asked = Arc::new(AtomicUsize::new(0));
+        let counter = asked.clone();
+        let ecdsa = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+        let ecdsa_pub = ecdsa.clone_public_key().unwrap();
+        let confirm = KeyConstraints {
+            confirm: Some(Arc::new(move |_: &PublicKey| {
+                counter.fetch_add(1, Ordering::SeqCst) == 0
+            })),
+            ..Default::default()
+        };
+        keyring.add(ecdsa, confirm).unwrap();
+        let expired = KeyConstraints {
+            lifetime: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let rsa = KeyPair::generate(KeyType::RSA, 2048).unwrap();
+        let rsa_pub = rsa.clone_public_key().unwrap();
+        keyring.add(rsa, expired).unwrap();
+
+        // Sign from the other threads with the clones of the keyring
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let keyring = keyring.clone();
+                let pubkey = PublicKey::from_blob(&ed25519_pub.blob().unwrap()).unwrap();
+                thread::spawn(move || keyring.sign(&pubkey, DATA, None).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            let sig = handle.join().unwrap();
+            assert!(ed25519_pub.verify_ssh_signature(DATA, &sig).unwrap());
+        }
+
+        let sig = keyring.sign(&ecdsa_pub, DATA, None).unwrap();
+        assert!(ecdsa_pub.verify_ssh_signature(DATA, &sig).unwrap());
+        assert_eq!(
+            keyring.sign(&ecdsa_pub, DATA, None).unwrap_err().kind(),
+            ErrorKind::Cancelled
+        );
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+
+        assert!(!keyring.contains(&rsa_pub));
+        assert_eq!(
+            keyring.sign(&rsa_pub, DATA, None).unwrap_err().kind(),
+            ErrorKind::InvalidArgument
+        );
+        let identities = keyring.identities().unwrap();
+        assert_eq!(identities.len(), 2);
+        assert!(identities[0] == ed25519_pub);
+        assert_eq!(keyring.len(), 2);
+
+        let signer = keyring.signer(&ed25519_pub).unwrap();
+        let sig = signer.sign_ssh_signature_with(DATA, None).unwrap();
+        assert!(ed25519_pub.verify_ssh_signature(DATA, &sig).unwrap());
+        assert!(keyring.remove(&ed25519_pub).unwrap());
+        assert!(!keyring.remove(&ed25519_pub).unwrap());
+        assert!(signer.sign_ssh_signature_with(DATA, None).is_err());
+        keyring.clear();
+        assert!(keyring.is_empty());
+    }
+
+    #[test]
+    fn keyring_key_replaced_while_confirming() {
+        let keyring = Keyring::new();
+        let keystr = KeyPair::generate(KeyType::ED25519, 0)
+            .unwrap()
+            .serialize_openssh(None, crate::cipher::Cipher::Null)
+            .unwrap();
+        let keypair = KeyPair::from_keystr(&keystr, None).unwrap();
+        let pubkey = keypair.clone_public_key().unwrap();
+
+        // The first callback replaces the key with one whose callback refuses every use
+        let replacing = keyring.clone();
+        let refused = Arc::new(AtomicUsize::new(0));
+        let counter = refused.clone();
+        let confirm = KeyConstraints {
+            confirm: Some(Arc::new(move |_: &PublicKey| {
+                let counter = counter.clone();
+                let refuse = KeyConstraints {
+                    confirm: Some(Arc::new(move |_: &PublicKey| {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        false
+                    })),
+                    ..Default::default()
+                };
+                replacing
+                    .add(KeyPair::from_keystr(&keystr, None).unwrap(), refuse)
+                    .unwrap();
+                true
+            })),
+            ..Default::default()
+        };
+        keyring.add(keypair, confirm).unwrap();
+
+        assert_eq!(
+            keyring.sign(&pubkey, DATA, None).unwrap_err().kind(),
+            ErrorKind::Cancelled
+        );
+        assert_eq!(refused.load(Ordering::SeqCst), 1);
+        assert_eq!(keyring.len(), 1);
+    }
+}
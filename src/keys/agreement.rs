@@ -0,0 +1,168 @@
+use super::derive::hkdf_sha512;
+use crate::error::*;
+use openssl::derive::Deriver;
+use openssl::pkey::{PKey, Private, Public};
+use zeroize::Zeroizing;
+
+/// The KDF step applied to the raw shared secret of the key agreement
+///
+/// The raw shared secret is not uniformly random, so it should go through a KDF before being used as a key.
+/// Besides [`HkdfSha512`] and [`RawSecret`], the closures taking the raw secret can be used as the KDF.
+pub trait SharedSecretKdf {
+    /// Derive the key material from the raw shared secret
+    fn derive(&self, secret: &[u8]) -> OsshResult<Zeroizing<Vec<u8>>>;
+}
+
+impl<F> SharedSecretKdf for F
+where
+    F: Fn(&[u8]) -> OsshResult<Zeroizing<Vec<u8>>>,
+{
+    fn derive(&self, secret: &[u8]) -> OsshResult<Zeroizing<Vec<u8>>> {
+        self(secret)
+    }
+}
+
+/// HKDF (RFC 5869) using HMAC-SHA512
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HkdfSha512<'a> {
+    /// The salt of the extract step
+    pub salt: &'a [u8],
+    /// The context of the expand step, which binds the key to its purpose
+    pub info: &'a [u8],
+    /// The length of the output in bytes, at most `255 * 64`
+    pub len: usize,
+}
+
+impl SharedSecretKdf for HkdfSha512<'_> {
+    fn derive(&self, secret: &[u8]) -> OsshResult<Zeroizing<Vec<u8>>> {
+        let mut okm = Zeroizing::new(vec![0; self.len]);
+        hkdf_sha512(self.salt, secret, &[self.info], &mut okm)?;
+        Ok(okm)
+    }
+}
+
+/// Return the raw shared secret as is, for the protocols which hash it themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawSecret;
+
+impl SharedSecretKdf for RawSecret {
+    fn derive(&self, secret: &[u8]) -> OsshResult<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(secret.to_vec()))
+    }
+}
+
+/// Compute the raw shared secret with OpenSSL
+///
+/// The all-zero secret of the X25519 small order points is refused with [`ErrorKind::InvalidKey`].
+pub(crate) fn ossl_derive(
+    key: PKey<Private>,
+    peer: PKey<Public>,
+) -> OsshResult<Zeroizing<Vec<u8>>> {
+    let mut deriver = Deriver::new(&key)?;
+    deriver.set_peer(&peer)?;
+    let secret = Zeroizing::new(
+        deriver
+            .derive_to_vec()
+            .map_err(|e| Error::with_error(ErrorKind::InvalidKey, e))?,
+    );
+    if secret.iter().all(|&b| b == 0) {
+        return Err(ErrorKind::InvalidKey.into());
+    }
+    Ok(secret)
+}
+
+#[cfg(all(
+    test,
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+mod test {
+    use super::*;
+    use crate::keys::ecdsa::EcDsaKeyPair;
+    use crate::keys::x25519::{X25519KeyPair, X25519PublicKey};
+    use crate::keys::{KeyPair, KeyType};
+    use hex_literal::hex;
+
+    // The test vectors of RFC 7748
+    const ALICE_SECRET: [u8; 32] =
+        hex!("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+    const BOB_PUBLIC: [u8; 32] =
+        hex!("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f");
+    const SHARED_SECRET: [u8; 32] =
+        hex!("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+
+    #[test]
+    fn x25519_shared_secret() {
+        let alice = X25519KeyPair::from_bytes(&ALICE_SECRET).unwrap();
+        let bob = X25519PublicKey::from_bytes(&BOB_PUBLIC).unwrap();
+        assert_eq!(*alice.derive_shared_secret(&bob).unwrap(), SHARED_SECRET);
+        let raw = alice.derive_shared_secret_with(&bob, &RawSecret).unwrap();
+        assert_eq!(*raw, SHARED_SECRET);
+
+        let kdf = HkdfSha512 {
+            salt: b"salt",
+            info: b"envelope v1",
+            len: 32,
+        };
+        let mut expected = [0u8; 32];
+        hkdf_sha512(b"salt", &SHARED_SECRET, &[b"envelope v1"], &mut expected).unwrap();
+        let key = alice.derive_shared_secret_with(&bob, &kdf).unwrap();
+        assert_eq!(*key, expected);
+
+        let truncate = |secret: &[u8]| Ok(Zeroizing::new(secret[..16].to_vec()));
+        let key = alice.derive_shared_secret_with(&bob, &truncate).unwrap();
+        assert_eq!(*key, SHARED_SECRET[..16]);
+
+        // A small order point gives the all-zero secret
+        let zero = X25519PublicKey::from_bytes(&[0; 32]).unwrap();
+        assert_eq!(
+            alice.derive_shared_secret(&zero).unwrap_err().kind(),
+            ErrorKind::InvalidKey
+        );
+    }
+
+    #[test]
+    fn ecdh_shared_secret() {
+        for bits in [256, 384, 521] {
+            let alice = KeyPair::generate(KeyType::ECDSA, bits).unwrap();
+            let bob: KeyPair = EcDsaKeyPair::generate(bits).unwrap().into();
+            let secret0 = alice
+                .derive_shared_secret(&bob.clone_public_key().unwrap())
+                .unwrap();
+            let secret1 = bob
+                .derive_shared_secret(&alice.clone_public_key().unwrap())
+                .unwrap();
+            assert_eq!(secret0, secret1);
+            assert_eq!(secret0.len(), (bits + 7) / 8);
+        }
+
+        let alice = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+        let bob = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+        let kdf = HkdfSha512 {
+            salt: &[],
+            info: b"test",
+            len: 64,
+        };
+        let secret0 = alice
+            .derive_shared_secret_with(&bob.clone_public_key().unwrap(), &kdf)
+            .unwrap();
+        let secret1 = bob
+            .derive_shared_secret_with(&alice.clone_public_key().unwrap(), &kdf)
+            .unwrap();
+        assert_eq!(secret0, secret1);
+
+        let p384 = KeyPair::generate(KeyType::ECDSA, 384).unwrap();
+        let ed25519 = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        for (keypair, peer) in [(&alice, &p384), (&alice, &ed25519), (&ed25519, &alice)] {
+            assert_eq!(
+                keypair
+                    .derive_shared_secret(&peer.clone_public_key().unwrap())
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::TypeNotMatch
+            );
+        }
+    }
+}
@@ -0,0 +1,782 @@
+use super::rsa::RsaSignature;
+use super::{
+    Fingerprint, FingerprintHash, Key, KeyPair, KeyType, PublicKey, PublicParts, SshSigner,
+    CERT_SUFFIX, SECTION_SIGNATURE,
+};
+use crate::error::*;
+use crate::format::base64;
+use crate::format::ossh_pubkey::split_ossh_keystr;
+use crate::sshbuf::{SshReader, SshWriteExt};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECTION_CERT: &str = "certificate";
+
+/// The `valid_before` value of the certificates which never expire
+pub const CERT_FOREVER: u64 = u64::MAX;
+
+/// The type of the certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CertType {
+    /// The user certificate, which authenticates the users to the servers
+    User,
+    /// The host certificate, which authenticates the servers to the users
+    Host,
+}
+
+impl CertType {
+    /// Parse from the certificate type value in the wire encoding
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(CertType::User),
+            2 => Some(CertType::Host),
+            _ => None,
+        }
+    }
+
+    /// The certificate type value in the wire encoding
+    pub fn code(self) -> u32 {
+        match self {
+            CertType::User => 1,
+            CertType::Host => 2,
+        }
+    }
+}
+
+impl fmt::Display for CertType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CertType::User => "user",
+            CertType::Host => "host",
+        })
+    }
+}
+
+/// The fields of a new certificate signed by [`Certificate::sign()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateFields {
+    pub serial: u64,
+    pub cert_type: CertType,
+    /// The key ID, which is logged by the server when the certificate is used
+    pub key_id: String,
+    /// The user names or the host names which the certificate is issued for
+    pub valid_principals: Vec<String>,
+    /// The start of the validity period in seconds since the Unix epoch
+    pub valid_after: u64,
+    /// The end of the validity period in seconds since the Unix epoch, or [`CERT_FOREVER`]
+    pub valid_before: u64,
+    /// The critical options as `(name, value)` pairs, which are sorted by the name when signing
+    pub critical_options: Vec<(String, String)>,
+    /// The extensions as `(name, value)` pairs, which are sorted by the name when signing
+    pub extensions: Vec<(String, String)>,
+}
+
+impl Default for CertificateFields {
+    fn default() -> Self {
+        CertificateFields {
+            serial: 0,
+            cert_type: CertType::User,
+            key_id: String::new(),
+            valid_principals: Vec::new(),
+            valid_after: 0,
+            valid_before: CERT_FOREVER,
+            critical_options: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+/// OpenSSH certificate, such as the `id_ed25519-cert.pub` file
+///
+/// The certificate binds a public key to the identities and the validity period, and is signed by a CA key.
+/// See [PROTOCOL.certkeys](https://github.com/openssh/openssh-portable/blob/master/PROTOCOL.certkeys) for the format.
+pub struct Certificate {
+    nonce: Vec<u8>,
+    key: PublicKey,
+    serial: u64,
+    cert_type: CertType,
+    key_id: String,
+    valid_principals: Vec<String>,
+    valid_after: u64,
+    valid_before: u64,
+    critical_options: Vec<(String, String)>,
+    extensions: Vec<(String, String)>,
+    signature_key: PublicKey,
+    signature: Vec<u8>,
+    blob: Vec<u8>,
+    signed_len: usize,
+    comment: String,
+}
+
+impl Certificate {
+    /// Decode the certificate from the SSH wire encoding blob
+    pub fn from_blob(blob: &[u8]) -> OsshResult<Self> {
+        let mut reader = SshReader::new(blob, SECTION_CERT);
+        let keyname = reader.read_utf8("keytype")?;
+        let basename = keyname
+            .strip_suffix(CERT_SUFFIX)
+            .ok_or_else(|| Error::from_kind(ErrorKind::UnsupportType(keyname.to_owned())))?;
+        let nonce = reader.read_string("nonce")?.to_vec();
+
+        // The key fields are the same as the plain public key blob without the key name
+        let nfields = match KeyType::from_str(basename)? {
+            #[cfg(feature = "rsa")]
+            KeyType::RSA => 2,
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA => 2,
+            #[cfg(feature = "dsa")]
+            KeyType::DSA => 4,
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519 => 1,
+        };
+        let key_start = reader.position();
+        for _ in 0..nfields {
+            reader.read_string("key")?;
+        }
+        let mut keyblob = Vec::new();
+        keyblob.write_utf8(basename)?;
+        keyblob.extend_from_slice(&blob[key_start..reader.position()]);
+        let key = PublicKey::from_blob(&keyblob)?;
+
+        let serial = reader.read_uint64("serial")?;
+        let type_pos = reader.position();
+        let cert_type = CertType::from_code(reader.read_uint32("type")?).ok_or_else(|| {
+            Error::from_kind(ErrorKind::InvalidFormat).at(SECTION_CERT, "type", type_pos)
+        })?;
+        let key_id = reader.read_utf8("key id")?.to_owned();
+
+        let mut principals = reader.read_nested("valid principals")?;
+        let mut valid_principals = Vec::new();
+        while !principals.is_empty() {
+            valid_principals.push(principals.read_utf8("principal")?.to_owned());
+        }
+        let valid_after = reader.read_uint64("valid after")?;
+        let valid_before = reader.read_uint64("valid before")?;
+        let critical_options = decode_options(reader.read_nested("critical options")?)?;
+        let extensions = decode_options(reader.read_nested("extensions")?)?;
+        reader.read_string("reserved")?;
+        let signature_key = PublicKey::from_blob(reader.read_string("signature key")?)?;
+        let signed_len = reader.position();
+        let signature = reader.read_string("signature")?.to_vec();
+        reader.finish()?;
+
+        Ok(Certificate {
+            nonce,
+            key,
+            serial,
+            cert_type,
+            key_id,
+            valid_principals,
+            valid_after,
+            valid_before,
+            critical_options,
+            extensions,
+            signature_key,
+            signature,
+            blob: blob.to_vec(),
+            signed_len,
+            comment: String::new(),
+        })
+    }
+
+    /// Sign a new certificate of the public key with the CA key
+    ///
+    /// The CA key can be a [`KeyPair`](super::KeyPair) or any other [`SshSigner`].
+    /// The RSA CA keys sign with `rsa_signature`, which is ignored for the other key types.
+    /// OpenSSH signs with `rsa-sha2-512` by default, and refuses the legacy `ssh-rsa` (SHA-1) certificate signatures since OpenSSH 8.2.
+    /// The certificate takes the comment of the public key.
+    pub fn sign<S: SshSigner + ?Sized>(
+        key: &PublicKey,
+        fields: &CertificateFields,
+        ca: &S,
+        rsa_signature: RsaSignature,
+    ) -> OsshResult<Self> {
+        let mut nonce = [0u8; 32];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut blob = Vec::new();
+        blob.write_utf8(&format!("{}{}", cert_basename(key), CERT_SUFFIX))?;
+        blob.write_string(&nonce)?;
+        // The key fields are the same as the plain public key blob without the key name
+        let keyblob = key.blob()?;
+        let mut reader = SshReader::new(&keyblob, SECTION_CERT);
+        reader.read_string("keytype")?;
+        blob.extend_from_slice(&keyblob[reader.position()..]);
+        blob.write_uint64(fields.serial)?;
+        blob.write_uint32(fields.cert_type.code())?;
+        blob.write_utf8(&fields.key_id)?;
+        let mut principals = Vec::new();
+        for principal in &fields.valid_principals {
+            principals.write_utf8(principal)?;
+        }
+        blob.write_string(&principals)?;
+        blob.write_uint64(fields.valid_after)?;
+        blob.write_uint64(fields.valid_before)?;
+        blob.write_string(&encode_options(&fields.critical_options)?)?;
+        blob.write_string(&encode_options(&fields.extensions)?)?;
+        blob.write_string(&[])?;
+        blob.write_string(&ca.clone_public_key()?.blob()?)?;
+        let signature = ca.sign_ssh_signature_with(&blob, Some(rsa_signature))?;
+        blob.write_string(&signature)?;
+
+        let mut cert = Self::from_blob(&blob)?;
+        cert.comment = key.comment().to_owned();
+        Ok(cert)
+    }
+
+    /// Parse the OpenSSH certificate string, such as the content of the `-cert.pub` file
+    pub fn from_keystr(keystr: &str) -> OsshResult<Self> {
+        let (keyname, blob, comment) = split_ossh_keystr(keystr)?;
+        let mut cert = Self::from_blob(&base64::decode(blob)?)?;
+        if cert.keyname() != keyname {
+            return Err(ErrorKind::TypeNotMatch.into());
+        }
+        cert.comment = comment.to_owned();
+        Ok(cert)
+    }
+
+    /// Get the name of the certificate type, such as `ssh-ed25519-cert-v01@openssh.com`
+    pub fn keyname(&self) -> String {
+        format!("{}{}", cert_basename(&self.key), CERT_SUFFIX)
+    }
+
+    /// The random nonce of the certificate
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// The public key being certified
+    pub fn public_key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// Check if the certified public key corresponds to the key pair
+    ///
+    /// A certificate which doesn't match its private key is refused by sshd, so it's worth checking before deploying them.
+    pub fn matches_keypair(&self, keypair: &KeyPair) -> bool {
+        keypair.matches_public(&self.key)
+    }
+
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    pub fn cert_type(&self) -> CertType {
+        self.cert_type
+    }
+
+    /// The key ID, which is logged by the server when the certificate is used
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The user names or the host names which the certificate is issued for
+    pub fn valid_principals(&self) -> &[String] {
+        &self.valid_principals
+    }
+
+    /// The start of the validity period in seconds since the Unix epoch
+    pub fn valid_after(&self) -> u64 {
+        self.valid_after
+    }
+
+    /// The end of the validity period in seconds since the Unix epoch, or [`CERT_FOREVER`]
+    pub fn valid_before(&self) -> u64 {
+        self.valid_before
+    }
+
+    /// The critical options as `(name, value)` pairs, such as `force-command`
+    pub fn critical_options(&self) -> &[(String, String)] {
+        &self.critical_options
+    }
+
+    /// The extensions as `(name, value)` pairs, such as `permit-pty`
+    pub fn extensions(&self) -> &[(String, String)] {
+        &self.extensions
+    }
+
+    /// The public key of the CA which signs the certificate
+    pub fn signature_key(&self) -> &PublicKey {
+        &self.signature_key
+    }
+
+    /// The signature of the CA in the SSH wire encoding
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// The algorithm of the CA signature, such as `rsa-sha2-512` for the RSA CA keys
+    pub fn signature_algorithm(&self) -> OsshResult<&str> {
+        SshReader::new(&self.signature, SECTION_SIGNATURE).read_utf8("algorithm")
+    }
+
+    /// Get the comment of the certificate
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// Get the mutable reference of the certificate comment
+    pub fn comment_mut(&mut self) -> &mut String {
+        &mut self.comment
+    }
+
+    /// Encode the certificate as the SSH wire encoding blob
+    pub fn to_blob(&self) -> Vec<u8> {
+        self.blob.clone()
+    }
+
+    /// Serialize the certificate as the OpenSSH format
+    pub fn serialize(&self) -> String {
+        let mut keystr = String::new();
+        // Writing into a String never fails
+        self.write_openssh(&mut keystr).unwrap();
+        keystr
+    }
+
+    /// Write the certificate in the OpenSSH format into the sink, without the trailing newline
+    pub fn write_openssh<W: fmt::Write + ?Sized>(&self, out: &mut W) -> OsshResult<()> {
+        write!(out, "{} ", self.keyname())?;
+        base64::write_encoded(out, &self.blob, 0)?;
+        if !self.comment.is_empty() {
+            write!(out, " {}", self.comment)?;
+        }
+        Ok(())
+    }
+
+    /// Describe the certificate in the same layout as `ssh-keygen -L`, without the leading line of the file name
+    ///
+    /// The fingerprints are SHA256, and the validity period is printed in UTC instead of the local time.
+    pub fn describe(&self) -> OsshResult<String> {
+        const INDENT: &str = "        ";
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{}Type: {} {} certificate",
+            INDENT,
+            self.keyname(),
+            self.cert_type
+        )?;
+        writeln!(
+            out,
+            "{}Public key: {}-CERT {}",
+            INDENT,
+            self.key.short_keyname(),
+            Fingerprint::of(&self.key, FingerprintHash::SHA256)?
+        )?;
+        writeln!(
+            out,
+            "{}Signing CA: {} {} (using {})",
+            INDENT,
+            self.signature_key.short_keyname(),
+            Fingerprint::of(&self.signature_key, FingerprintHash::SHA256)?,
+            self.signature_algorithm()?
+        )?;
+        writeln!(out, "{}Key ID: \"{}\"", INDENT, self.key_id)?;
+        writeln!(out, "{}Serial: {}", INDENT, self.serial)?;
+        let valid = match (self.valid_after, self.valid_before) {
+            (0, CERT_FOREVER) => "forever".to_owned(),
+            (0, before) => format!("before {}", format_time(before)),
+            (after, CERT_FOREVER) => format!("after {}", format_time(after)),
+            (after, before) => format!("from {} to {}", format_time(after), format_time(before)),
+        };
+        writeln!(out, "{}Valid: {}", INDENT, valid)?;
+
+        let lists = [
+            (
+                "Principals",
+                self.valid_principals
+                    .iter()
+                    .map(|p| (p, ""))
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "Critical Options",
+                self.critical_options
+                    .iter()
+                    .map(|(n, v)| (n, v.as_str()))
+                    .collect(),
+            ),
+            (
+                "Extensions",
+                self.extensions
+                    .iter()
+                    .map(|(n, v)| (n, v.as_str()))
+                    .collect(),
+            ),
+        ];
+        for (title, items) in lists {
+            write!(out, "{}{}: ", INDENT, title)?;
+            if items.is_empty() {
+                writeln!(out, "(none)")?;
+                continue;
+            }
+            writeln!(out)?;
+            for (name, value) in items {
+                write!(out, "{}{}{}", INDENT, INDENT, name)?;
+                if !value.is_empty() {
+                    write!(out, " {}", value)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Verify the signature with the CA key embedded in the certificate
+    ///
+    /// This proves nothing about the issuer, since anyone can sign a certificate with their own key.
+    /// Use [`Certificate::verify_at()`] to check the CA against the trusted keys, the type, the principal and the validity period too.
+    pub fn verify_signature(&self) -> OsshResult<bool> {
+        self.signature_key
+            .verify_ssh_signature(&self.blob[..self.signed_len], &self.signature)
+    }
+
+    /// Check if the time in seconds since the Unix epoch is in the validity period
+    pub fn is_valid_at(&self, time: u64) -> bool {
+        self.valid_after <= time && time < self.valid_before
+    }
+
+    /// Check the CA, the type, the principal, the validity period and the signature of the certificate
+    ///
+    /// The certificate is refused unless it is signed by one of the trusted CA keys.
+    /// The certificate with empty principals is refused here, like OpenSSH does for the host certificates.
+    pub fn verify_at(
+        &self,
+        trusted_cas: &[PublicKey],
+        cert_type: CertType,
+        principal: &str,
+        time: u64,
+    ) -> OsshResult<bool> {
+        Ok(trusted_cas.contains(&self.signature_key)
+            && self.cert_type == cert_type
+            && self.valid_principals.iter().any(|p| p == principal)
+            && self.is_valid_at(time)
+            && self.verify_signature()?)
+    }
+
+    /// Check the certificate like [`Certificate::verify_at()`] with the current time
+    pub fn verify(
+        &self,
+        trusted_cas: &[PublicKey],
+        cert_type: CertType,
+        principal: &str,
+    ) -> OsshResult<bool> {
+        self.verify_at(trusted_cas, cert_type, principal, unix_time())
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Certificate")
+            .field("keyname", &self.keyname())
+            .field("serial", &self.serial)
+            .field("cert_type", &self.cert_type)
+            .field("key_id", &self.key_id)
+            .field("valid_principals", &self.valid_principals)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.serialize())
+    }
+}
+
+/// The current time in seconds since the Unix epoch
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Format the time in seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SS` in UTC
+pub(crate) fn format_time(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Convert the days since the Unix epoch to the civil date `(year, month, day)`
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+fn cert_basename(key: &PublicKey) -> &'static str {
+    match key.keytype() {
+        #[cfg(feature = "rsa")]
+        KeyType::RSA => super::rsa::RSA_NAME,
+        #[cfg(feature = "dsa")]
+        KeyType::DSA => super::dsa::DSA_NAME,
+        #[cfg(feature = "ecdsa")]
+        KeyType::ECDSA => super::Key::keyname(key),
+        #[cfg(feature = "ed25519")]
+        KeyType::ED25519 => super::Key::keyname(key),
+    }
+}
+
+fn encode_options(options: &[(String, String)]) -> OsshResult<Vec<u8>> {
+    let mut options: Vec<_> = options.iter().collect();
+    options.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut buf = Vec::new();
+    for (name, value) in options {
+        buf.write_utf8(name)?;
+        if value.is_empty() {
+            buf.write_string(&[])?;
+        } else {
+            let mut data = Vec::new();
+            data.write_utf8(value)?;
+            buf.write_string(&data)?;
+        }
+    }
+    Ok(buf)
+}
+
+fn decode_options(mut reader: SshReader) -> OsshResult<Vec<(String, String)>> {
+    let mut options = Vec::new();
+    while !reader.is_empty() {
+        let name = reader.read_utf8("option name")?.to_owned();
+        let mut data = reader.read_nested("option data")?;
+        let value = if data.is_empty() {
+            String::new()
+        } else {
+            let value = data.read_utf8("option value")?.to_owned();
+            data.finish()?;
+            value
+        };
+        options.push((name, value));
+    }
+    Ok(options)
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::KeyPair;
+    use crate::keys::{FingerprintHash, PublicParts};
+    use std::fs;
+
+    fn read_cert(path: &str) -> Certificate {
+        Certificate::from_keystr(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn read_pubkey(path: &str) -> PublicKey {
+        PublicKey::from_keystr(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn certificate_matches_keypair() {
+        let cert = read_cert("assets/openssh_ed25519-cert.pub");
+        let keypair =
+            KeyPair::from_keystr(&fs::read_to_string("assets/openssh_ed25519").unwrap(), None)
+                .unwrap();
+        assert!(cert.matches_keypair(&keypair));
+
+        let other = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        assert!(!cert.matches_keypair(&other));
+        let rsa =
+            KeyPair::from_keystr(&fs::read_to_string("assets/openssh_rsa").unwrap(), None).unwrap();
+        assert!(!cert.matches_keypair(&rsa));
+    }
+
+    #[test]
+    fn user_certificate_parse() {
+        let cert = read_cert("assets/openssh_ed25519-cert.pub");
+        assert_eq!(cert.keyname(), "ssh-ed25519-cert-v01@openssh.com");
+        assert_eq!(cert.cert_type(), CertType::User);
+        assert_eq!(cert.serial(), 42);
+        assert_eq!(cert.key_id(), "user@example.com");
+        assert_eq!(cert.valid_principals(), ["alice", "bob"]);
+        assert_eq!(cert.valid_after(), 1577836800);
+        assert_eq!(cert.valid_before(), 4102358400);
+        assert_eq!(
+            cert.critical_options(),
+            [("force-command".to_string(), "/bin/true".to_string())]
+        );
+        assert_eq!(
+            cert.extensions(),
+            [("permit-pty".to_string(), String::new())]
+        );
+        assert!(*cert.public_key() == read_pubkey("assets/openssh_ed25519.pub"));
+        assert!(*cert.signature_key() == read_pubkey("assets/ca_ecdsa.pub"));
+        assert_eq!(
+            cert.signature_key()
+                .fingerprint(FingerprintHash::SHA256)
+                .unwrap(),
+            read_pubkey("assets/ca_ecdsa.pub")
+                .fingerprint(FingerprintHash::SHA256)
+                .unwrap()
+        );
+        assert!(cert.verify_signature().unwrap());
+
+        assert!(cert.is_valid_at(1577836800));
+        assert!(!cert.is_valid_at(1577836799));
+        assert!(!cert.is_valid_at(4102358400));
+        let cas = [read_pubkey("assets/ca_ecdsa.pub")];
+        assert!(cert
+            .verify_at(&cas, CertType::User, "alice", 1600000000)
+            .unwrap());
+        assert!(!cert
+            .verify_at(&cas, CertType::Host, "alice", 1600000000)
+            .unwrap());
+        assert!(!cert
+            .verify_at(&cas, CertType::User, "carol", 1600000000)
+            .unwrap());
+        let others = [read_pubkey("assets/ca_rsa.pub")];
+        assert!(!cert
+            .verify_at(&others, CertType::User, "alice", 1600000000)
+            .unwrap());
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn host_certificate_verify() {
+        for (path, ca) in [
+            ("assets/openssh_ecdsa-cert.pub", "assets/ca_ed25519.pub"),
+            ("assets/openssh_rsa-cert.pub", "assets/ca_rsa.pub"),
+        ] {
+            let cert = read_cert(path);
+            assert_eq!(cert.cert_type(), CertType::Host);
+            assert_eq!(cert.valid_before(), CERT_FOREVER);
+            assert!(*cert.signature_key() == read_pubkey(ca));
+            assert!(cert
+                .verify(&[read_pubkey(ca)], CertType::Host, "example.com")
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn certificate_roundtrip() {
+        let keystr = fs::read_to_string("assets/openssh_rsa-cert.pub").unwrap();
+        let mut cert = Certificate::from_keystr(&keystr).unwrap();
+        assert_eq!(cert.serialize(), keystr.trim_end());
+
+        *cert.comment_mut() = "user name@host".to_owned();
+        let parsed = Certificate::from_keystr(&cert.serialize()).unwrap();
+        assert_eq!(parsed.comment(), "user name@host");
+        let decoded = Certificate::from_blob(&cert.to_blob()).unwrap();
+        assert!(*decoded.public_key() == *cert.public_key());
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn certificate_sign_rsa_ca() {
+        let ca = KeyPair::from_keystr(&fs::read_to_string("assets/ca_rsa").unwrap(), None).unwrap();
+        let key = read_pubkey("assets/openssh_ed25519.pub");
+        let fields = CertificateFields {
+            serial: 7,
+            cert_type: CertType::Host,
+            key_id: "host".to_string(),
+            valid_principals: vec!["example.com".to_string()],
+            extensions: vec![
+                ("permit-pty".to_string(), String::new()),
+                ("permit-X11-forwarding".to_string(), String::new()),
+            ],
+            ..Default::default()
+        };
+        for (signhash, algo) in [
+            (RsaSignature::SHA2_512, "rsa-sha2-512"),
+            (RsaSignature::SHA2_256, "rsa-sha2-256"),
+            (RsaSignature::SHA1, "ssh-rsa"),
+        ] {
+            let cert = Certificate::sign(&key, &fields, &ca, signhash).unwrap();
+            assert_eq!(cert.signature_algorithm().unwrap(), algo);
+            assert_eq!(cert.keyname(), "ssh-ed25519-cert-v01@openssh.com");
+            assert!(*cert.public_key() == key);
+            assert_eq!(cert.serial(), 7);
+            assert_eq!(cert.extensions()[0].0, "permit-X11-forwarding");
+            let cas = [ca.clone_public_key().unwrap()];
+            assert!(cert.verify(&cas, CertType::Host, "example.com").unwrap());
+
+            let parsed = Certificate::from_keystr(&cert.serialize()).unwrap();
+            assert_eq!(parsed.to_blob(), cert.to_blob());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn certificate_self_signed() {
+        let key = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let fields = CertificateFields {
+            cert_type: CertType::Host,
+            valid_principals: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        let pubkey = key.clone_public_key().unwrap();
+        let cert = Certificate::sign(&pubkey, &fields, &key, RsaSignature::default()).unwrap();
+        assert!(cert.verify_signature().unwrap());
+        let cas = [read_pubkey("assets/ca_ed25519.pub")];
+        assert!(!cert.verify(&cas, CertType::Host, "example.com").unwrap());
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn certificate_tampered() {
+        let cert = read_cert("assets/openssh_ecdsa-cert.pub");
+        let mut blob = cert.to_blob();
+        // Change the principal `example.com`
+        let pos = blob.windows(11).position(|w| w == b"example.com").unwrap();
+        blob[pos] ^= 1;
+        let tampered = Certificate::from_blob(&blob).unwrap();
+        assert!(!tampered.verify_signature().unwrap());
+    }
+
+    #[test]
+    fn certificate_describe() {
+        // The output of `TZ=UTC ssh-keygen -L` without the file name
+        let user = "        Type: ssh-ed25519-cert-v01@openssh.com user certificate
+        Public key: ED25519-CERT SHA256:4u4w9KoU+yfqclRMG+SH/AmFt80Nx+8pMXVrWYw2zT0
+        Signing CA: ECDSA SHA256:ApEZJlSsh/OkhMxaTEXU62/qXnU1MGr/DgwhO3PWZYY (using ecdsa-sha2-nistp384)
+        Key ID: \"user@example.com\"
+        Serial: 42
+        Valid: from 2020-01-01T00:00:00 to 2099-12-31T00:00:00
+        Principals: 
+                alice
+                bob
+        Critical Options: 
+                force-command /bin/true
+        Extensions: 
+                permit-pty
+";
+        let host = "        Type: ssh-rsa-cert-v01@openssh.com host certificate
+        Public key: RSA-CERT SHA256:b0GYoqtCbxj7q7NqtjIszauhel1u0gp67T+JQKyz208
+        Signing CA: RSA SHA256:R8rcZ/O9NWSdBmhBstpoqYxpuAP682pFsPPBzWG5aLg (using rsa-sha2-512)
+        Key ID: \"host_rsa\"
+        Serial: 2
+        Valid: forever
+        Principals: 
+                example.com
+        Critical Options: (none)
+        Extensions: (none)
+";
+        let cert = read_cert("assets/openssh_ed25519-cert.pub");
+        assert_eq!(cert.describe().unwrap(), user);
+        let cert = read_cert("assets/openssh_rsa-cert.pub");
+        assert_eq!(cert.describe().unwrap(), host);
+
+        assert_eq!(format_time(0), "1970-01-01T00:00:00");
+        assert_eq!(format_time(951782400), "2000-02-29T00:00:00");
+        assert_eq!(format_time(4102444799), "2099-12-31T23:59:59");
+    }
+}
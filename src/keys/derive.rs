@@ -0,0 +1,227 @@
+#[cfg(feature = "ecdsa")]
+use super::ecdsa::{EcCurve, EcDsaKeyPair};
+#[cfg(feature = "ed25519")]
+use super::ed25519::Ed25519KeyPair;
+#[cfg(feature = "ed25519")]
+use super::pkey_cache::PKeyCache;
+use super::{KeyPair, KeyType};
+use crate::error::{Error, ErrorKind, OsshResult};
+#[cfg(feature = "ed25519")]
+use ed25519_dalek::{SigningKey, SECRET_KEY_LENGTH};
+use hmac::{Hmac, Mac};
+#[cfg(feature = "ecdsa")]
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::EcGroup,
+};
+use sha2::Sha512;
+use zeroize::Zeroizing;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// The minimum length of the seed in bytes
+pub const MIN_SEED_LEN: usize = 32;
+
+const HKDF_SHA512_LEN: usize = 64;
+#[cfg(any(feature = "ed25519", feature = "ecdsa"))]
+const V1_SALT: &[u8] = b"osshkeys key derivation v1";
+// Extra bytes to make the bias of the modulo reduction negligible
+#[cfg(feature = "ecdsa")]
+const ECDSA_EXTRA_BYTES: usize = 8;
+
+/// The versions of the deterministic key derivation scheme
+///
+/// The output of a released scheme version will never change,
+/// so the keys derived from the same seed and context can always be regenerated.
+///
+/// # Scheme V1
+/// 1. `PRK = HKDF-Extract(salt = "osshkeys key derivation v1", IKM = seed)` using HMAC-SHA512
+/// 2. `OKM = HKDF-Expand(PRK, info = keyname || 0x00 || context, L)`,
+///    where `keyname` is the SSH key name, such as `ssh-ed25519` or `ecdsa-sha2-nistp256`.
+/// 3. For Ed25519 keys, `L = 32`, and `OKM` is used as the secret key directly.
+/// 4. For EcDSA keys, `L = len(order) + 8`, and the private number is `(OKM mod (order - 1)) + 1`,
+///    where `OKM` is interpreted as a big endian integer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeriveScheme {
+    #[default]
+    V1,
+}
+
+/// Derive a key pair deterministically from the seed and the context string
+///
+/// Only Ed25519 and EcDSA keys are supported. The bits parameter has the same meaning as
+/// [`KeyPair::generate()`](../struct.KeyPair.html#method.generate).
+///
+/// The seed should be a high entropy secret with at least [`MIN_SEED_LEN`] bytes.
+/// Using different context strings with the same seed produces independent keys.
+pub fn derive_keypair(
+    scheme: DeriveScheme,
+    keytype: KeyType,
+    bits: usize,
+    seed: &[u8],
+    context: &str,
+) -> OsshResult<KeyPair> {
+    #[cfg(feature = "fips")]
+    crate::fips::refuse("deterministic key derivation")?;
+    if seed.len() < MIN_SEED_LEN {
+        return Err(ErrorKind::InvalidLength.into());
+    }
+    match scheme {
+        DeriveScheme::V1 => derive_v1(keytype, bits, seed, context),
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "ed25519", feature = "ecdsa")),
+    allow(unused_variables)
+)]
+fn derive_v1(keytype: KeyType, bits: usize, seed: &[u8], context: &str) -> OsshResult<KeyPair> {
+    match keytype {
+        #[cfg(feature = "ed25519")]
+        KeyType::ED25519 => {
+            if bits != 0 && bits != 256 {
+                return Err(ErrorKind::InvalidKeySize.into());
+            }
+            let mut secret = Zeroizing::new([0u8; SECRET_KEY_LENGTH]);
+            hkdf_sha512(
+                V1_SALT,
+                seed,
+                &[
+                    super::ed25519::ED25519_NAME.as_bytes(),
+                    b"\0",
+                    context.as_bytes(),
+                ],
+                &mut *secret,
+            )?;
+            Ok(Ed25519KeyPair {
+                key: Box::new(SigningKey::from_bytes(&secret)),
+                pkey: PKeyCache::new(),
+            }
+            .into())
+        }
+        #[cfg(feature = "ecdsa")]
+        KeyType::ECDSA => {
+            let curve = match bits {
+                0 | 256 => EcCurve::Nistp256,
+                384 => EcCurve::Nistp384,
+                521 => EcCurve::Nistp521,
+                _ => return Err(ErrorKind::InvalidKeySize.into()),
+            };
+            let group: EcGroup = curve.try_into()?;
+            let mut bn_ctx = BigNumContext::new()?;
+            let mut order = BigNum::new()?;
+            group.order(&mut order, &mut bn_ctx)?;
+
+            let mut okm = Zeroizing::new(vec![0u8; order.num_bytes() as usize + ECDSA_EXTRA_BYTES]);
+            hkdf_sha512(
+                V1_SALT,
+                seed,
+                &[curve.name().as_bytes(), b"\0", context.as_bytes()],
+                &mut okm,
+            )?;
+
+            let one = BigNum::from_u32(1)?;
+            let mut private_number = BigNum::from_slice(&okm)?;
+            let mut reduced = BigNum::new()?;
+            reduced.nnmod(&private_number, &(&order - &one), &mut bn_ctx)?;
+            private_number.clear();
+            let private_number = &reduced + &one;
+            reduced.clear();
+
+            Ok(EcDsaKeyPair::from_private_number(curve, &private_number)?.into())
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(ErrorKind::UnsupportType(format!("{:?}", keytype)).into()),
+    }
+}
+
+/// HKDF (RFC 5869) using HMAC-SHA512, the info is the concatenation of the slices
+pub(crate) fn hkdf_sha512(
+    salt: &[u8],
+    ikm: &[u8],
+    info: &[&[u8]],
+    okm: &mut [u8],
+) -> OsshResult<()> {
+    if okm.len() > 255 * HKDF_SHA512_LEN {
+        return Err(Error::from_kind(ErrorKind::InvalidLength));
+    }
+
+    let mut extract = HmacSha512::new_from_slice(salt).expect("HMAC accepts keys of any size");
+    extract.update(ikm);
+    let prk = Zeroizing::new(extract.finalize().into_bytes().to_vec());
+
+    let mut block = Zeroizing::new(Vec::with_capacity(HKDF_SHA512_LEN));
+    for (i, chunk) in okm.chunks_mut(HKDF_SHA512_LEN).enumerate() {
+        let mut expand = HmacSha512::new_from_slice(&prk).expect("HMAC accepts keys of any size");
+        expand.update(&block);
+        for part in info {
+            expand.update(part);
+        }
+        expand.update(&[(i + 1) as u8]);
+        block.clear();
+        block.extend_from_slice(&expand.finalize().into_bytes());
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::{FingerprintHash, PublicParts};
+
+    #[cfg(not(feature = "fips"))]
+    const SEED: [u8; 32] = [0x42; 32];
+
+    // RFC 5869 does not provide SHA-512 vectors, these are generated by Python's hmac module
+    const HKDF_OKM: [u8; 42] = [
+        0x57, 0x02, 0x6b, 0x6a, 0x13, 0x01, 0x4b, 0x87, 0x0f, 0x39, 0xe8, 0xb4, 0x61, 0x05, 0xc1,
+        0x2f, 0x29, 0x6e, 0xb0, 0x51, 0x5a, 0x81, 0xaf, 0xd6, 0xfb, 0x41, 0x9b, 0x0e, 0x63, 0xc5,
+        0xb5, 0x77, 0x75, 0x01, 0xcb, 0x46, 0x17, 0x54, 0x23, 0xba, 0x1b, 0x9f,
+    ];
+    #[cfg(not(feature = "fips"))]
+    const V1_ED25519_PUBKEY: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAqEWr48UubZUWl3J2bL64rf5Yzfu3be97zVK8cm7qGW";
+    #[cfg(not(feature = "fips"))]
+    const V1_ECDSA_PUBKEY: &str = "ecdsa-sha2-nistp384 AAAAE2VjZHNhLXNoYTItbmlzdHAzODQAAAAIbmlzdHAzODQAAABhBCZkHOrZU8e565SGeJrzVPtga0QAvi2ESIwWmq8XsgWlsD6ww9ggNiAtYFXqRS1G4PYX068zWxMXfR0qKoT3s0n1iZmY21T8fRLkkKBAQaxP7tajUCvKb6KAzskSR4mdSg==";
+
+    #[test]
+    fn hkdf_sha512_vector() {
+        let mut okm = [0u8; 42];
+        hkdf_sha512(b"salt", b"input key material", &[b"info"], &mut okm).unwrap();
+        assert_eq!(okm, HKDF_OKM);
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn derive_v1_vectors() {
+        let key = derive_keypair(DeriveScheme::V1, KeyType::ED25519, 0, &SEED, "host-1").unwrap();
+        assert_eq!(key.serialize_publickey().unwrap(), V1_ED25519_PUBKEY);
+        let key = derive_keypair(DeriveScheme::V1, KeyType::ECDSA, 384, &SEED, "host-1").unwrap();
+        assert_eq!(key.serialize_publickey().unwrap(), V1_ECDSA_PUBKEY);
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn derive_deterministic() {
+        for (keytype, bits) in [(KeyType::ED25519, 0), (KeyType::ECDSA, 384)] {
+            let key1 = derive_keypair(DeriveScheme::V1, keytype, bits, &SEED, "host-1").unwrap();
+            let key2 = derive_keypair(DeriveScheme::V1, keytype, bits, &SEED, "host-1").unwrap();
+            let key3 = derive_keypair(DeriveScheme::V1, keytype, bits, &SEED, "host-2").unwrap();
+            let fp1 = key1.fingerprint(FingerprintHash::SHA256).unwrap();
+            assert_eq!(fp1, key2.fingerprint(FingerprintHash::SHA256).unwrap());
+            assert_ne!(fp1, key3.fingerprint(FingerprintHash::SHA256).unwrap());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn derive_short_seed() {
+        let err = derive_keypair(DeriveScheme::V1, KeyType::ED25519, 0, &SEED[..16], "")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidLength);
+    }
+}
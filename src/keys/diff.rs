@@ -0,0 +1,223 @@
+use super::{KeyPair, PublicKey};
+use crate::error::*;
+use crate::format::ossh_privkey::{
+    encode_private_fields, ossh_priv_ciphername, ossh_priv_publickey,
+};
+use crate::sshbuf::SshReader;
+use std::fmt;
+use zeroize::Zeroizing;
+
+const SECTION_DIFF: &str = "diff";
+
+type Components = Vec<(&'static str, Zeroizing<Vec<u8>>)>;
+
+/// A difference between two key files found by [`diff_keys()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyDifference {
+    /// The key types differ, such as `ssh-rsa` and `ssh-ed25519`, so the components are not compared
+    KeyType(String, String),
+    /// The component of the key differs, named like the OpenSSH encoding, such as `n` of RSA or `Q` of EcDSA
+    Component(&'static str),
+    /// The comments differ, which is not a part of the key material
+    Comment(String, String),
+}
+
+impl fmt::Display for KeyDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyDifference::KeyType(a, b) => write!(f, "key type: {} != {}", a, b),
+            KeyDifference::Component(name) => write!(f, "component: {}", name),
+            KeyDifference::Comment(a, b) => write!(f, "comment: {:?} != {:?}", a, b),
+        }
+    }
+}
+
+/// The result of [`diff_keys()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDiff {
+    /// The first file has the private key
+    pub a_private: bool,
+    /// The second file has the private key
+    pub b_private: bool,
+    /// The differences in the order of the key type, the components and the comment
+    pub differences: Vec<KeyDifference>,
+}
+
+impl KeyDiff {
+    /// Check if the files contain the same key material
+    ///
+    /// Only the public components are compared if either file has no private key, and the comments are ignored.
+    pub fn same_key(&self) -> bool {
+        self.differences
+            .iter()
+            .all(|d| matches!(d, KeyDifference::Comment(..)))
+    }
+}
+
+/// Compare the keys of two key files in any supported format, like [`diff_keys_with()`] without the passphrases
+pub fn diff_keys(a: &str, b: &str) -> OsshResult<KeyDiff> {
+    diff_keys_with(a, None, b, None)
+}
+
+/// Compare the keys of two key files in any supported format, and report which components differ
+///
+/// Each file can be a private key or a public key. The private components are only compared if both files have the private key.
+/// Without the passphrase, the encrypted OpenSSH private key is compared as its public key,
+/// and the other encrypted private keys fail with [`ErrorKind::IncorrectPass`].
+pub fn diff_keys_with(
+    a: &str,
+    a_passphrase: Option<&str>,
+    b: &str,
+    b_passphrase: Option<&str>,
+) -> OsshResult<KeyDiff> {
+    let a = KeyComponents::parse(a, a_passphrase)?;
+    let b = KeyComponents::parse(b, b_passphrase)?;
+    let mut differences = Vec::new();
+    if a.keyname != b.keyname {
+        differences.push(KeyDifference::KeyType(a.keyname, b.keyname));
+    } else {
+        for (name, value) in &a.components {
+            let other = b.components.iter().find(|(n, _)| n == name);
+            if matches!(other, Some((_, other)) if other != value) {
+                differences.push(KeyDifference::Component(name));
+            }
+        }
+    }
+    if a.comment != b.comment {
+        differences.push(KeyDifference::Comment(a.comment, b.comment));
+    }
+    Ok(KeyDiff {
+        a_private: a.private,
+        b_private: b.private,
+        differences,
+    })
+}
+
+struct KeyComponents {
+    keyname: String,
+    comment: String,
+    private: bool,
+    components: Components,
+}
+
+impl KeyComponents {
+    fn parse(keystr: &str, passphrase: Option<&str>) -> OsshResult<Self> {
+        let trimmed = keystr.trim_start();
+        if !(trimmed.starts_with("-----BEGIN") && trimmed.contains("PRIVATE KEY-----")) {
+            return Self::from_public(&PublicKey::from_keystr(keystr)?);
+        }
+        if passphrase.is_none() {
+            let pemdata = ::pem::parse(keystr)?;
+            if pemdata.tag() == "OPENSSH PRIVATE KEY"
+                && ossh_priv_ciphername(pemdata.contents())? != "none"
+            {
+                return Self::from_public(&ossh_priv_publickey(pemdata.contents())?);
+            }
+        }
+        Self::from_private(&KeyPair::from_keystr(keystr, passphrase)?)
+    }
+
+    fn from_public(key: &PublicKey) -> OsshResult<Self> {
+        let blob = key.to_blob()?;
+        let mut reader = SshReader::new(&blob, SECTION_DIFF);
+        let keyname = reader.read_utf8("keyname")?.to_owned();
+        let components = read_components(&mut reader, public_fields(&keyname))?;
+        reader.finish()?;
+        Ok(KeyComponents {
+            keyname,
+            comment: key.comment().to_owned(),
+            private: false,
+            components,
+        })
+    }
+
+    fn from_private(key: &KeyPair) -> OsshResult<Self> {
+        let fields = encode_private_fields(key)?;
+        let mut reader = SshReader::new(&fields, SECTION_DIFF);
+        let keyname = reader.read_utf8("keyname")?.to_owned();
+        let components = read_components(&mut reader, private_fields(&keyname))?;
+        reader.finish()?;
+        Ok(KeyComponents {
+            keyname,
+            comment: key.comment().to_owned(),
+            private: true,
+            components,
+        })
+    }
+}
+
+fn read_components(reader: &mut SshReader, fields: &[&'static str]) -> OsshResult<Components> {
+    fields
+        .iter()
+        .map(|&name| Ok((name, Zeroizing::new(reader.read_string(name)?.to_vec()))))
+        .collect()
+}
+
+// The fields of the public key blob after the key name
+fn public_fields(keyname: &str) -> &'static [&'static str] {
+    match keyname {
+        "ssh-rsa" => &["e", "n"],
+        "ssh-dss" => &["p", "q", "g", "y"],
+        "ssh-ed25519" => &["pk"],
+        _ => &["curve", "Q"],
+    }
+}
+
+// The fields of the private section of the OpenSSH private key after the key name
+fn private_fields(keyname: &str) -> &'static [&'static str] {
+    match keyname {
+        "ssh-rsa" => &["n", "e", "d", "iqmp", "p", "q"],
+        "ssh-dss" => &["p", "q", "g", "y", "x"],
+        "ssh-ed25519" => &["pk", "sk"],
+        _ => &["curve", "Q", "d"],
+    }
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ed25519", not(feature = "fips")))]
+mod test {
+    use super::*;
+    use crate::cipher::Cipher;
+    use crate::keys::KeyType;
+
+    #[test]
+    fn diff_key_files() {
+        let rsa = include_str!("../../assets/openssh_rsa");
+        let rsa_pub = include_str!("../../assets/openssh_rsa.pub");
+
+        // The same key converted to the other formats
+        let keypair = KeyPair::from_keystr(rsa, None).unwrap();
+        let pkcs8 = keypair.serialize_pkcs8(None).unwrap();
+        let diff = diff_keys(rsa, &pkcs8).unwrap();
+        assert!(diff.same_key());
+        assert!(diff.a_private && diff.b_private);
+        assert!(matches!(diff.differences[..], [KeyDifference::Comment(..)]));
+        let diff = diff_keys(rsa, rsa_pub).unwrap();
+        assert!(diff.same_key());
+        assert!(!diff.b_private);
+        let encrypted = keypair
+            .serialize_openssh(Some("12345678"), Cipher::Aes256_Ctr)
+            .unwrap();
+        assert!(diff_keys(&encrypted, rsa_pub).unwrap().same_key());
+        let diff = diff_keys_with(&encrypted, Some("12345678"), rsa, None).unwrap();
+        assert!(diff.same_key() && diff.a_private);
+
+        // Another RSA key
+        let other = KeyPair::generate(KeyType::RSA, 2048)
+            .unwrap()
+            .serialize_pem(None)
+            .unwrap();
+        let diff = diff_keys(rsa, &other).unwrap();
+        assert!(!diff.same_key());
+        assert!(diff.differences.contains(&KeyDifference::Component("n")));
+        assert!(diff.differences.contains(&KeyDifference::Component("d")));
+        assert!(!diff.differences.contains(&KeyDifference::Component("e")));
+
+        let ed25519 = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let diff = diff_keys(rsa_pub, &ed25519.serialize_publickey().unwrap()).unwrap();
+        assert_eq!(
+            diff.differences[0],
+            KeyDifference::KeyType("ssh-rsa".into(), "ssh-ed25519".into())
+        );
+        assert!(diff_keys(rsa, "garbage").is_err());
+    }
+}
@@ -1,24 +1,32 @@
-use super::{Key, PrivateParts, PublicParts};
+#![cfg_attr(not(feature = "dsa"), allow(unused_imports))]
+
+use super::pkey_cache::PKeyCache;
+use super::{debug_keypair, Key, PrivateParts, PublicParts};
+use crate::backend::{Backend, CryptoBackend, HashAlg};
 use crate::error::{Error, ErrorKind, OsshResult};
 use crate::format::ossh_pubkey::*;
-use openssl::bn::BigNum;
-use openssl::dsa::{Dsa, DsaRef};
-use openssl::hash::MessageDigest;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::dsa::{Dsa, DsaRef, DsaSig};
 use openssl::pkey::{PKey, Private, Public};
-use openssl::sign::{Signer, Verifier};
+use rand_core::CryptoRngCore;
 use std::fmt;
+use zeroize::ZeroizeOnDrop;
 
 /// The key name returned by [`Key::keyname()`](../trait.Key.html#method.keyname)
 pub const DSA_NAME: &str = "ssh-dss";
 /// The key name returned by [`Key::short_keyname()`](../trait.Key.html#method.short_keyname)
 pub const DSA_SHORT_NAME: &str = "DSA";
+#[cfg(feature = "dsa")]
+pub(crate) const DSA_SIG_LEN: usize = 40;
 
 /// Represent the DSA public key
+#[cfg(feature = "dsa")]
 #[derive(Debug, Clone)]
 pub struct DsaPublicKey {
     dsa: Dsa<Public>,
 }
 
+#[cfg(feature = "dsa")]
 impl DsaPublicKey {
     /// Create the DSA public key from public components
     pub fn new(
@@ -43,8 +51,20 @@ impl DsaPublicKey {
     pub(crate) fn ossl_pkey(&self) -> Result<PKey<Public>, openssl::error::ErrorStack> {
         PKey::from_dsa(self.dsa.clone())
     }
+
+    /// Verify the signature blob in the SSH encoding, which is the 20 bytes `r` followed by the 20 bytes `s`
+    pub(crate) fn verify_ssh(&self, data: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        if sig.len() != DSA_SIG_LEN {
+            return Err(ErrorKind::InvalidLength.into());
+        }
+        let (r, s) = sig.split_at(DSA_SIG_LEN / 2);
+        let der = DsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?
+            .to_der()?;
+        self.verify(data, &der)
+    }
 }
 
+#[cfg(feature = "dsa")]
 impl Key for DsaPublicKey {
     fn size(&self) -> usize {
         self.dsa.p().num_bits() as usize
@@ -59,6 +79,7 @@ impl Key for DsaPublicKey {
     }
 }
 
+#[cfg(feature = "dsa")]
 impl PublicParts for DsaPublicKey {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_dsa_pubkey(&self.dsa)
@@ -66,12 +87,11 @@ impl PublicParts for DsaPublicKey {
 
     fn verify(&self, data: &[u8], sig: &[u8]) -> Result<bool, Error> {
         let pkey = PKey::from_dsa(self.dsa.clone())?;
-        let mut veri = Verifier::new(MessageDigest::sha1(), &pkey)?;
-        veri.update(data)?;
-        Ok(veri.verify(sig)?)
+        Backend::verify(&pkey, Some(HashAlg::Sha1), data, sig)
     }
 }
 
+#[cfg(feature = "dsa")]
 impl PartialEq for DsaPublicKey {
     fn eq(&self, other: &Self) -> bool {
         (self.dsa.p() == other.dsa.p())
@@ -81,6 +101,7 @@ impl PartialEq for DsaPublicKey {
     }
 }
 
+#[cfg(feature = "dsa")]
 impl fmt::Display for DsaPublicKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(&serialize_ossh_pubkey(self, "").unwrap())
@@ -88,23 +109,50 @@ impl fmt::Display for DsaPublicKey {
 }
 
 /// Represent the DSA key pair
+#[cfg(feature = "dsa")]
 pub struct DsaKeyPair {
     dsa: Dsa<Private>,
+    pkey: PKeyCache<Private>,
+}
+
+#[cfg(feature = "dsa")]
+impl ZeroizeOnDrop for DsaKeyPair {}
+
+#[cfg(feature = "dsa")]
+impl fmt::Debug for DsaKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(unused_mut)]
+        let mut s = debug_keypair(f, "DsaKeyPair", self);
+        #[cfg(feature = "full-debug")]
+        s.field("priv_key", &self.dsa.priv_key());
+        s.finish_non_exhaustive()
+    }
 }
 
+#[cfg(feature = "dsa")]
 impl DsaKeyPair {
     pub(crate) fn from_ossl_dsa(key: Dsa<Private>) -> Self {
-        Self { dsa: key }
+        Self {
+            dsa: key,
+            pkey: PKeyCache::new(),
+        }
     }
 
     pub(crate) fn ossl_dsa(&self) -> &DsaRef<Private> {
         &self.dsa
     }
 
+    pub(crate) fn ossl_pkey(&self) -> Result<PKey<Private>, openssl::error::ErrorStack> {
+        self.pkey
+            .get_or_try_init(|| PKey::from_dsa(self.dsa.clone()))
+    }
+
     /// Generate DSA key pair
     ///
     /// The bits parameter should be 1024 bits or `0` to use default length (1024 bits).
     pub fn generate(mut bits: usize) -> OsshResult<Self> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(DSA_NAME)?;
         if bits == 0 {
             bits = 1024;
         }
@@ -113,6 +161,39 @@ impl DsaKeyPair {
         }
         Ok(DsaKeyPair {
             dsa: Dsa::generate(bits as u32)?,
+            pkey: PKeyCache::new(),
+        })
+    }
+
+    /// Generate DSA key pair using the given random number generator
+    ///
+    /// Only the private key is drawn from `rng`, the domain parameters are still generated by OpenSSL.
+    /// The bits parameter has the same limitation as [`generate()`](#method.generate).
+    pub fn generate_with_rng<R: CryptoRngCore + ?Sized>(
+        mut bits: usize,
+        rng: &mut R,
+    ) -> OsshResult<Self> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(DSA_NAME)?;
+        if bits == 0 {
+            bits = 1024;
+        }
+        if bits != 1024 {
+            return Err(Error::from_kind(ErrorKind::InvalidKeySize));
+        }
+        let params = Dsa::generate_params(bits as u32)?;
+        let p = params.p().to_owned()?;
+        let q = params.q().to_owned()?;
+        let g = params.g().to_owned()?;
+
+        let mut ctx = BigNumContext::new()?;
+        let priv_key = super::random_bignum_below(rng, &q)?;
+        let mut pub_key = BigNum::new()?;
+        pub_key.mod_exp(&g, &priv_key, &p, &mut ctx)?;
+
+        Ok(DsaKeyPair {
+            dsa: Dsa::from_private_components(p, q, g, priv_key, pub_key)?,
+            pkey: PKeyCache::new(),
         })
     }
 
@@ -124,8 +205,16 @@ impl DsaKeyPair {
         let pub_key = self.dsa.pub_key().to_owned()?;
         Ok(DsaPublicKey::new(p, q, g, pub_key)?)
     }
+    /// Sign the data and encode the signature blob in the SSH encoding, which is the 20 bytes `r` followed by the 20 bytes `s`
+    pub(crate) fn sign_ssh(&self, data: &[u8]) -> OsshResult<Vec<u8>> {
+        let sig = DsaSig::from_der(&self.sign(data)?)?;
+        let mut buf = sig.r().to_vec_padded((DSA_SIG_LEN / 2) as i32)?;
+        buf.extend(sig.s().to_vec_padded((DSA_SIG_LEN / 2) as i32)?);
+        Ok(buf)
+    }
 }
 
+#[cfg(feature = "dsa")]
 impl Key for DsaKeyPair {
     fn size(&self) -> usize {
         self.dsa.p().num_bits() as usize
@@ -140,6 +229,7 @@ impl Key for DsaKeyPair {
     }
 }
 
+#[cfg(feature = "dsa")]
 impl PublicParts for DsaKeyPair {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_dsa_pubkey(&self.dsa)
@@ -150,17 +240,18 @@ impl PublicParts for DsaKeyPair {
     }
 }
 
+#[cfg(feature = "dsa")]
 impl PrivateParts for DsaKeyPair {
     fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-        let pkey = PKey::from_dsa(self.dsa.clone())?;
-        let mut sign = Signer::new(MessageDigest::sha1(), &pkey)?;
-        sign.update(data)?;
-        Ok(sign.sign_to_vec()?)
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(DSA_NAME)?;
+        let pkey = self.ossl_pkey()?;
+        Backend::sign(&pkey, Some(HashAlg::Sha1), data)
     }
 }
 
 #[allow(non_upper_case_globals)]
-#[cfg(test)]
+#[cfg(all(test, feature = "dsa"))]
 mod test {
     use super::*;
     use openssl::bn::BigNum;
@@ -1,15 +1,23 @@
-use super::{Key, PrivateParts, PublicParts};
+#![cfg_attr(not(feature = "ecdsa"), allow(unused_imports))]
+
+use super::agreement::{ossl_derive, SharedSecretKdf};
+use super::pkey_cache::PKeyCache;
+use super::{debug_keypair, Key, PrivateParts, PublicParts};
+use crate::backend::{Backend, CryptoBackend, HashAlg};
 use crate::error::{Error, ErrorKind, OsshResult};
 use crate::format::ossh_pubkey::*;
-use openssl::bn::{BigNumContext, BigNumRef};
+use crate::sshbuf::{SshReader, SshWriteExt};
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
 use openssl::ec::{EcGroup, EcKey, EcKeyRef, EcPoint, EcPointRef};
-use openssl::hash::MessageDigest;
+use openssl::ecdsa::EcdsaSig;
 use openssl::nid::Nid;
 use openssl::pkey::{PKey, Private, Public};
-use openssl::sign::{Signer, Verifier};
+use rand_core::CryptoRngCore;
 use std::fmt;
 use std::str::FromStr;
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 
+#[cfg(feature = "ecdsa")]
 const ECDSA_DEF_SIZE: usize = 256;
 /// The name of 256 bits curve key returned by [`Key::keyname()`](../trait.Key.html#method.keyname)
 pub const NIST_P256_NAME: &str = "ecdsa-sha2-nistp256";
@@ -66,7 +74,17 @@ impl EcCurve {
         }
     }
 
-    fn nid(self) -> Nid {
+    /// The digest used by the SSH signatures of this curve
+    #[cfg(feature = "ecdsa")]
+    pub(crate) fn digest(self) -> HashAlg {
+        match self {
+            EcCurve::Nistp256 => HashAlg::Sha256,
+            EcCurve::Nistp384 => HashAlg::Sha384,
+            EcCurve::Nistp521 => HashAlg::Sha512,
+        }
+    }
+
+    pub(crate) fn nid(self) -> Nid {
         match self {
             EcCurve::Nistp256 => Nid::X9_62_PRIME256V1,
             EcCurve::Nistp384 => Nid::SECP384R1,
@@ -77,16 +95,24 @@ impl EcCurve {
 
 impl FromStr for EcCurve {
     type Err = Error;
+    /// Parse from the curve identifier, such as `nistp256`, or the ecdsa key name
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "nistp256" => Ok(EcCurve::Nistp256),
             "nistp384" => Ok(EcCurve::Nistp384),
             "nistp521" => Ok(EcCurve::Nistp521),
-            _ => Err(ErrorKind::UnsupportCurve.into()),
+            _ => EcCurve::from_name(s.strip_suffix(super::CERT_SUFFIX).unwrap_or(s)),
         }
     }
 }
 
+impl fmt::Display for EcCurve {
+    /// Write the curve identifier, such as `nistp256`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.ident())
+    }
+}
+
 impl TryInto<EcGroup> for EcCurve {
     type Error = openssl::error::ErrorStack;
     fn try_into(self) -> Result<EcGroup, Self::Error> {
@@ -95,12 +121,14 @@ impl TryInto<EcGroup> for EcCurve {
 }
 
 /// Represent the EcDSA public key
+#[cfg(feature = "ecdsa")]
 #[derive(Clone, Debug)]
 pub struct EcDsaPublicKey {
     key: EcKey<Public>,
     curve: EcCurve,
 }
 
+#[cfg(feature = "ecdsa")]
 impl EcDsaPublicKey {
     /// Create the EcDSA public key from the elliptic curve and the public point
     pub(crate) fn new(
@@ -146,8 +174,21 @@ impl EcDsaPublicKey {
     pub(crate) fn ossl_pkey(&self) -> Result<PKey<Public>, openssl::error::ErrorStack> {
         PKey::from_ec_key(self.key.clone())
     }
+
+    /// Verify the signature blob in the SSH encoding `mpint r, mpint s`
+    pub(crate) fn verify_ssh(&self, data: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        let mut reader = SshReader::new(sig, super::SECTION_SIGNATURE);
+        let r = reader.read_mpint("r")?;
+        let s = reader.read_mpint("s")?;
+        reader.finish()?;
+        let der = EcdsaSig::from_private_components(r, s)?.to_der()?;
+
+        let pkey = self.ossl_pkey()?;
+        Backend::verify(&pkey, Some(self.curve.digest()), data, &der)
+    }
 }
 
+#[cfg(feature = "ecdsa")]
 impl Key for EcDsaPublicKey {
     fn size(&self) -> usize {
         self.curve.size()
@@ -162,6 +203,7 @@ impl Key for EcDsaPublicKey {
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl PublicParts for EcDsaPublicKey {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_ecdsa_pubkey(self.curve, &self.key)
@@ -169,12 +211,11 @@ impl PublicParts for EcDsaPublicKey {
 
     fn verify(&self, data: &[u8], sig: &[u8]) -> Result<bool, Error> {
         let pkey = PKey::from_ec_key(self.key.clone())?;
-        let mut veri = Verifier::new(MessageDigest::sha1(), &pkey)?;
-        veri.update(data)?;
-        Ok(veri.verify(sig)?)
+        Backend::verify(&pkey, Some(HashAlg::Sha1), data, sig)
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl PartialEq for EcDsaPublicKey {
     fn eq(&self, other: &Self) -> bool {
         let mut bn_ctx = BigNumContext::new().unwrap();
@@ -188,6 +229,7 @@ impl PartialEq for EcDsaPublicKey {
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl fmt::Display for EcDsaPublicKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(&serialize_ossh_pubkey(self, "").unwrap())
@@ -195,11 +237,28 @@ impl fmt::Display for EcDsaPublicKey {
 }
 
 /// Represent the EcDSA key pair
+#[cfg(feature = "ecdsa")]
 pub struct EcDsaKeyPair {
     key: EcKey<Private>,
     curve: EcCurve,
+    pkey: PKeyCache<Private>,
 }
 
+#[cfg(feature = "ecdsa")]
+impl ZeroizeOnDrop for EcDsaKeyPair {}
+
+#[cfg(feature = "ecdsa")]
+impl fmt::Debug for EcDsaKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(unused_mut)]
+        let mut s = debug_keypair(f, "EcDsaKeyPair", self);
+        #[cfg(feature = "full-debug")]
+        s.field("private_key", &self.key.private_key());
+        s.finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "ecdsa")]
 impl EcDsaKeyPair {
     pub(crate) fn from_ossl_ec(key: EcKey<Private>) -> OsshResult<Self> {
         let curve = match key.group().curve_name().unwrap_or(Nid::UNDEF) {
@@ -209,13 +268,22 @@ impl EcDsaKeyPair {
             _ => return Err(ErrorKind::UnsupportCurve.into()),
         };
 
-        Ok(Self { key, curve })
+        Ok(Self {
+            key,
+            curve,
+            pkey: PKeyCache::new(),
+        })
     }
 
     pub(crate) fn ossl_ec(&self) -> &EcKeyRef<Private> {
         &self.key
     }
 
+    pub(crate) fn ossl_pkey(&self) -> Result<PKey<Private>, openssl::error::ErrorStack> {
+        self.pkey
+            .get_or_try_init(|| PKey::from_ec_key(self.key.clone()))
+    }
+
     pub(crate) fn new(
         curve: EcCurve,
         public_key: &EcPointRef,
@@ -226,6 +294,7 @@ impl EcDsaKeyPair {
         Ok(Self {
             key: EcKey::from_private_components(&group, private_number, public_key)?,
             curve,
+            pkey: PKeyCache::new(),
         })
     }
 
@@ -260,9 +329,54 @@ impl EcDsaKeyPair {
         Ok(EcDsaKeyPair {
             key: EcKey::generate(&group)?,
             curve,
+            pkey: PKeyCache::new(),
         })
     }
 
+    /// Generate EcDSA key pair using the given random number generator
+    ///
+    /// The bits parameter has the same limitation as [`generate()`](#method.generate).
+    pub fn generate_with_rng<R: CryptoRngCore + ?Sized>(
+        mut bits: usize,
+        rng: &mut R,
+    ) -> OsshResult<Self> {
+        // The private number is not generated by the validated module
+        #[cfg(feature = "fips")]
+        crate::fips::refuse("EcDSA key generation with a custom random number generator")?;
+        if bits == 0 {
+            bits = ECDSA_DEF_SIZE;
+        }
+        let curve = match bits {
+            256 => EcCurve::Nistp256,
+            384 => EcCurve::Nistp384,
+            521 => EcCurve::Nistp521,
+            _ => return Err(Error::from_kind(ErrorKind::InvalidKeySize)),
+        };
+        let group: EcGroup = curve.try_into()?;
+
+        let mut bn_ctx = BigNumContext::new()?;
+        let mut order = BigNum::new()?;
+        group.order(&mut order, &mut bn_ctx)?;
+        let private_number = super::random_bignum_below(rng, &order)?;
+
+        Self::from_private_number(curve, &private_number)
+    }
+
+    /// Construct the key pair from the private number, computing the public point from it
+    pub(crate) fn from_private_number(
+        curve: EcCurve,
+        private_number: &BigNumRef,
+    ) -> OsshResult<Self> {
+        let group: EcGroup = curve.try_into()?;
+        let bn_ctx = BigNumContext::new()?;
+        let mut public_key = EcPoint::new(&group)?;
+        // `mul_generator2()` is only available in the newer versions of rust-openssl
+        #[allow(deprecated)]
+        public_key.mul_generator(&group, private_number, &bn_ctx)?;
+
+        Self::new(curve, &public_key, private_number)
+    }
+
     /// Get the key's elliptic curve type
     pub fn curve(&self) -> EcCurve {
         self.curve
@@ -272,8 +386,41 @@ impl EcDsaKeyPair {
     pub fn clone_public_key(&self) -> Result<EcDsaPublicKey, Error> {
         Ok(EcDsaPublicKey::new(self.curve, self.key.public_key())?)
     }
+
+    /// Sign the data and encode the signature blob in the SSH encoding `mpint r, mpint s`
+    /// Compute the ECDH shared secret with the peer public key, which is the x-coordinate of the shared point
+    ///
+    /// Fail with [`ErrorKind::TypeNotMatch`] if the curves are different.
+    /// The raw secret should go through a KDF, see [`derive_shared_secret_with()`](#method.derive_shared_secret_with).
+    pub fn derive_shared_secret(&self, peer: &EcDsaPublicKey) -> OsshResult<Zeroizing<Vec<u8>>> {
+        if self.curve != peer.curve {
+            return Err(ErrorKind::TypeNotMatch.into());
+        }
+        ossl_derive(self.ossl_pkey()?, peer.ossl_pkey()?)
+    }
+
+    /// Compute the ECDH shared secret with the peer public key, then derive the key material with the KDF
+    pub fn derive_shared_secret_with<K: SharedSecretKdf + ?Sized>(
+        &self,
+        peer: &EcDsaPublicKey,
+        kdf: &K,
+    ) -> OsshResult<Zeroizing<Vec<u8>>> {
+        kdf.derive(&self.derive_shared_secret(peer)?)
+    }
+
+    pub(crate) fn sign_ssh(&self, data: &[u8]) -> OsshResult<Vec<u8>> {
+        let pkey = self.ossl_pkey()?;
+        let der = Backend::sign(&pkey, Some(self.curve.digest()), data)?;
+        let sig = EcdsaSig::from_der(&der)?;
+
+        let mut buf = Vec::new();
+        buf.write_mpint(sig.r())?;
+        buf.write_mpint(sig.s())?;
+        Ok(buf)
+    }
 }
 
+#[cfg(feature = "ecdsa")]
 impl Key for EcDsaKeyPair {
     fn size(&self) -> usize {
         self.curve.size()
@@ -288,6 +435,7 @@ impl Key for EcDsaKeyPair {
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl PublicParts for EcDsaKeyPair {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_ecdsa_pubkey(self.curve, &self.key)
@@ -298,15 +446,15 @@ impl PublicParts for EcDsaKeyPair {
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl PrivateParts for EcDsaKeyPair {
     fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-        let pkey = PKey::from_ec_key(self.key.clone())?;
-        let mut sign = Signer::new(MessageDigest::sha1(), &pkey)?;
-        sign.update(data)?;
-        Ok(sign.sign_to_vec()?)
+        let pkey = self.ossl_pkey()?;
+        Backend::sign(&pkey, Some(HashAlg::Sha1), data)
     }
 }
 
+#[cfg(feature = "ecdsa")]
 fn into_ec_point(curve: EcCurve, public_key: &[u8]) -> Result<EcPoint, openssl::error::ErrorStack> {
     let mut bn_ctx = BigNumContext::new()?;
     let group: EcGroup = curve.try_into()?;
@@ -314,7 +462,7 @@ fn into_ec_point(curve: EcCurve, public_key: &[u8]) -> Result<EcPoint, openssl::
 }
 
 #[allow(non_upper_case_globals)]
-#[cfg(test)]
+#[cfg(all(test, feature = "ecdsa"))]
 mod test {
     use super::*;
     use openssl::bn::BigNumContext;
@@ -350,4 +498,19 @@ mod test {
         let key = get_test_pubkey().unwrap();
         assert_eq!(key.size(), 256);
     }
+
+    #[test]
+    fn ecdsa_curve_names() {
+        for curve in [EcCurve::Nistp256, EcCurve::Nistp384, EcCurve::Nistp521] {
+            assert_eq!(curve.to_string().parse::<EcCurve>().unwrap(), curve);
+            assert_eq!(curve.name().parse::<EcCurve>().unwrap(), curve);
+        }
+        assert_eq!(
+            "ecdsa-sha2-nistp521-cert-v01@openssh.com"
+                .parse::<EcCurve>()
+                .unwrap(),
+            EcCurve::Nistp521
+        );
+        assert!("nistp192".parse::<EcCurve>().is_err());
+    }
 }
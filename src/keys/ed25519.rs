@@ -1,6 +1,13 @@
-use super::{Key, PrivateParts, PublicParts};
+#![cfg_attr(not(feature = "ed25519"), allow(unused_imports))]
+
+use super::pkey_cache::PKeyCache;
+use super::{debug_keypair, Key, PrivateParts, PublicParts};
+#[cfg(feature = "ed25519-batch")]
+use super::{PublicKey, PublicKeyType};
 use crate::error::{Error, ErrorKind, OsshResult};
 use crate::format::ossh_pubkey::*;
+#[cfg(feature = "ed25519-batch")]
+use crate::sshbuf::SshReader;
 use openssl::pkey::{Id, PKey, Private, Public};
 #[rustfmt::skip]
 use ed25519_dalek::{
@@ -11,20 +18,32 @@ use ed25519_dalek::{
     Verifier,
     PUBLIC_KEY_LENGTH,
 };
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::{clamp_integer, Scalar};
 use rand::rngs::OsRng;
+use rand_core::CryptoRngCore;
+use sha2::{Digest, Sha512};
 use std::fmt;
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 
 /// The key name returned by [`Key::keyname()`](../trait.Key.html#method.keyname)
 pub const ED25519_NAME: &str = "ssh-ed25519";
 /// The short key name returned by [`Key::short_keyname()`](../trait.Key.html#method.short_keyname)
 pub const ED25519_SHORT_NAME: &str = "ED25519";
 
+#[cfg(feature = "ed25519")]
+const DOM2_PREFIX: &[u8] = b"SigEd25519 no Ed25519 collisions";
+#[cfg(feature = "ed25519")]
+const MAX_CONTEXT_LEN: usize = 255;
+
 /// Represent the Ed25519 public key
+#[cfg(feature = "ed25519")]
 #[derive(Debug, Clone)]
 pub struct Ed25519PublicKey {
     key: Box<VerifyingKey>,
 }
 
+#[cfg(feature = "ed25519")]
 impl Ed25519PublicKey {
     /// Create the Ed25519 public key from public components
     pub fn new(key: &[u8; PUBLIC_KEY_LENGTH]) -> Result<Self, ed25519_dalek::SignatureError> {
@@ -42,8 +61,53 @@ impl Ed25519PublicKey {
     pub(crate) fn ossl_pkey(&self) -> Result<PKey<Public>, openssl::error::ErrorStack> {
         PKey::public_key_from_raw_bytes(self.key.as_bytes(), Id::ED25519)
     }
+
+    pub(crate) fn as_bytes(&self) -> &[u8; PUBLIC_KEY_LENGTH] {
+        self.key.as_bytes()
+    }
+
+    /// The u-coordinate of the point in Montgomery form, which is the X25519 public key
+    pub(crate) fn to_montgomery(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.key.to_montgomery().to_bytes()
+    }
+
+    /// Verify the Ed25519ctx signature of RFC 8032 made by [`Ed25519KeyPair::sign_with_context()`]
+    pub fn verify_with_context(&self, data: &[u8], context: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        if context.is_empty() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        self.verify_dom2(0, context, data, sig)
+    }
+
+    /// Verify the Ed25519ph signature of RFC 8032 made by [`Ed25519KeyPair::sign_prehashed()`]
+    pub fn verify_prehashed(
+        &self,
+        data: &[u8],
+        context: Option<&[u8]>,
+        sig: &[u8],
+    ) -> OsshResult<bool> {
+        self.verify_dom2(1, context.unwrap_or_default(), &Sha512::digest(data), sig)
+    }
+
+    fn verify_dom2(&self, flag: u8, context: &[u8], msg: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(ED25519_NAME)?;
+        let sig = Signature::try_from(sig)?;
+        let pubkey = CompressedEdwardsY(*self.key.as_bytes());
+        let (a, s) = match (
+            pubkey.decompress(),
+            Option::<Scalar>::from(Scalar::from_canonical_bytes(*sig.s_bytes())),
+        ) {
+            (Some(a), Some(s)) => (a, s),
+            _ => return Ok(false),
+        };
+        let k = dom2_hash(flag, context, &[sig.r_bytes(), pubkey.as_bytes(), msg])?;
+        let r = EdwardsPoint::vartime_double_scalar_mul_basepoint(&k, &-a, &s);
+        Ok(r.compress().as_bytes() == sig.r_bytes())
+    }
 }
 
+#[cfg(feature = "ed25519")]
 impl Key for Ed25519PublicKey {
     fn size(&self) -> usize {
         256
@@ -58,23 +122,28 @@ impl Key for Ed25519PublicKey {
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl PublicParts for Ed25519PublicKey {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_ed25519_pubkey(&self.key)
     }
 
     fn verify(&self, data: &[u8], sig: &[u8]) -> Result<bool, Error> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(ED25519_NAME)?;
         let ed25519_sig = Signature::try_from(sig)?;
         Ok(self.key.verify(data, &ed25519_sig).is_ok())
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl PartialEq for Ed25519PublicKey {
     fn eq(&self, other: &Self) -> bool {
         self.key == other.key
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl fmt::Display for Ed25519PublicKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(&serialize_ossh_pubkey(self, "").unwrap())
@@ -82,10 +151,27 @@ impl fmt::Display for Ed25519PublicKey {
 }
 
 /// Represent the Ed25519 key pair
+#[cfg(feature = "ed25519")]
 pub struct Ed25519KeyPair {
     pub(crate) key: Box<SigningKey>,
+    pub(crate) pkey: PKeyCache<Private>,
 }
 
+#[cfg(feature = "ed25519")]
+impl ZeroizeOnDrop for Ed25519KeyPair {}
+
+#[cfg(feature = "ed25519")]
+impl fmt::Debug for Ed25519KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(unused_mut)]
+        let mut s = debug_keypair(f, "Ed25519KeyPair", self);
+        #[cfg(feature = "full-debug")]
+        s.field("secret", self.key.as_bytes());
+        s.finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "ed25519")]
 impl Key for Ed25519KeyPair {
     fn size(&self) -> usize {
         256
@@ -100,17 +186,31 @@ impl Key for Ed25519KeyPair {
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl Ed25519KeyPair {
     /// Generate Ed25519 key pair
     ///
     /// The bits parameter should be 256 bits or `0` to use default length (256 bits).
     pub fn generate(bits: usize) -> OsshResult<Self> {
+        Self::generate_with_rng(bits, &mut OsRng)
+    }
+
+    /// Generate Ed25519 key pair using the given random number generator
+    ///
+    /// The bits parameter has the same limitation as [`generate()`](#method.generate).
+    pub fn generate_with_rng<R: CryptoRngCore + ?Sized>(
+        bits: usize,
+        rng: &mut R,
+    ) -> OsshResult<Self> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(ED25519_NAME)?;
         if bits != 0 && bits != 256 {
             return Err(Error::from_kind(ErrorKind::InvalidKeySize));
         }
 
         Ok(Ed25519KeyPair {
-            key: Box::new(SigningKey::generate(&mut OsRng)),
+            key: Box::new(SigningKey::generate(rng)),
+            pkey: PKeyCache::new(),
         })
     }
 
@@ -122,6 +222,7 @@ impl Ed25519KeyPair {
         }
         Ok(Ed25519KeyPair {
             key: Box::new(secret_key),
+            pkey: PKeyCache::new(),
         })
     }
 
@@ -135,35 +236,134 @@ impl Ed25519KeyPair {
     pub(crate) fn from_ossl_ed25519(key: &[u8]) -> Result<Self, Error> {
         Ok(Self {
             key: Box::new(SigningKey::from_keypair_bytes(key.try_into()?)?),
+            pkey: PKeyCache::new(),
         })
     }
 
     pub(crate) fn ossl_pkey(&self) -> Result<PKey<Private>, openssl::error::ErrorStack> {
-        PKey::private_key_from_raw_bytes(&self.key.to_bytes(), Id::ED25519)
+        self.pkey.get_or_try_init(|| {
+            let secret = Zeroizing::new(self.key.to_bytes());
+            PKey::private_key_from_raw_bytes(&*secret, Id::ED25519)
+        })
+    }
+
+    /// Sign the data with the Ed25519ctx variant of RFC 8032, binding the signature to the context
+    ///
+    /// The context must be 1 to 255 bytes, or fail with [`ErrorKind::InvalidArgument`].
+    /// The signature is not compatible with the pure Ed25519 used by SSH.
+    pub fn sign_with_context(&self, data: &[u8], context: &[u8]) -> OsshResult<Vec<u8>> {
+        if context.is_empty() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        self.sign_dom2(0, context, data)
+    }
+
+    /// Sign the SHA-512 digest of the data with the Ed25519ph variant of RFC 8032
+    ///
+    /// The optional context must be at most 255 bytes, or fail with [`ErrorKind::InvalidArgument`].
+    pub fn sign_prehashed(&self, data: &[u8], context: Option<&[u8]>) -> OsshResult<Vec<u8>> {
+        self.sign_dom2(1, context.unwrap_or_default(), &Sha512::digest(data))
+    }
+
+    fn sign_dom2(&self, flag: u8, context: &[u8], msg: &[u8]) -> OsshResult<Vec<u8>> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(ED25519_NAME)?;
+        let mut hash = Zeroizing::new([0u8; 64]);
+        hash.copy_from_slice(&Sha512::digest(self.key.as_bytes()));
+        let mut lower = Zeroizing::new([0u8; 32]);
+        lower.copy_from_slice(&hash[..32]);
+        let s = Zeroizing::new(Scalar::from_bytes_mod_order(clamp_integer(*lower)));
+        let pubkey = self.key.verifying_key();
+
+        let r = Zeroizing::new(dom2_hash(flag, context, &[&hash[32..], msg])?);
+        let big_r = EdwardsPoint::mul_base(&r).compress();
+        let k = dom2_hash(flag, context, &[big_r.as_bytes(), pubkey.as_bytes(), msg])?;
+        let mut sig = big_r.to_bytes().to_vec();
+        sig.extend_from_slice((k * *s + *r).as_bytes());
+        Ok(sig)
     }
 }
 
+// The SHA-512 of `dom2(flag, context) || parts...` reduced to a scalar
+#[cfg(feature = "ed25519")]
+fn dom2_hash(flag: u8, context: &[u8], parts: &[&[u8]]) -> OsshResult<Scalar> {
+    if context.len() > MAX_CONTEXT_LEN {
+        return Err(ErrorKind::InvalidArgument.into());
+    }
+    let mut hasher = Sha512::new();
+    hasher.update(DOM2_PREFIX);
+    hasher.update([flag, context.len() as u8]);
+    hasher.update(context);
+    for part in parts {
+        hasher.update(part);
+    }
+    Ok(Scalar::from_hash(hasher))
+}
+
+#[cfg(feature = "ed25519")]
 impl PublicParts for Ed25519KeyPair {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_ed25519_pubkey(&self.key.verifying_key())
     }
 
     fn verify(&self, data: &[u8], sig: &[u8]) -> Result<bool, Error> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(ED25519_NAME)?;
         let ed25519_sig = Signature::try_from(sig)?;
         Ok(self.key.verify(data, &ed25519_sig).is_ok())
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl PrivateParts for Ed25519KeyPair {
     fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "fips")]
+        crate::fips::refuse(ED25519_NAME)?;
         Ok(self.key.sign(data).to_bytes().to_vec())
     }
 }
 
+/// Verify many Ed25519 SSH signatures at once with the batch verification of ed25519-dalek (Feature `ed25519-batch`)
+///
+/// Each item is the public key, the signed data and the signature in the SSH encoding,
+/// like [`PublicKey::verify_ssh_signature()`]. Return `true` only if all the signatures are valid,
+/// so verify them one by one to find the invalid ones. An empty batch is valid.
+///
+/// The batch equation is cofactored and randomized, so it may accept a few crafted signatures
+/// which the single verification rejects. Fail with [`ErrorKind::TypeNotMatch`] if any key or signature is not Ed25519.
+#[cfg(feature = "ed25519-batch")]
+pub fn verify_batch(items: &[(&PublicKey, &[u8], &[u8])]) -> OsshResult<bool> {
+    #[cfg(feature = "fips")]
+    crate::fips::refuse(ED25519_NAME)?;
+    let mut messages = Vec::with_capacity(items.len());
+    let mut signatures = Vec::with_capacity(items.len());
+    let mut keys = Vec::with_capacity(items.len());
+    for &(pubkey, data, sig) in items {
+        let key = match &pubkey.key {
+            #[cfg(feature = "ed25519")]
+            PublicKeyType::ED25519(key) => key,
+            _ => return Err(ErrorKind::TypeNotMatch.into()),
+        };
+        let mut reader = SshReader::new(sig, super::SECTION_SIGNATURE);
+        let algo = reader.read_utf8("algorithm")?;
+        let sig = reader.read_string("signature")?;
+        reader.finish()?;
+        if algo != ED25519_NAME {
+            return Err(ErrorKind::TypeNotMatch.into());
+        }
+        messages.push(data);
+        signatures.push(Signature::try_from(sig)?);
+        keys.push(*key.key);
+    }
+    Ok(ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok())
+}
+
 #[allow(non_upper_case_globals)]
-#[cfg(test)]
+#[cfg(all(test, feature = "ed25519"))]
 mod test {
     use super::*;
+    #[cfg(not(feature = "fips"))]
+    use hex_literal::hex;
 
     const pub_str: &str =
         "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIMoWBluPErgKhNja3lHEf7ie6AVzR24mPRd742xEYodC";
@@ -188,4 +388,101 @@ mod test {
         let key = get_test_pubkey().unwrap();
         assert_eq!(key.size(), 256);
     }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn ed25519_context_signatures() {
+        let keypair = |seed: [u8; 32], pk: [u8; 32]| {
+            Ed25519KeyPair::from_bytes(&pk, &[seed, pk].concat()).unwrap()
+        };
+
+        // Test vectors of RFC 8032 section 7.2
+        let key = keypair(
+            hex!("0305334e381af78f141cb666f6199f57bc3495335a256a95bd2a55bf546663f6"),
+            hex!("dfc9425e4f968f7f0c29f0259cf5f9aed6851c2bb4ad8bfb860cfee0ab248292"),
+        );
+        let msg = hex!("f726936d19c800494e3fdaff20b276a8");
+        let sig = key.sign_with_context(&msg, b"foo").unwrap();
+        assert_eq!(
+            sig,
+            hex!(
+                "55a4cc2f70a54e04288c5f4cd1e45a7bb520b36292911876cada7323198dd87a"
+                "8b36950b95130022907a7fb7c4e9b2d5f6cca685a587b4b21f4b888e4e7edb0d"
+            )
+        );
+        let pubkey = key.clone_public_key().unwrap();
+        assert!(pubkey.verify_with_context(&msg, b"foo", &sig).unwrap());
+        assert!(!pubkey.verify_with_context(&msg, b"bar", &sig).unwrap());
+        assert!(!pubkey.verify(&msg, &sig).unwrap());
+        assert_eq!(
+            key.sign_with_context(&msg, b"").unwrap_err().kind(),
+            ErrorKind::InvalidArgument
+        );
+        assert_eq!(
+            key.sign_with_context(&msg, &[0; 256]).unwrap_err().kind(),
+            ErrorKind::InvalidArgument
+        );
+
+        // Test vectors of RFC 8032 section 7.3
+        let key = keypair(
+            hex!("833fe62409237b9d62ec77587520911e9a759cec1d19755b7da901b96dca3d42"),
+            hex!("ec172b93ad5e563bf4932c70e1245034c35467ef2efd4d64ebf819683467e2bf"),
+        );
+        let sig = key.sign_prehashed(b"abc", None).unwrap();
+        assert_eq!(
+            sig,
+            hex!(
+                "98a70222f0b8121aa9d30f813d683f809e462b469c7ff87639499bb94e6dae41"
+                "31f85042463c2a355a2003d062adf5aaa10b8c61e636062aaad11c2a26083406"
+            )
+        );
+        let pubkey = key.clone_public_key().unwrap();
+        assert!(pubkey.verify_prehashed(b"abc", None, &sig).unwrap());
+        assert!(!pubkey.verify_prehashed(b"abd", None, &sig).unwrap());
+        assert!(!pubkey.verify_prehashed(b"abc", Some(b"ctx"), &sig).unwrap());
+        let sig = key.sign_prehashed(b"abc", Some(b"ctx")).unwrap();
+        assert!(pubkey.verify_prehashed(b"abc", Some(b"ctx"), &sig).unwrap());
+    }
+
+    #[cfg(feature = "ed25519-batch")]
+    #[test]
+    fn ed25519_verify_batch() {
+        use crate::keys::{KeyPair, KeyType};
+
+        let keypairs: Vec<_> = (0..4)
+            .map(|_| KeyPair::generate(KeyType::ED25519, 0).unwrap())
+            .collect();
+        let pubkeys: Vec<_> = keypairs
+            .iter()
+            .map(|k| k.clone_public_key().unwrap())
+            .collect();
+        let data: Vec<_> = (0..4u8).map(|i| vec![i; 32]).collect();
+        let mut sigs: Vec<_> = keypairs
+            .iter()
+            .zip(&data)
+            .map(|(k, d)| k.sign_ssh_signature(d).unwrap())
+            .collect();
+
+        let batch = |sigs: &[Vec<u8>]| {
+            let items: Vec<_> = pubkeys
+                .iter()
+                .zip(&data)
+                .zip(sigs)
+                .map(|((k, d), s)| (k, d.as_slice(), s.as_slice()))
+                .collect();
+            verify_batch(&items)
+        };
+        assert!(batch(&sigs).unwrap());
+        assert!(verify_batch(&[]).unwrap());
+
+        let last = sigs[3].len() - 1;
+        sigs[3][last] ^= 1;
+        assert!(!batch(&sigs).unwrap());
+
+        let rsa = KeyPair::generate(KeyType::RSA, 0).unwrap();
+        let rsa_pub = rsa.clone_public_key().unwrap();
+        let rsa_sig = rsa.sign_ssh_signature(&data[0]).unwrap();
+        let err = verify_batch(&[(&rsa_pub, &data[0], &rsa_sig)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TypeNotMatch);
+    }
 }
@@ -0,0 +1,249 @@
+use super::{FingerprintHash, PublicParts};
+use crate::error::*;
+use crate::format::base64;
+use std::fmt;
+use std::str::FromStr;
+use subtle::{Choice, ConstantTimeEq};
+
+/// The textual representation of the fingerprint digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintFormat {
+    /// Base64 without padding, the default of SHA2 fingerprints in OpenSSH
+    Base64,
+    /// Base64 with padding
+    Base64Padded,
+    /// Lowercase hex without separators
+    Hex,
+    /// Colon separated lowercase hex, the default of MD5 fingerprints in OpenSSH
+    HexColon,
+}
+
+impl FingerprintFormat {
+    /// Get the format used by OpenSSH for the hash algorithm
+    pub fn openssh_default(hash: FingerprintHash) -> Self {
+        match hash {
+            FingerprintHash::MD5 => FingerprintFormat::HexColon,
+            _ => FingerprintFormat::Base64,
+        }
+    }
+}
+
+/// A fingerprint of a public key
+///
+/// It can be parsed from and displayed in the same formats as `ssh-keygen -l` prints:
+/// - `MD5:` followed by colon separated lowercase hex, such as `MD5:d2:95:52:...`
+/// - `SHA256:`, `SHA384:` or `SHA512:` followed by base64 without padding, such as `SHA256:kjdf...`
+///
+/// The legacy MD5 format without the prefix and all the [`FingerprintFormat`]s are also accepted when parsing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    hash: FingerprintHash,
+    digest: Vec<u8>,
+}
+
+impl Fingerprint {
+    /// Create the fingerprint from the raw digest
+    ///
+    /// Return [`ErrorKind::InvalidLength`] if the digest length doesn't match the hash algorithm.
+    pub fn new(hash: FingerprintHash, digest: Vec<u8>) -> OsshResult<Self> {
+        if digest.len() != hash.digest_len() {
+            return Err(ErrorKind::InvalidLength.into());
+        }
+        Ok(Fingerprint { hash, digest })
+    }
+
+    /// Compute the fingerprint of the key
+    pub fn of<K: PublicParts + ?Sized>(key: &K, hash: FingerprintHash) -> OsshResult<Self> {
+        Ok(Fingerprint {
+            hash,
+            digest: key.fingerprint(hash)?,
+        })
+    }
+
+    /// Get the hash algorithm of the fingerprint
+    pub fn hash(&self) -> FingerprintHash {
+        self.hash
+    }
+
+    /// Get the raw digest of the fingerprint
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Encode the digest in the given format, without the hash name prefix
+    pub fn encode(&self, format: FingerprintFormat) -> String {
+        match format {
+            FingerprintFormat::Base64 => base64::encode_unpadded(&self.digest),
+            FingerprintFormat::Base64Padded => base64::encode(&self.digest),
+            FingerprintFormat::Hex => self.digest.iter().map(|b| format!("{:02x}", b)).collect(),
+            FingerprintFormat::HexColon => self
+                .digest
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+        }
+    }
+
+    /// Encode the digest in the given format, with the hash name prefix such as `SHA256:`
+    pub fn to_string_with(&self, format: FingerprintFormat) -> String {
+        format!("{}:{}", self.hash.name(), self.encode(format))
+    }
+
+    /// Check if the fingerprint belongs to the key
+    ///
+    /// The key is hashed with the same algorithm as this fingerprint.
+    pub fn matches<K: PublicParts + ?Sized>(&self, key: &K) -> bool {
+        match key.fingerprint(self.hash) {
+            Ok(digest) => digest.ct_eq(&self.digest).into(),
+            Err(_) => false,
+        }
+    }
+
+    /// Check if the fingerprint is in the allowlist, in constant time
+    ///
+    /// All the entries are compared without short-circuiting, so the timing only depends on
+    /// the length of the allowlist, not on which entry matches or how much of a digest matches.
+    /// Entries using a different hash algorithm never match.
+    pub fn matches_any(&self, allowlist: &[Fingerprint]) -> bool {
+        let mut found = Choice::from(0);
+        for fp in allowlist {
+            found |= Choice::from((fp.hash == self.hash) as u8) & fp.digest.ct_eq(&self.digest);
+        }
+        found.into()
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> OsshResult<Self> {
+        let s = s.trim();
+        let (hash, value) = match s.split_once(':') {
+            Some((name, value)) => match FingerprintHash::from_name(name) {
+                Some(hash) => (hash, value),
+                // Legacy MD5 fingerprint without the prefix
+                None => (FingerprintHash::MD5, s),
+            },
+            None => return Err(ErrorKind::InvalidFormat.into()),
+        };
+
+        let hex_len = hash.digest_len() * 2;
+        let digest = if value.contains(':') {
+            value
+                .split(':')
+                .map(|byte| {
+                    if byte.len() != 2 {
+                        return Err(Error::from_kind(ErrorKind::InvalidFormat));
+                    }
+                    u8::from_str_radix(byte, 16)
+                        .map_err(|e| Error::with_error(ErrorKind::InvalidFormat, e))
+                })
+                .collect::<OsshResult<Vec<u8>>>()?
+        } else if value.len() == hex_len && value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            (0..hex_len)
+                .step_by(2)
+                .map(|i| {
+                    u8::from_str_radix(&value[i..i + 2], 16)
+                        .map_err(|e| Error::with_error(ErrorKind::InvalidFormat, e))
+                })
+                .collect::<OsshResult<Vec<u8>>>()?
+        } else {
+            base64::decode_unpadded(value.trim_end_matches('='))?
+        };
+        Fingerprint::new(hash, digest)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = FingerprintFormat::openssh_default(self.hash);
+        f.write_str(&self.to_string_with(format))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MD5_FP: &str = "MD5:d2:95:52:b0:c8:7d:7f:f1:ac:b3:c2:22:9e:78:33:21";
+    const SHA256_FP: &str = "SHA256:HqhiW+Si82ht68YVkIroDfvazlfCrQInWM4hgyPyBBs";
+    const SHA384_FP: &str =
+        "SHA384:Wn8LOLRD8vlHxrwZHlIspijFsUEsVyiFGr2j3K2c6ysuTmZnMKSYc8JXZYOcTGx6";
+
+    #[test]
+    fn fingerprint_parse_display() {
+        let fp: Fingerprint = MD5_FP.parse().unwrap();
+        assert_eq!(fp.hash(), FingerprintHash::MD5);
+        assert_eq!(fp.digest().len(), 16);
+        assert_eq!(fp.to_string(), MD5_FP);
+        let legacy: Fingerprint = MD5_FP[4..].parse().unwrap();
+        assert_eq!(legacy, fp);
+
+        let fp: Fingerprint = SHA256_FP.parse().unwrap();
+        assert_eq!(fp.hash(), FingerprintHash::SHA256);
+        assert_eq!(fp.to_string(), SHA256_FP);
+        let padded: Fingerprint = format!("{}=", SHA256_FP).parse().unwrap();
+        assert_eq!(padded, fp);
+
+        let fp: Fingerprint = SHA384_FP.parse().unwrap();
+        assert_eq!(fp.hash(), FingerprintHash::SHA384);
+        assert_eq!(fp.digest().len(), 48);
+        assert_eq!(fp.to_string(), SHA384_FP);
+    }
+
+    #[test]
+    fn fingerprint_formats() {
+        let fp: Fingerprint = SHA256_FP.parse().unwrap();
+        let hex = "1ea8625be4a2f3686debc615908ae80dfbdace57c2ad022758ce218323f2041b";
+        assert_eq!(fp.encode(FingerprintFormat::Hex), hex);
+        assert_eq!(
+            fp.encode(FingerprintFormat::Base64Padded),
+            "HqhiW+Si82ht68YVkIroDfvazlfCrQInWM4hgyPyBBs="
+        );
+        assert_eq!(
+            fp.to_string_with(FingerprintFormat::Base64),
+            SHA256_FP.to_string()
+        );
+        for format in [
+            FingerprintFormat::Base64,
+            FingerprintFormat::Base64Padded,
+            FingerprintFormat::Hex,
+            FingerprintFormat::HexColon,
+        ] {
+            let s = fp.to_string_with(format);
+            assert_eq!(s.parse::<Fingerprint>().unwrap(), fp);
+        }
+
+        let md5: Fingerprint = MD5_FP.parse().unwrap();
+        assert_eq!(
+            md5.to_string_with(FingerprintFormat::Hex),
+            "MD5:d29552b0c87d7ff1acb3c2229e783321"
+        );
+    }
+
+    #[test]
+    fn fingerprint_matches_any() {
+        let md5: Fingerprint = MD5_FP.parse().unwrap();
+        let sha256: Fingerprint = SHA256_FP.parse().unwrap();
+        let other = Fingerprint::new(FingerprintHash::SHA256, vec![0; 32]).unwrap();
+
+        assert!(sha256.matches_any(&[other.clone(), md5.clone(), sha256.clone()]));
+        assert!(md5.matches_any(std::slice::from_ref(&md5)));
+        assert!(!sha256.matches_any(&[other, md5]));
+        assert!(!sha256.matches_any(&[]));
+    }
+
+    #[test]
+    fn fingerprint_parse_invalid() {
+        assert!("SHA256".parse::<Fingerprint>().is_err());
+        assert!("SHA256:AAAA".parse::<Fingerprint>().is_err());
+        assert!("MD5:d2:95".parse::<Fingerprint>().is_err());
+        assert!("MD5:d2:95:52:b0:c8:7d:7f:f1:ac:b3:c2:22:9e:78:33:2"
+            .parse::<Fingerprint>()
+            .is_err());
+        assert!("MD5:zz:95:52:b0:c8:7d:7f:f1:ac:b3:c2:22:9e:78:33:21"
+            .parse::<Fingerprint>()
+            .is_err());
+    }
+}
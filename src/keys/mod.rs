@@ -1,36 +1,149 @@
 use crate::cipher::Cipher;
+use crate::defaults::{defaults, Defaults};
 use crate::error::*;
+use crate::format::base64;
 use crate::format::ossh_privkey::*;
 use crate::format::ossh_pubkey::*;
-use crate::format::parse_keystr;
 use crate::format::pem::*;
 use crate::format::pkcs8::*;
+use crate::format::ppk::*;
+use crate::format::rfc4716::*;
+use crate::format::{is_encrypted, parse_keystr};
+use crate::hooks::{report_key_usage, KeyOperation};
+use crate::policy::Policy;
+use crate::sshbuf::{SshReader, SshWriteExt};
 use digest::{Digest, FixedOutputReset};
 use md5::Md5;
+#[cfg(any(feature = "dsa", feature = "ecdsa"))]
+use openssl::bn::BigNum;
+#[cfg(any(feature = "rsa", feature = "dsa", feature = "ecdsa"))]
+use openssl::bn::BigNumRef;
 use openssl::pkey::{Id, PKey, PKeyRef, Private, Public};
-use sha2::{Sha256, Sha512};
-use std::fmt;
-
+use rand::rngs::OsRng;
+use rand_core::CryptoRngCore;
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+use std::cmp::Ordering;
+use std::fmt::{self, Write as _};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::thread::{self, JoinHandle};
+use zeroize::{ZeroizeOnDrop, Zeroizing};
+
+pub use fingerprint::{Fingerprint, FingerprintFormat};
+
+/// Key agreement of the EcDSA and X25519 keys
+pub mod agreement;
+/// OpenSSH certificates
+pub mod cert;
+/// Deterministic key derivation from a seed
+pub mod derive;
+/// Compare the key material of the key files
+pub mod diff;
 /// DSA key type
 pub mod dsa;
 /// EcDSA key type
 pub mod ecdsa;
 /// Ed25519 key type
 pub mod ed25519;
+/// Fingerprint of the public keys
+pub mod fingerprint;
+/// YubiKey PIV keys through Yubico's PKCS#11 module (Feature `yubikey-piv`)
+#[cfg(feature = "yubikey-piv")]
+pub mod piv;
+/// Signing with the keys kept in the PKCS#11 tokens (Feature `pkcs11`)
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+mod pkey_cache;
+/// Keys loaded from the OpenSSL providers (Feature `openssl-provider`)
+#[cfg(feature = "openssl-provider")]
+pub mod provider;
 /// RSA key type
 pub mod rsa;
+/// Serde support of the keys (Feature `serde`)
+#[cfg(feature = "serde")]
+pub mod serde_support;
+/// In-memory shielding of the key pairs
+pub mod shielded;
+/// Keys created under a TPM 2.0 (Feature `tpm2`)
+#[cfg(feature = "tpm2")]
+pub mod tpm;
+/// UniFFI bindings of the keys (Feature `uniffi`)
+#[cfg(feature = "uniffi")]
+pub mod uniffi_support;
+/// X25519 keys for the key agreement
+pub mod x25519;
+
+#[cfg(not(any(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+)))]
+compile_error!("No key type is selected! Please enable at least one key type feature.");
 
 /// The name of the MD5 hashing algorithm returned by [`FingerprintHash::name()`](enum.FingerprintHash.html#method.name)
 pub const MD5_NAME: &str = "MD5";
 /// The name of the sha2-256 algorithm returned by [`FingerprintHash::name()`](enum.FingerprintHash.html#method.name)
 pub const SHA256_NAME: &str = "SHA256";
+/// The name of the sha2-384 algorithm returned by [`FingerprintHash::name()`](enum.FingerprintHash.html#method.name)
+pub const SHA384_NAME: &str = "SHA384";
 /// The name of the sha2-512 algorithm returned by [`FingerprintHash::name()`](enum.FingerprintHash.html#method.name)
 pub const SHA512_NAME: &str = "SHA512";
 
+pub(crate) const SECTION_SIGNATURE: &str = "signature";
+
+static LEGACY_ALGORITHMS: AtomicBool = AtomicBool::new(true);
+
+/// Allow or refuse the legacy algorithms globally
+///
+/// The legacy algorithms are the SHA-1 based `ssh-rsa` signatures and the DSA keys,
+/// which are disabled by default since OpenSSH 8.8. They are allowed unless refused here.
+/// This is used by the SSH signature functions called with [`LegacyAlgorithms::Global`],
+/// such as [`KeyPair::sign_ssh_signature()`] and [`PublicKey::verify_ssh_signature()`].
+pub fn set_legacy_algorithms(allowed: bool) {
+    LEGACY_ALGORITHMS.store(allowed, AtomicOrdering::Relaxed);
+}
+
+/// Check if the legacy algorithms are allowed globally, see [`set_legacy_algorithms()`]
+pub fn legacy_algorithms_allowed() -> bool {
+    LEGACY_ALGORITHMS.load(AtomicOrdering::Relaxed)
+}
+
+/// Whether the SSH signature functions allow the legacy `ssh-rsa` signatures and the DSA keys
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyAlgorithms {
+    /// Follow the global setting of [`set_legacy_algorithms()`]
+    #[default]
+    Global,
+    Allow,
+    Refuse,
+}
+
+impl LegacyAlgorithms {
+    /// Check if the legacy algorithms are allowed
+    pub fn allowed(self) -> bool {
+        match self {
+            LegacyAlgorithms::Global => legacy_algorithms_allowed(),
+            LegacyAlgorithms::Allow => true,
+            LegacyAlgorithms::Refuse => false,
+        }
+    }
+
+    /// Fail with [`ErrorKind::LegacyAlgorithm`] if the signature algorithm is legacy and refused
+    pub(crate) fn check(self, algo: &str) -> OsshResult<()> {
+        if (algo == rsa::RSA_NAME || algo == dsa::DSA_NAME) && !self.allowed() {
+            return Err(ErrorKind::LegacyAlgorithm(algo.to_owned()).into());
+        }
+        Ok(())
+    }
+}
+
 /// An enum representing the hash function used to generate fingerprint
 ///
 /// Used with [`PublicPart::fingerprint()`](trait.PublicPart.html#method.fingerprint) and
-/// [`PublicPart::fingerprint_randomart()`](trait.PublicPart.html#method.fingerprint) to generate
+/// [`PublicPart::fingerprint_randomart()`](trait.PublicPart.html#method.fingerprint_randomart) to generate
 /// different types fingerprint and randomarts.
 ///
 /// # Hash Algorithm
@@ -38,11 +151,15 @@ pub const SHA512_NAME: &str = "SHA512";
 ///
 /// SHA2-256: Since OpenSSH 6.8, this became the default option of fingerprint.
 ///
+/// SHA2-384: Not supported by OpenSSH, provided for the policies which mandate it.
+///
 /// SHA2-512: Although not being documented, it can also be used.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 pub enum FingerprintHash {
     MD5,
     SHA256,
+    SHA384,
     SHA512,
 }
 
@@ -59,44 +176,139 @@ impl FingerprintHash {
         match self {
             FingerprintHash::MD5 => digest_hash(&mut Md5::default(), data),
             FingerprintHash::SHA256 => digest_hash(&mut Sha256::default(), data),
+            FingerprintHash::SHA384 => digest_hash(&mut Sha384::default(), data),
             FingerprintHash::SHA512 => digest_hash(&mut Sha512::default(), data),
         }
     }
-    fn name(self) -> &'static str {
+    /// Parse from the name of the hash algorithm, such as `SHA256`
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            MD5_NAME => Some(FingerprintHash::MD5),
+            SHA256_NAME => Some(FingerprintHash::SHA256),
+            SHA384_NAME => Some(FingerprintHash::SHA384),
+            SHA512_NAME => Some(FingerprintHash::SHA512),
+            _ => None,
+        }
+    }
+    /// Get the name of the hash algorithm, which is used as the prefix of the fingerprint
+    pub fn name(self) -> &'static str {
         match self {
             FingerprintHash::MD5 => MD5_NAME,
             FingerprintHash::SHA256 => SHA256_NAME,
+            FingerprintHash::SHA384 => SHA384_NAME,
             FingerprintHash::SHA512 => SHA512_NAME,
         }
     }
+    /// Get the length of the digest in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            FingerprintHash::MD5 => 16,
+            FingerprintHash::SHA256 => 32,
+            FingerprintHash::SHA384 => 48,
+            FingerprintHash::SHA512 => 64,
+        }
+    }
 }
 
 /// An enum representing the type of key being stored
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 pub enum KeyType {
+    #[cfg(feature = "rsa")]
     RSA,
+    #[cfg(feature = "dsa")]
     DSA,
+    #[cfg(feature = "ecdsa")]
     ECDSA,
+    #[cfg(feature = "ed25519")]
     ED25519,
 }
 
+/// The suffix of the OpenSSH certificate key names, such as `ssh-ed25519-cert-v01@openssh.com`
+pub const CERT_SUFFIX: &str = "-cert-v01@openssh.com";
+
+impl FromStr for KeyType {
+    type Err = Error;
+
+    /// Parse from the SSH algorithm name, such as `ssh-ed25519` or `rsa-sha2-512`
+    ///
+    /// The certificate key names and the `ssh-keygen -t` names (`rsa`, `dsa`, `ecdsa`, `ed25519`) are also accepted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_suffix(CERT_SUFFIX).unwrap_or(s);
+        match name {
+            #[cfg(feature = "rsa")]
+            rsa::RSA_NAME | rsa::RSA_SHA256_NAME | rsa::RSA_SHA512_NAME | "rsa" => Ok(KeyType::RSA),
+            #[cfg(feature = "dsa")]
+            dsa::DSA_NAME | "dsa" => Ok(KeyType::DSA),
+            #[cfg(feature = "ecdsa")]
+            ecdsa::NIST_P256_NAME | ecdsa::NIST_P384_NAME | ecdsa::NIST_P521_NAME | "ecdsa" => {
+                Ok(KeyType::ECDSA)
+            }
+            #[cfg(feature = "ed25519")]
+            ed25519::ED25519_NAME | "ed25519" => Ok(KeyType::ED25519),
+            _ => Err(ErrorKind::UnsupportType(s.to_string()).into()),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    /// Write the SSH key name, such as `ssh-ed25519`
+    ///
+    /// EcDSA is written as the name of the default curve `ecdsa-sha2-nistp256`,
+    /// use [`EcCurve`](ecdsa/enum.EcCurve.html) for the name of the other curves.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            #[cfg(feature = "rsa")]
+            KeyType::RSA => rsa::RSA_NAME,
+            #[cfg(feature = "dsa")]
+            KeyType::DSA => dsa::DSA_NAME,
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA => ecdsa::NIST_P256_NAME,
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519 => ed25519::ED25519_NAME,
+        })
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub(crate) enum PublicKeyType {
+    #[cfg(feature = "rsa")]
     RSA(rsa::RsaPublicKey),
+    #[cfg(feature = "dsa")]
     DSA(dsa::DsaPublicKey),
+    #[cfg(feature = "ecdsa")]
     ECDSA(ecdsa::EcDsaPublicKey),
+    #[cfg(feature = "ed25519")]
     ED25519(ed25519::Ed25519PublicKey),
 }
 
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
 pub(crate) enum KeyPairType {
+    #[cfg(feature = "rsa")]
     RSA(rsa::RsaKeyPair),
+    #[cfg(feature = "dsa")]
     DSA(dsa::DsaKeyPair),
+    #[cfg(feature = "ecdsa")]
     ECDSA(ecdsa::EcDsaKeyPair),
+    #[cfg(feature = "ed25519")]
     ED25519(ed25519::Ed25519KeyPair),
 }
 
+/// The rendering of the one-line OpenSSH public key, used by [`PublicKey::to_string_with()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublicKeyFormat<'a> {
+    /// `keyname base64 comment`, the same as [`PublicKey::serialize()`]
+    Full,
+    /// `keyname base64`, without the comment
+    NoComment,
+    /// `keyname base64` followed by the given comment instead of the key's own one
+    Comment(&'a str),
+    /// Only the base64 encoded blob
+    Blob,
+}
+
 /// General public key type
 ///
 /// This is a type to make it easy to store different types of public key in the container.
@@ -106,40 +318,70 @@ pub(crate) enum KeyPairType {
 pub struct PublicKey {
     pub(crate) key: PublicKeyType,
     comment: String,
+    pub(crate) options: String,
 }
 
 impl PublicKey {
     pub(crate) fn from_ossl_pkey(pkey: &PKeyRef<Public>) -> OsshResult<Self> {
         match pkey.id() {
+            #[cfg(feature = "rsa")]
             Id::RSA => {
                 Ok(rsa::RsaPublicKey::from_ossl_rsa(pkey.rsa()?, rsa::RsaSignature::SHA1)?.into())
             }
+            #[cfg(feature = "dsa")]
             Id::DSA => Ok(dsa::DsaPublicKey::from_ossl_dsa(pkey.dsa()?).into()),
+            #[cfg(feature = "ecdsa")]
             Id::EC => Ok(ecdsa::EcDsaPublicKey::from_ossl_ec(pkey.ec_key()?)?.into()),
+            #[cfg(feature = "ed25519")]
             Id::ED25519 => {
                 Ok(ed25519::Ed25519PublicKey::from_ossl_ed25519(&pkey.raw_public_key()?)?.into())
             }
-            _ => Err(ErrorKind::UnsupportType.into()),
+            id => Err(ErrorKind::UnsupportType(ossl_pkey_name(id)).into()),
         }
     }
 
-    /// Parse the openssh/PEM format public key file
+    /// Parse the openssh/PEM/RFC 4716 format public key file
+    ///
+    /// The OpenSSH format also accepts a line of the authorized_keys file, which may have the options before the key,
+    /// see [`PublicKey::options()`]. The surrounding whitespaces and the CRLF line ending are ignored.
     pub fn from_keystr(keystr: &str) -> OsshResult<Self> {
         if keystr.trim().starts_with("-----BEGIN") {
             // PEM format
             Ok(parse_pem_pubkey(keystr.as_bytes())?)
+        } else if keystr.trim().starts_with(RFC4716_BEGIN) {
+            // SSH2 public key format
+            parse_rfc4716_pubkey(keystr)
         } else {
             // openssh format
             Ok(parse_ossh_pubkey(keystr)?)
         }
     }
 
+    /// Parse the public key like [`PublicKey::from_keystr()`], and check it with the policy
+    pub fn from_keystr_with_policy(keystr: &str, policy: &Policy) -> OsshResult<Self> {
+        let pubkey = Self::from_keystr(keystr)?;
+        policy.check_key(pubkey.keytype(), pubkey.size())?;
+        Ok(pubkey)
+    }
+
+    /// Decode the raw SSH wire encoding blob of the public key
+    ///
+    /// The blob is the binary form found in the protocol packets, the certificates and the agent replies,
+    /// which is the base64 decoded second field of the OpenSSH public key string. The comment is empty.
+    pub fn from_blob(blob: &[u8]) -> OsshResult<Self> {
+        decode_ossh_pubkey(blob)
+    }
+
     /// Indicate the key type being stored
     pub fn keytype(&self) -> KeyType {
         match &self.key {
+            #[cfg(feature = "rsa")]
             PublicKeyType::RSA(_) => KeyType::RSA,
+            #[cfg(feature = "dsa")]
             PublicKeyType::DSA(_) => KeyType::DSA,
+            #[cfg(feature = "ecdsa")]
             PublicKeyType::ECDSA(_) => KeyType::ECDSA,
+            #[cfg(feature = "ed25519")]
             PublicKeyType::ED25519(_) => KeyType::ED25519,
         }
     }
@@ -154,11 +396,145 @@ impl PublicKey {
         &mut self.comment
     }
 
+    /// Replace the comment of the key
+    ///
+    /// The comment is written into the OpenSSH and RFC 4716 formats, while the PEM format cannot store it.
+    pub fn set_comment(&mut self, comment: &str) {
+        self.comment = comment.to_owned();
+    }
+
+    /// Get the authorized_keys options before the key, such as `from="10.0.0.?",no-pty`
+    ///
+    /// It's empty if the key wasn't parsed from an authorized_keys line with the options.
+    /// The options are not written by the serialize functions.
+    pub fn options(&self) -> &str {
+        &self.options
+    }
+
     /// Serialize the public key as OpenSSH format
     pub fn serialize(&self) -> OsshResult<String> {
         serialize_ossh_pubkey(self, &self.comment)
     }
 
+    /// Write the public key in the OpenSSH format into the sink, without the trailing newline
+    ///
+    /// Use [`IoWriter`](crate::format::IoWriter) to write into an [`std::io::Write`].
+    pub fn write_openssh<W: fmt::Write + ?Sized>(&self, out: &mut W) -> OsshResult<()> {
+        write_ossh_pubkey(out, self, &self.comment)
+    }
+
+    /// Serialize the public key as OpenSSH format with the given rendering
+    pub fn to_string_with(&self, format: PublicKeyFormat) -> OsshResult<String> {
+        let mut keystr = String::new();
+        self.write_openssh_with(&mut keystr, format)?;
+        Ok(keystr)
+    }
+
+    /// Write the public key in the OpenSSH format with the given rendering into the sink, without the trailing newline
+    pub fn write_openssh_with<W: fmt::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        format: PublicKeyFormat,
+    ) -> OsshResult<()> {
+        match format {
+            PublicKeyFormat::Full => write_ossh_pubkey(out, self, &self.comment),
+            PublicKeyFormat::NoComment => write_ossh_pubkey(out, self, ""),
+            PublicKeyFormat::Comment(comment) => write_ossh_pubkey(out, self, comment),
+            PublicKeyFormat::Blob => Ok(base64::write_encoded(out, &self.blob()?, 0)?),
+        }
+    }
+
+    /// Encode the public key as the canonical SSH wire encoding blob
+    ///
+    /// This is the reverse of [`PublicKey::from_blob()`], and the same as [`PublicParts::blob()`](trait.PublicParts.html#tymethod.blob).
+    pub fn to_blob(&self) -> OsshResult<Vec<u8>> {
+        self.blob()
+    }
+
+    /// Verify the signature in the SSH wire encoding `string algorithm, string signature`
+    ///
+    /// This is the form used by the SSH protocol and the certificates,
+    /// unlike [`PublicParts::verify()`](trait.PublicParts.html#tymethod.verify) which takes the raw signature.
+    pub fn verify_ssh_signature(&self, data: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        self.verify_ssh_signature_checked(data, sig, LegacyAlgorithms::Global)
+    }
+
+    /// Verify the signature like [`PublicKey::verify_ssh_signature()`], with the legacy algorithms allowed or refused
+    ///
+    /// Fail with [`ErrorKind::LegacyAlgorithm`] if the signature is `ssh-rsa` or `ssh-dss` and `legacy` refuses it.
+    pub fn verify_ssh_signature_checked(
+        &self,
+        data: &[u8],
+        sig: &[u8],
+        legacy: LegacyAlgorithms,
+    ) -> OsshResult<bool> {
+        let mut reader = SshReader::new(sig, SECTION_SIGNATURE);
+        let algo = reader.read_utf8("algorithm")?;
+        let sig = reader.read_string("signature")?;
+        reader.finish()?;
+        legacy.check(algo)?;
+        match &self.key {
+            #[cfg(feature = "rsa")]
+            PublicKeyType::RSA(key) => {
+                let signhash = rsa::RsaSignature::from_name(algo).ok_or(ErrorKind::TypeNotMatch)?;
+                let mut key = key.clone();
+                key.set_sign_type(signhash);
+                key.verify(data, sig)
+            }
+            #[cfg(feature = "dsa")]
+            PublicKeyType::DSA(key) if algo == dsa::DSA_NAME => key.verify_ssh(data, sig),
+            #[cfg(feature = "ecdsa")]
+            PublicKeyType::ECDSA(key) if algo == key.keyname() => key.verify_ssh(data, sig),
+            #[cfg(feature = "ed25519")]
+            PublicKeyType::ED25519(key) if algo == ed25519::ED25519_NAME => key.verify(data, sig),
+            #[allow(unreachable_patterns)]
+            _ => Err(ErrorKind::TypeNotMatch.into()),
+        }
+    }
+
+    /// Verify the signature like [`PublicKey::verify_ssh_signature()`], and check the key and the algorithm with the policy
+    pub fn verify_ssh_signature_with_policy(
+        &self,
+        data: &[u8],
+        sig: &[u8],
+        policy: &Policy,
+    ) -> OsshResult<bool> {
+        policy.check_key(self.keytype(), self.size())?;
+        policy.check_signature_algorithm(
+            SshReader::new(sig, SECTION_SIGNATURE).read_utf8("algorithm")?,
+        )?;
+        self.verify_ssh_signature_checked(data, sig, policy.legacy)
+    }
+
+    /// Verify the raw Ed25519ctx signature of RFC 8032 made by [`KeyPair::sign_ed25519ctx()`]
+    ///
+    /// Fail with [`ErrorKind::TypeNotMatch`] for the other keys.
+    #[cfg(feature = "ed25519")]
+    pub fn verify_ed25519ctx(&self, data: &[u8], context: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        match &self.key {
+            PublicKeyType::ED25519(key) => key.verify_with_context(data, context, sig),
+            #[allow(unreachable_patterns)]
+            _ => Err(ErrorKind::TypeNotMatch.into()),
+        }
+    }
+
+    /// Verify the raw Ed25519ph signature of RFC 8032 made by [`KeyPair::sign_ed25519ph()`]
+    ///
+    /// Fail with [`ErrorKind::TypeNotMatch`] for the other keys.
+    #[cfg(feature = "ed25519")]
+    pub fn verify_ed25519ph(
+        &self,
+        data: &[u8],
+        context: Option<&[u8]>,
+        sig: &[u8],
+    ) -> OsshResult<bool> {
+        match &self.key {
+            PublicKeyType::ED25519(key) => key.verify_prehashed(data, context, sig),
+            #[allow(unreachable_patterns)]
+            _ => Err(ErrorKind::TypeNotMatch.into()),
+        }
+    }
+
     /// Serialize the public key as PEM format
     ///
     /// # Representation
@@ -173,11 +549,101 @@ impl PublicKey {
         stringify_pem_pubkey(self)
     }
 
+    /// Serialize the public key as the SSH2 public key file format (RFC 4716)
+    ///
+    /// This is the format written by `ssh-keygen -e` and read by the commercial SSH implementations.
+    pub fn serialize_rfc4716(&self) -> OsshResult<String> {
+        serialize_rfc4716_pubkey(self)
+    }
+
+    /// Write the public key in the SSH2 public key file format (RFC 4716) into the sink
+    pub fn write_rfc4716<W: fmt::Write + ?Sized>(&self, out: &mut W) -> OsshResult<()> {
+        write_rfc4716_pubkey_with_headers(out, self, &[])
+    }
+
+    /// Serialize the public key as the SSH2 public key file format (RFC 4716) with the additional headers
+    ///
+    /// See [`serialize_rfc4716_pubkey_with_headers()`](crate::format::rfc4716::serialize_rfc4716_pubkey_with_headers)
+    /// for the restrictions of the headers.
+    pub fn serialize_rfc4716_with_headers(&self, headers: &[(&str, &str)]) -> OsshResult<String> {
+        serialize_rfc4716_pubkey_with_headers(self, headers)
+    }
+
+    /// Draw the randomart of the key
+    ///
+    /// The output is identical to the visual host key printed by `ssh-keygen -lv` without the trailing newline.
+    /// This is the same as [`PublicParts::fingerprint_randomart()`](trait.PublicParts.html#method.fingerprint_randomart).
+    pub fn randomart(&self, hash: FingerprintHash) -> OsshResult<String> {
+        self.fingerprint_randomart(hash)
+    }
+
+    /// Dump the key in a human-readable form, like `openssl pkey -text -noout`
+    ///
+    /// The dump has the key type, the size, the curve, the public components in hex and the fingerprints.
+    /// It's meant for debugging, and the layout may change between the versions.
+    pub fn describe(&self) -> OsshResult<String> {
+        let mut out = String::new();
+        writeln!(out, "Type: {} ({})", self.keyname(), self.short_keyname())?;
+        writeln!(out, "Size: {} bit", self.size())?;
+        if !self.comment.is_empty() {
+            writeln!(out, "Comment: {}", self.comment)?;
+        }
+        match &self.key {
+            #[cfg(feature = "rsa")]
+            PublicKeyType::RSA(key) => {
+                let rsa = key.ossl_rsa();
+                write_hex_block(&mut out, "Modulus", &mpint_bytes(rsa.n()))?;
+                let e = rsa.e().to_dec_str()?;
+                let e_hex = rsa.e().to_hex_str()?;
+                writeln!(
+                    out,
+                    "Exponent: {} (0x{})",
+                    e,
+                    e_hex.trim_start_matches('0').to_lowercase()
+                )?;
+            }
+            #[cfg(feature = "dsa")]
+            PublicKeyType::DSA(key) => {
+                let dsa = key.ossl_dsa();
+                write_hex_block(&mut out, "pub", &mpint_bytes(dsa.pub_key()))?;
+                write_hex_block(&mut out, "P", &mpint_bytes(dsa.p()))?;
+                write_hex_block(&mut out, "Q", &mpint_bytes(dsa.q()))?;
+                write_hex_block(&mut out, "G", &mpint_bytes(dsa.g()))?;
+            }
+            #[cfg(feature = "ecdsa")]
+            PublicKeyType::ECDSA(key) => {
+                let ec = key.ossl_ec();
+                let mut ctx = openssl::bn::BigNumContext::new()?;
+                let point = ec.public_key().to_bytes(
+                    ec.group(),
+                    openssl::ec::PointConversionForm::UNCOMPRESSED,
+                    &mut ctx,
+                )?;
+                write_hex_block(&mut out, "pub", &point)?;
+                writeln!(out, "Curve: {}", key.curve())?;
+                writeln!(out, "ASN1 OID: {}", key.curve().nid().short_name()?)?;
+            }
+            #[cfg(feature = "ed25519")]
+            PublicKeyType::ED25519(key) => {
+                write_hex_block(&mut out, "pub", key.as_bytes())?;
+            }
+        }
+        writeln!(out, "Fingerprints:")?;
+        for hash in [FingerprintHash::MD5, FingerprintHash::SHA256] {
+            writeln!(out, "    {}", Fingerprint::of(self, hash)?)?;
+        }
+        Ok(out)
+    }
+
     fn inner_key(&self) -> &dyn PublicParts {
         match &self.key {
+            #[cfg(feature = "rsa")]
             PublicKeyType::RSA(key) => key,
+            #[cfg(feature = "dsa")]
             PublicKeyType::DSA(key) => key,
+            #[cfg(feature = "ecdsa")]
             PublicKeyType::ECDSA(key) => key,
+            #[cfg(feature = "ed25519")]
             PublicKeyType::ED25519(key) => key,
         }
     }
@@ -211,44 +677,87 @@ impl PublicParts for PublicKey {
     }
 }
 
+impl PartialEq for PublicKey {
+    /// Compare the key parts only, the comment is ignored
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl Hash for PublicKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // The blob is the canonical form of the key, so equal keys always have the same blob
+        self.blob().unwrap_or_default().hash(state);
+    }
+}
+
+impl PartialOrd for PublicKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PublicKey {
+    /// Order by the key type first, then by the key blob
+    ///
+    /// The comment is ignored, which is consistent with the equality.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.keytype().cmp(&other.keytype()).then_with(|| {
+            self.blob()
+                .unwrap_or_default()
+                .cmp(&other.blob().unwrap_or_default())
+        })
+    }
+}
+
 impl fmt::Display for PublicKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.serialize().unwrap())
     }
 }
 
+#[cfg(feature = "rsa")]
 impl From<rsa::RsaPublicKey> for PublicKey {
     fn from(inner: rsa::RsaPublicKey) -> PublicKey {
         PublicKey {
             key: PublicKeyType::RSA(inner),
             comment: String::new(),
+            options: String::new(),
         }
     }
 }
 
+#[cfg(feature = "dsa")]
 impl From<dsa::DsaPublicKey> for PublicKey {
     fn from(inner: dsa::DsaPublicKey) -> PublicKey {
         PublicKey {
             key: PublicKeyType::DSA(inner),
             comment: String::new(),
+            options: String::new(),
         }
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl From<ecdsa::EcDsaPublicKey> for PublicKey {
     fn from(inner: ecdsa::EcDsaPublicKey) -> PublicKey {
         PublicKey {
             key: PublicKeyType::ECDSA(inner),
             comment: String::new(),
+            options: String::new(),
         }
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl From<ed25519::Ed25519PublicKey> for PublicKey {
     fn from(inner: ed25519::Ed25519PublicKey) -> PublicKey {
         PublicKey {
             key: PublicKeyType::ED25519(inner),
             comment: String::new(),
+            options: String::new(),
         }
     }
 }
@@ -262,28 +771,47 @@ impl From<ed25519::Ed25519PublicKey> for PublicKey {
 pub struct KeyPair {
     pub(crate) key: KeyPairType,
     comment: String,
+    // The original bytes of the comment which is not valid UTF-8
+    raw_comment: Option<Vec<u8>>,
+    // The data of the OpenSSH private key unknown to this crate, written back on serialization
+    pub(crate) ossh_unknown: Option<Box<OsshUnknownData>>,
+    // The encoding recorded by `from_keystr_exact()`
+    ossh_encoding: Option<Box<OsshEncoding>>,
 }
 
+// The key pair types only mark the wiping done by their private parts:
+// OpenSSL frees the private components of the RSA, DSA and EcDSA keys with `BN_clear_free()`,
+// and the `SigningKey` of Ed25519 zeroizes the secret key on drop.
+impl ZeroizeOnDrop for KeyPair {}
+
 impl KeyPair {
     pub(crate) fn from_ossl_pkey(pkey: &PKeyRef<Private>) -> OsshResult<Self> {
         match pkey.id() {
+            #[cfg(feature = "rsa")]
             Id::RSA => {
                 Ok(rsa::RsaKeyPair::from_ossl_rsa(pkey.rsa()?, rsa::RsaSignature::SHA1)?.into())
             }
+            #[cfg(feature = "dsa")]
             Id::DSA => Ok(dsa::DsaKeyPair::from_ossl_dsa(pkey.dsa()?).into()),
+            #[cfg(feature = "ecdsa")]
             Id::EC => Ok(ecdsa::EcDsaKeyPair::from_ossl_ec(pkey.ec_key()?)?.into()),
+            #[cfg(feature = "ed25519")]
             Id::ED25519 => {
                 Ok(ed25519::Ed25519KeyPair::from_ossl_ed25519(&pkey.raw_private_key()?)?.into())
             }
-            _ => Err(ErrorKind::UnsupportType.into()),
+            id => Err(ErrorKind::UnsupportType(ossl_pkey_name(id)).into()),
         }
     }
 
     pub(crate) fn ossl_pkey(&self) -> OsshResult<PKey<Private>> {
         match &self.key {
-            KeyPairType::RSA(key) => Ok(PKey::from_rsa(key.ossl_rsa().to_owned())?),
-            KeyPairType::DSA(key) => Ok(PKey::from_dsa(key.ossl_dsa().to_owned())?),
-            KeyPairType::ECDSA(key) => Ok(PKey::from_ec_key(key.ossl_ec().to_owned())?),
+            #[cfg(feature = "rsa")]
+            KeyPairType::RSA(key) => Ok(key.ossl_pkey()?),
+            #[cfg(feature = "dsa")]
+            KeyPairType::DSA(key) => Ok(key.ossl_pkey()?),
+            #[cfg(feature = "ecdsa")]
+            KeyPairType::ECDSA(key) => Ok(key.ossl_pkey()?),
+            #[cfg(feature = "ed25519")]
             KeyPairType::ED25519(key) => Ok(key.ossl_pkey()?),
         }
     }
@@ -310,6 +838,76 @@ impl KeyPair {
         parse_keystr(pem.as_bytes(), passphrase)
     }
 
+    /// Parse the OpenSSH private key, and record its encoding to write it back byte-for-byte with [`KeyPair::serialize_exact()`]
+    ///
+    /// The cipher, the KDF rounds and salt, the check integers, the unknown fields and the line wrapping are recorded.
+    /// Fail with [`ErrorKind::UnsupportType`] for the other formats,
+    /// and with [`ErrorKind::InvalidFormat`] if the lines are not wrapped at a fixed width or there is any text around the key.
+    pub fn from_keystr_exact(pem: &str, passphrase: Option<&str>) -> OsshResult<Self> {
+        let pemdata = ::pem::parse(pem)?;
+        if pemdata.tag() != "OPENSSH PRIVATE KEY" {
+            return Err(ErrorKind::UnsupportType(pemdata.tag().to_owned()).into());
+        }
+        let (mut keypair, mut encoding) =
+            decode_ossh_priv_encoding(pemdata.contents(), passphrase)?;
+        encoding.read_layout(pem)?;
+        keypair.ossh_encoding = Some(Box::new(encoding));
+        if is_encrypted(&pemdata) {
+            report_key_usage(KeyOperation::Decrypt, &keypair);
+        }
+        Ok(keypair)
+    }
+
+    /// Parse the private key like [`KeyPair::from_keystr()`], but accept the encoding quirks of the Ed25519 PKCS#8 keys in the wild
+    ///
+    /// The PKCS#8 Ed25519 keys are decoded by this crate if OpenSSL refuses them, and the following quirks are accepted:
+    /// - The NULL parameters in the algorithm identifier
+    /// - The seed without the inner OCTET STRING, or wrapped in it twice
+    /// - The 64 bytes of the seed and the public key instead of the seed
+    /// - The public key tagged as `[1] EXPLICIT` BIT STRING, or without the unused bits byte
+    /// - The data after the key
+    ///
+    /// The attributes are skipped, and the public key is checked to match the seed if present.
+    pub fn from_keystr_lenient(pem: &str, passphrase: Option<&str>) -> OsshResult<Self> {
+        match parse_keystr(pem.as_bytes(), passphrase) {
+            Ok(keypair) => Ok(keypair),
+            Err(err) => match ::pem::parse(pem) {
+                Ok(pemdata) if matches!(pemdata.tag(), "PRIVATE KEY" | "ENCRYPTED PRIVATE KEY") => {
+                    let keypair =
+                        parse_ed25519_pkcs8(pem.as_bytes(), passphrase, true).map_err(|_| err)?;
+                    if is_encrypted(&pemdata) {
+                        report_key_usage(KeyOperation::Decrypt, &keypair);
+                    }
+                    Ok(keypair)
+                }
+                _ => Err(err),
+            },
+        }
+    }
+
+    /// Parse the private key like [`KeyPair::from_keystr()`], and check it with the policy
+    ///
+    /// The encryption is checked before decrypting the key.
+    pub fn from_keystr_with_policy(
+        pem: &str,
+        passphrase: Option<&str>,
+        policy: &Policy,
+    ) -> OsshResult<Self> {
+        policy.check_keystr(pem)?;
+        let keypair = Self::from_keystr(pem, passphrase)?;
+        policy.check_key(keypair.keytype(), keypair.size())?;
+        Ok(keypair)
+    }
+
+    /// Parse the RSA or Ed25519 OpenPGP secret key in the ASCII armor, like `gpg --export-secret-keys --armor` (Feature `openpgp`)
+    ///
+    /// The primary key is used, and its first user ID becomes the comment.
+    /// See [`decode_openpgp_secret_key()`](crate::format::openpgp::decode_openpgp_secret_key) for the supported keys.
+    #[cfg(feature = "openpgp")]
+    pub fn from_openpgp(armored: &str, passphrase: Option<&str>) -> OsshResult<Self> {
+        crate::format::openpgp::parse_openpgp_secret_key(armored, passphrase)
+    }
+
     /// Generate a key of the specified type and size
     ///
     /// # Key Size
@@ -319,26 +917,136 @@ impl KeyPair {
     /// - EcDSA: the size should be `256`, `384`, or `521` bits.
     /// - Ed25519: the size should be `256` bits.
     ///
-    /// If the key size parameter is zero, then it will use the default size to generate the key
+    /// If the key size parameter is zero, then it will use the size of the process-wide [`defaults()`](crate::defaults::defaults())
     /// - RSA: `2048` bits
     /// - DSA: `1024` bits
     /// - EcDSA: `256` bits
     /// - Ed25519: `256` bits
     pub fn generate(keytype: KeyType, bits: usize) -> OsshResult<Self> {
+        Self::generate_with_defaults(keytype, bits, &defaults())
+    }
+
+    /// Generate the key like [`KeyPair::generate()`], with the key size of `defaults` if `bits` is zero
+    pub fn generate_with_defaults(
+        keytype: KeyType,
+        bits: usize,
+        defaults: &Defaults,
+    ) -> OsshResult<Self> {
+        let bits = if bits == 0 {
+            defaults.key_bits(keytype)
+        } else {
+            bits
+        };
         Ok(match keytype {
+            #[cfg(feature = "rsa")]
             KeyType::RSA => rsa::RsaKeyPair::generate(bits)?.into(),
+            #[cfg(feature = "dsa")]
             KeyType::DSA => dsa::DsaKeyPair::generate(bits)?.into(),
+            #[cfg(feature = "ecdsa")]
             KeyType::ECDSA => ecdsa::EcDsaKeyPair::generate(bits)?.into(),
+            #[cfg(feature = "ed25519")]
             KeyType::ED25519 => ed25519::Ed25519KeyPair::generate(bits)?.into(),
         })
     }
 
+    /// Generate the key like [`KeyPair::generate()`], and check it with the policy
+    ///
+    /// The key type and the explicit key size are checked before generating the key.
+    pub fn generate_with_policy(
+        keytype: KeyType,
+        bits: usize,
+        policy: &Policy,
+    ) -> OsshResult<Self> {
+        if bits == 0 {
+            policy.check_key_type(keytype)?;
+        } else {
+            policy.check_key(keytype, bits)?;
+        }
+        let keypair = Self::generate(keytype, bits)?;
+        policy.check_key(keytype, keypair.size())?;
+        Ok(keypair)
+    }
+
+    /// Generate a key of the specified type and size using the given random number generator
+    ///
+    /// The key size has the same limitations as [`generate()`](#method.generate).
+    ///
+    /// All the secret values are drawn from `rng`, which allows using an auditable or
+    /// deterministic DRBG. The only exception is the DSA domain parameters,
+    /// which are still generated by OpenSSL.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(rng), err(level = "debug"))
+    )]
+    pub fn generate_with_rng<R: CryptoRngCore + ?Sized>(
+        keytype: KeyType,
+        bits: usize,
+        rng: &mut R,
+    ) -> OsshResult<Self> {
+        let bits = if bits == 0 {
+            defaults().key_bits(keytype)
+        } else {
+            bits
+        };
+        Ok(match keytype {
+            #[cfg(feature = "rsa")]
+            KeyType::RSA => rsa::RsaKeyPair::generate_with_rng(bits, rng)?.into(),
+            #[cfg(feature = "dsa")]
+            KeyType::DSA => dsa::DsaKeyPair::generate_with_rng(bits, rng)?.into(),
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA => ecdsa::EcDsaKeyPair::generate_with_rng(bits, rng)?.into(),
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519 => ed25519::Ed25519KeyPair::generate_with_rng(bits, rng)?.into(),
+        })
+    }
+
+    /// Generate a key in a new thread
+    ///
+    /// Large RSA keys can take seconds to generate, this avoids blocking the caller.
+    /// The parameters are the same as [`generate()`](#method.generate).
+    pub fn generate_in_thread(keytype: KeyType, bits: usize) -> GenerateHandle {
+        GenerateHandle {
+            inner: thread::spawn(move || Self::generate(keytype, bits)),
+        }
+    }
+
+    /// Generate a key on the blocking thread pool of the current tokio runtime
+    ///
+    /// The parameters are the same as [`generate()`](#method.generate).
+    ///
+    /// # Panics
+    /// Panics if it is not called within a tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn generate_async(keytype: KeyType, bits: usize) -> OsshResult<Self> {
+        tokio::task::spawn_blocking(move || Self::generate(keytype, bits))
+            .await
+            .map_err(|e| Error::with_error(ErrorKind::Unknown, e))?
+    }
+
+    /// Derive a key deterministically from a high entropy seed and a context string
+    ///
+    /// Only Ed25519 and EcDSA keys are supported.
+    /// See [`derive::DeriveScheme`](derive/enum.DeriveScheme.html) for the details of the scheme.
+    pub fn derive_from_seed(
+        scheme: derive::DeriveScheme,
+        keytype: KeyType,
+        bits: usize,
+        seed: &[u8],
+        context: &str,
+    ) -> OsshResult<Self> {
+        derive::derive_keypair(scheme, keytype, bits, seed, context)
+    }
+
     /// Indicate the key type being stored
     pub fn keytype(&self) -> KeyType {
         match &self.key {
+            #[cfg(feature = "rsa")]
             KeyPairType::RSA(_) => KeyType::RSA,
+            #[cfg(feature = "dsa")]
             KeyPairType::DSA(_) => KeyType::DSA,
+            #[cfg(feature = "ecdsa")]
             KeyPairType::ECDSA(_) => KeyType::ECDSA,
+            #[cfg(feature = "ed25519")]
             KeyPairType::ED25519(_) => KeyType::ED25519,
         }
     }
@@ -350,6 +1058,31 @@ impl KeyPair {
         stringify_pem_privkey(self, passphrase)
     }
 
+    /// Serialize the keypair to the OpenSSL PEM format using the given random number generator
+    ///
+    /// The random number generator is used to generate the IV of the encrypted key, which is also the KDF salt.
+    /// The Ed25519 keys are encrypted in the PKCS#8 format by OpenSSL, which always uses its own random number generator.
+    pub fn serialize_pem_with_rng<R: CryptoRngCore + ?Sized>(
+        &self,
+        passphrase: Option<&str>,
+        rng: &mut R,
+    ) -> OsshResult<String> {
+        stringify_pem_privkey_with_rng(self, passphrase, rng)
+    }
+
+    /// Serialize the RSA or Ed25519 keypair as an unencrypted OpenPGP secret key in the ASCII armor (Feature `openpgp`)
+    ///
+    /// The key is certified for the user ID by itself, so it can be imported by gpg and sign.
+    /// See [`encode_openpgp_secret_key()`](crate::format::openpgp::encode_openpgp_secret_key) for the details.
+    #[cfg(feature = "openpgp")]
+    pub fn serialize_openpgp(
+        &self,
+        user_id: &str,
+        creation_time: std::time::SystemTime,
+    ) -> OsshResult<String> {
+        crate::format::openpgp::serialize_openpgp_secret_key(self, user_id, creation_time)
+    }
+
     /// Serialize the keypair to the OpenSSL PKCS#8 PEM format
     ///
     /// If the passphrase is given (set to `Some(...)`), then the generated PKCS#8 key will be encrypted.
@@ -357,6 +1090,33 @@ impl KeyPair {
         serialize_pkcs8_privkey(self, passphrase)
     }
 
+    /// Serialize the keypair to the PKCS#8 PEM format of the version
+    ///
+    /// The version 2 (RFC 5958) embeds the public key, which some consumers require, and is only available for the Ed25519 keys.
+    pub fn serialize_pkcs8_with(
+        &self,
+        passphrase: Option<&str>,
+        version: Pkcs8Version,
+    ) -> OsshResult<String> {
+        serialize_pkcs8_privkey_with(self, passphrase, version)
+    }
+
+    /// Serialize the keypair to the PuTTY private key format (version 2)
+    ///
+    /// If the passphrase is given (set to `Some(...)`) and not empty, then the key will be encrypted with AES-256-CBC.
+    pub fn serialize_ppk(&self, passphrase: Option<&str>) -> OsshResult<String> {
+        serialize_ppk(self, passphrase)
+    }
+
+    /// Write the keypair in the PuTTY private key format (version 2) into the sink
+    pub fn write_ppk<W: fmt::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        passphrase: Option<&str>,
+    ) -> OsshResult<()> {
+        write_ppk(out, self, passphrase)
+    }
+
     /// Serialize the keypair to the OpenSSH private key format
     ///
     /// If the passphrase is given (set to `Some(...)`) and cipher is not null,
@@ -366,61 +1126,382 @@ impl KeyPair {
         passphrase: Option<&str>,
         cipher: Cipher,
     ) -> OsshResult<String> {
-        if let Some(passphrase) = passphrase {
-            Ok(serialize_ossh_privkey(self, passphrase, cipher, 0)?)
-        } else {
-            Ok(serialize_ossh_privkey(self, "", Cipher::Null, 0)?)
+        self.serialize_openssh_with_rng(passphrase, cipher, &mut OsRng)
+    }
+
+    /// Serialize the keypair to the OpenSSH private key format with the cipher and the KDF rounds of `defaults`
+    ///
+    /// The key is not encrypted if the passphrase is `None`.
+    pub fn serialize_openssh_with_defaults(
+        &self,
+        passphrase: Option<&str>,
+        defaults: &Defaults,
+    ) -> OsshResult<String> {
+        let (passphrase, cipher) = match passphrase {
+            Some(passphrase) => (passphrase, defaults.cipher),
+            None => ("", Cipher::Null),
+        };
+        let mut keystr = String::new();
+        write_ossh_privkey_with_rng(
+            &mut keystr,
+            self,
+            passphrase,
+            cipher,
+            defaults.kdf_rounds,
+            &mut OsRng,
+        )?;
+        Ok(keystr)
+    }
+
+    /// Serialize the keypair with the encoding recorded by [`KeyPair::from_keystr_exact()`]
+    ///
+    /// With the same passphrase, the unchanged key is written back byte-for-byte as it was parsed.
+    /// The key is written with the recorded cipher even if the passphrase is different.
+    /// Fail with [`ErrorKind::InvalidArgument`] if the encoding was not recorded.
+    pub fn serialize_exact(&self, passphrase: Option<&str>) -> OsshResult<String> {
+        let encoding = self
+            .ossh_encoding
+            .as_ref()
+            .ok_or(ErrorKind::InvalidArgument)?;
+        serialize_ossh_privkey_exact(self, passphrase.unwrap_or(""), encoding)
+    }
+
+    /// Serialize the keypair to the OpenSSH private key format using the given random number generator
+    ///
+    /// The random number generator is used to generate the KDF salt and the check integers.
+    pub fn serialize_openssh_with_rng<R: CryptoRngCore + ?Sized>(
+        &self,
+        passphrase: Option<&str>,
+        cipher: Cipher,
+        rng: &mut R,
+    ) -> OsshResult<String> {
+        let mut keystr = String::new();
+        self.write_openssh_with_rng(&mut keystr, passphrase, cipher, rng)?;
+        Ok(keystr)
+    }
+
+    /// Write the keypair in the OpenSSH private key format into the sink
+    ///
+    /// The sink receives the plaintext private key if the key is not encrypted, so wipe it after use in that case.
+    pub fn write_openssh<W: fmt::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        passphrase: Option<&str>,
+        cipher: Cipher,
+    ) -> OsshResult<()> {
+        self.write_openssh_with_rng(out, passphrase, cipher, &mut OsRng)
+    }
+
+    /// Write the keypair in the OpenSSH private key format into the sink using the given random number generator
+    pub fn write_openssh_with_rng<W: fmt::Write + ?Sized, R: CryptoRngCore + ?Sized>(
+        &self,
+        out: &mut W,
+        passphrase: Option<&str>,
+        cipher: Cipher,
+        rng: &mut R,
+    ) -> OsshResult<()> {
+        match passphrase {
+            Some(passphrase) => write_ossh_privkey_with_rng(out, self, passphrase, cipher, 0, rng),
+            None => write_ossh_privkey_with_rng(out, self, "", Cipher::Null, 0, rng),
         }
     }
 
     /// Get the comment of the key
+    ///
+    /// The comment of the OpenSSH private keys can contain arbitrary bytes,
+    /// so the invalid UTF-8 sequences are replaced by `U+FFFD`. See [`KeyPair::comment_bytes()`] for the raw bytes.
     pub fn comment(&self) -> &str {
         &self.comment
     }
 
+    /// Get the raw bytes of the key comment
+    ///
+    /// The original bytes of a non-UTF-8 comment are kept until the comment is changed,
+    /// and they are written back when serializing the key in the OpenSSH format.
+    pub fn comment_bytes(&self) -> &[u8] {
+        match &self.raw_comment {
+            Some(raw) if String::from_utf8_lossy(raw) == self.comment => raw,
+            _ => self.comment.as_bytes(),
+        }
+    }
+
     /// Get the mutable reference of the key comment
     pub fn comment_mut(&mut self) -> &mut String {
         &mut self.comment
     }
 
+    /// Replace the comment of the key
+    ///
+    /// The comment is written into the OpenSSH, PuTTY and RFC 4716 formats,
+    /// while the PEM and PKCS#8 formats cannot store it.
+    pub fn set_comment(&mut self, comment: &str) {
+        self.comment = comment.to_owned();
+        self.raw_comment = None;
+    }
+
+    /// Replace the comment of the key with the raw bytes, which are not necessarily valid UTF-8
+    pub fn set_comment_bytes(&mut self, comment: &[u8]) {
+        match std::str::from_utf8(comment) {
+            Ok(comment) => self.set_comment(comment),
+            Err(_) => {
+                self.comment = String::from_utf8_lossy(comment).into_owned();
+                self.raw_comment = Some(comment.to_vec());
+            }
+        }
+    }
+
     /// Get the OpenSSH public key of the public parts
     pub fn serialize_publickey(&self) -> OsshResult<String> {
         serialize_ossh_pubkey(self, &self.comment)
     }
 
+    /// Write the OpenSSH public key of the public parts into the sink, without the trailing newline
+    pub fn write_publickey<W: fmt::Write + ?Sized>(&self, out: &mut W) -> OsshResult<()> {
+        write_ossh_pubkey(out, self, &self.comment)
+    }
+
+    /// Dump the public parts of the key pair in a human-readable form, see [`PublicKey::describe()`]
+    ///
+    /// The private components are never written.
+    pub fn describe(&self) -> OsshResult<String> {
+        self.clone_public_key()?.describe()
+    }
+
+    /// Check if the public key corresponds to this key pair
+    ///
+    /// The comments of the keys are ignored.
+    pub fn matches_public(&self, pubkey: &PublicKey) -> bool {
+        match (self.blob(), pubkey.blob()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Sign the data and encode the signature in the SSH wire encoding `string algorithm, string signature`
+    ///
+    /// RSA keys are signed with the hash algorithm of the key.
+    /// The signature can be verified by [`PublicKey::verify_ssh_signature()`].
+    pub fn sign_ssh_signature(&self, data: &[u8]) -> OsshResult<Vec<u8>> {
+        self.sign_ssh_signature_with(data, None)
+    }
+
+    /// Sign the data like [`KeyPair::sign_ssh_signature()`], with the RSA hash algorithm overridden if given
+    ///
+    /// The `rsa_signature` is ignored for the other key types.
+    pub fn sign_ssh_signature_with(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<rsa::RsaSignature>,
+    ) -> OsshResult<Vec<u8>> {
+        self.sign_ssh_signature_checked(data, rsa_signature, LegacyAlgorithms::Global)
+    }
+
+    /// Sign the data like [`KeyPair::sign_ssh_signature_with()`], with the legacy algorithms allowed or refused
+    ///
+    /// Fail with [`ErrorKind::LegacyAlgorithm`] if the signature would be `ssh-rsa` or `ssh-dss` and `legacy` refuses it.
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    pub fn sign_ssh_signature_checked(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<rsa::RsaSignature>,
+        legacy: LegacyAlgorithms,
+    ) -> OsshResult<Vec<u8>> {
+        let (algo, sig) = match &self.key {
+            #[cfg(feature = "rsa")]
+            KeyPairType::RSA(key) => {
+                let signhash = rsa_signature.unwrap_or_else(|| key.sign_type());
+                legacy.check(signhash.name())?;
+                (signhash.name(), key.sign_with(data, signhash)?)
+            }
+            #[cfg(feature = "dsa")]
+            KeyPairType::DSA(key) => {
+                legacy.check(key.keyname())?;
+                (key.keyname(), key.sign_ssh(data)?)
+            }
+            #[cfg(feature = "ecdsa")]
+            KeyPairType::ECDSA(key) => (key.keyname(), key.sign_ssh(data)?),
+            #[cfg(feature = "ed25519")]
+            KeyPairType::ED25519(key) => (key.keyname(), key.sign(data)?),
+        };
+        report_key_usage(KeyOperation::Sign, self);
+        let mut buf = Vec::new();
+        buf.write_utf8(algo)?;
+        buf.write_string(&sig)?;
+        Ok(buf)
+    }
+
+    /// Sign the data like [`KeyPair::sign_ssh_signature_with()`], and check the key and the algorithm with the policy
+    pub fn sign_ssh_signature_with_policy(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<rsa::RsaSignature>,
+        policy: &Policy,
+    ) -> OsshResult<Vec<u8>> {
+        policy.check_key(self.keytype(), self.size())?;
+        let algo = match &self.key {
+            #[cfg(feature = "rsa")]
+            KeyPairType::RSA(key) => rsa_signature.unwrap_or_else(|| key.sign_type()).name(),
+            #[allow(unreachable_patterns)]
+            _ => self.keyname(),
+        };
+        policy.check_signature_algorithm(algo)?;
+        self.sign_ssh_signature_checked(data, rsa_signature, policy.legacy)
+    }
+
+    /// Borrow the public parts of the key pair
+    ///
+    /// Unlike [`KeyPair::clone_public_key()`], nothing is converted or copied,
+    /// so it's cheap to fingerprint or serialize the public key repeatedly.
+    pub fn public_key(&self) -> &dyn PublicParts {
+        self.inner_key_pub()
+    }
+
     /// Clone the public parts of the key pair
     pub fn clone_public_key(&self) -> Result<PublicKey, Error> {
         let key = match &self.key {
+            #[cfg(feature = "rsa")]
             KeyPairType::RSA(key) => PublicKeyType::RSA(key.clone_public_key()?),
+            #[cfg(feature = "dsa")]
             KeyPairType::DSA(key) => PublicKeyType::DSA(key.clone_public_key()?),
+            #[cfg(feature = "ecdsa")]
             KeyPairType::ECDSA(key) => PublicKeyType::ECDSA(key.clone_public_key()?),
+            #[cfg(feature = "ed25519")]
             KeyPairType::ED25519(key) => PublicKeyType::ED25519(key.clone_public_key()?),
         };
         Ok(PublicKey {
             key,
             comment: self.comment.clone(),
+            options: String::new(),
         })
     }
 
+    /// Compute the ECDH shared secret of the EcDSA key pair with the peer public key on the same curve
+    ///
+    /// Fail with [`ErrorKind::TypeNotMatch`] for the other keys.
+    /// See [`EcDsaKeyPair::derive_shared_secret()`](ecdsa::EcDsaKeyPair::derive_shared_secret) for the details,
+    /// and [`x25519`] for the X25519 keys.
+    pub fn derive_shared_secret(&self, peer: &PublicKey) -> OsshResult<Zeroizing<Vec<u8>>> {
+        match (&self.key, &peer.key) {
+            #[cfg(feature = "ecdsa")]
+            (KeyPairType::ECDSA(key), PublicKeyType::ECDSA(peer)) => key.derive_shared_secret(peer),
+            #[allow(unreachable_patterns)]
+            _ => Err(ErrorKind::TypeNotMatch.into()),
+        }
+    }
+
+    /// Compute the ECDH shared secret like [`KeyPair::derive_shared_secret()`], then derive the key material with the KDF
+    pub fn derive_shared_secret_with<K: agreement::SharedSecretKdf + ?Sized>(
+        &self,
+        peer: &PublicKey,
+        kdf: &K,
+    ) -> OsshResult<Zeroizing<Vec<u8>>> {
+        kdf.derive(&self.derive_shared_secret(peer)?)
+    }
+
+    /// Sign the data with the Ed25519ctx variant of RFC 8032, returning the raw 64 bytes signature
+    ///
+    /// Fail with [`ErrorKind::TypeNotMatch`] for the other keys.
+    /// See [`Ed25519KeyPair::sign_with_context()`](ed25519::Ed25519KeyPair::sign_with_context) for the details.
+    #[cfg(feature = "ed25519")]
+    pub fn sign_ed25519ctx(&self, data: &[u8], context: &[u8]) -> OsshResult<Vec<u8>> {
+        let sig = match &self.key {
+            KeyPairType::ED25519(key) => key.sign_with_context(data, context)?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(ErrorKind::TypeNotMatch.into()),
+        };
+        report_key_usage(KeyOperation::Sign, self);
+        Ok(sig)
+    }
+
+    /// Sign the data with the prehashed Ed25519ph variant of RFC 8032, returning the raw 64 bytes signature
+    ///
+    /// Fail with [`ErrorKind::TypeNotMatch`] for the other keys.
+    /// See [`Ed25519KeyPair::sign_prehashed()`](ed25519::Ed25519KeyPair::sign_prehashed) for the details.
+    #[cfg(feature = "ed25519")]
+    pub fn sign_ed25519ph(&self, data: &[u8], context: Option<&[u8]>) -> OsshResult<Vec<u8>> {
+        let sig = match &self.key {
+            KeyPairType::ED25519(key) => key.sign_prehashed(data, context)?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(ErrorKind::TypeNotMatch.into()),
+        };
+        report_key_usage(KeyOperation::Sign, self);
+        Ok(sig)
+    }
+
     fn inner_key(&self) -> &dyn PrivateParts {
         match &self.key {
+            #[cfg(feature = "rsa")]
             KeyPairType::RSA(key) => key,
+            #[cfg(feature = "dsa")]
             KeyPairType::DSA(key) => key,
+            #[cfg(feature = "ecdsa")]
             KeyPairType::ECDSA(key) => key,
+            #[cfg(feature = "ed25519")]
             KeyPairType::ED25519(key) => key,
         }
     }
 
     fn inner_key_pub(&self) -> &dyn PublicParts {
         match &self.key {
+            #[cfg(feature = "rsa")]
             KeyPairType::RSA(key) => key,
+            #[cfg(feature = "dsa")]
             KeyPairType::DSA(key) => key,
+            #[cfg(feature = "ecdsa")]
             KeyPairType::ECDSA(key) => key,
+            #[cfg(feature = "ed25519")]
             KeyPairType::ED25519(key) => key,
         }
     }
 }
 
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = debug_keypair(f, "KeyPair", self);
+        s.field("comment", &self.comment());
+        #[cfg(feature = "full-debug")]
+        s.field("key", &self.key);
+        s.finish_non_exhaustive()
+    }
+}
+
+/// Start the `Debug` output of a key pair with its key name, size and SHA256 fingerprint
+///
+/// The private components are only added by the callers with the feature `full-debug`.
+pub(crate) fn debug_keypair<'a, 'b: 'a, K: PublicParts + ?Sized>(
+    f: &'a mut fmt::Formatter<'b>,
+    name: &str,
+    key: &K,
+) -> fmt::DebugStruct<'a, 'b> {
+    let mut s = f.debug_struct(name);
+    s.field("keyname", &key.keyname())
+        .field("size", &key.size());
+    if let Ok(fingerprint) = Fingerprint::of(key, FingerprintHash::SHA256) {
+        s.field("fingerprint", &format_args!("{}", fingerprint));
+    }
+    s
+}
+
+/// A handle to the key being generated by [`KeyPair::generate_in_thread()`](struct.KeyPair.html#method.generate_in_thread)
+#[derive(Debug)]
+pub struct GenerateHandle {
+    inner: JoinHandle<OsshResult<KeyPair>>,
+}
+
+impl GenerateHandle {
+    /// Check if the generation has finished, without blocking
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+
+    /// Wait for the generation to finish and get the key
+    pub fn join(self) -> OsshResult<KeyPair> {
+        self.inner
+            .join()
+            .map_err(|_| Error::from_kind(ErrorKind::Unknown))?
+    }
+}
+
 impl Key for KeyPair {
     fn size(&self) -> usize {
         self.inner_key().size()
@@ -443,43 +1524,65 @@ impl PublicParts for KeyPair {
 }
 
 impl PrivateParts for KeyPair {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(keytype = ?self.keytype()))
+    )]
     fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-        self.inner_key().sign(data)
+        let sig = self.inner_key().sign(data)?;
+        report_key_usage(KeyOperation::Sign, self);
+        Ok(sig)
     }
 }
 
+#[cfg(feature = "rsa")]
 impl From<rsa::RsaKeyPair> for KeyPair {
     fn from(inner: rsa::RsaKeyPair) -> KeyPair {
         KeyPair {
             key: KeyPairType::RSA(inner),
             comment: String::new(),
+            raw_comment: None,
+            ossh_unknown: None,
+            ossh_encoding: None,
         }
     }
 }
 
+#[cfg(feature = "dsa")]
 impl From<dsa::DsaKeyPair> for KeyPair {
     fn from(inner: dsa::DsaKeyPair) -> KeyPair {
         KeyPair {
             key: KeyPairType::DSA(inner),
             comment: String::new(),
+            raw_comment: None,
+            ossh_unknown: None,
+            ossh_encoding: None,
         }
     }
 }
 
+#[cfg(feature = "ecdsa")]
 impl From<ecdsa::EcDsaKeyPair> for KeyPair {
     fn from(inner: ecdsa::EcDsaKeyPair) -> KeyPair {
         KeyPair {
             key: KeyPairType::ECDSA(inner),
             comment: String::new(),
+            raw_comment: None,
+            ossh_unknown: None,
+            ossh_encoding: None,
         }
     }
 }
 
+#[cfg(feature = "ed25519")]
 impl From<ed25519::Ed25519KeyPair> for KeyPair {
     fn from(inner: ed25519::Ed25519KeyPair) -> KeyPair {
         KeyPair {
             key: KeyPairType::ED25519(inner),
             comment: String::new(),
+            raw_comment: None,
+            ossh_unknown: None,
+            ossh_encoding: None,
         }
     }
 }
@@ -506,6 +1609,12 @@ pub trait PublicParts: Key {
         Ok(hash.hash(&b))
     }
 
+    /// Encode the SHA-1 digest of the blob in the bubble babble format, like `ssh-keygen -B`
+    fn fingerprint_bubblebabble(&self) -> OsshResult<String> {
+        let b = self.blob()?;
+        Ok(bubblebabble(&Sha1::digest(b)))
+    }
+
     // Rewritten from the OpenSSH project. OpenBSD notice is included below.
 
     /* $OpenBSD: sshkey.c,v 1.120 2022/01/06 22:05:42 djm Exp $ */
@@ -627,10 +1736,60 @@ pub trait PrivateParts: Key {
     fn sign(&self, data: &[u8]) -> OsshResult<Vec<u8>>;
 }
 
+/// A key which makes the SSH signatures, such as a [`KeyPair`] or a key kept outside of the process
+pub trait SshSigner {
+    /// Clone the public key of the signing key
+    fn clone_public_key(&self) -> OsshResult<PublicKey>;
+    /// Sign the data and encode the signature in the SSH wire encoding, like [`KeyPair::sign_ssh_signature_with()`]
+    fn sign_ssh_signature_with(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<rsa::RsaSignature>,
+    ) -> OsshResult<Vec<u8>>;
+}
+
+impl SshSigner for KeyPair {
+    fn clone_public_key(&self) -> OsshResult<PublicKey> {
+        KeyPair::clone_public_key(self)
+    }
+    fn sign_ssh_signature_with(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<rsa::RsaSignature>,
+    ) -> OsshResult<Vec<u8>> {
+        KeyPair::sign_ssh_signature_with(self, data, rsa_signature)
+    }
+}
+
+/// Draw an uniformly distributed integer within `[1, upper)` from the random number generator
+#[cfg(any(feature = "dsa", feature = "ecdsa"))]
+fn random_bignum_below<R: CryptoRngCore + ?Sized>(
+    rng: &mut R,
+    upper: &BigNumRef,
+) -> OsshResult<BigNum> {
+    let bits = upper.num_bits() as usize;
+    let mut buf = Zeroizing::new(vec![0u8; (bits + 7) / 8]);
+    loop {
+        rng.fill_bytes(&mut buf);
+        // Clear the excess bits to make the rejection rate below 50%
+        buf[0] &= 0xff >> (buf.len() * 8 - bits);
+        let n = BigNum::from_slice(&buf)?;
+        if n.num_bits() > 0 && n.ucmp(upper) == Ordering::Less {
+            return Ok(n);
+        }
+    }
+}
+
 // This test is used to print the struct size of [`PublicKey`] and [`KeyPair`].
 // It is intented to be run manually, and the result is read by the developers.
 #[test]
 #[ignore]
+#[cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
 fn test_size() {
     use std::mem::size_of;
 
@@ -648,3 +1807,338 @@ fn test_size() {
     eprintln!("\tECDSA: {} bytes", size_of::<ecdsa::EcDsaKeyPair>());
     eprintln!("\tED25519: {} bytes", size_of::<ed25519::Ed25519KeyPair>());
 }
+
+/// Get the OpenSSL short name of the key type, such as `X25519`
+fn ossl_pkey_name(id: Id) -> String {
+    openssl::nid::Nid::from_raw(id.as_raw())
+        .short_name()
+        .map_or_else(
+            |_| format!("OpenSSL key type {}", id.as_raw()),
+            str::to_owned,
+        )
+}
+
+/// Get the big-endian bytes of the number with a leading zero if the high bit is set, as openssl prints them
+#[cfg(any(feature = "rsa", feature = "dsa"))]
+fn mpint_bytes(num: &BigNumRef) -> Vec<u8> {
+    let mut bytes = num.to_vec();
+    if bytes.first().map_or(false, |b| b & 0x80 != 0) {
+        bytes.insert(0, 0);
+    }
+    bytes
+}
+
+/// Write the bytes as the colon separated hex with 15 bytes per line, like `openssl -text`
+fn write_hex_block(out: &mut String, name: &str, bytes: &[u8]) -> fmt::Result {
+    writeln!(out, "{}:", name)?;
+    let lines = bytes.chunks(15).count();
+    for (i, line) in bytes.chunks(15).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let sep = if i + 1 < lines { ":" } else { "" };
+        writeln!(out, "    {}{}", hex.join(":"), sep)?;
+    }
+    Ok(())
+}
+
+/// Encode the data in the bubble babble format
+fn bubblebabble(data: &[u8]) -> String {
+    const VOWELS: &[u8] = b"aeiouy";
+    const CONSONANTS: &[u8] = b"bcdfghklmnprstvzx";
+
+    let rounds = data.len() / 2 + 1;
+    let mut seed = 1usize;
+    let mut out = String::with_capacity(rounds * 6);
+    out.push('x');
+    for i in 0..rounds {
+        if i + 1 < rounds || data.len() % 2 != 0 {
+            let b0 = data[2 * i] as usize;
+            out.push(VOWELS[(((b0 >> 6) & 3) + seed) % 6] as char);
+            out.push(CONSONANTS[(b0 >> 2) & 15] as char);
+            out.push(VOWELS[((b0 & 3) + seed / 6) % 6] as char);
+            if i + 1 < rounds {
+                let b1 = data[2 * i + 1] as usize;
+                out.push(CONSONANTS[(b1 >> 4) & 15] as char);
+                out.push('-');
+                out.push(CONSONANTS[b1 & 15] as char);
+                seed = (seed * 5 + b0 * 7 + b1) % 36;
+            }
+        } else {
+            out.push(VOWELS[seed % 6] as char);
+            out.push(CONSONANTS[16] as char);
+            out.push(VOWELS[seed / 6] as char);
+        }
+    }
+    out.push('x');
+    out
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keytype_names() {
+        for keytype in [KeyType::RSA, KeyType::DSA, KeyType::ECDSA, KeyType::ED25519] {
+            assert_eq!(keytype.to_string().parse::<KeyType>().unwrap(), keytype);
+        }
+        assert_eq!(KeyType::ED25519.to_string(), "ssh-ed25519");
+        assert_eq!("rsa-sha2-512".parse::<KeyType>().unwrap(), KeyType::RSA);
+        assert_eq!(
+            "ecdsa-sha2-nistp384-cert-v01@openssh.com"
+                .parse::<KeyType>()
+                .unwrap(),
+            KeyType::ECDSA
+        );
+        assert_eq!("ed25519".parse::<KeyType>().unwrap(), KeyType::ED25519);
+        assert_eq!(
+            "ssh-foo".parse::<KeyType>().unwrap_err().kind(),
+            ErrorKind::UnsupportType("ssh-foo".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn comment_roundtrip() {
+        const COMMENT: &str = "user name@host  with spaces";
+        let mut keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        keypair.set_comment(COMMENT);
+        assert_eq!(keypair.comment(), COMMENT);
+
+        for passphrase in [None, Some("12345678")] {
+            let keystr = keypair
+                .serialize_openssh(passphrase, Cipher::Aes256_Ctr)
+                .unwrap();
+            let parsed = KeyPair::from_keystr(&keystr, passphrase).unwrap();
+            assert_eq!(parsed.comment(), COMMENT);
+        }
+
+        let pubkey = keypair.clone_public_key().unwrap();
+        assert_eq!(pubkey.comment(), COMMENT);
+        for keystr in [
+            keypair.serialize_publickey().unwrap(),
+            pubkey.serialize_rfc4716().unwrap(),
+        ] {
+            assert_eq!(PublicKey::from_keystr(&keystr).unwrap().comment(), COMMENT);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn publickey_formats() {
+        let mut pubkey = KeyPair::generate(KeyType::ED25519, 0)
+            .unwrap()
+            .clone_public_key()
+            .unwrap();
+        pubkey.set_comment("user@host");
+        let blob = base64::encode(pubkey.to_blob().unwrap());
+
+        assert_eq!(
+            pubkey.to_string_with(PublicKeyFormat::Full).unwrap(),
+            pubkey.serialize().unwrap()
+        );
+        assert_eq!(
+            pubkey.to_string_with(PublicKeyFormat::NoComment).unwrap(),
+            format!("ssh-ed25519 {}", blob)
+        );
+        assert_eq!(
+            pubkey
+                .to_string_with(PublicKeyFormat::Comment("deploy key"))
+                .unwrap(),
+            format!("ssh-ed25519 {} deploy key", blob)
+        );
+        assert_eq!(pubkey.to_string_with(PublicKeyFormat::Blob).unwrap(), blob);
+        assert_eq!(pubkey.comment(), "user@host");
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn keypair_public_key_view() {
+        for keytype in [KeyType::RSA, KeyType::ECDSA, KeyType::ED25519] {
+            let keypair = KeyPair::generate(keytype, 0).unwrap();
+            let pubkey = keypair.clone_public_key().unwrap();
+            let view = keypair.public_key();
+            assert_eq!(view.keyname(), pubkey.keyname());
+            assert_eq!(view.size(), pubkey.size());
+            assert_eq!(view.blob().unwrap(), pubkey.blob().unwrap());
+            assert_eq!(
+                view.fingerprint(FingerprintHash::SHA256).unwrap(),
+                pubkey.fingerprint(FingerprintHash::SHA256).unwrap()
+            );
+            assert_eq!(
+                serialize_ossh_pubkey(view, "").unwrap(),
+                pubkey.to_string_with(PublicKeyFormat::NoComment).unwrap()
+            );
+            let sig = keypair.sign(b"data").unwrap();
+            assert!(view.verify(b"data", &sig).unwrap());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn publickey_blob_roundtrip() {
+        for keytype in [KeyType::ECDSA, KeyType::ED25519] {
+            let keypair = KeyPair::generate(keytype, 0).unwrap();
+            let pubkey = keypair.clone_public_key().unwrap();
+            let blob = pubkey.to_blob().unwrap();
+            assert_eq!(blob, keypair.blob().unwrap());
+
+            let decoded = PublicKey::from_blob(&blob).unwrap();
+            assert!(decoded == pubkey);
+            assert_eq!(decoded.to_blob().unwrap(), blob);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn ssh_signature_roundtrip() {
+        const DATA: &[u8] = b"8Kn9PPQV";
+        for (keytype, bits) in [
+            (KeyType::RSA, 1024),
+            (KeyType::DSA, 0),
+            (KeyType::ECDSA, 384),
+            (KeyType::ED25519, 0),
+        ] {
+            let keypair = KeyPair::generate(keytype, bits).unwrap();
+            let pubkey = keypair.clone_public_key().unwrap();
+            let sig = keypair.sign_ssh_signature(DATA).unwrap();
+            assert!(pubkey.verify_ssh_signature(DATA, &sig).unwrap());
+            assert!(!pubkey.verify_ssh_signature(b"tampered", &sig).unwrap());
+        }
+
+        let keypair = KeyPair::generate(KeyType::RSA, 1024).unwrap();
+        let sig = keypair
+            .sign_ssh_signature_with(DATA, Some(rsa::RsaSignature::SHA2_256))
+            .unwrap();
+        assert_eq!(
+            SshReader::new(&sig, SECTION_SIGNATURE)
+                .read_utf8("algorithm")
+                .unwrap(),
+            rsa::RSA_SHA256_NAME
+        );
+        let pubkey = keypair.clone_public_key().unwrap();
+        assert!(pubkey.verify_ssh_signature(DATA, &sig).unwrap());
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn legacy_algorithms_checked() {
+        const DATA: &[u8] = b"8Kn9PPQV";
+        let keypair = KeyPair::generate(KeyType::RSA, 1024).unwrap();
+        let pubkey = keypair.clone_public_key().unwrap();
+        let err = keypair
+            .sign_ssh_signature_checked(
+                DATA,
+                Some(rsa::RsaSignature::SHA1),
+                LegacyAlgorithms::Refuse,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            ErrorKind::LegacyAlgorithm(rsa::RSA_NAME.to_string())
+        );
+        let sig = keypair
+            .sign_ssh_signature_checked(
+                DATA,
+                Some(rsa::RsaSignature::SHA1),
+                LegacyAlgorithms::Allow,
+            )
+            .unwrap();
+        assert!(pubkey
+            .verify_ssh_signature_checked(DATA, &sig, LegacyAlgorithms::Allow)
+            .unwrap());
+        assert!(pubkey
+            .verify_ssh_signature_checked(DATA, &sig, LegacyAlgorithms::Refuse)
+            .is_err());
+        let sig = keypair
+            .sign_ssh_signature_checked(DATA, None, LegacyAlgorithms::Refuse)
+            .unwrap();
+        assert!(pubkey
+            .verify_ssh_signature_checked(DATA, &sig, LegacyAlgorithms::Refuse)
+            .unwrap());
+
+        let keypair = KeyPair::generate(KeyType::DSA, 0).unwrap();
+        assert!(keypair
+            .sign_ssh_signature_checked(DATA, None, LegacyAlgorithms::Refuse)
+            .is_err());
+    }
+
+    #[test]
+    fn describe_keys() {
+        // The hex blocks are compared with `openssl pkey -pubin -text -noout`
+        let rsa = KeyPair::from_keystr(include_str!("../../assets/openssh_rsa"), None).unwrap();
+        let dump = rsa.describe().unwrap();
+        assert!(dump.starts_with("Type: ssh-rsa (RSA)\nSize: 2048 bit\n"));
+        assert!(dump.contains(
+            "Modulus:\n    00:bd:34:2b:4a:a0:2f:f8:1a:4f:b3:4b:c5:c6:b4:\n    0e:5a:18:2f:41:78:57:dc:38:28:3f:f4:2d:78:a6:\n"
+        ));
+        assert!(dump.contains("    5e:69\nExponent: 65537 (0x10001)\n"));
+        assert!(!dump.contains("Private"));
+        assert_eq!(dump, rsa.clone_public_key().unwrap().describe().unwrap());
+
+        let ecdsa = PublicKey::from_keystr(include_str!("../../assets/openssh_ecdsa.pub")).unwrap();
+        let dump = ecdsa.describe().unwrap();
+        assert!(dump.contains("    fa:4e:0b:51:5d\nCurve: nistp256\nASN1 OID: prime256v1\n"));
+
+        let dsa = PublicKey::from_keystr(include_str!("../../assets/openssh_dsa.pub")).unwrap();
+        let dump = dsa.describe().unwrap();
+        assert!(dump.contains(
+            "Q:\n    00:fa:db:27:c5:cc:9c:73:df:5d:da:a0:85:60:a0:\n    c1:68:cf:c7:05:ff\n"
+        ));
+
+        let ed25519 =
+            PublicKey::from_keystr(include_str!("../../assets/openssh_ed25519.pub")).unwrap();
+        let dump = ed25519.describe().unwrap();
+        assert!(dump.starts_with("Type: ssh-ed25519 (ED25519)\nSize: 256 bit\n"));
+        let fingerprints = format!(
+            "Fingerprints:\n    {}\n    {}\n",
+            Fingerprint::of(&ed25519, FingerprintHash::MD5).unwrap(),
+            Fingerprint::of(&ed25519, FingerprintHash::SHA256).unwrap()
+        );
+        assert!(dump.ends_with(&fingerprints));
+    }
+
+    #[test]
+    fn bubblebabble_vectors() {
+        assert_eq!(bubblebabble(b""), "xexax");
+        assert_eq!(
+            bubblebabble(b"1234567890"),
+            "xesef-disof-gytuf-katof-movif-baxux"
+        );
+        assert_eq!(bubblebabble(b"Pineapple"), "xigak-nyryk-humil-bosek-sonax");
+    }
+
+    #[cfg(not(feature = "full-debug"))]
+    #[test]
+    fn keypair_debug_redacted() {
+        let keypair = KeyPair::from_keystr(include_str!("../../assets/openssh_rsa"), None).unwrap();
+        let fingerprint = Fingerprint::of(&keypair, FingerprintHash::SHA256).unwrap();
+        let debug = format!("{:?}", keypair);
+        assert!(debug.starts_with("KeyPair { keyname: \"ssh-rsa\", size: "));
+        assert!(debug.contains(&format!("fingerprint: {}", fingerprint)));
+        match &keypair.key {
+            KeyPairType::RSA(key) => {
+                let d = key.ossl_rsa().d().to_dec_str().unwrap().to_string();
+                assert!(!debug.contains(&d));
+                assert!(!format!("{:?}", key).contains(&d));
+            }
+            _ => unreachable!(),
+        }
+
+        let keypair =
+            KeyPair::from_keystr(include_str!("../../assets/openssh_ed25519"), None).unwrap();
+        match &keypair.key {
+            KeyPairType::ED25519(key) => {
+                let secret = format!("{:?}", key.key.as_bytes());
+                assert!(!format!("{:?}", keypair).contains(&secret));
+                assert!(format!("{:?}", key).starts_with("Ed25519KeyPair { keyname: "));
+            }
+            _ => unreachable!(),
+        }
+    }
+}
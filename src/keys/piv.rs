@@ -0,0 +1,291 @@
+use super::pkcs11::{Pkcs11Module, Pkcs11Signer};
+use super::PublicKey;
+use crate::error::*;
+use openssl::x509::{X509VerifyResult, X509};
+use std::sync::Arc;
+
+/// The label of the attestation intermediate certificate (slot `f9`) in ykcs11
+const ATTESTATION_INTERMEDIATE_LABEL: &str = "X.509 Certificate for PIV Attestation";
+
+/// The PIV slots of the YubiKey holding the asymmetric keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PivSlot {
+    /// Slot `9a`, the PIV authentication key
+    Authentication,
+    /// Slot `9c`, the digital signature key
+    Signature,
+    /// Slot `9d`, the key management key
+    KeyManagement,
+    /// Slot `9e`, the card authentication key, which is used without the PIN
+    CardAuthentication,
+}
+
+impl PivSlot {
+    /// Parse from the slot number, such as `0x9a`
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x9a => Some(PivSlot::Authentication),
+            0x9c => Some(PivSlot::Signature),
+            0x9d => Some(PivSlot::KeyManagement),
+            0x9e => Some(PivSlot::CardAuthentication),
+            _ => None,
+        }
+    }
+
+    /// The slot number, such as `0x9a`
+    pub fn id(self) -> u8 {
+        match self {
+            PivSlot::Authentication => 0x9a,
+            PivSlot::Signature => 0x9c,
+            PivSlot::KeyManagement => 0x9d,
+            PivSlot::CardAuthentication => 0x9e,
+        }
+    }
+
+    fn object_name(self) -> &'static str {
+        match self {
+            PivSlot::Authentication => "PIV Authentication",
+            PivSlot::Signature => "Digital Signature",
+            PivSlot::KeyManagement => "Key Management",
+            PivSlot::CardAuthentication => "Card Authentication",
+        }
+    }
+
+    /// The label of the private key object of the slot in ykcs11
+    pub fn key_label(self) -> String {
+        format!("Private key for {}", self.object_name())
+    }
+
+    /// The label of the public key object of the slot in ykcs11
+    pub fn public_key_label(self) -> String {
+        format!("Public key for {}", self.object_name())
+    }
+
+    /// The label of the certificate object of the slot in ykcs11
+    pub fn certificate_label(self) -> String {
+        format!("X.509 Certificate for {}", self.object_name())
+    }
+
+    /// The label of the attestation certificate object of the slot in ykcs11
+    pub fn attestation_label(self) -> String {
+        format!("{} {:02x}", ATTESTATION_INTERMEDIATE_LABEL, self.id())
+    }
+}
+
+/// Open the key in the PIV slot of a YubiKey as a signer, with Yubico's PKCS#11 module (ykcs11)
+///
+/// The `token_slot` is the PKCS#11 slot of the YubiKey, which is `0` if only one YubiKey is connected.
+/// The key comment is set to `YubiKey PIV <slot>`.
+pub fn open_piv_signer(
+    module: &Arc<Pkcs11Module>,
+    token_slot: u64,
+    slot: PivSlot,
+    pin: Option<&str>,
+) -> OsshResult<Pkcs11Signer> {
+    let mut signer = Pkcs11Signer::open_with_public_label(
+        module,
+        token_slot,
+        &slot.key_label(),
+        &slot.public_key_label(),
+        pin,
+    )?;
+    *signer.comment_mut() = format!("YubiKey PIV {:02x}", slot.id());
+    Ok(signer)
+}
+
+/// The attestation of a key generated in a PIV slot of a YubiKey
+///
+/// The attestation certificate is signed by the intermediate certificate of the YubiKey,
+/// which is signed by Yubico's PIV root CA.
+pub struct PivAttestation {
+    certificate: X509,
+    intermediate: X509,
+}
+
+impl PivAttestation {
+    /// Read the attestation of the key in the PIV slot and the intermediate certificate with ykcs11
+    pub fn read(module: &Pkcs11Module, token_slot: u64, slot: PivSlot) -> OsshResult<Self> {
+        let certificate = module.read_certificate(token_slot, &slot.attestation_label())?;
+        let intermediate = module.read_certificate(token_slot, ATTESTATION_INTERMEDIATE_LABEL)?;
+        Self::from_der(&certificate, &intermediate)
+    }
+
+    /// Parse the DER encoded attestation certificate and intermediate certificate
+    pub fn from_der(certificate: &[u8], intermediate: &[u8]) -> OsshResult<Self> {
+        Ok(PivAttestation {
+            certificate: X509::from_der(certificate)?,
+            intermediate: X509::from_der(intermediate)?,
+        })
+    }
+
+    /// Get the public key of the attested key
+    pub fn public_key(&self) -> OsshResult<PublicKey> {
+        PublicKey::from_ossl_pkey(&*self.certificate.public_key()?)
+    }
+
+    /// Check if the attestation certificate is signed by the intermediate certificate
+    ///
+    /// The intermediate certificate should be checked against Yubico's PIV root CA separately,
+    /// use [`PivAttestation::verify_chain()`] to check both.
+    pub fn verify(&self) -> OsshResult<bool> {
+        Ok(self.certificate.verify(&*self.intermediate.public_key()?)?)
+    }
+
+    /// Check the whole chain from the attestation certificate up to the DER encoded root certificate
+    ///
+    /// The root should be Yubico's PIV root CA, which is published at <https://developers.yubico.com/PIV/Introduction/PIV_attestation.html>.
+    /// The issuer names and the signatures of both the attestation certificate and the intermediate certificate are checked.
+    pub fn verify_chain(&self, root: &[u8]) -> OsshResult<bool> {
+        let root = X509::from_der(root)?;
+        Ok(root.issued(&self.intermediate) == X509VerifyResult::OK
+            && self.intermediate.issued(&self.certificate) == X509VerifyResult::OK
+            && self.intermediate.verify(&*root.public_key()?)?
+            && self.verify()?)
+    }
+
+    /// Get the DER encoded attestation certificate
+    pub fn certificate_der(&self) -> OsshResult<Vec<u8>> {
+        Ok(self.certificate.to_der()?)
+    }
+
+    /// Get the DER encoded intermediate certificate
+    pub fn intermediate_der(&self) -> OsshResult<Vec<u8>> {
+        Ok(self.intermediate.to_der()?)
+    }
+}
+
+#[cfg(all(test, feature = "ecdsa"))]
+mod test {
+    use super::*;
+    use crate::keys::PublicParts;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{HasPublic, PKey, PKeyRef, Private};
+    use openssl::x509::{X509Name, X509NameRef, X509};
+
+    fn certificate<T: HasPublic>(
+        subject: &str,
+        key: &PKeyRef<T>,
+        issuer: &X509NameRef,
+        ca_key: &PKeyRef<Private>,
+    ) -> X509 {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, subject).unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(issuer).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(ca_key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn ca() -> (PKey<Private>, X509) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "Yubico PIV Attestation")
+            .unwrap();
+        let cert = certificate("Yubico PIV Attestation", &key, &name.build(), &key);
+        (key, cert)
+    }
+
+    #[test]
+    fn piv_slots() {
+        for id in [0x9a, 0x9c, 0x9d, 0x9e] {
+            assert_eq!(PivSlot::from_id(id).unwrap().id(), id);
+        }
+        assert_eq!(PivSlot::from_id(0xf9), None);
+        assert_eq!(
+            PivSlot::Authentication.key_label(),
+            "Private key for PIV Authentication"
+        );
+        assert_eq!(
+            PivSlot::Signature.attestation_label(),
+            "X.509 Certificate for PIV Attestation 9c"
+        );
+    }
+
+    #[test]
+    fn piv_attestation() {
+        let keystr = std::fs::read_to_string("assets/pem_ecdsa.pub").unwrap();
+        let pubkey = PublicKey::from_keystr(&keystr).unwrap();
+        let key = PKey::public_key_from_pem(&pubkey.serialize_pem().unwrap().into_bytes()).unwrap();
+
+        let (ca_key, ca_cert) = ca();
+        let attestation = certificate(
+            "YubiKey PIV Attestation 9a",
+            &key,
+            ca_cert.subject_name(),
+            &ca_key,
+        );
+        let attestation =
+            PivAttestation::from_der(&attestation.to_der().unwrap(), &ca_cert.to_der().unwrap())
+                .unwrap();
+        assert!(attestation.verify().unwrap());
+        assert_eq!(
+            attestation.public_key().unwrap().blob().unwrap(),
+            pubkey.blob().unwrap()
+        );
+
+        let (_, other_ca) = ca();
+        let forged = PivAttestation::from_der(
+            &attestation.certificate_der().unwrap(),
+            &other_ca.to_der().unwrap(),
+        )
+        .unwrap();
+        assert!(!forged.verify().unwrap());
+    }
+
+    #[test]
+    fn piv_attestation_chain() {
+        let keystr = std::fs::read_to_string("assets/pem_ecdsa.pub").unwrap();
+        let pubkey = PublicKey::from_keystr(&keystr).unwrap();
+        let key = PKey::public_key_from_pem(&pubkey.serialize_pem().unwrap().into_bytes()).unwrap();
+
+        let (root_key, root) = ca();
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let intermediate_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let intermediate = certificate(
+            "Yubico PIV Attestation Intermediate",
+            &intermediate_key,
+            root.subject_name(),
+            &root_key,
+        );
+        let attestation = certificate(
+            "YubiKey PIV Attestation 9a",
+            &key,
+            intermediate.subject_name(),
+            &intermediate_key,
+        );
+        let attestation = PivAttestation::from_der(
+            &attestation.to_der().unwrap(),
+            &intermediate.to_der().unwrap(),
+        )
+        .unwrap();
+        assert!(attestation.verify().unwrap());
+        assert!(attestation.verify_chain(&root.to_der().unwrap()).unwrap());
+
+        // Another root with the same name
+        let (_, other_root) = ca();
+        assert!(!attestation
+            .verify_chain(&other_root.to_der().unwrap())
+            .unwrap());
+        // The intermediate is not a root
+        assert!(!attestation
+            .verify_chain(&intermediate.to_der().unwrap())
+            .unwrap());
+    }
+}
@@ -0,0 +1,881 @@
+#![allow(non_camel_case_types, non_snake_case)]
+#![cfg_attr(
+    not(all(feature = "rsa", feature = "ecdsa", feature = "ed25519")),
+    allow(dead_code)
+)]
+
+#[cfg(feature = "ecdsa")]
+use super::ecdsa::{EcCurve, EcDsaPublicKey};
+#[cfg(feature = "ed25519")]
+use super::ed25519::{Ed25519PublicKey, ED25519_NAME};
+use super::rsa::RsaSignature;
+#[cfg(feature = "rsa")]
+use super::{rsa::RsaPublicKey, LegacyAlgorithms};
+use super::{Key, KeyType, PublicKey, PublicKeyType, PublicParts, SshSigner};
+use crate::error::*;
+use crate::sshbuf::SshWriteExt;
+use libloading::{Library, Symbol};
+use openssl::bn::BigNum;
+#[cfg(feature = "ecdsa")]
+use openssl::hash::hash;
+use std::ffi::OsStr;
+use std::os::raw::{c_ulong, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+type CK_ULONG = c_ulong;
+type CK_RV = CK_ULONG;
+type CK_SESSION_HANDLE = CK_ULONG;
+type CK_OBJECT_HANDLE = CK_ULONG;
+
+const CKR_OK: CK_RV = 0x000;
+const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x100;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x191;
+const CKF_OS_LOCKING_OK: CK_ULONG = 0x2;
+const CKF_SERIAL_SESSION: CK_ULONG = 0x4;
+const CKU_USER: CK_ULONG = 1;
+const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+
+#[cfg(feature = "yubikey-piv")]
+const CKO_CERTIFICATE: CK_ULONG = 1;
+const CKO_PUBLIC_KEY: CK_ULONG = 2;
+const CKO_PRIVATE_KEY: CK_ULONG = 3;
+const CKK_RSA: CK_ULONG = 0x00;
+const CKK_EC: CK_ULONG = 0x03;
+const CKK_EC_EDWARDS: CK_ULONG = 0x40;
+
+const CKA_CLASS: CK_ULONG = 0x000;
+const CKA_LABEL: CK_ULONG = 0x003;
+#[cfg(feature = "yubikey-piv")]
+const CKA_VALUE: CK_ULONG = 0x011;
+const CKA_KEY_TYPE: CK_ULONG = 0x100;
+const CKA_MODULUS: CK_ULONG = 0x120;
+const CKA_PUBLIC_EXPONENT: CK_ULONG = 0x122;
+const CKA_EC_PARAMS: CK_ULONG = 0x180;
+const CKA_EC_POINT: CK_ULONG = 0x181;
+
+const CKM_SHA1_RSA_PKCS: CK_ULONG = 0x0006;
+const CKM_SHA256_RSA_PKCS: CK_ULONG = 0x0040;
+const CKM_SHA512_RSA_PKCS: CK_ULONG = 0x0042;
+const CKM_ECDSA: CK_ULONG = 0x1041;
+const CKM_EDDSA: CK_ULONG = 0x1057;
+
+// The DER encoded OIDs of the curves in CKA_EC_PARAMS
+const OID_NISTP256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_NISTP384: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_NISTP521: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x23];
+
+#[repr(C)]
+#[cfg_attr(windows, repr(packed))]
+struct CK_ATTRIBUTE {
+    type_: CK_ULONG,
+    pValue: *mut c_void,
+    ulValueLen: CK_ULONG,
+}
+
+#[repr(C)]
+#[cfg_attr(windows, repr(packed))]
+struct CK_MECHANISM {
+    mechanism: CK_ULONG,
+    pParameter: *mut c_void,
+    ulParameterLen: CK_ULONG,
+}
+
+#[repr(C)]
+#[cfg_attr(windows, repr(packed))]
+struct CK_C_INITIALIZE_ARGS {
+    CreateMutex: *const c_void,
+    DestroyMutex: *const c_void,
+    LockMutex: *const c_void,
+    UnlockMutex: *const c_void,
+    flags: CK_ULONG,
+    pReserved: *mut c_void,
+}
+
+// Only the functions up to C_Sign are declared, the rest of the list is never accessed
+#[repr(C)]
+#[cfg_attr(windows, repr(packed))]
+struct CK_FUNCTION_LIST {
+    version: [u8; 2],
+    C_Initialize: unsafe extern "C" fn(*mut c_void) -> CK_RV,
+    C_Finalize: unsafe extern "C" fn(*mut c_void) -> CK_RV,
+    _C_GetInfo_to_C_InitPIN: [usize; 10],
+    C_OpenSession: unsafe extern "C" fn(
+        CK_ULONG,
+        CK_ULONG,
+        *mut c_void,
+        *const c_void,
+        *mut CK_SESSION_HANDLE,
+    ) -> CK_RV,
+    C_CloseSession: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+    _C_CloseAllSessions_to_C_SetOperationState: [usize; 4],
+    C_Login: unsafe extern "C" fn(CK_SESSION_HANDLE, CK_ULONG, *const u8, CK_ULONG) -> CK_RV,
+    _C_Logout_to_C_GetObjectSize: [usize; 5],
+    C_GetAttributeValue: unsafe extern "C" fn(
+        CK_SESSION_HANDLE,
+        CK_OBJECT_HANDLE,
+        *mut CK_ATTRIBUTE,
+        CK_ULONG,
+    ) -> CK_RV,
+    _C_SetAttributeValue: usize,
+    C_FindObjectsInit:
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *const CK_ATTRIBUTE, CK_ULONG) -> CK_RV,
+    C_FindObjects: unsafe extern "C" fn(
+        CK_SESSION_HANDLE,
+        *mut CK_OBJECT_HANDLE,
+        CK_ULONG,
+        *mut CK_ULONG,
+    ) -> CK_RV,
+    C_FindObjectsFinal: unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV,
+    _C_EncryptInit_to_C_DigestFinal: [usize; 13],
+    C_SignInit:
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *const CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    C_Sign: unsafe extern "C" fn(
+        CK_SESSION_HANDLE,
+        *const u8,
+        CK_ULONG,
+        *mut u8,
+        *mut CK_ULONG,
+    ) -> CK_RV,
+}
+
+// CK_RV is 32 bits on Windows
+#[allow(clippy::useless_conversion)]
+fn check(rv: CK_RV) -> OsshResult<()> {
+    match rv {
+        CKR_OK => Ok(()),
+        rv => Err(ErrorKind::Pkcs11Error(u64::from(rv)).into()),
+    }
+}
+
+/// A loaded PKCS#11 module, shared by the [`Pkcs11Signer`]s of its tokens
+///
+/// The module is finalized when the last signer using it is dropped.
+pub struct Pkcs11Module {
+    funcs: *const CK_FUNCTION_LIST,
+    finalize: bool,
+    // Keep the library loaded while the function list is used
+    _lib: Option<Library>,
+}
+
+// The module is initialized with CKF_OS_LOCKING_OK, so it can be called from any thread
+unsafe impl Send for Pkcs11Module {}
+unsafe impl Sync for Pkcs11Module {}
+
+impl Pkcs11Module {
+    /// Load and initialize the PKCS#11 module, such as `/usr/lib/softhsm/libsofthsm2.so`
+    pub fn load<P: AsRef<OsStr>>(path: P) -> OsshResult<Arc<Self>> {
+        unsafe {
+            let lib = Library::new(path).map_err(|e| Error::with_error(ErrorKind::IOError, e))?;
+            let mut funcs = ptr::null();
+            {
+                let get_function_list: Symbol<
+                    unsafe extern "C" fn(*mut *const CK_FUNCTION_LIST) -> CK_RV,
+                > = lib
+                    .get(b"C_GetFunctionList\0")
+                    .map_err(|e| Error::with_error(ErrorKind::IOError, e))?;
+                check(get_function_list(&mut funcs))?;
+            }
+            Self::initialize(funcs, Some(lib))
+        }
+    }
+
+    unsafe fn initialize(
+        funcs: *const CK_FUNCTION_LIST,
+        lib: Option<Library>,
+    ) -> OsshResult<Arc<Self>> {
+        if funcs.is_null() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        let mut args = CK_C_INITIALIZE_ARGS {
+            CreateMutex: ptr::null(),
+            DestroyMutex: ptr::null(),
+            LockMutex: ptr::null(),
+            UnlockMutex: ptr::null(),
+            flags: CKF_OS_LOCKING_OK,
+            pReserved: ptr::null_mut(),
+        };
+        // Leave the module initialized if it is already used by someone else in the process
+        let finalize = match ((*funcs).C_Initialize)(&mut args as *mut _ as *mut c_void) {
+            CKR_CRYPTOKI_ALREADY_INITIALIZED => false,
+            rv => {
+                check(rv)?;
+                true
+            }
+        };
+        Ok(Arc::new(Pkcs11Module {
+            funcs,
+            finalize,
+            _lib: lib,
+        }))
+    }
+
+    fn funcs(&self) -> &CK_FUNCTION_LIST {
+        unsafe { &*self.funcs }
+    }
+
+    fn open_session(&self, slot: u64) -> OsshResult<CK_SESSION_HANDLE> {
+        let slot = CK_ULONG::try_from(slot).map_err(|_| ErrorKind::InvalidArgument)?;
+        let mut session = 0;
+        unsafe {
+            check((self.funcs().C_OpenSession)(
+                slot,
+                CKF_SERIAL_SESSION,
+                ptr::null_mut(),
+                ptr::null(),
+                &mut session,
+            ))?;
+        }
+        Ok(session)
+    }
+
+    fn close_session(&self, session: CK_SESSION_HANDLE) {
+        unsafe {
+            (self.funcs().C_CloseSession)(session);
+        }
+    }
+
+    /// Read the DER encoded value of the certificate object with the label in the token
+    #[cfg(feature = "yubikey-piv")]
+    pub(crate) fn read_certificate(&self, slot: u64, label: &str) -> OsshResult<Vec<u8>> {
+        let session = self.open_session(slot)?;
+        let value = self
+            .find_object(session, CKO_CERTIFICATE, label)
+            .and_then(|cert| {
+                self.attribute(session, cert.ok_or(ErrorKind::InvalidArgument)?, CKA_VALUE)
+            });
+        self.close_session(session);
+        value
+    }
+
+    fn find_object(
+        &self,
+        session: CK_SESSION_HANDLE,
+        class: CK_ULONG,
+        label: &str,
+    ) -> OsshResult<Option<CK_OBJECT_HANDLE>> {
+        let mut class = class;
+        let template = [
+            CK_ATTRIBUTE {
+                type_: CKA_CLASS,
+                pValue: &mut class as *mut _ as *mut c_void,
+                ulValueLen: std::mem::size_of::<CK_ULONG>() as CK_ULONG,
+            },
+            CK_ATTRIBUTE {
+                type_: CKA_LABEL,
+                pValue: label.as_ptr() as *mut c_void,
+                ulValueLen: label.len() as CK_ULONG,
+            },
+        ];
+        let mut object = 0;
+        let mut count = 0;
+        unsafe {
+            check((self.funcs().C_FindObjectsInit)(
+                session,
+                template.as_ptr(),
+                template.len() as CK_ULONG,
+            ))?;
+            let rv = (self.funcs().C_FindObjects)(session, &mut object, 1, &mut count);
+            check((self.funcs().C_FindObjectsFinal)(session))?;
+            check(rv)?;
+        }
+        Ok(if count > 0 { Some(object) } else { None })
+    }
+
+    fn attribute(
+        &self,
+        session: CK_SESSION_HANDLE,
+        object: CK_OBJECT_HANDLE,
+        type_: CK_ULONG,
+    ) -> OsshResult<Vec<u8>> {
+        let mut attr = CK_ATTRIBUTE {
+            type_,
+            pValue: ptr::null_mut(),
+            ulValueLen: 0,
+        };
+        unsafe {
+            check((self.funcs().C_GetAttributeValue)(
+                session, object, &mut attr, 1,
+            ))?;
+            if attr.ulValueLen == CK_UNAVAILABLE_INFORMATION {
+                return Err(ErrorKind::InvalidKey.into());
+            }
+            let mut value = vec![0u8; attr.ulValueLen as usize];
+            attr.pValue = value.as_mut_ptr() as *mut c_void;
+            check((self.funcs().C_GetAttributeValue)(
+                session, object, &mut attr, 1,
+            ))?;
+            value.truncate(attr.ulValueLen as usize);
+            Ok(value)
+        }
+    }
+
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    fn read_public_key(
+        &self,
+        session: CK_SESSION_HANDLE,
+        privkey: CK_OBJECT_HANDLE,
+        public_label: &str,
+    ) -> OsshResult<PublicKey> {
+        let keytype = self.attribute(session, privkey, CKA_KEY_TYPE)?;
+        let keytype = CK_ULONG::from_ne_bytes(keytype.as_slice().try_into()?);
+        // The private key objects of RSA contain the public components, but not those of EC
+        let pubkey = self.find_object(session, CKO_PUBLIC_KEY, public_label)?;
+        let pubkey_or_private = pubkey.unwrap_or(privkey);
+        match keytype {
+            #[cfg(feature = "rsa")]
+            CKK_RSA => {
+                let n = self.attribute(session, pubkey_or_private, CKA_MODULUS)?;
+                let e = self.attribute(session, pubkey_or_private, CKA_PUBLIC_EXPONENT)?;
+                Ok(RsaPublicKey::new_with_signhash(
+                    BigNum::from_slice(&n)?,
+                    BigNum::from_slice(&e)?,
+                    RsaSignature::SHA1,
+                )?
+                .into())
+            }
+            #[cfg(feature = "ecdsa")]
+            CKK_EC => {
+                let params = self.attribute(session, privkey, CKA_EC_PARAMS)?;
+                let curve = match params.as_slice() {
+                    OID_NISTP256 => EcCurve::Nistp256,
+                    OID_NISTP384 => EcCurve::Nistp384,
+                    OID_NISTP521 => EcCurve::Nistp521,
+                    _ => return Err(ErrorKind::UnsupportCurve.into()),
+                };
+                let pubkey = pubkey.ok_or(ErrorKind::InvalidKey)?;
+                let point = self.attribute(session, pubkey, CKA_EC_POINT)?;
+                let point = decode_ec_point(&point, 1 + (curve.size() + 7) / 8 * 2)?;
+                Ok(EcDsaPublicKey::from_bytes(curve, point)?.into())
+            }
+            #[cfg(feature = "ed25519")]
+            CKK_EC_EDWARDS => {
+                let pubkey = pubkey.ok_or(ErrorKind::InvalidKey)?;
+                let point = self.attribute(session, pubkey, CKA_EC_POINT)?;
+                let point = decode_ec_point(&point, 32)?;
+                Ok(Ed25519PublicKey::from_ossl_ed25519(point)?.into())
+            }
+            keytype => {
+                Err(ErrorKind::UnsupportType(format!("PKCS#11 key type 0x{:x}", keytype)).into())
+            }
+        }
+    }
+}
+
+impl Drop for Pkcs11Module {
+    fn drop(&mut self) {
+        if self.finalize {
+            unsafe {
+                (self.funcs().C_Finalize)(ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// Unwrap the DER OCTET STRING of `CKA_EC_POINT`, some modules return the raw point instead
+fn decode_ec_point(data: &[u8], len: usize) -> OsshResult<&[u8]> {
+    if data.len() == len {
+        return Ok(data);
+    }
+    let content = match data {
+        [0x04, n, rest @ ..] if (*n as usize) < 0x80 => rest,
+        [0x04, 0x81, _, rest @ ..] => rest,
+        [0x04, 0x82, _, _, rest @ ..] => rest,
+        _ => return Err(ErrorKind::InvalidKey.into()),
+    };
+    if content.len() != len {
+        return Err(ErrorKind::InvalidKey.into());
+    }
+    Ok(content)
+}
+
+/// A signer with the private key kept in a PKCS#11 token, such as a hardware security module
+///
+/// The private key never leaves the token. It can sign the certificates with
+/// [`Certificate::sign()`](super::cert::Certificate::sign) like a [`KeyPair`](super::KeyPair).
+/// The RSA, EcDSA and Ed25519 keys are supported.
+pub struct Pkcs11Signer {
+    module: Arc<Pkcs11Module>,
+    // A session must not be used by multiple threads at once
+    session: Mutex<CK_SESSION_HANDLE>,
+    key: CK_OBJECT_HANDLE,
+    pubkey: PublicKey,
+}
+
+impl Pkcs11Signer {
+    /// Open a session to the token in the slot, log in with the PIN and find the private key by its label
+    ///
+    /// The public key is read from the public key object with the same label,
+    /// or from the private key object for the RSA keys.
+    pub fn open(
+        module: &Arc<Pkcs11Module>,
+        slot: u64,
+        label: &str,
+        pin: Option<&str>,
+    ) -> OsshResult<Self> {
+        Self::open_with_public_label(module, slot, label, label, pin)
+    }
+
+    /// Open the signer like [`Pkcs11Signer::open()`], with the public key object found by another label
+    pub(crate) fn open_with_public_label(
+        module: &Arc<Pkcs11Module>,
+        slot: u64,
+        label: &str,
+        public_label: &str,
+        pin: Option<&str>,
+    ) -> OsshResult<Self> {
+        let session = module.open_session(slot)?;
+        match Self::find_key(module, session, label, public_label, pin) {
+            Ok((key, pubkey)) => Ok(Pkcs11Signer {
+                module: module.clone(),
+                session: Mutex::new(session),
+                key,
+                pubkey,
+            }),
+            Err(e) => {
+                module.close_session(session);
+                Err(e)
+            }
+        }
+    }
+
+    fn find_key(
+        module: &Pkcs11Module,
+        session: CK_SESSION_HANDLE,
+        label: &str,
+        public_label: &str,
+        pin: Option<&str>,
+    ) -> OsshResult<(CK_OBJECT_HANDLE, PublicKey)> {
+        if let Some(pin) = pin {
+            let rv = unsafe {
+                (module.funcs().C_Login)(session, CKU_USER, pin.as_ptr(), pin.len() as CK_ULONG)
+            };
+            if rv != CKR_USER_ALREADY_LOGGED_IN {
+                check(rv)?;
+            }
+        }
+        let key = module
+            .find_object(session, CKO_PRIVATE_KEY, label)?
+            .ok_or(ErrorKind::InvalidArgument)?;
+        let mut pubkey = module.read_public_key(session, key, public_label)?;
+        *pubkey.comment_mut() = label.to_owned();
+        Ok((key, pubkey))
+    }
+
+    /// Get the type of the key
+    pub fn keytype(&self) -> KeyType {
+        self.pubkey.keytype()
+    }
+
+    /// Get the comment of the key, which is the label of the key by default
+    pub fn comment(&self) -> &str {
+        self.pubkey.comment()
+    }
+
+    /// Get the mutable reference of the key comment
+    pub fn comment_mut(&mut self) -> &mut String {
+        self.pubkey.comment_mut()
+    }
+
+    fn sign_mechanism(&self, mechanism: CK_ULONG, data: &[u8]) -> OsshResult<Vec<u8>> {
+        let mechanism = CK_MECHANISM {
+            mechanism,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        let session = *self.session.lock().unwrap();
+        let funcs = self.module.funcs();
+        let mut len = 0;
+        unsafe {
+            check((funcs.C_SignInit)(session, &mechanism, self.key))?;
+            // Get the signature length first, which doesn't terminate the operation
+            check((funcs.C_Sign)(
+                session,
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                ptr::null_mut(),
+                &mut len,
+            ))?;
+            let mut sig = vec![0u8; len as usize];
+            check((funcs.C_Sign)(
+                session,
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                sig.as_mut_ptr(),
+                &mut len,
+            ))?;
+            sig.truncate(len as usize);
+            Ok(sig)
+        }
+    }
+}
+
+impl Drop for Pkcs11Signer {
+    fn drop(&mut self) {
+        let session = *self.session.get_mut().unwrap();
+        self.module.close_session(session);
+    }
+}
+
+impl Key for Pkcs11Signer {
+    fn size(&self) -> usize {
+        self.pubkey.size()
+    }
+    fn keyname(&self) -> &'static str {
+        self.pubkey.keyname()
+    }
+    fn short_keyname(&self) -> &'static str {
+        self.pubkey.short_keyname()
+    }
+}
+
+impl PublicParts for Pkcs11Signer {
+    fn verify(&self, data: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        self.pubkey.verify(data, sig)
+    }
+    fn blob(&self) -> OsshResult<Vec<u8>> {
+        self.pubkey.blob()
+    }
+}
+
+impl SshSigner for Pkcs11Signer {
+    fn clone_public_key(&self) -> OsshResult<PublicKey> {
+        let mut pubkey = PublicKey::from_blob(&self.pubkey.blob()?)?;
+        *pubkey.comment_mut() = self.comment().to_owned();
+        Ok(pubkey)
+    }
+
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    fn sign_ssh_signature_with(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<RsaSignature>,
+    ) -> OsshResult<Vec<u8>> {
+        let (algo, sig): (&str, Vec<u8>) = match &self.pubkey.key {
+            #[cfg(feature = "rsa")]
+            PublicKeyType::RSA(key) => {
+                let signhash = rsa_signature.unwrap_or_else(|| key.sign_type());
+                LegacyAlgorithms::Global.check(signhash.name())?;
+                let mechanism = match signhash {
+                    RsaSignature::SHA1 => {
+                        #[cfg(feature = "fips")]
+                        crate::fips::refuse(signhash.name())?;
+                        CKM_SHA1_RSA_PKCS
+                    }
+                    RsaSignature::SHA2_256 => CKM_SHA256_RSA_PKCS,
+                    RsaSignature::SHA2_512 => CKM_SHA512_RSA_PKCS,
+                };
+                (signhash.name(), self.sign_mechanism(mechanism, data)?)
+            }
+            #[cfg(feature = "ecdsa")]
+            PublicKeyType::ECDSA(key) => {
+                // CKM_ECDSA signs the digest, and returns the concatenation of r and s
+                let digest = hash(key.curve().digest().message_digest(), data)?;
+                let sig = self.sign_mechanism(CKM_ECDSA, &digest)?;
+                let (r, s) = sig.split_at(sig.len() / 2);
+                let mut buf = Vec::new();
+                buf.write_mpint(&*BigNum::from_slice(r)?)?;
+                buf.write_mpint(&*BigNum::from_slice(s)?)?;
+                (key.keyname(), buf)
+            }
+            #[cfg(feature = "ed25519")]
+            PublicKeyType::ED25519(_) => {
+                #[cfg(feature = "fips")]
+                crate::fips::refuse(ED25519_NAME)?;
+                (ED25519_NAME, self.sign_mechanism(CKM_EDDSA, data)?)
+            }
+            #[cfg(feature = "dsa")]
+            PublicKeyType::DSA(_) => {
+                return Err(ErrorKind::UnsupportType(self.keyname().to_owned()).into())
+            }
+        };
+        let mut buf = Vec::new();
+        buf.write_utf8(algo)?;
+        buf.write_string(&sig)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    use crate::keys::cert::{CertType, Certificate, CertificateFields};
+    use openssl::bn::BigNumContext;
+    use openssl::ec::PointConversionForm;
+    use openssl::ecdsa::EcdsaSig;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::sign::Signer;
+    use std::slice;
+
+    const CKR_ARGUMENTS_BAD: CK_RV = 0x07;
+    const CKR_PIN_INCORRECT: CK_RV = 0xa0;
+
+    // The objects of the mock token: 1 and 2 are the RSA private and public keys, 3 and 4 are the EcDSA ones,
+    // and 5 is a certificate
+    struct MockToken {
+        rsa: PKey<Private>,
+        ecdsa: PKey<Private>,
+        found: Option<CK_OBJECT_HANDLE>,
+        signing: Option<(CK_ULONG, CK_OBJECT_HANDLE)>,
+    }
+
+    static TOKEN: Mutex<Option<MockToken>> = Mutex::new(None);
+
+    fn with_token<T>(f: impl FnOnce(&mut MockToken) -> T) -> T {
+        f(TOKEN.lock().unwrap().as_mut().unwrap())
+    }
+
+    unsafe fn write_output(buf: *mut u8, len: *mut CK_ULONG, value: &[u8]) -> CK_RV {
+        if !buf.is_null() {
+            if (*len as usize) < value.len() {
+                return CKR_ARGUMENTS_BAD;
+            }
+            ptr::copy_nonoverlapping(value.as_ptr(), buf, value.len());
+        }
+        *len = value.len() as CK_ULONG;
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_initialize(_: *mut c_void) -> CK_RV {
+        let read = |path| PKey::private_key_from_pem(&std::fs::read(path).unwrap()).unwrap();
+        *TOKEN.lock().unwrap() = Some(MockToken {
+            rsa: read("assets/pem_rsa"),
+            ecdsa: read("assets/pem_ecdsa"),
+            found: None,
+            signing: None,
+        });
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_finalize(_: *mut c_void) -> CK_RV {
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_open_session(
+        _: CK_ULONG,
+        _: CK_ULONG,
+        _: *mut c_void,
+        _: *const c_void,
+        session: *mut CK_SESSION_HANDLE,
+    ) -> CK_RV {
+        *session = 1;
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_close_session(_: CK_SESSION_HANDLE) -> CK_RV {
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_login(
+        _: CK_SESSION_HANDLE,
+        _: CK_ULONG,
+        pin: *const u8,
+        len: CK_ULONG,
+    ) -> CK_RV {
+        match slice::from_raw_parts(pin, len as usize) {
+            b"1234" => CKR_OK,
+            _ => CKR_PIN_INCORRECT,
+        }
+    }
+
+    unsafe extern "C" fn mock_get_attribute_value(
+        _: CK_SESSION_HANDLE,
+        object: CK_OBJECT_HANDLE,
+        template: *mut CK_ATTRIBUTE,
+        count: CK_ULONG,
+    ) -> CK_RV {
+        with_token(|token| {
+            for attr in slice::from_raw_parts_mut(template, count as usize) {
+                let value = match (object, attr.type_) {
+                    (1 | 2, CKA_KEY_TYPE) => CKK_RSA.to_ne_bytes().to_vec(),
+                    (3 | 4, CKA_KEY_TYPE) => CKK_EC.to_ne_bytes().to_vec(),
+                    (1 | 2, CKA_MODULUS) => token.rsa.rsa().unwrap().n().to_vec(),
+                    (1 | 2, CKA_PUBLIC_EXPONENT) => token.rsa.rsa().unwrap().e().to_vec(),
+                    (3 | 4, CKA_EC_PARAMS) => OID_NISTP256.to_vec(),
+                    #[cfg(feature = "yubikey-piv")]
+                    (5, CKA_VALUE) => b"certificate".to_vec(),
+                    (4, CKA_EC_POINT) => {
+                        let ec = token.ecdsa.ec_key().unwrap();
+                        let mut ctx = BigNumContext::new().unwrap();
+                        let point = ec
+                            .public_key()
+                            .to_bytes(ec.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)
+                            .unwrap();
+                        [&[0x04, point.len() as u8], point.as_slice()].concat()
+                    }
+                    _ => {
+                        attr.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                        continue;
+                    }
+                };
+                let rv = write_output(attr.pValue as *mut u8, &mut attr.ulValueLen, &value);
+                if rv != CKR_OK {
+                    return rv;
+                }
+            }
+            CKR_OK
+        })
+    }
+
+    unsafe extern "C" fn mock_find_objects_init(
+        _: CK_SESSION_HANDLE,
+        template: *const CK_ATTRIBUTE,
+        count: CK_ULONG,
+    ) -> CK_RV {
+        let mut class = None;
+        let mut label = None;
+        for attr in slice::from_raw_parts(template, count as usize) {
+            let value = slice::from_raw_parts(attr.pValue as *const u8, attr.ulValueLen as usize);
+            match attr.type_ {
+                CKA_CLASS => class = Some(CK_ULONG::from_ne_bytes(value.try_into().unwrap())),
+                CKA_LABEL => label = Some(value),
+                _ => return CKR_ARGUMENTS_BAD,
+            }
+        }
+        let found = match (label, class) {
+            (Some(b"rsa"), Some(CKO_PRIVATE_KEY)) => Some(1),
+            (Some(b"rsa"), Some(CKO_PUBLIC_KEY)) => Some(2),
+            (Some(b"ecdsa"), Some(CKO_PRIVATE_KEY)) => Some(3),
+            (Some(b"ecdsa" | b"ecdsa public"), Some(CKO_PUBLIC_KEY)) => Some(4),
+            #[cfg(feature = "yubikey-piv")]
+            (Some(b"certificate"), Some(CKO_CERTIFICATE)) => Some(5),
+            _ => None,
+        };
+        with_token(|token| token.found = found);
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_find_objects(
+        _: CK_SESSION_HANDLE,
+        objects: *mut CK_OBJECT_HANDLE,
+        _: CK_ULONG,
+        count: *mut CK_ULONG,
+    ) -> CK_RV {
+        match with_token(|token| token.found.take()) {
+            Some(found) => {
+                *objects = found;
+                *count = 1;
+            }
+            None => *count = 0,
+        }
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_find_objects_final(_: CK_SESSION_HANDLE) -> CK_RV {
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_sign_init(
+        _: CK_SESSION_HANDLE,
+        mechanism: *const CK_MECHANISM,
+        key: CK_OBJECT_HANDLE,
+    ) -> CK_RV {
+        with_token(|token| token.signing = Some(((*mechanism).mechanism, key)));
+        CKR_OK
+    }
+
+    unsafe extern "C" fn mock_sign(
+        _: CK_SESSION_HANDLE,
+        data: *const u8,
+        data_len: CK_ULONG,
+        sig: *mut u8,
+        sig_len: *mut CK_ULONG,
+    ) -> CK_RV {
+        let data = slice::from_raw_parts(data, data_len as usize);
+        let value = with_token(|token| match token.signing {
+            Some((CKM_SHA512_RSA_PKCS, 1)) => {
+                let mut signer = Signer::new(MessageDigest::sha512(), &token.rsa).unwrap();
+                Some(signer.sign_oneshot_to_vec(data).unwrap())
+            }
+            Some((CKM_ECDSA, 3)) => {
+                let sig = EcdsaSig::sign(data, &token.ecdsa.ec_key().unwrap()).unwrap();
+                let mut buf = sig.r().to_vec_padded(32).unwrap();
+                buf.extend(sig.s().to_vec_padded(32).unwrap());
+                Some(buf)
+            }
+            _ => None,
+        });
+        match value {
+            Some(value) => write_output(sig, sig_len, &value),
+            None => CKR_ARGUMENTS_BAD,
+        }
+    }
+
+    static MOCK_FUNCTIONS: CK_FUNCTION_LIST = CK_FUNCTION_LIST {
+        version: [2, 40],
+        C_Initialize: mock_initialize,
+        C_Finalize: mock_finalize,
+        _C_GetInfo_to_C_InitPIN: [0; 10],
+        C_OpenSession: mock_open_session,
+        C_CloseSession: mock_close_session,
+        _C_CloseAllSessions_to_C_SetOperationState: [0; 4],
+        C_Login: mock_login,
+        _C_Logout_to_C_GetObjectSize: [0; 5],
+        C_GetAttributeValue: mock_get_attribute_value,
+        _C_SetAttributeValue: 0,
+        C_FindObjectsInit: mock_find_objects_init,
+        C_FindObjects: mock_find_objects,
+        C_FindObjectsFinal: mock_find_objects_final,
+        _C_EncryptInit_to_C_DigestFinal: [0; 13],
+        C_SignInit: mock_sign_init,
+        C_Sign: mock_sign,
+    };
+
+    #[test]
+    fn pkcs11_signer() {
+        let module = unsafe { Pkcs11Module::initialize(&MOCK_FUNCTIONS, None).unwrap() };
+        let err = Pkcs11Signer::open(&module, 0, "ecdsa", Some("0000"))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Pkcs11Error(0xa0));
+        assert!(Pkcs11Signer::open(&module, 0, "missing", Some("1234")).is_err());
+        #[cfg(feature = "yubikey-piv")]
+        {
+            assert_eq!(
+                module.read_certificate(0, "certificate").unwrap(),
+                b"certificate"
+            );
+            assert!(module.read_certificate(0, "missing").is_err());
+        }
+        let signer =
+            Pkcs11Signer::open_with_public_label(&module, 0, "ecdsa", "ecdsa public", None)
+                .unwrap();
+        assert_eq!(signer.keytype(), KeyType::ECDSA);
+
+        let key =
+            PublicKey::from_keystr(&std::fs::read_to_string("assets/openssh_ed25519.pub").unwrap())
+                .unwrap();
+        let fields = CertificateFields {
+            valid_principals: vec!["user".to_string()],
+            ..Default::default()
+        };
+        for (label, pubkey) in [
+            ("rsa", "assets/pem_rsa.pub"),
+            ("ecdsa", "assets/pem_ecdsa.pub"),
+        ] {
+            let signer = Pkcs11Signer::open(&module, 0, label, Some("1234")).unwrap();
+            let pubkey = PublicKey::from_keystr(&std::fs::read_to_string(pubkey).unwrap()).unwrap();
+            assert_eq!(signer.blob().unwrap(), pubkey.blob().unwrap());
+            assert_eq!(signer.comment(), label);
+
+            let sig = signer
+                .sign_ssh_signature_with(b"data", Some(RsaSignature::SHA2_512))
+                .unwrap();
+            assert!(pubkey.verify_ssh_signature(b"data", &sig).unwrap());
+            let cert = Certificate::sign(&key, &fields, &signer, RsaSignature::SHA2_512).unwrap();
+            assert!(cert.verify(&[pubkey], CertType::User, "user").unwrap());
+        }
+    }
+
+    #[test]
+    fn pkcs11_ec_point() {
+        let point = [0x04; 65];
+        assert_eq!(decode_ec_point(&point, 65).unwrap(), &point[..]);
+        let der = [&[0x04, 0x41][..], &point].concat();
+        assert_eq!(decode_ec_point(&der, 65).unwrap(), &point[..]);
+        let der = [&[0x04, 0x81, 0x85][..], &[0x04; 0x85]].concat();
+        assert_eq!(decode_ec_point(&der, 0x85).unwrap(), &[0x04; 0x85][..]);
+        assert!(decode_ec_point(&der, 65).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use once_cell::sync::OnceCell;
+use openssl::error::ErrorStack;
+use openssl::pkey::PKey;
+
+/// The OpenSSL `PKey` converted from a key pair, which is created on the first use and reused afterward
+///
+/// The key pairs are immutable, so the cached handle never goes stale.
+/// The public keys don't keep it, since the interior mutability doesn't fit their use as the hash keys.
+pub(crate) struct PKeyCache<T> {
+    pkey: OnceCell<PKey<T>>,
+}
+
+impl<T> PKeyCache<T> {
+    pub(crate) fn new() -> Self {
+        PKeyCache {
+            pkey: OnceCell::new(),
+        }
+    }
+
+    /// Get the cached `PKey`, or convert the key with `f` if it's the first call
+    ///
+    /// The returned `PKey` shares the handle, since it's reference counted.
+    pub(crate) fn get_or_try_init<F>(&self, f: F) -> Result<PKey<T>, ErrorStack>
+    where
+        F: FnOnce() -> Result<PKey<T>, ErrorStack>,
+    {
+        self.pkey.get_or_try_init(f).cloned()
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+mod test {
+    use crate::keys::{KeyPair, KeyType};
+
+    #[test]
+    fn pkey_cache_reused() {
+        for keytype in [KeyType::RSA, KeyType::ECDSA, KeyType::ED25519] {
+            let keypair = KeyPair::generate(keytype, 0).unwrap();
+            let pkey0 = keypair.ossl_pkey().unwrap();
+            let pkey1 = keypair.ossl_pkey().unwrap();
+            assert!(std::ptr::eq(&*pkey0, &*pkey1));
+            assert_eq!(
+                keypair.serialize_pkcs8(None).unwrap(),
+                keypair.serialize_pkcs8(None).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,296 @@
+#[cfg(feature = "dsa")]
+use super::dsa::{DSA_NAME, DSA_SIG_LEN};
+use super::rsa::RsaSignature;
+use super::{
+    Key, KeyType, LegacyAlgorithms, PrivateParts, PublicKey, PublicKeyType, PublicParts, SshSigner,
+};
+use crate::backend::{Backend, CryptoBackend, HashAlg};
+use crate::error::*;
+use crate::format::ossh_pubkey::decode_ossh_pubkey;
+use crate::sshbuf::SshWriteExt;
+use foreign_types::ForeignType;
+#[cfg(feature = "dsa")]
+use openssl::dsa::DsaSig;
+#[cfg(feature = "ecdsa")]
+use openssl::ecdsa::EcdsaSig;
+use openssl::error::ErrorStack;
+use openssl::pkey::{PKey, Private};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+const OSSL_STORE_INFO_PKEY: c_int = 4;
+
+#[allow(non_camel_case_types)]
+enum OSSL_STORE_CTX {}
+#[allow(non_camel_case_types)]
+enum OSSL_STORE_INFO {}
+
+// The OSSL_STORE API is not bound by `openssl-sys`
+extern "C" {
+    fn OSSL_STORE_open(
+        uri: *const c_char,
+        ui_method: *const c_void,
+        ui_data: *mut c_void,
+        post_process: *const c_void,
+        post_process_data: *mut c_void,
+    ) -> *mut OSSL_STORE_CTX;
+    fn OSSL_STORE_load(ctx: *mut OSSL_STORE_CTX) -> *mut OSSL_STORE_INFO;
+    fn OSSL_STORE_eof(ctx: *mut OSSL_STORE_CTX) -> c_int;
+    fn OSSL_STORE_error(ctx: *mut OSSL_STORE_CTX) -> c_int;
+    fn OSSL_STORE_close(ctx: *mut OSSL_STORE_CTX) -> c_int;
+    fn OSSL_STORE_INFO_get_type(info: *const OSSL_STORE_INFO) -> c_int;
+    fn OSSL_STORE_INFO_get1_PKEY(info: *const OSSL_STORE_INFO) -> *mut openssl_sys::EVP_PKEY;
+    fn OSSL_STORE_INFO_free(info: *mut OSSL_STORE_INFO);
+}
+
+/// A key pair whose private key is held by an OpenSSL provider, such as a PKCS#11 token
+///
+/// The private key never leaves the provider. Only the public key is read from it,
+/// and the signing is delegated to the provider.
+pub struct ProviderKeyPair {
+    pkey: PKey<Private>,
+    pubkey: PublicKey,
+}
+
+impl ProviderKeyPair {
+    /// Load the first private key found by the OpenSSL store URI,
+    /// such as `pkcs11:token=ca;object=ssh-ca` or `file:/path/to/key.pem`
+    ///
+    /// The provider handling the URI scheme must be loaded beforehand by the OpenSSL configuration,
+    /// or by `openssl::provider::Provider::try_load()` on OpenSSL 3.
+    /// The deprecated OpenSSL ENGINEs are not supported, and the keys requiring a passphrase cannot be loaded.
+    pub fn load(uri: &str) -> OsshResult<Self> {
+        openssl::init();
+        let c_uri = CString::new(uri).map_err(|_| ErrorKind::InvalidArgument)?;
+        let pkey = unsafe {
+            let ctx = OSSL_STORE_open(
+                c_uri.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null_mut(),
+            );
+            if ctx.is_null() {
+                return Err(ErrorStack::get().into());
+            }
+            let mut pkey = None;
+            while pkey.is_none() && OSSL_STORE_eof(ctx) == 0 {
+                let info = OSSL_STORE_load(ctx);
+                if info.is_null() {
+                    if OSSL_STORE_error(ctx) != 0 {
+                        break;
+                    }
+                    continue;
+                }
+                if OSSL_STORE_INFO_get_type(info) == OSSL_STORE_INFO_PKEY {
+                    let raw = OSSL_STORE_INFO_get1_PKEY(info);
+                    if !raw.is_null() {
+                        pkey = Some(PKey::<Private>::from_ptr(raw));
+                    }
+                }
+                OSSL_STORE_INFO_free(info);
+            }
+            OSSL_STORE_close(ctx);
+            pkey
+        };
+        match pkey {
+            Some(pkey) => Self::from_ossl_pkey(pkey),
+            None => {
+                let errors = ErrorStack::get();
+                if errors.errors().is_empty() {
+                    Err(ErrorKind::InvalidArgument.into())
+                } else {
+                    Err(errors.into())
+                }
+            }
+        }
+    }
+
+    pub(crate) fn from_ossl_pkey(pkey: PKey<Private>) -> OsshResult<Self> {
+        // The provider keys may have no legacy key type, so decode the public key with the default provider
+        let public = PKey::public_key_from_der(&pkey.public_key_to_der()?)?;
+        let pubkey = PublicKey::from_ossl_pkey(&public)?;
+        Ok(ProviderKeyPair { pkey, pubkey })
+    }
+
+    /// Get the type of the key
+    pub fn keytype(&self) -> KeyType {
+        self.pubkey.keytype()
+    }
+
+    /// Get the comment of the key
+    pub fn comment(&self) -> &str {
+        self.pubkey.comment()
+    }
+
+    /// Get the mutable reference of the key comment
+    pub fn comment_mut(&mut self) -> &mut String {
+        self.pubkey.comment_mut()
+    }
+
+    /// Encode the private key in the PEM format with the encoder of its provider
+    ///
+    /// The keys of a TPM are written as the `TSS2 PRIVATE KEY` containing the key wrapped by the TPM,
+    /// while the keys of the default provider are written as the plaintext PKCS#8 private keys.
+    /// The written key can be loaded back by [`ProviderKeyPair::load()`] with the `file:` URI.
+    pub fn serialize_provider_pem(&self) -> OsshResult<String> {
+        String::from_utf8(self.pkey.private_key_to_pem_pkcs8()?)
+            .map_err(|_| ErrorKind::InvalidPemFormat.into())
+    }
+
+    fn sign_digest(&self, data: &[u8], digest: Option<HashAlg>) -> OsshResult<Vec<u8>> {
+        Backend::sign(&self.pkey, digest, data)
+    }
+}
+
+impl Key for ProviderKeyPair {
+    fn size(&self) -> usize {
+        self.pubkey.size()
+    }
+    fn keyname(&self) -> &'static str {
+        self.pubkey.keyname()
+    }
+    fn short_keyname(&self) -> &'static str {
+        self.pubkey.short_keyname()
+    }
+}
+
+impl PublicParts for ProviderKeyPair {
+    fn verify(&self, data: &[u8], sig: &[u8]) -> OsshResult<bool> {
+        self.pubkey.verify(data, sig)
+    }
+    fn blob(&self) -> OsshResult<Vec<u8>> {
+        self.pubkey.blob()
+    }
+}
+
+impl PrivateParts for ProviderKeyPair {
+    fn sign(&self, data: &[u8]) -> OsshResult<Vec<u8>> {
+        let digest = match &self.pubkey.key {
+            #[cfg(feature = "rsa")]
+            PublicKeyType::RSA(key) => Some(key.sign_type().get_digest()),
+            #[cfg(feature = "dsa")]
+            PublicKeyType::DSA(_) => Some(HashAlg::Sha1),
+            #[cfg(feature = "ecdsa")]
+            PublicKeyType::ECDSA(_) => Some(HashAlg::Sha1),
+            #[cfg(feature = "ed25519")]
+            PublicKeyType::ED25519(_) => None,
+        };
+        self.sign_digest(data, digest)
+    }
+}
+
+impl SshSigner for ProviderKeyPair {
+    fn clone_public_key(&self) -> OsshResult<PublicKey> {
+        let mut pubkey = decode_ossh_pubkey(&self.pubkey.blob()?)?;
+        *pubkey.comment_mut() = self.comment().to_owned();
+        Ok(pubkey)
+    }
+
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    fn sign_ssh_signature_with(
+        &self,
+        data: &[u8],
+        rsa_signature: Option<RsaSignature>,
+    ) -> OsshResult<Vec<u8>> {
+        let legacy = LegacyAlgorithms::Global;
+        let (algo, sig) = match &self.pubkey.key {
+            #[cfg(feature = "rsa")]
+            PublicKeyType::RSA(key) => {
+                let signhash = rsa_signature.unwrap_or_else(|| key.sign_type());
+                legacy.check(signhash.name())?;
+                #[cfg(feature = "fips")]
+                if signhash == RsaSignature::SHA1 {
+                    crate::fips::refuse(signhash.name())?;
+                }
+                (
+                    signhash.name(),
+                    self.sign_digest(data, Some(signhash.get_digest()))?,
+                )
+            }
+            #[cfg(feature = "dsa")]
+            PublicKeyType::DSA(_) => {
+                legacy.check(DSA_NAME)?;
+                #[cfg(feature = "fips")]
+                crate::fips::refuse(DSA_NAME)?;
+                let sig = DsaSig::from_der(&self.sign_digest(data, Some(HashAlg::Sha1))?)?;
+                let mut buf = sig.r().to_vec_padded((DSA_SIG_LEN / 2) as i32)?;
+                buf.extend(sig.s().to_vec_padded((DSA_SIG_LEN / 2) as i32)?);
+                (DSA_NAME, buf)
+            }
+            #[cfg(feature = "ecdsa")]
+            PublicKeyType::ECDSA(key) => {
+                let sig = EcdsaSig::from_der(&self.sign_digest(data, Some(key.curve().digest()))?)?;
+                let mut buf = Vec::new();
+                buf.write_mpint(sig.r())?;
+                buf.write_mpint(sig.s())?;
+                (key.keyname(), buf)
+            }
+            #[cfg(feature = "ed25519")]
+            PublicKeyType::ED25519(key) => {
+                #[cfg(feature = "fips")]
+                crate::fips::refuse(key.keyname())?;
+                (key.keyname(), self.sign_digest(data, None)?)
+            }
+        };
+        let mut buf = Vec::new();
+        buf.write_utf8(algo)?;
+        buf.write_string(&sig)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+mod test {
+    use super::*;
+    use crate::keys::cert::{CertType, Certificate, CertificateFields};
+    use crate::keys::{FingerprintHash, KeyPair};
+    use std::fs;
+
+    fn uri(path: &str) -> String {
+        format!("file:{}/{}", env!("CARGO_MANIFEST_DIR"), path)
+    }
+
+    #[test]
+    fn provider_keys() {
+        for path in ["assets/pem_rsa", "assets/pem_ecdsa", "assets/pem_dsa"] {
+            let key = ProviderKeyPair::load(&uri(path)).unwrap();
+            let keypair = KeyPair::from_keystr(&fs::read_to_string(path).unwrap(), None).unwrap();
+            assert_eq!(key.keytype(), keypair.keytype());
+            assert_eq!(
+                key.fingerprint(FingerprintHash::SHA256).unwrap(),
+                keypair.fingerprint(FingerprintHash::SHA256).unwrap()
+            );
+
+            let pubkey = SshSigner::clone_public_key(&key).unwrap();
+            let sig = key.sign_ssh_signature_with(b"data", None).unwrap();
+            assert!(pubkey.verify_ssh_signature(b"data", &sig).unwrap());
+            assert!(key.verify(b"data", &key.sign(b"data").unwrap()).unwrap());
+        }
+        assert!(ProviderKeyPair::load(&uri("assets/not_exist")).is_err());
+    }
+
+    #[test]
+    fn provider_key_sign_certificate() {
+        let ca = ProviderKeyPair::load(&uri("assets/pem_rsa")).unwrap();
+        let key =
+            PublicKey::from_keystr(&fs::read_to_string("assets/openssh_ed25519.pub").unwrap())
+                .unwrap();
+        let fields = CertificateFields {
+            valid_principals: vec!["user".to_string()],
+            ..Default::default()
+        };
+        let cert = Certificate::sign(&key, &fields, &ca, RsaSignature::SHA2_512).unwrap();
+        assert_eq!(cert.signature_algorithm().unwrap(), "rsa-sha2-512");
+        let cas =
+            [PublicKey::from_keystr(&fs::read_to_string("assets/pem_rsa.pub").unwrap()).unwrap()];
+        assert!(cert.verify(&cas, CertType::User, "user").unwrap());
+    }
+}
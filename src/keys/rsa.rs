@@ -1,16 +1,25 @@
-use super::{Key, PrivateParts, PublicParts};
+#![cfg_attr(not(feature = "rsa"), allow(unused_imports))]
+
+use super::pkey_cache::PKeyCache;
+use super::{debug_keypair, Key, PrivateParts, PublicParts};
+use crate::backend::{Backend, CryptoBackend, HashAlg};
 use crate::error::{Error, ErrorKind, OsshResult};
 use crate::format::ossh_pubkey::*;
-use openssl::bn::BigNum;
-use openssl::hash::MessageDigest;
+use openssl::bn::{BigNum, BigNumContext, BigNumContextRef, BigNumRef};
 use openssl::pkey::{PKey, Private, Public};
 use openssl::rsa::{Rsa, RsaRef};
-use openssl::sign::{Signer, Verifier};
+use rand_core::CryptoRngCore;
 use std::fmt;
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 
+#[cfg(feature = "rsa")]
 const RSA_DEF_SIZE: usize = 2048;
+#[cfg(feature = "rsa")]
 const RSA_MIN_SIZE: usize = 1024;
+#[cfg(feature = "rsa")]
 const RSA_MAX_SIZE: usize = 16384;
+#[cfg(feature = "rsa")]
+const RSA_DEF_EXPONENT: u32 = 65537;
 /// The default name of RSA key returned by [`Key::keyname()`](../trait.Key.html#method.keyname)
 pub const RSA_NAME: &str = "ssh-rsa";
 /// The sha2-256 algorithm name of RSA key returned by [`Key::keyname()`](../trait.Key.html#method.keyname)
@@ -20,6 +29,19 @@ pub const RSA_SHA512_NAME: &str = "rsa-sha2-512";
 /// The short name of the of RSA key returned by [`Key::short_keyname()`](../trait.Key.html#method.short_keyname)
 pub const RSA_SHORT_NAME: &str = "RSA";
 
+/// The progress reported during the RSA key generation
+///
+/// `prime` is the index of the prime being generated, `0` for `p` and `1` for `q`.
+/// Both primes may be regenerated if they don't form a valid key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RsaGenProgress {
+    /// A new prime candidate is going to be tested, `attempt` counts from 1
+    Candidate { prime: u8, attempt: u32 },
+    /// A prime has been found
+    PrimeFound { prime: u8 },
+}
+
 /// An enum determining the hash function which used to sign or verify
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum RsaSignature {
@@ -33,8 +55,11 @@ impl RsaSignature {
     /// Parse from key name
     pub fn from_name(s: &str) -> Option<Self> {
         match s {
+            #[cfg(feature = "rsa")]
             RSA_NAME => Some(RsaSignature::SHA1),
+            #[cfg(feature = "rsa")]
             RSA_SHA256_NAME => Some(RsaSignature::SHA2_256),
+            #[cfg(feature = "rsa")]
             RSA_SHA512_NAME => Some(RsaSignature::SHA2_512),
             _ => None,
         }
@@ -49,23 +74,26 @@ impl RsaSignature {
         }
     }
 
-    fn get_digest(self) -> MessageDigest {
+    #[cfg(feature = "rsa")]
+    pub(crate) fn get_digest(self) -> HashAlg {
         use RsaSignature::*;
         match self {
-            SHA1 => MessageDigest::sha1(),
-            SHA2_256 => MessageDigest::sha256(),
-            SHA2_512 => MessageDigest::sha512(),
+            SHA1 => HashAlg::Sha1,
+            SHA2_256 => HashAlg::Sha256,
+            SHA2_512 => HashAlg::Sha512,
         }
     }
 }
 
 /// Represent the RSA public key
+#[cfg(feature = "rsa")]
 #[derive(Debug, Clone)]
 pub struct RsaPublicKey {
     rsa: Rsa<Public>,
     signhash: RsaSignature,
 }
 
+#[cfg(feature = "rsa")]
 impl RsaPublicKey {
     /// Create the RSA public key from public components
     pub fn new(n: BigNum, e: BigNum) -> Result<RsaPublicKey, openssl::error::ErrorStack> {
@@ -113,6 +141,7 @@ impl RsaPublicKey {
     }
 }
 
+#[cfg(feature = "rsa")]
 impl Key for RsaPublicKey {
     fn size(&self) -> usize {
         self.rsa.n().num_bits() as usize
@@ -127,6 +156,7 @@ impl Key for RsaPublicKey {
     }
 }
 
+#[cfg(feature = "rsa")]
 impl PublicParts for RsaPublicKey {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_rsa_pubkey(&self.rsa)
@@ -137,18 +167,18 @@ impl PublicParts for RsaPublicKey {
             return Err(ErrorKind::InvalidKeySize.into());
         }
         let pkey = PKey::from_rsa(self.rsa.clone())?;
-        let mut veri = Verifier::new(self.signhash.get_digest(), &pkey)?;
-        veri.update(data)?;
-        Ok(veri.verify(sig)?)
+        Backend::verify(&pkey, Some(self.signhash.get_digest()), data, sig)
     }
 }
 
+#[cfg(feature = "rsa")]
 impl PartialEq for RsaPublicKey {
     fn eq(&self, other: &RsaPublicKey) -> bool {
         self.rsa.e() == other.rsa.e() && self.rsa.n() == other.rsa.n()
     }
 }
 
+#[cfg(feature = "rsa")]
 impl fmt::Display for RsaPublicKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(&serialize_ossh_pubkey(self, "").unwrap())
@@ -156,14 +186,41 @@ impl fmt::Display for RsaPublicKey {
 }
 
 /// Represent the RSA key pair
+///
+/// The private key operations are always performed by OpenSSL,
+/// which applies base blinding and constant-time modular exponentiation to them,
+/// as reported by [`RsaKeyPair::is_side_channel_hardened()`].
+#[cfg(feature = "rsa")]
 pub struct RsaKeyPair {
     rsa: Rsa<Private>,
     signhash: RsaSignature,
+    pkey: PKeyCache<Private>,
 }
 
+#[cfg(feature = "rsa")]
+impl ZeroizeOnDrop for RsaKeyPair {}
+
+#[cfg(feature = "rsa")]
+impl fmt::Debug for RsaKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(unused_mut)]
+        let mut s = debug_keypair(f, "RsaKeyPair", self);
+        #[cfg(feature = "full-debug")]
+        s.field("d", &self.rsa.d())
+            .field("p", &self.rsa.p())
+            .field("q", &self.rsa.q());
+        s.finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "rsa")]
 impl RsaKeyPair {
     pub(crate) fn from_ossl_rsa(key: Rsa<Private>, signhash: RsaSignature) -> OsshResult<Self> {
-        let rsa = Self { rsa: key, signhash };
+        let rsa = Self {
+            rsa: key,
+            signhash,
+            pkey: PKeyCache::new(),
+        };
         if rsa.size() >= RSA_MIN_SIZE && rsa.size() <= RSA_MAX_SIZE {
             Ok(rsa)
         } else {
@@ -175,6 +232,19 @@ impl RsaKeyPair {
         &self.rsa
     }
 
+    pub(crate) fn ossl_pkey(&self) -> Result<PKey<Private>, openssl::error::ErrorStack> {
+        self.pkey
+            .get_or_try_init(|| PKey::from_rsa(self.rsa.clone()))
+    }
+
+    /// Check if the private key operations apply base blinding and constant-time modular exponentiation
+    ///
+    /// This is a static guarantee of the backend selected by the features, not a check of the key itself.
+    /// The keys are only created by this crate, which never disables the blinding or the constant-time operations of OpenSSL.
+    pub fn is_side_channel_hardened() -> bool {
+        Backend::RSA_SIDE_CHANNEL_HARDENED
+    }
+
     /// Generate RSA key pair
     ///
     /// The bits parameter should be within 1024 ~ 16384 bits or `0` to use default length (2048 bits).
@@ -185,12 +255,90 @@ impl RsaKeyPair {
         if !(RSA_MIN_SIZE..=RSA_MAX_SIZE).contains(&bits) {
             return Err(Error::from_kind(ErrorKind::InvalidKeySize));
         }
+        #[cfg(feature = "fips")]
+        if bits < crate::fips::FIPS_MIN_RSA_SIZE {
+            crate::fips::refuse(&format!("{}-bit RSA key", bits))?;
+        }
         Ok(RsaKeyPair {
             rsa: Rsa::generate(bits as u32)?,
             signhash: RsaSignature::default(),
+            pkey: PKeyCache::new(),
         })
     }
 
+    /// Generate RSA key pair using the given random number generator
+    ///
+    /// The prime candidates are drawn from `rng` and tested by OpenSSL.
+    /// The bits parameter has the same limitation as [`generate()`](#method.generate).
+    pub fn generate_with_rng<R: CryptoRngCore + ?Sized>(
+        bits: usize,
+        rng: &mut R,
+    ) -> OsshResult<Self> {
+        Self::generate_with_progress(bits, rng, |_| true)
+    }
+
+    /// Generate RSA key pair and report the progress to the callback
+    ///
+    /// The callback is invoked after each tested prime candidate and each found prime.
+    /// Returning `false` from the callback aborts the generation with [`ErrorKind::Cancelled`].
+    /// The other parameters are the same as [`generate_with_rng()`](#method.generate_with_rng).
+    pub fn generate_with_progress<R, F>(
+        mut bits: usize,
+        rng: &mut R,
+        mut progress: F,
+    ) -> OsshResult<Self>
+    where
+        R: CryptoRngCore + ?Sized,
+        F: FnMut(RsaGenProgress) -> bool,
+    {
+        // The primes are not generated by the validated module
+        #[cfg(feature = "fips")]
+        crate::fips::refuse("RSA key generation with a custom random number generator")?;
+        if bits == 0 {
+            bits = RSA_DEF_SIZE;
+        }
+        if !(RSA_MIN_SIZE..=RSA_MAX_SIZE).contains(&bits) {
+            return Err(Error::from_kind(ErrorKind::InvalidKeySize));
+        }
+
+        let mut ctx = BigNumContext::new()?;
+        let one = BigNum::from_u32(1)?;
+        let e = BigNum::from_u32(RSA_DEF_EXPONENT)?;
+        loop {
+            let p = generate_prime(0, bits - bits / 2, &e, rng, &mut ctx, &mut progress)?;
+            let q = generate_prime(1, bits / 2, &e, rng, &mut ctx, &mut progress)?;
+            if p == q {
+                continue;
+            }
+            // Keep p > q, as the CRT coefficient is q^-1 mod p
+            let (p, q) = if p > q { (p, q) } else { (q, p) };
+
+            let n = &p * &q;
+            if n.num_bits() as usize != bits {
+                continue;
+            }
+            let p1 = &p - &one;
+            let q1 = &q - &one;
+            let mut gcd = BigNum::new()?;
+            gcd.gcd(&p1, &q1, &mut ctx)?;
+            let lambda = &(&p1 * &q1) / &gcd;
+
+            let mut d = BigNum::new()?;
+            d.mod_inverse(&e, &lambda, &mut ctx)?;
+            let dmp1 = &d % &p1;
+            let dmq1 = &d % &q1;
+            let mut iqmp = BigNum::new()?;
+            iqmp.mod_inverse(&q, &p, &mut ctx)?;
+
+            let rsa = Rsa::from_private_components(n, e, d, p, q, dmp1, dmq1, iqmp)?;
+            return Ok(RsaKeyPair {
+                rsa,
+                signhash: RsaSignature::default(),
+                pkey: PKeyCache::new(),
+            });
+        }
+    }
+
     /// Get the signature hash type
     pub fn sign_type(&self) -> RsaSignature {
         self.signhash
@@ -201,6 +349,19 @@ impl RsaKeyPair {
         self.signhash = sig;
     }
 
+    /// Sign the data with the given hash algorithm instead of the one of the key
+    pub(crate) fn sign_with(&self, data: &[u8], signhash: RsaSignature) -> OsshResult<Vec<u8>> {
+        #[cfg(feature = "fips")]
+        if signhash == RsaSignature::SHA1 {
+            crate::fips::refuse(RSA_NAME)?;
+        }
+        if self.size() < RSA_MIN_SIZE {
+            return Err(ErrorKind::InvalidKeySize.into());
+        }
+        let pkey = self.ossl_pkey()?;
+        Backend::sign(&pkey, Some(signhash.get_digest()), data)
+    }
+
     /// Clone the public parts to generate public key
     pub fn clone_public_key(&self) -> Result<RsaPublicKey, Error> {
         let n = self.rsa.n().to_owned()?;
@@ -209,6 +370,7 @@ impl RsaKeyPair {
     }
 }
 
+#[cfg(feature = "rsa")]
 impl Key for RsaKeyPair {
     fn size(&self) -> usize {
         self.rsa.n().num_bits() as usize
@@ -223,6 +385,7 @@ impl Key for RsaKeyPair {
     }
 }
 
+#[cfg(feature = "rsa")]
 impl PublicParts for RsaKeyPair {
     fn blob(&self) -> Result<Vec<u8>, Error> {
         encode_rsa_pubkey(&self.rsa)
@@ -233,23 +396,64 @@ impl PublicParts for RsaKeyPair {
     }
 }
 
+#[cfg(feature = "rsa")]
 impl PrivateParts for RsaKeyPair {
     fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-        if self.size() < RSA_MIN_SIZE {
-            return Err(ErrorKind::InvalidKeySize.into());
+        self.sign_with(data, self.signhash)
+    }
+}
+
+/// Find a prime of exactly `bits` bits with `gcd(p - 1, e) == 1` from the random number generator
+#[cfg(feature = "rsa")]
+fn generate_prime<R, F>(
+    prime: u8,
+    bits: usize,
+    e: &BigNumRef,
+    rng: &mut R,
+    ctx: &mut BigNumContextRef,
+    progress: &mut F,
+) -> OsshResult<BigNum>
+where
+    R: CryptoRngCore + ?Sized,
+    F: FnMut(RsaGenProgress) -> bool,
+{
+    let one = BigNum::from_u32(1)?;
+    let mut buf = Zeroizing::new(vec![0u8; (bits + 7) / 8]);
+    let mut gcd = BigNum::new()?;
+    let mut attempt = 0u32;
+    loop {
+        attempt = attempt.wrapping_add(1);
+        if !progress(RsaGenProgress::Candidate { prime, attempt }) {
+            return Err(Error::from_kind(ErrorKind::Cancelled));
+        }
+        rng.fill_bytes(&mut buf);
+        buf[0] &= 0xff >> (buf.len() * 8 - bits);
+        let mut candidate = BigNum::from_slice(&buf)?;
+        // Setting the top two bits makes the product of two primes have the full length
+        candidate.set_bit(bits as i32 - 1)?;
+        candidate.set_bit(bits as i32 - 2)?;
+        candidate.set_bit(0)?;
+
+        if !candidate.is_prime_fasttest(0, ctx, true)? {
+            continue;
+        }
+        gcd.gcd(&(&candidate - &one), e, ctx)?;
+        if gcd == one {
+            if !progress(RsaGenProgress::PrimeFound { prime }) {
+                return Err(Error::from_kind(ErrorKind::Cancelled));
+            }
+            return Ok(candidate);
         }
-        let pkey = PKey::from_rsa(self.rsa.clone())?;
-        let mut sign = Signer::new(self.signhash.get_digest(), &pkey)?;
-        sign.update(data)?;
-        Ok(sign.sign_to_vec()?)
     }
 }
 
 #[allow(non_upper_case_globals)]
-#[cfg(test)]
+#[cfg(all(test, feature = "rsa"))]
 mod test {
     use super::*;
     use openssl::bn::BigNum;
+    #[cfg(not(feature = "fips"))]
+    use rand::rngs::OsRng;
 
     const pub_str: &str = "rsa-sha2-512 AAAAB3NzaC1yc2EAAAADAQABAAABAQC9NCtKoC/4Gk+zS8XGtA5aGC9BeFfcOCg/9C14ph4oHVXzWlR5t3HdHJK6EJGLlC6fj5vI+6cviX7NUbXJXQ/hJe4m4c5AGzubX/jfzNTjBa+hB+5CEqSztA20aHgEWzBwoakhkOd0knT6IvHV/vqTzHVbtfWIiof2SenyHv7yD9RbS9SCmkjISi4wQWzJ1Yu0O1CbH/U1c18WnP46/HBiaJcmV9hk/L3vjSoI7kpjXfSq4d3KLnwsUdrFdhh3eN7K4/ZdnrZC8n1liDXyMAWiaAL8cu8K5wmBmnHTcqIwxYu7g+k46OzcaZxVy0i9hFBM2bzvGvsCJOF3Hh6zF15p";
     const e: [u8; 3] = [0x01, 0x00, 0x01];
@@ -291,4 +495,35 @@ mod test {
         let key = get_test_pubkey().unwrap();
         assert_eq!(key.size(), 2048);
     }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn rsa_generate_with_rng() {
+        let key = RsaKeyPair::generate_with_rng(1024, &mut OsRng).unwrap();
+        assert_eq!(key.size(), 1024);
+        assert!(key.ossl_rsa().check_key().unwrap());
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn rsa_generate_progress_cancel() {
+        let mut found = 0;
+        let key = RsaKeyPair::generate_with_progress(1024, &mut OsRng, |p| {
+            if let RsaGenProgress::PrimeFound { .. } = p {
+                found += 1;
+            }
+            true
+        })
+        .unwrap();
+        assert!(key.ossl_rsa().check_key().unwrap());
+        assert!(found >= 2);
+
+        let err = RsaKeyPair::generate_with_progress(1024, &mut OsRng, |p| match p {
+            RsaGenProgress::Candidate { attempt, .. } => attempt < 3,
+            _ => true,
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Cancelled);
+    }
 }
@@ -0,0 +1,136 @@
+use super::{KeyPair, PublicKey};
+use crate::cipher::Cipher;
+use crate::error::*;
+use crate::format::ossh_privkey::ossh_priv_ciphername;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize, Serializer};
+use std::str::FromStr;
+
+impl Serialize for PublicKey {
+    /// Serialize as the one-line OpenSSH public key string
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let keystr = self.serialize().map_err(ser::Error::custom)?;
+        serializer.serialize_str(&keystr)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    /// Deserialize from the OpenSSH or PEM public key string
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keystr = String::deserialize(deserializer)?;
+        PublicKey::from_keystr(&keystr).map_err(de::Error::custom)
+    }
+}
+
+/// A key pair stored in the encrypted OpenSSH private key format
+///
+/// [`KeyPair`] doesn't implement `Serialize` to avoid leaking the private key accidentally.
+/// Use this wrapper to store the key pair in the application state instead,
+/// it is (de)serialized as the encrypted private key string.
+///
+/// An unencrypted key is refused by both [`encrypt()`](#method.encrypt) and the deserialization.
+#[derive(Clone)]
+pub struct EncryptedKeyPair {
+    keystr: String,
+}
+
+impl EncryptedKeyPair {
+    /// Encrypt the key pair
+    ///
+    /// Return [`ErrorKind::InvalidArgument`] if the passphrase is empty or the cipher is null.
+    pub fn encrypt(keypair: &KeyPair, passphrase: &str, cipher: Cipher) -> OsshResult<Self> {
+        if passphrase.is_empty() || cipher.is_null() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        Ok(EncryptedKeyPair {
+            keystr: keypair.serialize_openssh(Some(passphrase), cipher)?,
+        })
+    }
+
+    /// Decrypt the key pair
+    pub fn decrypt(&self, passphrase: &str) -> OsshResult<KeyPair> {
+        KeyPair::from_keystr(&self.keystr, Some(passphrase))
+    }
+
+    /// Get the encrypted OpenSSH private key string
+    pub fn as_str(&self) -> &str {
+        &self.keystr
+    }
+}
+
+impl FromStr for EncryptedKeyPair {
+    type Err = Error;
+
+    /// Parse the encrypted OpenSSH private key string
+    ///
+    /// Return [`ErrorKind::InvalidArgument`] if the key is not encrypted.
+    fn from_str(s: &str) -> OsshResult<Self> {
+        let pemdata = pem::parse(s)?;
+        if pemdata.tag() != "OPENSSH PRIVATE KEY" {
+            return Err(ErrorKind::InvalidKeyFormat.into());
+        }
+        let cipher = Cipher::from_str(&ossh_priv_ciphername(pemdata.contents())?)?;
+        if cipher.is_null() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        Ok(EncryptedKeyPair {
+            keystr: s.to_owned(),
+        })
+    }
+}
+
+impl Serialize for EncryptedKeyPair {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.keystr)
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptedKeyPair {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keystr = String::deserialize(deserializer)?;
+        EncryptedKeyPair::from_str(&keystr).map_err(de::Error::custom)
+    }
+}
+
+/// Serialize/Deserialize [`PublicKey`] in a structured form
+///
+/// The key is represented as a map with the `type`, `key` and `comment` fields,
+/// where `key` is the base64 encoded key blob. Unlike the one-line form,
+/// the comment can contain whitespaces.
+///
+/// Use it with `#[serde(with = "osshkeys::keys::serde_support::structured")]`.
+pub mod structured {
+    use super::super::{Key, PublicKey, PublicParts};
+    use crate::format::base64;
+    use serde::de::{self, Deserializer};
+    use serde::ser::{self, Serializer};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct StructuredKey {
+        #[serde(rename = "type")]
+        keytype: String,
+        key: String,
+        #[serde(default)]
+        comment: String,
+    }
+
+    /// Serialize the public key in the structured form
+    pub fn serialize<S: Serializer>(key: &PublicKey, serializer: S) -> Result<S::Ok, S::Error> {
+        StructuredKey {
+            keytype: key.keyname().to_owned(),
+            key: base64::encode(key.blob().map_err(ser::Error::custom)?),
+            comment: key.comment().to_owned(),
+        }
+        .serialize(serializer)
+    }
+
+    /// Deserialize the public key from the structured form
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PublicKey, D::Error> {
+        let s = StructuredKey::deserialize(deserializer)?;
+        let mut key = PublicKey::from_keystr(&format!("{} {}", s.keytype, s.key))
+            .map_err(de::Error::custom)?;
+        *key.comment_mut() = s.comment;
+        Ok(key)
+    }
+}
@@ -0,0 +1,161 @@
+use super::{KeyPair, KeyType, PrivateParts, PublicKey};
+use crate::cipher::Cipher;
+use crate::error::*;
+use crate::format::ossh_privkey::{decode_ossh_priv, encode_ossh_priv};
+use rand::rngs::OsRng;
+use rand_core::CryptoRngCore;
+use sha2::{Digest, Sha512};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// The length of the random prekey, the same as OpenSSH's `SSHKEY_SHIELD_PREKEY_LEN`
+pub const SHIELD_PREKEY_LEN: usize = 16 * 1024;
+
+const SHIELD_CIPHER: Cipher = Cipher::Aes256_Ctr;
+
+/// A key pair which is encrypted in the memory, like the key shielding of OpenSSH
+///
+/// The private key is encrypted by AES-256-CTR with the key and IV derived from the SHA-512 hash of
+/// a [`SHIELD_PREKEY_LEN`] bytes random prekey. An attacker has to read the whole prekey without any error
+/// to recover the private key, which makes the side channel attacks, such as Spectre and Rowhammer, much harder.
+///
+/// The private key is only decrypted temporarily for each operation, and wiped after it is done.
+pub struct ShieldedKeyPair {
+    publickey: PublicKey,
+    prekey: Zeroizing<Vec<u8>>,
+    shielded: Vec<u8>,
+}
+
+impl ShieldedKeyPair {
+    /// Shield the key pair
+    ///
+    /// The given key pair is wiped when it is dropped at the end of this function.
+    pub fn new(keypair: KeyPair) -> OsshResult<Self> {
+        Self::new_with_rng(keypair, &mut OsRng)
+    }
+
+    /// Shield the key pair using the given random number generator to generate the prekey
+    pub fn new_with_rng<R: CryptoRngCore + ?Sized>(
+        keypair: KeyPair,
+        rng: &mut R,
+    ) -> OsshResult<Self> {
+        let publickey = keypair.clone_public_key()?;
+        let mut prekey = Zeroizing::new(vec![0u8; SHIELD_PREKEY_LEN]);
+        rng.fill_bytes(&mut prekey);
+
+        let plain = Zeroizing::new(encode_ossh_priv(&keypair, "", Cipher::Null, 0)?);
+        let keyder = shield_key(&prekey);
+        let shielded = SHIELD_CIPHER.encrypt(
+            &plain,
+            &keyder[..SHIELD_CIPHER.key_len()],
+            &keyder[SHIELD_CIPHER.key_len()..][..SHIELD_CIPHER.iv_len()],
+        )?;
+        Ok(ShieldedKeyPair {
+            publickey,
+            prekey,
+            shielded,
+        })
+    }
+
+    /// Get the type of the key
+    pub fn keytype(&self) -> KeyType {
+        self.publickey.keytype()
+    }
+
+    /// Get the public key, the comment is also included
+    pub fn public_key(&self) -> &PublicKey {
+        &self.publickey
+    }
+
+    /// Replace the comment of the key
+    ///
+    /// The key pair is shielded again with a new prekey, so the unshielded key pair also has the new comment.
+    pub fn set_comment(&mut self, comment: &str) -> OsshResult<()> {
+        let mut keypair = self.unshield()?;
+        keypair.set_comment(comment);
+        *self = Self::new(keypair)?;
+        Ok(())
+    }
+
+    /// Decrypt the key pair
+    ///
+    /// Prefer [`ShieldedKeyPair::with_keypair()`] to keep the unshielded key pair short-lived.
+    pub fn unshield(&self) -> OsshResult<KeyPair> {
+        let keyder = shield_key(&self.prekey);
+        let plain = Zeroizing::new(SHIELD_CIPHER.decrypt(
+            &self.shielded,
+            &keyder[..SHIELD_CIPHER.key_len()],
+            &keyder[SHIELD_CIPHER.key_len()..][..SHIELD_CIPHER.iv_len()],
+        )?);
+        decode_ossh_priv(&plain, None)
+    }
+
+    /// Unshield the key pair temporarily and pass it to the closure
+    ///
+    /// The unshielded key pair is wiped after the closure returns.
+    pub fn with_keypair<R, F: FnOnce(&KeyPair) -> R>(&self, f: F) -> OsshResult<R> {
+        let keypair = self.unshield()?;
+        Ok(f(&keypair))
+    }
+
+    /// Sign the data with the key pair
+    pub fn sign(&self, data: &[u8]) -> OsshResult<Vec<u8>> {
+        self.with_keypair(|keypair| keypair.sign(data))?
+    }
+}
+
+impl fmt::Debug for ShieldedKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShieldedKeyPair")
+            .field("keytype", &self.keytype())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Derive the key and IV of the shielding cipher from the prekey
+fn shield_key(prekey: &[u8]) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(Sha512::digest(prekey).to_vec())
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519", not(feature = "fips")))]
+mod test {
+    use super::*;
+    use crate::keys::PublicParts;
+
+    #[test]
+    fn shielded_keypair_roundtrip() {
+        const DATA: &[u8] = b"8Kn9PPQV";
+        for keytype in [KeyType::ECDSA, KeyType::ED25519] {
+            let keypair = KeyPair::generate(keytype, 0).unwrap();
+            let plain = encode_ossh_priv(&keypair, "", Cipher::Null, 0).unwrap();
+            let publickey = keypair.clone_public_key().unwrap();
+
+            let shielded = ShieldedKeyPair::new(keypair).unwrap();
+            assert_eq!(shielded.keytype(), keytype);
+            assert_eq!(shielded.prekey.len(), SHIELD_PREKEY_LEN);
+            assert_ne!(shielded.shielded, plain);
+
+            let unshielded = shielded.unshield().unwrap();
+            assert!(unshielded.matches_public(&publickey));
+            let sig = shielded.sign(DATA).unwrap();
+            assert!(publickey.verify(DATA, &sig).unwrap());
+        }
+    }
+
+    #[test]
+    fn shielded_keypair_corrupted_prekey() {
+        let keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let mut shielded = ShieldedKeyPair::new(keypair).unwrap();
+        shielded.prekey[SHIELD_PREKEY_LEN / 2] ^= 1;
+        assert!(shielded.unshield().is_err());
+    }
+
+    #[test]
+    fn shielded_keypair_set_comment() {
+        let keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let mut shielded = ShieldedKeyPair::new(keypair).unwrap();
+        shielded.set_comment("shielded comment").unwrap();
+        assert_eq!(shielded.public_key().comment(), "shielded comment");
+        assert_eq!(shielded.unshield().unwrap().comment(), "shielded comment");
+    }
+}
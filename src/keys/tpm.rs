@@ -0,0 +1,114 @@
+#[cfg(feature = "ecdsa")]
+use super::ecdsa::EcCurve;
+use super::provider::ProviderKeyPair;
+use super::KeyType;
+use crate::error::*;
+use foreign_types::ForeignType;
+use openssl::error::ErrorStack;
+use openssl::pkey::{PKey, Private};
+use openssl::provider::Provider;
+use openssl_sys as ffi;
+use std::ffi::CString;
+use std::os::raw::c_int;
+use std::ptr;
+
+/// The property query selecting the algorithms of the tpm2-openssl provider
+pub const TPM2_PROPERTIES: &str = "provider=tpm2";
+
+/// Load the tpm2-openssl provider, keeping the default provider available for the other operations
+///
+/// The provider is unloaded when the returned value is dropped, so keep it alive while using the TPM keys.
+/// The TPM keys can also be loaded by [`ProviderKeyPair::load()`] with the `handle:` URI of the persistent keys,
+/// or with the `file:` URI of the keys written by [`ProviderKeyPair::serialize_provider_pem()`].
+pub fn load_tpm2_provider() -> OsshResult<Provider> {
+    Ok(Provider::try_load(None, "tpm2", true)?)
+}
+
+/// Create a new RSA or EcDSA key pair under the TPM
+///
+/// The private key is created by the TPM and never leaves it unwrapped.
+/// The bits parameter has the same meaning as [`KeyPair::generate()`](super::KeyPair::generate),
+/// but the TPMs usually only support the 2048-bit RSA keys and the NIST P-256 and P-384 curves.
+pub fn generate_tpm2_key(keytype: KeyType, bits: usize) -> OsshResult<ProviderKeyPair> {
+    generate_with_properties(keytype, bits, TPM2_PROPERTIES)
+}
+
+fn generate_with_properties(
+    keytype: KeyType,
+    bits: usize,
+    properties: &str,
+) -> OsshResult<ProviderKeyPair> {
+    let (name, param): (&str, c_int) = match (keytype, bits) {
+        #[cfg(feature = "rsa")]
+        (KeyType::RSA, 0) => ("RSA", 2048),
+        #[cfg(feature = "rsa")]
+        (KeyType::RSA, bits) => (
+            "RSA",
+            c_int::try_from(bits).map_err(|_| ErrorKind::InvalidKeySize)?,
+        ),
+        #[cfg(feature = "ecdsa")]
+        (KeyType::ECDSA, 0 | 256) => ("EC", EcCurve::Nistp256.nid().as_raw()),
+        #[cfg(feature = "ecdsa")]
+        (KeyType::ECDSA, 384) => ("EC", EcCurve::Nistp384.nid().as_raw()),
+        #[cfg(feature = "ecdsa")]
+        (KeyType::ECDSA, 521) => ("EC", EcCurve::Nistp521.nid().as_raw()),
+        #[cfg(feature = "ecdsa")]
+        (KeyType::ECDSA, _) => return Err(ErrorKind::InvalidKeySize.into()),
+        (keytype, _) => return Err(ErrorKind::UnsupportType(keytype.to_string()).into()),
+    };
+    let name = CString::new(name).unwrap();
+    let properties = CString::new(properties).map_err(|_| ErrorKind::InvalidArgument)?;
+    let pkey = unsafe {
+        let ctx =
+            ffi::EVP_PKEY_CTX_new_from_name(ptr::null_mut(), name.as_ptr(), properties.as_ptr());
+        if ctx.is_null() {
+            return Err(ErrorStack::get().into());
+        }
+        let mut raw = ptr::null_mut();
+        let generated = ffi::EVP_PKEY_keygen_init(ctx) > 0
+            && match keytype {
+                #[cfg(feature = "rsa")]
+                KeyType::RSA => ffi::EVP_PKEY_CTX_set_rsa_keygen_bits(ctx, param) > 0,
+                _ => ffi::EVP_PKEY_CTX_set_ec_paramgen_curve_nid(ctx, param) > 0,
+            }
+            && ffi::EVP_PKEY_generate(ctx, &mut raw) > 0;
+        ffi::EVP_PKEY_CTX_free(ctx);
+        if !generated {
+            return Err(ErrorStack::get().into());
+        }
+        PKey::<Private>::from_ptr(raw)
+    };
+    ProviderKeyPair::from_ossl_pkey(pkey)
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    use crate::keys::{Key, PublicParts, SshSigner};
+
+    #[test]
+    fn provider_key_generation() {
+        // The default provider stands in for the TPM, which is not available in the tests
+        let key = generate_with_properties(KeyType::ECDSA, 384, "provider=default").unwrap();
+        assert_eq!(key.keytype(), KeyType::ECDSA);
+        assert_eq!(key.size(), 384);
+
+        let pem = key.serialize_provider_pem().unwrap();
+        let path = std::env::temp_dir().join(format!("osshkeys-tpm2-{}.pem", std::process::id()));
+        std::fs::write(&path, pem).unwrap();
+        let loaded = ProviderKeyPair::load(&format!("file:{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.blob().unwrap(), key.blob().unwrap());
+
+        let sig = loaded.sign_ssh_signature_with(b"data", None).unwrap();
+        assert!(key
+            .clone_public_key()
+            .unwrap()
+            .verify_ssh_signature(b"data", &sig)
+            .unwrap());
+
+        assert!(generate_with_properties(KeyType::ED25519, 0, "provider=default").is_err());
+        assert!(generate_with_properties(KeyType::ECDSA, 0, "provider=missing").is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use super::{Fingerprint, FingerprintHash, KeyPair, KeyType, PrivateParts, PublicKey, PublicParts};
+use crate::defaults::defaults;
+use crate::error::{Error, ErrorKind};
+use std::fmt;
+use std::sync::Arc;
+
+/// The error type of the bindings
+#[derive(Debug, uniffi::Error)]
+pub enum OsshError {
+    InvalidArgument { message: String },
+    InvalidKey { message: String },
+    IncorrectPass { message: String },
+    Unsupported { message: String },
+    Crypto { message: String },
+    Io { message: String },
+    Unknown { message: String },
+}
+
+impl From<Error> for OsshError {
+    fn from(e: Error) -> Self {
+        use ErrorKind::*;
+        let message = e.to_string();
+        match e.kind() {
+            InvalidArgument => OsshError::InvalidArgument { message },
+            InvalidKeyFormat | InvalidFormat | InvalidKey | InvalidKeySize | InvalidLength
+            | InvalidPemFormat | InvalidKeyIvLength | TypeNotMatch => {
+                OsshError::InvalidKey { message }
+            }
+            IncorrectPass | MacMismatch => OsshError::IncorrectPass { message },
+            UnsupportCurve | UnsupportCipher(_) | UnsupportType(_) | LegacyAlgorithm(_)
+            | PolicyViolation(_) | FipsNotApproved(_) => OsshError::Unsupported { message },
+            OpenSslError | Pkcs11Error(_) => OsshError::Crypto { message },
+            IOError | FmtError => OsshError::Io { message },
+            _ => OsshError::Unknown { message },
+        }
+    }
+}
+
+impl fmt::Display for OsshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OsshError::InvalidArgument { message }
+            | OsshError::InvalidKey { message }
+            | OsshError::IncorrectPass { message }
+            | OsshError::Unsupported { message }
+            | OsshError::Crypto { message }
+            | OsshError::Io { message }
+            | OsshError::Unknown { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for OsshError {}
+
+type BindingResult<T> = Result<T, OsshError>;
+
+/// The private key format of [`SshKeyPair::serialize()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum KeyFormat {
+    /// The OpenSSH private key format, encrypted with AES-256-CTR if a passphrase is given
+    Openssh,
+    /// The traditional OpenSSL PEM format
+    Pem,
+    /// The PKCS#8 format
+    Pkcs8,
+}
+
+/// The [`KeyPair`] object of the bindings
+#[derive(uniffi::Object)]
+pub struct SshKeyPair(KeyPair);
+
+#[uniffi::export]
+impl SshKeyPair {
+    /// Generate a new key pair, set `bits` to 0 to use the default key size
+    #[uniffi::constructor]
+    pub fn generate(keytype: KeyType, bits: u64) -> BindingResult<Arc<Self>> {
+        let bits =
+            usize::try_from(bits).map_err(|e| Error::with_error(ErrorKind::InvalidArgument, e))?;
+        Ok(Arc::new(SshKeyPair(KeyPair::generate(keytype, bits)?)))
+    }
+
+    /// Parse a private key in the OpenSSH, PEM or PKCS#8 format
+    #[uniffi::constructor]
+    pub fn from_keystr(keystr: String, passphrase: Option<String>) -> BindingResult<Arc<Self>> {
+        Ok(Arc::new(SshKeyPair(KeyPair::from_keystr(
+            &keystr,
+            passphrase.as_deref(),
+        )?)))
+    }
+
+    pub fn keytype(&self) -> KeyType {
+        self.0.keytype()
+    }
+
+    pub fn comment(&self) -> String {
+        self.0.comment().to_string()
+    }
+
+    pub fn public_key(&self) -> BindingResult<Arc<SshPublicKey>> {
+        Ok(Arc::new(SshPublicKey(self.0.clone_public_key()?)))
+    }
+
+    /// Get the fingerprint, such as `SHA256:...`
+    pub fn fingerprint(&self, hash: FingerprintHash) -> BindingResult<String> {
+        Ok(Fingerprint::of(&self.0, hash)?.to_string())
+    }
+
+    pub fn sign(&self, data: Vec<u8>) -> BindingResult<Vec<u8>> {
+        Ok(self.0.sign(&data)?)
+    }
+
+    /// Serialize the private key, the key is not encrypted if the passphrase is empty
+    pub fn serialize(
+        &self,
+        format: KeyFormat,
+        passphrase: Option<String>,
+    ) -> BindingResult<String> {
+        let passphrase = passphrase.as_deref().filter(|p| !p.is_empty());
+        let keystr = match format {
+            KeyFormat::Openssh => self
+                .0
+                .serialize_openssh_with_defaults(passphrase, &defaults())?,
+            KeyFormat::Pem => self.0.serialize_pem(passphrase)?,
+            KeyFormat::Pkcs8 => self.0.serialize_pkcs8(passphrase)?,
+        };
+        Ok(keystr)
+    }
+
+    /// Serialize the public key in the OpenSSH format
+    pub fn serialize_publickey(&self) -> BindingResult<String> {
+        Ok(self.0.serialize_publickey()?)
+    }
+}
+
+/// The [`PublicKey`] object of the bindings
+#[derive(uniffi::Object)]
+pub struct SshPublicKey(PublicKey);
+
+#[uniffi::export]
+impl SshPublicKey {
+    /// Parse a public key in the OpenSSH or PEM format
+    #[uniffi::constructor]
+    pub fn from_keystr(keystr: String) -> BindingResult<Arc<Self>> {
+        Ok(Arc::new(SshPublicKey(PublicKey::from_keystr(&keystr)?)))
+    }
+
+    pub fn keytype(&self) -> KeyType {
+        self.0.keytype()
+    }
+
+    pub fn comment(&self) -> String {
+        self.0.comment().to_string()
+    }
+
+    /// Get the fingerprint, such as `SHA256:...`
+    pub fn fingerprint(&self, hash: FingerprintHash) -> BindingResult<String> {
+        Ok(Fingerprint::of(&self.0, hash)?.to_string())
+    }
+
+    pub fn verify(&self, data: Vec<u8>, sig: Vec<u8>) -> BindingResult<bool> {
+        Ok(self.0.verify(&data, &sig)?)
+    }
+
+    /// Serialize the public key in the OpenSSH format
+    pub fn serialize(&self) -> BindingResult<String> {
+        Ok(self.0.serialize()?)
+    }
+}
+
+#[cfg(all(test, feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    use crate::keys::Key;
+
+    const DATA: &[u8] = b"8Kn9PPQV";
+
+    #[test]
+    fn uniffi_keypair_roundtrip() {
+        let keystr = std::fs::read_to_string("assets/openssh_ed25519_enc").unwrap();
+        assert!(matches!(
+            SshKeyPair::from_keystr(keystr.clone(), None),
+            Err(OsshError::IncorrectPass { .. })
+        ));
+        let keypair = SshKeyPair::from_keystr(keystr, Some("12345678".to_string())).unwrap();
+        assert_eq!(keypair.keytype(), KeyType::ED25519);
+
+        let pubkey = SshPublicKey::from_keystr(keypair.serialize_publickey().unwrap()).unwrap();
+        assert_eq!(
+            pubkey.fingerprint(FingerprintHash::SHA256).unwrap(),
+            keypair.fingerprint(FingerprintHash::SHA256).unwrap()
+        );
+        let sig = keypair.sign(DATA.to_vec()).unwrap();
+        assert!(pubkey.verify(DATA.to_vec(), sig).unwrap());
+
+        let serialized = keypair
+            .serialize(KeyFormat::Openssh, Some("87654321".to_string()))
+            .unwrap();
+        let keypair2 = SshKeyPair::from_keystr(serialized, Some("87654321".to_string())).unwrap();
+        assert!(keypair2.0.matches_public(&pubkey.0));
+    }
+
+    #[test]
+    fn uniffi_generate() {
+        let keypair = SshKeyPair::generate(KeyType::ECDSA, 384).unwrap();
+        assert_eq!(keypair.keytype(), KeyType::ECDSA);
+        assert_eq!(keypair.public_key().unwrap().0.size(), 384);
+        assert!(matches!(
+            SshKeyPair::generate(KeyType::ECDSA, 100),
+            Err(OsshError::InvalidKey { .. } | OsshError::Unsupported { .. })
+        ));
+    }
+}
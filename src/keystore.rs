@@ -0,0 +1,347 @@
+use crate::cipher::Cipher;
+use crate::defaults::defaults;
+use crate::error::*;
+use crate::format::ossh_privkey::{bcrypt_kdf, KDF_BCRYPT, SALT_LEN};
+use crate::hooks::{report_key_usage, KeyOperation};
+use crate::hostkeys::write_key_file;
+use crate::keys::{Fingerprint, KeyPair};
+use crate::sshbuf::{SshReader, SshWriteExt};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const KEYSTORE_MAGIC: &[u8] = b"osshkeys-keystore-v1\0";
+const CIPHER_AES256_CTR_HMAC: &str = "aes256-ctr-hmac-sha256";
+const CIPHER: Cipher = Cipher::Aes256_Ctr;
+const KEY_LEN: usize = 32;
+const MAC_KEY_LEN: usize = 32;
+const IV_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+/// The most bcrypt KDF rounds accepted from a keystore, so a crafted file can't hang the caller
+pub const MAX_KDF_ROUNDS: u32 = 1024;
+const SECTION_KEYSTORE: &str = "keystore";
+
+/// A named key in the [`Keystore`]
+pub struct KeystoreEntry {
+    label: String,
+    keypair: KeyPair,
+}
+
+impl KeystoreEntry {
+    /// Get the label of the key
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Get the key pair
+    pub fn keypair(&self) -> &KeyPair {
+        &self.keypair
+    }
+}
+
+/// A container holding many named key pairs, sealed in a single file with one passphrase
+///
+/// The passphrase goes through the bcrypt KDF like the encrypted OpenSSH keys,
+/// and the whole content is encrypted with AES-256-CTR by the cipher backend selected by the features,
+/// then authenticated with HMAC-SHA256.
+/// The labels, the keys and the number of the keys are all encrypted.
+#[derive(Default)]
+pub struct Keystore {
+    entries: Vec<KeystoreEntry>,
+}
+
+impl Keystore {
+    /// Create an empty keystore
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decrypt the sealed keystore
+    ///
+    /// Fail with [`ErrorKind::MacMismatch`] if the passphrase is incorrect or the data is corrupted,
+    /// and with [`ErrorKind::InvalidKeyFormat`] if the KDF rounds are more than [`MAX_KDF_ROUNDS`].
+    pub fn open(data: &[u8], passphrase: &str) -> OsshResult<Self> {
+        let mut reader = SshReader::new(data, SECTION_KEYSTORE);
+        if reader.read_bytes("magic", KEYSTORE_MAGIC.len())? != KEYSTORE_MAGIC {
+            return Err(Error::from_kind(ErrorKind::InvalidKeyFormat).at(
+                SECTION_KEYSTORE,
+                "magic",
+                0,
+            ));
+        }
+        let kdfname = reader.read_utf8("kdfname")?;
+        if kdfname != KDF_BCRYPT {
+            return Err(ErrorKind::UnsupportCipher(kdfname.to_owned()).into());
+        }
+        let mut kdfoptions = reader.read_nested("kdfoptions")?;
+        let salt = kdfoptions.read_string("salt")?;
+        let rounds = kdfoptions.read_uint32("rounds")?;
+        if rounds > MAX_KDF_ROUNDS {
+            return Err(Error::from_kind(ErrorKind::InvalidKeyFormat).at(
+                SECTION_KEYSTORE,
+                "rounds",
+                reader.position(),
+            ));
+        }
+        kdfoptions.finish()?;
+        let ciphername = reader.read_utf8("ciphername")?;
+        if ciphername != CIPHER_AES256_CTR_HMAC {
+            return Err(ErrorKind::UnsupportCipher(ciphername.to_owned()).into());
+        }
+        let iv = reader.read_bytes("iv", IV_LEN)?;
+        let header = &data[..reader.position()];
+        let encrypted = reader.read_string("encrypted")?;
+        reader.finish()?;
+        if encrypted.len() < TAG_LEN {
+            return Err(ErrorKind::InvalidLength.into());
+        }
+        let (encrypted, tag) = encrypted.split_at(encrypted.len() - TAG_LEN);
+
+        let key = derive_key(passphrase, salt, rounds)?;
+        let (enckey, mackey) = key.split_at(KEY_LEN);
+        mac(mackey, header, encrypted)
+            .verify_slice(tag)
+            .map_err(|_| ErrorKind::MacMismatch)?;
+        let plain = Zeroizing::new(CIPHER.decrypt(encrypted, enckey, iv)?);
+
+        let mut reader = SshReader::new(&plain, SECTION_KEYSTORE);
+        let count = reader.read_uint32("count")?;
+        let mut keystore = Keystore::new();
+        for _ in 0..count {
+            let label = reader.read_utf8("label")?;
+            let keystr = reader.read_utf8("key")?;
+            let keypair = KeyPair::from_keystr(keystr, None)?;
+            report_key_usage(KeyOperation::Decrypt, &keypair);
+            keystore.add(label, keypair)?;
+        }
+        reader.finish()?;
+        Ok(keystore)
+    }
+
+    /// Read and decrypt the keystore file
+    pub fn load<P: AsRef<Path>>(path: P, passphrase: &str) -> OsshResult<Self> {
+        Self::open(&fs::read(path)?, passphrase)
+    }
+
+    /// Encrypt the keystore with the passphrase and the KDF rounds of [`defaults()`]
+    pub fn seal(&self, passphrase: &str) -> OsshResult<Vec<u8>> {
+        self.seal_with_rounds(passphrase, defaults().kdf_rounds)
+    }
+
+    /// Encrypt the keystore with the passphrase and the given bcrypt KDF rounds
+    ///
+    /// Fail with [`ErrorKind::IncorrectPass`] if the passphrase is empty,
+    /// and with [`ErrorKind::InvalidArgument`] if the rounds are more than [`MAX_KDF_ROUNDS`].
+    pub fn seal_with_rounds(&self, passphrase: &str, rounds: u32) -> OsshResult<Vec<u8>> {
+        if passphrase.is_empty() {
+            return Err(ErrorKind::IncorrectPass.into());
+        }
+        if rounds > MAX_KDF_ROUNDS {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        let mut salt = [0u8; SALT_LEN];
+        let mut iv = [0u8; IV_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+
+        let keystrs = self
+            .entries
+            .iter()
+            .map(|entry| {
+                Ok(Zeroizing::new(
+                    entry.keypair.serialize_openssh(None, Cipher::Null)?,
+                ))
+            })
+            .collect::<OsshResult<Vec<_>>>()?;
+        // Allocate the plaintext once, so the keys aren't left behind in the memory freed by growing it
+        let size = self
+            .entries
+            .iter()
+            .zip(&keystrs)
+            .map(|(entry, keystr)| 8 + entry.label.len() + keystr.len())
+            .sum::<usize>();
+        let mut plain = Zeroizing::new(Vec::with_capacity(4 + size));
+        plain.write_uint32(self.entries.len() as u32)?;
+        for (entry, keystr) in self.entries.iter().zip(&keystrs) {
+            plain.write_utf8(&entry.label)?;
+            plain.write_utf8(keystr)?;
+        }
+
+        let mut buf = KEYSTORE_MAGIC.to_vec();
+        buf.write_utf8(KDF_BCRYPT)?;
+        let mut kdfoptions = Vec::new();
+        kdfoptions.write_string(&salt)?;
+        kdfoptions.write_uint32(rounds)?;
+        buf.write_string(&kdfoptions)?;
+        buf.write_utf8(CIPHER_AES256_CTR_HMAC)?;
+        buf.extend_from_slice(&iv);
+
+        let key = derive_key(passphrase, &salt, rounds)?;
+        let (enckey, mackey) = key.split_at(KEY_LEN);
+        let mut encrypted = CIPHER.encrypt(&plain, enckey, &iv)?;
+        let tag = mac(mackey, &buf, &encrypted).finalize().into_bytes();
+        encrypted.extend_from_slice(&tag);
+        buf.write_string(&encrypted)?;
+        Ok(buf)
+    }
+
+    /// Encrypt the keystore and write it into the file with the permission `0600`
+    pub fn save<P: AsRef<Path>>(&self, path: P, passphrase: &str) -> OsshResult<()> {
+        write_key_file(path.as_ref(), &self.seal(passphrase)?, 0o600)
+    }
+
+    /// Add the key pair with the label
+    ///
+    /// Fail with [`ErrorKind::InvalidArgument`] if the label is empty or already used.
+    pub fn add(&mut self, label: &str, keypair: KeyPair) -> OsshResult<()> {
+        if label.is_empty() || self.get(label).is_some() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        self.entries.push(KeystoreEntry {
+            label: label.to_owned(),
+            keypair,
+        });
+        Ok(())
+    }
+
+    /// Get the key pair with the label
+    pub fn get(&self, label: &str) -> Option<&KeyPair> {
+        self.entries
+            .iter()
+            .find(|entry| entry.label == label)
+            .map(|entry| &entry.keypair)
+    }
+
+    /// Find the key whose public key has the fingerprint
+    pub fn find(&self, fingerprint: &Fingerprint) -> Option<&KeystoreEntry> {
+        self.entries
+            .iter()
+            .find(|entry| fingerprint.matches(&entry.keypair))
+    }
+
+    /// Remove the key pair with the label, and return it
+    pub fn remove(&mut self, label: &str) -> Option<KeyPair> {
+        let index = self.entries.iter().position(|entry| entry.label == label)?;
+        Some(self.entries.remove(index).keypair)
+    }
+
+    /// List the keys in the order they were added
+    pub fn entries(&self) -> &[KeystoreEntry] {
+        &self.entries
+    }
+
+    /// Get the number of the keys
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if there is no key
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Derive the cipher key followed by the MAC key
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    rounds: u32,
+) -> OsshResult<Zeroizing<[u8; KEY_LEN + MAC_KEY_LEN]>> {
+    let mut key = Zeroizing::new([0u8; KEY_LEN + MAC_KEY_LEN]);
+    bcrypt_kdf(passphrase, salt, rounds, &mut *key)?;
+    Ok(key)
+}
+
+/// Authenticate the header and the ciphertext
+fn mac(mackey: &[u8], header: &[u8], encrypted: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(mackey).expect("HMAC accepts keys of any size");
+    mac.update(header);
+    mac.update(encrypted);
+    mac
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+mod test {
+    use super::*;
+    use crate::keys::{FingerprintHash, KeyType, PublicParts};
+
+    #[test]
+    fn keystore_roundtrip() {
+        let mut keystore = Keystore::new();
+        let ed25519 =
+            KeyPair::from_keystr(include_str!("../assets/openssh_ed25519"), None).unwrap();
+        let fingerprint = Fingerprint::of(&ed25519, FingerprintHash::SHA256).unwrap();
+        keystore.add("deploy", ed25519).unwrap();
+        keystore
+            .add("backup", KeyPair::generate(KeyType::ECDSA, 384).unwrap())
+            .unwrap();
+        keystore
+            .add("old", KeyPair::generate(KeyType::RSA, 2048).unwrap())
+            .unwrap();
+        assert_eq!(
+            keystore
+                .add("deploy", KeyPair::generate(KeyType::ED25519, 0).unwrap())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidArgument
+        );
+        assert!(keystore.remove("old").is_some());
+        assert!(keystore.remove("old").is_none());
+
+        let sealed = keystore.seal_with_rounds("12345678", 4).unwrap();
+        let opened = Keystore::open(&sealed, "12345678").unwrap();
+        let labels: Vec<_> = opened.entries().iter().map(|e| e.label()).collect();
+        assert_eq!(labels, ["deploy", "backup"]);
+        for entry in keystore.entries() {
+            let keypair = opened.get(entry.label()).unwrap();
+            assert_eq!(keypair.blob().unwrap(), entry.keypair().blob().unwrap());
+            assert_eq!(keypair.comment(), entry.keypair().comment());
+        }
+        assert_eq!(opened.find(&fingerprint).unwrap().label(), "deploy");
+
+        assert_eq!(
+            Keystore::open(&sealed, "87654321").err().unwrap().kind(),
+            ErrorKind::MacMismatch
+        );
+        let mut tampered = sealed.clone();
+        tampered[KEYSTORE_MAGIC.len() + 12] ^= 1;
+        assert!(Keystore::open(&tampered, "12345678").is_err());
+        assert_eq!(
+            keystore.seal("").unwrap_err().kind(),
+            ErrorKind::IncorrectPass
+        );
+
+        let empty = Keystore::new().seal_with_rounds("12345678", 4).unwrap();
+        assert!(Keystore::open(&empty, "12345678").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keystore_refuse_excessive_rounds() {
+        assert_eq!(
+            Keystore::new()
+                .seal_with_rounds("12345678", MAX_KDF_ROUNDS + 1)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidArgument
+        );
+        let mut sealed = Keystore::new().seal_with_rounds("12345678", 4).unwrap();
+        // The rounds follow the magic, the KDF name, the kdfoptions length and the salt
+        let pos = KEYSTORE_MAGIC.len() + 4 + KDF_BCRYPT.len() + 4 + 4 + SALT_LEN;
+        assert_eq!(sealed[pos..pos + 4], 4u32.to_be_bytes());
+        sealed[pos..pos + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            Keystore::open(&sealed, "12345678").err().unwrap().kind(),
+            ErrorKind::InvalidKeyFormat
+        );
+    }
+}
@@ -0,0 +1,888 @@
+use crate::error::*;
+use crate::format::base64;
+use crate::keys::cert::{unix_time, CertType, Certificate};
+#[cfg(feature = "ecdsa")]
+use crate::keys::Key;
+use crate::keys::{KeyType, PublicKey};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::fmt;
+use std::str::FromStr;
+
+/// The prefix of the hashed host names
+const HASH_MAGIC: &str = "|1|";
+pub(crate) const SSH_DEFAULT_PORT: u16 = 22;
+
+/// The marker of the known_hosts entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    /// `@cert-authority`, the key is a CA which signs the host certificates
+    CertAuthority,
+    /// `@revoked`, the key must never be accepted
+    Revoked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HostPatterns {
+    Patterns(Vec<String>),
+    Hashed { salt: Vec<u8>, hash: Vec<u8> },
+}
+
+impl HostPatterns {
+    fn parse(s: &str) -> OsshResult<Self> {
+        if let Some(hashed) = s.strip_prefix(HASH_MAGIC) {
+            let (salt, hash) = hashed.split_once('|').ok_or(ErrorKind::InvalidFormat)?;
+            Ok(HostPatterns::Hashed {
+                salt: base64::decode(salt)?,
+                hash: base64::decode(hash)?,
+            })
+        } else {
+            Ok(HostPatterns::Patterns(
+                s.split(',').map(str::to_ascii_lowercase).collect(),
+            ))
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            HostPatterns::Patterns(patterns) => {
+                match_pattern_list(patterns.iter().map(String::as_str), name)
+            }
+            HostPatterns::Hashed { salt, hash } => {
+                let mut mac = match Hmac::<Sha1>::new_from_slice(salt) {
+                    Ok(mac) => mac,
+                    Err(_) => return false,
+                };
+                mac.update(name.as_bytes());
+                mac.verify_slice(hash).is_ok()
+            }
+        }
+    }
+}
+
+/// Match the host name against the patterns, any negated pattern starting with `!` refuses the match
+pub(crate) fn match_pattern_list<'a, I>(patterns: I, name: &str) -> bool
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if match_pattern(negated, name) {
+                return false;
+            }
+        } else if match_pattern(pattern, name) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// Match the host name against the pattern with the `*` and `?` wildcards
+fn match_pattern(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bn)) => {
+                    p = bp + 1;
+                    n = bn + 1;
+                    backtrack = Some((bp, bn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// An entry of the known_hosts file
+pub struct KnownHostEntry {
+    marker: Option<Marker>,
+    hosts: HostPatterns,
+    key: PublicKey,
+}
+
+impl KnownHostEntry {
+    /// Parse a line of the known_hosts file
+    pub fn parse(line: &str) -> OsshResult<Self> {
+        let mut fields = line.split_ascii_whitespace();
+        let mut field = fields.next().ok_or(ErrorKind::InvalidFormat)?;
+        let marker = match field {
+            "@cert-authority" => Some(Marker::CertAuthority),
+            "@revoked" => Some(Marker::Revoked),
+            _ if field.starts_with('@') => return Err(ErrorKind::InvalidFormat.into()),
+            _ => None,
+        };
+        if marker.is_some() {
+            field = fields.next().ok_or(ErrorKind::InvalidFormat)?;
+        }
+        let hosts = HostPatterns::parse(field)?;
+
+        let keyname = fields.next().ok_or(ErrorKind::InvalidFormat)?;
+        let blob = base64::decode(fields.next().ok_or(ErrorKind::InvalidFormat)?)?;
+        let mut key = PublicKey::from_blob(&blob)?;
+        if KeyType::from_str(keyname)? != key.keytype() {
+            return Err(ErrorKind::TypeNotMatch.into());
+        }
+        *key.comment_mut() = fields.collect::<Vec<_>>().join(" ");
+
+        Ok(KnownHostEntry { marker, hosts, key })
+    }
+
+    pub fn marker(&self) -> Option<Marker> {
+        self.marker
+    }
+
+    /// Check if the host patterns or the hashed host name match the name
+    ///
+    /// The name is `host` for the port 22, otherwise `[host]:port`.
+    pub fn matches_host(&self, name: &str) -> bool {
+        self.hosts.matches(&name.to_ascii_lowercase())
+    }
+
+    /// Check if the host names are hashed
+    pub fn is_hashed(&self) -> bool {
+        matches!(self.hosts, HostPatterns::Hashed { .. })
+    }
+
+    /// The host key, or the CA key for the `@cert-authority` entry
+    pub fn key(&self) -> &PublicKey {
+        &self.key
+    }
+}
+
+impl fmt::Debug for KnownHostEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KnownHostEntry")
+            .field("marker", &self.marker)
+            .field("hosts", &self.hosts)
+            .field("keytype", &self.key.keytype())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The host key presented by the server
+#[derive(Clone, Copy)]
+pub enum HostKey<'a> {
+    Key(&'a PublicKey),
+    Certificate(&'a Certificate),
+}
+
+impl<'a> From<&'a PublicKey> for HostKey<'a> {
+    fn from(key: &'a PublicKey) -> Self {
+        HostKey::Key(key)
+    }
+}
+
+impl<'a> From<&'a Certificate> for HostKey<'a> {
+    fn from(cert: &'a Certificate) -> Self {
+        HostKey::Certificate(cert)
+    }
+}
+
+/// The result of [`KnownHosts::verify_host()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostVerdict {
+    /// The key is listed for the host
+    Trusted,
+    /// The certificate is valid and signed by a CA which is trusted for the host
+    TrustedCertificate,
+    /// The key, the certified key or the CA is revoked
+    Revoked,
+    /// The certificate is signed by a trusted CA, but has the wrong type or principal, is expired or has a bad signature
+    InvalidCertificate,
+    /// The host is known with the other keys of the same type, which may be a man-in-the-middle attack
+    Changed,
+    /// The host is not known
+    Unknown,
+}
+
+/// The parsed known_hosts file
+///
+/// The entries with the unsupported key types are skipped.
+/// The malformed lines are skipped like OpenSSH does, and their errors are kept in [`KnownHosts::errors()`].
+#[derive(Debug, Default)]
+pub struct KnownHosts {
+    entries: Vec<KnownHostEntry>,
+    errors: Vec<(usize, Error)>,
+}
+
+impl KnownHosts {
+    /// Parse the content of the known_hosts file
+    pub fn parse(s: &str) -> OsshResult<Self> {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match KnownHostEntry::parse(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) if matches!(e.kind(), ErrorKind::UnsupportType(_)) => {}
+                Err(e) => errors.push((i + 1, e)),
+            }
+        }
+        Ok(KnownHosts { entries, errors })
+    }
+
+    pub fn entries(&self) -> &[KnownHostEntry] {
+        &self.entries
+    }
+
+    /// The line numbers, starting from 1, and the errors of the malformed lines skipped by [`KnownHosts::parse()`]
+    pub fn errors(&self) -> &[(usize, Error)] {
+        &self.errors
+    }
+
+    /// Add the key of the host, such as a pair from [`parse_keyscan()`]
+    ///
+    /// The host is the comma-separated patterns or the hashed host name of the known_hosts file.
+    pub fn add(&mut self, host: &str, key: PublicKey) -> OsshResult<()> {
+        if host.is_empty() || host.starts_with('@') || host.contains(char::is_whitespace) {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        self.entries.push(KnownHostEntry {
+            marker: None,
+            hosts: HostPatterns::parse(host)?,
+            key,
+        });
+        Ok(())
+    }
+
+    /// Verify the host key or the host certificate presented by the host
+    ///
+    /// The revoked keys are always refused first. The certificate is checked against the `@cert-authority` entries,
+    /// then its certified key is checked against the plain entries like a plain host key.
+    pub fn verify_host<'a, K: Into<HostKey<'a>>>(
+        &self,
+        host: &str,
+        port: u16,
+        key: K,
+    ) -> HostVerdict {
+        self.verify_host_at(host, port, key, unix_time())
+    }
+
+    /// Verify the host key like [`KnownHosts::verify_host()`],
+    /// with the time in seconds since the Unix epoch to check the validity period of the certificate
+    pub fn verify_host_at<'a, K: Into<HostKey<'a>>>(
+        &self,
+        host: &str,
+        port: u16,
+        key: K,
+        time: u64,
+    ) -> HostVerdict {
+        let (host, name) = host_names(host, port);
+        let (key, cert) = match key.into() {
+            HostKey::Key(key) => (key, None),
+            HostKey::Certificate(cert) => (cert.public_key(), Some(cert)),
+        };
+        let entries: Vec<&KnownHostEntry> = self
+            .entries
+            .iter()
+            .filter(|e| e.hosts.matches(&name))
+            .collect();
+
+        let revoked = entries.iter().any(|e| {
+            e.marker == Some(Marker::Revoked)
+                && (e.key == *key || cert.map_or(false, |c| e.key == *c.signature_key()))
+        });
+        if revoked {
+            return HostVerdict::Revoked;
+        }
+
+        let mut invalid_cert = false;
+        if let Some(cert) = cert {
+            let trusted_ca = entries.iter().find(|e| {
+                e.marker == Some(Marker::CertAuthority) && e.key == *cert.signature_key()
+            });
+            if let Some(ca) = trusted_ca {
+                // Check the principals and the critical options like HostCertVerifier
+                let mut verifier = HostCertVerifier::new();
+                let verdict = verifier
+                    .trust_ca(&ca.key)
+                    .and_then(|_| verifier.verify_at(cert, &host, time));
+                if let Ok(HostCertVerdict::Valid) = verdict {
+                    return HostVerdict::TrustedCertificate;
+                }
+                invalid_cert = true;
+            }
+        }
+
+        // The host is only known to have changed if it has a key of the same type, like OpenSSH
+        let mut known = false;
+        for entry in entries.iter().filter(|e| e.marker.is_none()) {
+            if entry.key == *key {
+                return HostVerdict::Trusted;
+            }
+            known |= same_key_type(&entry.key, key);
+        }
+        if invalid_cert {
+            HostVerdict::InvalidCertificate
+        } else if known {
+            HostVerdict::Changed
+        } else {
+            HostVerdict::Unknown
+        }
+    }
+}
+
+/// The detailed result of [`HostCertVerifier::verify()`]
+///
+/// The checks run in the order of the variants, and the first failure is returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostCertVerdict {
+    /// The certificate is valid for the host
+    Valid,
+    /// The certified key, the CA key or the serial number of the certificate is revoked
+    Revoked,
+    /// The CA key is not one of the trusted CA keys
+    UntrustedCa,
+    /// The signature of the CA is invalid
+    BadSignature,
+    /// The certificate is a user certificate
+    NotHostCertificate,
+    /// The validity period has not started yet
+    NotYetValid,
+    /// The validity period has ended
+    Expired,
+    /// The host name is not one of the principals
+    HostnameMismatch,
+    /// The certificate has a critical option, containing its name, which the host certificates never have
+    UnsupportedCriticalOption(String),
+}
+
+impl HostCertVerdict {
+    /// Check if the certificate is valid
+    pub fn is_valid(&self) -> bool {
+        *self == HostCertVerdict::Valid
+    }
+}
+
+/// Verify the host certificates presented to a client, like ssh does with the `@cert-authority` and `@revoked` entries
+///
+/// Unlike [`KnownHosts::verify_host()`], the trusted CAs and the revocations are given directly,
+/// and the verdict tells which check has failed.
+#[derive(Debug, Default)]
+pub struct HostCertVerifier {
+    trusted_cas: Vec<Vec<u8>>,
+    revoked_keys: Vec<Vec<u8>>,
+    revoked_serials: Vec<(Vec<u8>, u64)>,
+}
+
+impl HostCertVerifier {
+    /// Create a verifier without any trusted CA
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect the `@cert-authority` and `@revoked` entries matching the host from the known_hosts file
+    pub fn from_known_hosts(known_hosts: &KnownHosts, host: &str, port: u16) -> OsshResult<Self> {
+        let (_, name) = host_names(host, port);
+        let mut verifier = Self::new();
+        for entry in known_hosts
+            .entries
+            .iter()
+            .filter(|e| e.hosts.matches(&name))
+        {
+            match entry.marker {
+                Some(Marker::CertAuthority) => verifier.trust_ca(&entry.key)?,
+                Some(Marker::Revoked) => verifier.revoke_key(&entry.key)?,
+                None => {}
+            }
+        }
+        Ok(verifier)
+    }
+
+    /// Trust the CA key to sign the host certificates
+    pub fn trust_ca(&mut self, ca: &PublicKey) -> OsshResult<()> {
+        self.trusted_cas.push(ca.to_blob()?);
+        Ok(())
+    }
+
+    /// Revoke the key, which refuses the certificates of the key and the certificates signed by it
+    pub fn revoke_key(&mut self, key: &PublicKey) -> OsshResult<()> {
+        self.revoked_keys.push(key.to_blob()?);
+        Ok(())
+    }
+
+    /// Revoke the certificate with the serial number signed by the CA, like the serial sections of a KRL
+    pub fn revoke_serial(&mut self, ca: &PublicKey, serial: u64) -> OsshResult<()> {
+        self.revoked_serials.push((ca.to_blob()?, serial));
+        Ok(())
+    }
+
+    /// Verify the host certificate presented by the host with the current time
+    ///
+    /// The host is the name the client connects to, which is compared with the principals in lowercase.
+    /// The principals may have the `*` and `?` wildcards, which OpenSSH 8.5 and later accept for the host certificates.
+    pub fn verify(&self, cert: &Certificate, host: &str) -> OsshResult<HostCertVerdict> {
+        self.verify_at(cert, host, unix_time())
+    }
+
+    /// Verify the host certificate like [`HostCertVerifier::verify()`], with the time in seconds since the Unix epoch
+    pub fn verify_at(
+        &self,
+        cert: &Certificate,
+        host: &str,
+        time: u64,
+    ) -> OsshResult<HostCertVerdict> {
+        let key = cert.public_key().to_blob()?;
+        let ca = cert.signature_key().to_blob()?;
+        let revoked = self.revoked_keys.iter().any(|k| *k == key || *k == ca)
+            || self
+                .revoked_serials
+                .iter()
+                .any(|(k, serial)| *k == ca && *serial == cert.serial());
+        let verdict = if revoked {
+            HostCertVerdict::Revoked
+        } else if !self.trusted_cas.contains(&ca) {
+            HostCertVerdict::UntrustedCa
+        } else if !cert.verify_signature()? {
+            HostCertVerdict::BadSignature
+        } else if cert.cert_type() != CertType::Host {
+            HostCertVerdict::NotHostCertificate
+        } else if time < cert.valid_after() {
+            HostCertVerdict::NotYetValid
+        } else if time >= cert.valid_before() {
+            HostCertVerdict::Expired
+        } else if !cert
+            .valid_principals()
+            .iter()
+            .any(|p| match_pattern(&p.to_ascii_lowercase(), &host.to_ascii_lowercase()))
+        {
+            HostCertVerdict::HostnameMismatch
+        } else if let Some((name, _)) = cert.critical_options().first() {
+            HostCertVerdict::UnsupportedCriticalOption(name.clone())
+        } else {
+            HostCertVerdict::Valid
+        };
+        Ok(verdict)
+    }
+}
+
+/// Check if the keys have the same type, where the EcDSA keys of the different curves are the different types
+fn same_key_type(a: &PublicKey, b: &PublicKey) -> bool {
+    if a.keytype() != b.keytype() {
+        return false;
+    }
+    #[cfg(feature = "ecdsa")]
+    if a.keytype() == KeyType::ECDSA {
+        return Key::size(a) == Key::size(b);
+    }
+    true
+}
+
+/// Get the lowercase host name and the name looked up in the known_hosts file, which has the port if it's not 22
+fn host_names(host: &str, port: u16) -> (String, String) {
+    let host = host.to_ascii_lowercase();
+    let name = if port == SSH_DEFAULT_PORT {
+        host.clone()
+    } else {
+        format!("[{}]:{}", host, port)
+    };
+    (host, name)
+}
+
+/// Parse the output of ssh-keyscan into the pairs of the host and the key
+///
+/// The host is `host` for the port 22, otherwise `[host]:port`, or the hashed host name of `ssh-keyscan -H`.
+/// The comment lines, such as the server banners, and the keys of the unsupported types are skipped.
+pub fn parse_keyscan(s: &str) -> OsshResult<Vec<(String, PublicKey)>> {
+    let mut keys = Vec::new();
+    for line in s.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = match KnownHostEntry::parse(line) {
+            Ok(entry) => entry,
+            Err(e) if matches!(e.kind(), ErrorKind::UnsupportType(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        if entry.marker.is_some() {
+            return Err(ErrorKind::InvalidFormat.into());
+        }
+        let host = line.split_ascii_whitespace().next().unwrap_or_default();
+        keys.push((host.to_owned(), entry.key));
+    }
+    Ok(keys)
+}
+
+impl FromStr for KnownHosts {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::cert::CertificateFields;
+    #[cfg(not(feature = "fips"))]
+    use crate::keys::rsa::RsaSignature;
+    use crate::keys::{KeyPair, PublicParts};
+    use crate::sshbuf::SshWriteExt;
+    use std::fs;
+
+    fn read_file(path: &str) -> String {
+        fs::read_to_string(path).unwrap().trim().to_string()
+    }
+
+    fn read_pubkey(path: &str) -> PublicKey {
+        PublicKey::from_keystr(&read_file(path)).unwrap()
+    }
+
+    #[cfg(not(feature = "fips"))]
+    fn read_cert(path: &str) -> Certificate {
+        Certificate::from_keystr(&read_file(path)).unwrap()
+    }
+
+    #[test]
+    fn host_pattern_match() {
+        assert!(match_pattern("*.example.com", "www.example.com"));
+        assert!(!match_pattern("*.example.com", "example.com"));
+        assert!(match_pattern("192.0.2.?", "192.0.2.1"));
+        assert!(!match_pattern("192.0.2.?", "192.0.2.10"));
+        assert!(match_pattern("*", "[example.com]:2222"));
+        assert!(match_pattern("a*b*c", "aXXbYYbc"));
+
+        let patterns = HostPatterns::parse("*.example.com,!bad.example.com").unwrap();
+        assert!(patterns.matches("good.example.com"));
+        assert!(!patterns.matches("bad.example.com"));
+        assert!(!patterns.matches("example.org"));
+    }
+
+    #[test]
+    fn known_hosts_skip_malformed_lines() {
+        let known_hosts = KnownHosts::parse(&format!(
+            "bad.example.com ssh-ed25519 !!!notbase64\n\
+             example.com {}\n\
+             mismatch.example.com ssh-rsa {}\n",
+            read_file("assets/openssh_ecdsa.pub"),
+            read_file("assets/openssh_ed25519.pub")
+                .split_whitespace()
+                .nth(1)
+                .unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(known_hosts.entries().len(), 1);
+        let lines: Vec<usize> = known_hosts.errors().iter().map(|(i, _)| *i).collect();
+        assert_eq!(lines, [1, 3]);
+        assert_eq!(
+            known_hosts.verify_host("example.com", 22, &read_pubkey("assets/openssh_ecdsa.pub")),
+            HostVerdict::Trusted
+        );
+    }
+
+    #[test]
+    fn known_hosts_plain_and_hashed() {
+        let known_hosts = KnownHosts::parse(&format!(
+            "# comment\n\
+             example.com,192.0.2.1 {}\n\
+             |1|jhVjbhxJl+Sn+C8xwBgEd/8ce00=|2SWtFsX0Eyco6HgU8V/JOx2aETY= {}\n\
+             |1|gdesliy6TKwrp3N1UwS4+Z0Kmk0=|DH4OXZAxqBIAznCMaZTv+gqre8w= {}\n\
+             example.net sk-ssh-ed25519@openssh.com AAAAGnNrLXNzaC1lZDI1NTE5QG9wZW5zc2guY29tAAAAIA== x\n",
+            read_file("assets/openssh_ecdsa.pub"),
+            read_file("assets/openssh_ed25519.pub"),
+            read_file("assets/openssh_rsa.pub"),
+        ))
+        .unwrap();
+        assert_eq!(known_hosts.entries().len(), 3);
+        assert!(known_hosts.entries()[1].is_hashed());
+        assert!(known_hosts.errors().is_empty());
+
+        let ecdsa = read_pubkey("assets/openssh_ecdsa.pub");
+        let ed25519 = read_pubkey("assets/openssh_ed25519.pub");
+        let rsa = read_pubkey("assets/openssh_rsa.pub");
+        assert_eq!(
+            known_hosts.verify_host("EXAMPLE.com", 22, &ecdsa),
+            HostVerdict::Trusted
+        );
+        // The host known only with the other key types hasn't seen this type
+        assert_eq!(
+            known_hosts.verify_host("192.0.2.1", 22, &ed25519),
+            HostVerdict::Unknown
+        );
+        let other_ecdsa = KeyPair::generate(KeyType::ECDSA, 256)
+            .unwrap()
+            .clone_public_key()
+            .unwrap();
+        assert_eq!(
+            known_hosts.verify_host("192.0.2.1", 22, &other_ecdsa),
+            HostVerdict::Changed
+        );
+        let p384 = KeyPair::generate(KeyType::ECDSA, 384)
+            .unwrap()
+            .clone_public_key()
+            .unwrap();
+        assert_eq!(
+            known_hosts.verify_host("192.0.2.1", 22, &p384),
+            HostVerdict::Unknown
+        );
+        assert_eq!(
+            known_hosts.verify_host("example.org", 22, &ed25519),
+            HostVerdict::Trusted
+        );
+        assert_eq!(
+            known_hosts.verify_host("example.org", 2222, &rsa),
+            HostVerdict::Trusted
+        );
+        assert_eq!(
+            known_hosts.verify_host("example.org", 2222, &ed25519),
+            HostVerdict::Unknown
+        );
+        assert_eq!(
+            known_hosts.verify_host("example.net", 22, &ed25519),
+            HostVerdict::Unknown
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn known_hosts_cert_authority_and_revoked() {
+        let ca = read_file("assets/ca_ed25519.pub");
+        let cert = read_cert("assets/openssh_ecdsa-cert.pub");
+        let known_hosts =
+            KnownHosts::parse(&format!("@cert-authority *.com,192.0.2.* {}\n", ca)).unwrap();
+        assert_eq!(
+            known_hosts.entries()[0].marker(),
+            Some(Marker::CertAuthority)
+        );
+        assert_eq!(
+            known_hosts.verify_host("example.com", 22, &cert),
+            HostVerdict::TrustedCertificate
+        );
+        assert_eq!(
+            known_hosts.verify_host("192.0.2.1", 2222, &cert),
+            HostVerdict::Unknown
+        );
+        // The principal doesn't match the host
+        assert_eq!(
+            known_hosts.verify_host("other.com", 22, &cert),
+            HostVerdict::InvalidCertificate
+        );
+        // The certified key is not trusted without the certificate
+        assert_eq!(
+            known_hosts.verify_host("example.com", 22, cert.public_key()),
+            HostVerdict::Unknown
+        );
+
+        // Signed by another CA
+        let rsa_cert = read_cert("assets/openssh_rsa-cert.pub");
+        assert_eq!(
+            known_hosts.verify_host("example.com", 22, &rsa_cert),
+            HostVerdict::Unknown
+        );
+
+        let revoked = KnownHosts::parse(&format!(
+            "@cert-authority * {}\n@revoked * {}\n",
+            ca,
+            read_file("assets/openssh_ecdsa.pub")
+        ))
+        .unwrap();
+        assert_eq!(
+            revoked.verify_host("example.com", 22, &cert),
+            HostVerdict::Revoked
+        );
+        let revoked_ca = KnownHosts::parse(&format!("@revoked * {}\n", ca)).unwrap();
+        assert_eq!(
+            revoked_ca.verify_host("example.com", 22, &cert),
+            HostVerdict::Revoked
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn host_cert_verifier() {
+        let ca = KeyPair::from_keystr(&read_file("assets/ca_ed25519"), None).unwrap();
+        let ca_pub = ca.clone_public_key().unwrap();
+        let key = read_pubkey("assets/openssh_ed25519.pub");
+        let fields = CertificateFields {
+            serial: 7,
+            cert_type: CertType::Host,
+            valid_principals: vec!["example.com".into()],
+            valid_after: 1000,
+            valid_before: 2000,
+            ..Default::default()
+        };
+        let sign = |fields: &CertificateFields| {
+            Certificate::sign(&key, fields, &ca, RsaSignature::SHA2_512).unwrap()
+        };
+        let cert = sign(&fields);
+
+        let mut verifier = HostCertVerifier::new();
+        let verdict = |verifier: &HostCertVerifier, cert, host, time| {
+            verifier.verify_at(cert, host, time).unwrap()
+        };
+        assert_eq!(
+            verdict(&verifier, &cert, "example.com", 1500),
+            HostCertVerdict::UntrustedCa
+        );
+        verifier.trust_ca(&ca_pub).unwrap();
+        assert!(verdict(&verifier, &cert, "Example.COM", 1500).is_valid());
+        assert_eq!(
+            verdict(&verifier, &cert, "example.com", 999),
+            HostCertVerdict::NotYetValid
+        );
+        assert_eq!(
+            verdict(&verifier, &cert, "example.com", 2000),
+            HostCertVerdict::Expired
+        );
+        assert_eq!(
+            verdict(&verifier, &cert, "example.org", 1500),
+            HostCertVerdict::HostnameMismatch
+        );
+        let user_cert = sign(&CertificateFields {
+            cert_type: CertType::User,
+            ..fields.clone()
+        });
+        assert_eq!(
+            verdict(&verifier, &user_cert, "example.com", 1500),
+            HostCertVerdict::NotHostCertificate
+        );
+        let forced = sign(&CertificateFields {
+            critical_options: vec![("force-command".into(), "true".into())],
+            ..fields.clone()
+        });
+        assert_eq!(
+            verdict(&verifier, &forced, "example.com", 1500),
+            HostCertVerdict::UnsupportedCriticalOption("force-command".into())
+        );
+        let wildcard = sign(&CertificateFields {
+            valid_principals: vec!["Host-?.EXAMPLE.com".into(), "*.example.net".into()],
+            ..fields.clone()
+        });
+        assert!(verdict(&verifier, &wildcard, "host-1.example.com", 1500).is_valid());
+        assert!(verdict(&verifier, &wildcard, "WWW.example.net", 1500).is_valid());
+        assert_eq!(
+            verdict(&verifier, &wildcard, "host-10.example.com", 1500),
+            HostCertVerdict::HostnameMismatch
+        );
+
+        verifier.revoke_serial(&ca_pub, 8).unwrap();
+        assert!(verdict(&verifier, &cert, "example.com", 1500).is_valid());
+        verifier.revoke_serial(&ca_pub, 7).unwrap();
+        assert_eq!(
+            verdict(&verifier, &cert, "example.com", 1500),
+            HostCertVerdict::Revoked
+        );
+
+        let known_hosts = KnownHosts::parse(&format!(
+            "@cert-authority *.com {}\n@revoked other.com {}\n",
+            read_file("assets/ca_ed25519.pub"),
+            read_file("assets/openssh_ed25519.pub"),
+        ))
+        .unwrap();
+        let verifier = HostCertVerifier::from_known_hosts(&known_hosts, "example.com", 22).unwrap();
+        assert!(verdict(&verifier, &cert, "example.com", 1500).is_valid());
+        let verifier = HostCertVerifier::from_known_hosts(&known_hosts, "other.com", 22).unwrap();
+        assert_eq!(
+            verdict(&verifier, &cert, "other.com", 1500),
+            HostCertVerdict::Revoked
+        );
+        let verifier =
+            HostCertVerifier::from_known_hosts(&known_hosts, "example.com", 2222).unwrap();
+        assert_eq!(
+            verdict(&verifier, &cert, "example.com", 1500),
+            HostCertVerdict::UntrustedCa
+        );
+
+        // The known hosts check the principals and the critical options like the verifier
+        let known_hosts = KnownHosts::parse(&format!(
+            "@cert-authority * {}\n",
+            read_file("assets/ca_ed25519.pub")
+        ))
+        .unwrap();
+        assert_eq!(
+            known_hosts.verify_host_at("Host-1.Example.com", 22, &wildcard, 1500),
+            HostVerdict::TrustedCertificate
+        );
+        assert_eq!(
+            known_hosts.verify_host_at("example.com", 22, &forced, 1500),
+            HostVerdict::InvalidCertificate
+        );
+    }
+
+    #[test]
+    fn known_hosts_invalid_line() {
+        for line in [
+            "example.com ssh-ed25519\n",
+            "@unknown example.com ssh-ed25519 AAAA\n",
+        ] {
+            let known_hosts = KnownHosts::parse(line).unwrap();
+            assert!(known_hosts.entries().is_empty());
+            assert_eq!(known_hosts.errors().len(), 1);
+            assert_eq!(known_hosts.errors()[0].0, 1);
+        }
+    }
+
+    #[test]
+    fn keyscan_output() {
+        let ed25519 = read_pubkey("assets/openssh_ed25519.pub");
+        let rsa = read_pubkey("assets/openssh_rsa.pub");
+        let ecdsa = read_pubkey("assets/openssh_ecdsa.pub");
+        let blob = |key: &PublicKey| base64::encode(key.blob().unwrap());
+        let mut unsupported = Vec::new();
+        unsupported
+            .write_utf8("sk-ssh-ed25519@openssh.com")
+            .unwrap();
+        let output = format!(
+            "# example.com:22 SSH-2.0-OpenSSH_9.6\n\
+             example.com ssh-ed25519 {}\n\
+             example.com sk-ssh-ed25519@openssh.com {}\n\
+             # example.com:2222 SSH-2.0-OpenSSH_9.6\n\
+             [example.com]:2222 ssh-rsa {}\r\n\
+             |1|JfKTdBh7rNbXkVAQCRp4OQoPfmI=|USECr3SWf1JUPsms5AqfD5QfxkM= ecdsa-sha2-nistp256 {}\n",
+            blob(&ed25519),
+            base64::encode(&unsupported),
+            blob(&rsa),
+            blob(&ecdsa)
+        );
+        let keys = parse_keyscan(&output).unwrap();
+        assert_eq!(
+            keys.iter()
+                .map(|(host, _)| host.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "example.com",
+                "[example.com]:2222",
+                "|1|JfKTdBh7rNbXkVAQCRp4OQoPfmI=|USECr3SWf1JUPsms5AqfD5QfxkM="
+            ]
+        );
+        assert!(keys[0].1 == ed25519);
+        assert!(keys[1].1 == rsa);
+        assert!(keys[2].1 == ecdsa);
+
+        let mut known_hosts = KnownHosts::default();
+        for (host, key) in keys {
+            known_hosts.add(&host, key).unwrap();
+        }
+        assert_eq!(
+            known_hosts.verify_host("example.com", 22, &ed25519),
+            HostVerdict::Trusted
+        );
+        assert_eq!(
+            known_hosts.verify_host("example.com", 2222, &rsa),
+            HostVerdict::Trusted
+        );
+        assert!(known_hosts.entries()[2].is_hashed());
+        assert!(known_hosts.add("", ed25519).is_err());
+
+        assert!(parse_keyscan("example.com ssh-ed25519\n").is_err());
+        assert!(parse_keyscan(&format!("@revoked example.com ssh-rsa {}", blob(&rsa))).is_err());
+    }
+}
@@ -22,12 +22,46 @@
 //! - `openssl-cipher`: [default] Using OpenSSL as symmetric cipher
 //! - `rustcrypto-cipher`: Using RustCrypto as symmetric cipher
 //!     - Choicing this does not remove the `openssl` dependency, since many places still require to use OpenSSL.
+//! - `rsa`, `dsa`, `ecdsa`, `ed25519`: [default] The key types
+//!     - Disable the default features to compile out the unused key types, at least one of them is required.
+//!       The key names and `EcCurve` stay available, while the variants of the key enums are removed.
+//! - `aes-cbc`, `3des-cbc`: [default] The CBC ciphers of the encrypted keys, AES-CTR is always available
+//!     - The traditional PEM keys can only be encrypted with `aes-cbc`.
+//! - `legacy-des`: Decrypt the traditional PEM keys encrypted with `DES-CBC`, which OpenSSL 3 refuses without its legacy provider
+//!     - Single DES is broken, so only use it to read the old keys once and convert them.
 //! - `openssl-vendored`: Build with `openssl/vendored` feature
+//! - `serde`: Serialize/Deserialize the keys with serde
+//! - `tokio`: Generate and parse keys on the tokio blocking thread pool
+//! - `cli`: Build the `osshkeys` command line tool to generate, convert, fingerprint and inspect keys
+//! - `tracing`: Emit [tracing](https://docs.rs/tracing) spans for parsing, KDF, decryption, key generation and signing
+//!     - The key material and the passphrases are never recorded.
+//! - `memsec`: Store the private keys in page-locked and guarded memory
+//! - `simd-base64`: Encode and decode base64 with the SIMD instructions, which speeds up parsing and fingerprinting many keys
+//! - `ffi`: Export the C API in the [`ffi`] module to build a shared library
+//! - `uniffi`: Export the [UniFFI](https://mozilla.github.io/uniffi-rs/) bindings in [`keys::uniffi_support`] for Kotlin, Swift and Python
+//!     - Build the shared library like the `ffi` feature,
+//!       then generate the bindings with `uniffi-bindgen generate --library <path of the library>`.
+//! - `keychain`: Store the keys and the passphrases in the macOS Keychain, the Windows Credential Manager or the Secret Service
+//! - `ed25519-batch`: Verify many Ed25519 signatures at once with [`keys::ed25519::verify_batch()`]
+//! - `openpgp`: Export and import the RSA and Ed25519 key pairs as the OpenPGP secret keys with [`KeyPair::serialize_openpgp()`] and [`KeyPair::from_openpgp()`]
+//! - `miette`: Implement [`miette::Diagnostic`](https://docs.rs/miette) for [`Error`](error::Error), labeling the offending part of the key text
+//!     - The key text is not kept in the error, attach it with `miette::Report::new(err).with_source_code(keystr)`.
+//! - `rayon`: Compute the fingerprints and check the strength of large key sets in parallel in the [`audit`] module
+//! - `num-bigint`: Convert [`sshbuf::Mpint`] from and to [`num_bigint::BigUint`](https://docs.rs/num-bigint)
+//! - `full-debug`: Print the private components in the `Debug` output of the key pairs
+//!     - The key pairs only print their type, size and fingerprint otherwise. Never enable it in the release builds.
+//!
+//! # Malformed Input
+//! Parsing the keys, the certificates, the signatures and the other SSH encoded data never panics on malformed input,
+//! but fails with an [`Error`](error::Error) instead. The length fields are checked against the remaining data
+//! without overflowing, the buffers only grow with the data actually read, and the decoders don't recurse.
+//! The inputs found by fuzzing are kept as the regression tests.
 //!
 //! # Example
 //! ```rust
 //! # #![allow(unused)]
 //! # #[macro_use] extern crate hex_literal;
+//! # #[cfg(all(feature = "ed25519", not(feature = "fips")))] {
 //! use osshkeys::{KeyPair, KeyType, Key as _, PublicParts as _, PrivateParts as _};
 //! use osshkeys::keys::FingerprintHash;
 //!
@@ -49,19 +83,102 @@
 //!
 //! assert_eq!(sign.as_slice(), hex!("7206f04ef062ec35f8fb9f9e8a17ec023070ecf5f6e1021ea2af73137b1b832bba08766e5ad95fdca81af37b27898428f9a7dbeb044dd550afeb46efb94fe808").as_ref());
 //! assert!(publickey.verify(SOME_DATA, &sign).unwrap());
+//! # }
 //! ```
 
+/// Scan the key files for the weak, blacklisted and expiring keys,
+/// and compute the fingerprints and check the strength of large key sets in parallel (Feature `rayon`)
+pub mod audit;
+/// The crypto backend selected by the features `openssl-cipher` and `rustcrypto-cipher`
+mod backend;
+/// A small SSH certificate authority issuing the certificates with a policy and persistent serial numbers
+pub mod ca;
+/// The key types, the ciphers and the formats supported by this build
+pub mod capabilities;
 /// Containing the encrypt/decrypt algorithm
 pub mod cipher;
+/// The defaults of the key generation and the private key encryption
+pub mod defaults;
 /// Containing the error type of this crate
 pub mod error;
+/// The C API of this crate
+///
+/// Build the shared library with `cargo rustc --release --features ffi --crate-type cdylib`.
+/// The C header `include/osshkeys.h` is generated by [cbindgen](https://github.com/mozilla/cbindgen)
+/// with `cbindgen --config cbindgen.toml --output include/osshkeys.h`.
+///
+/// All the functions return an [`ffi::OsshStatus`], and the message of the last error is available from [`ffi::ossh_last_error()`].
+/// The objects returned by the functions are owned by the caller, and must be freed by the corresponding `*_free()` function.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Restrictions of the FIPS 140 mode (Feature `fips`)
+///
+/// With this feature, the algorithms and the operations which are not FIPS approved fail with
+/// [`ErrorKind::FipsNotApproved`](error::ErrorKind::FipsNotApproved), including Ed25519 keys, DSA key generation and signing,
+/// RSA keys smaller than 2048 bits, `ssh-rsa` (SHA-1) signing, the bcrypt KDF of the encrypted OpenSSH keys,
+/// 3DES encryption and the key generation with a custom random number generator.
+/// The crypto operations should also run in the OpenSSL FIPS provider, see [`fips::load_openssl_fips_provider()`].
+#[cfg(feature = "fips")]
+pub mod fips;
 /// Serialize/Deserialize key files
 pub mod format;
+/// The keygrips and the sshcontrol file of the ssh-agent emulation of gpg-agent
+///
+/// This crate has no ssh-agent client, so the quirks of gpg-agent in the agent protocol,
+/// such as its extensions and the comments of the identities, are left to the agent client.
+/// The private keys are still added to gpg-agent by the agent client like `ssh-add`.
+pub mod gpg_agent;
+/// The hooks reporting the usage of the private keys for the audit trails
+pub mod hooks;
+/// Generate and store the standard set of host keys
+pub mod hostkeys;
 /// Representing different types of public/private keys
 pub mod keys;
+/// Store the private keys and the passphrases in the OS keychain (Feature `keychain`)
+#[cfg(feature = "keychain")]
+pub mod keychain;
+/// An in-memory keyring of the unlocked keys with the usage constraints, the building block of an ssh-agent
+pub mod keyring;
+/// A passphrase protected file holding many named keys
+pub mod keystore;
+/// Verify the host keys and the host certificates with the known_hosts file
+pub mod known_hosts;
+/// Page-locked memory for the private keys
+#[cfg(feature = "memsec")]
+pub mod locked;
+/// Generate and screen the Diffie-Hellman group moduli of the `/etc/ssh/moduli` file
+pub mod moduli;
+/// Crypto policy to restrict the keys, the key encryption and the signatures
+pub mod policy;
+/// Locate the keys which ssh uses for a host with the ssh_config file
+pub mod ssh_config;
 /// Extension to read/write ssh data type representations defined in [RFC 4251](https://tools.ietf.org/html/rfc4251#section-5)
+///
+/// These are the primitives used by the key formats of this crate,
+/// which can also be used to encode and decode the other SSH structures.
+///
+/// ```rust
+/// use osshkeys::sshbuf::{SshReadExt, SshWriteExt};
+/// use std::io::Cursor;
+///
+/// let mut buf = Vec::new();
+/// buf.write_utf8("ssh-ed25519").unwrap();
+/// buf.write_uint32(42).unwrap();
+/// buf.write_list(["zlib", "none"]).unwrap();
+///
+/// let mut reader = Cursor::new(buf);
+/// assert_eq!(reader.read_utf8().unwrap(), "ssh-ed25519");
+/// assert_eq!(reader.read_uint32().unwrap(), 42);
+/// assert_eq!(reader.read_list::<Vec<String>>().unwrap(), ["zlib", "none"]);
+/// ```
 pub mod sshbuf;
+/// Sign and verify the data with the SSH signatures of `ssh-keygen -Y` (SSHSIG)
+pub mod sshsig;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
+pub use capabilities::capabilities;
 pub use keys::Key;
 pub use keys::KeyPair;
 pub use keys::KeyType;
@@ -0,0 +1,204 @@
+use crate::cipher::Cipher;
+use crate::error::*;
+use crate::format::ossh_privkey::{decode_ossh_priv, encode_ossh_priv};
+use crate::keys::{KeyPair, KeyType, PrivateParts, PublicKey};
+use memsec::Prot;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// A fixed size buffer in a page-locked and guarded allocation
+///
+/// The memory is allocated by [`memsec::malloc_sized()`], which surrounds it with guard pages
+/// and locks it in RAM with `mlock()`, so it won't be swapped out.
+/// The pages are inaccessible except inside [`LockedBuffer::with_bytes()`],
+/// and the memory is wiped before it is freed.
+///
+/// Locking the pages may fail silently if the limit of the locked memory (`RLIMIT_MEMLOCK`) is reached.
+pub struct LockedBuffer {
+    ptr: NonNull<[u8]>,
+    len: usize,
+    // The number of the running accessors
+    readers: Mutex<usize>,
+}
+
+// The memory is exclusively owned and only read through `with_bytes()`
+unsafe impl Send for LockedBuffer {}
+unsafe impl Sync for LockedBuffer {}
+
+impl LockedBuffer {
+    /// Copy the data into a new locked buffer
+    pub fn from_slice(data: &[u8]) -> OsshResult<Self> {
+        // memsec aborts on zero sized allocations, always allocate at least one byte
+        let ptr = unsafe { memsec::malloc_sized(data.len().max(1)) }
+            .ok_or_else(|| Error::from_kind(ErrorKind::Unknown))?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), ptr.as_ptr() as *mut u8, data.len());
+            memsec::mprotect(ptr, Prot::NoAccess);
+        }
+        Ok(LockedBuffer {
+            ptr,
+            len: data.len(),
+            readers: Mutex::new(0),
+        })
+    }
+
+    /// Get the length of the data
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Make the data readable and pass it to the closure
+    ///
+    /// The pages become inaccessible again after the last running closure returns.
+    pub fn with_bytes<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        {
+            let mut readers = self.readers.lock().unwrap_or_else(|e| e.into_inner());
+            if *readers == 0 {
+                unsafe { memsec::mprotect(self.ptr, Prot::ReadOnly) };
+            }
+            *readers += 1;
+        }
+        // Restore the protection even if the closure panics
+        let _guard = ReadGuard(self);
+        let data = unsafe { std::slice::from_raw_parts(self.ptr.as_ptr() as *const u8, self.len) };
+        f(data)
+    }
+}
+
+struct ReadGuard<'a>(&'a LockedBuffer);
+
+impl Drop for ReadGuard<'_> {
+    fn drop(&mut self) {
+        let mut readers = self.0.readers.lock().unwrap_or_else(|e| e.into_inner());
+        *readers -= 1;
+        if *readers == 0 {
+            unsafe { memsec::mprotect(self.0.ptr, Prot::NoAccess) };
+        }
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            memsec::mprotect(self.ptr, Prot::ReadWrite);
+            memsec::memzero(self.ptr.as_ptr() as *mut u8, self.len);
+            memsec::free(self.ptr);
+        }
+    }
+}
+
+impl fmt::Debug for LockedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LockedBuffer")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A key pair whose private parts are stored in a [`LockedBuffer`]
+///
+/// The private key is kept in the unencrypted OpenSSH format inside the locked memory,
+/// and is only decoded into a [`KeyPair`] temporarily for each operation.
+/// It is intended for long-running processes, such as agents and CA daemons,
+/// which hold the private keys for a long time.
+pub struct LockedKeyPair {
+    publickey: PublicKey,
+    privkey: LockedBuffer,
+}
+
+impl LockedKeyPair {
+    /// Move the key pair into the locked memory
+    ///
+    /// The given key pair is wiped when it is dropped at the end of this function.
+    pub fn new(keypair: KeyPair) -> OsshResult<Self> {
+        let publickey = keypair.clone_public_key()?;
+        let encoded = Zeroizing::new(encode_ossh_priv(&keypair, "", Cipher::Null, 0)?);
+        Ok(LockedKeyPair {
+            publickey,
+            privkey: LockedBuffer::from_slice(&encoded)?,
+        })
+    }
+
+    /// Get the type of the key
+    pub fn keytype(&self) -> KeyType {
+        self.publickey.keytype()
+    }
+
+    /// Get the public key, the comment is also included
+    pub fn public_key(&self) -> &PublicKey {
+        &self.publickey
+    }
+
+    /// Replace the comment of the key
+    ///
+    /// The key pair in the locked memory is replaced too, so the decoded key pair also has the new comment.
+    pub fn set_comment(&mut self, comment: &str) -> OsshResult<()> {
+        let mut keypair = self
+            .privkey
+            .with_bytes(|data| decode_ossh_priv(data, None))?;
+        keypair.set_comment(comment);
+        *self = Self::new(keypair)?;
+        Ok(())
+    }
+
+    /// Decode the key pair temporarily and pass it to the closure
+    ///
+    /// The decoded key pair is wiped after the closure returns.
+    pub fn with_keypair<R, F: FnOnce(&KeyPair) -> R>(&self, f: F) -> OsshResult<R> {
+        let keypair = self
+            .privkey
+            .with_bytes(|data| decode_ossh_priv(data, None))?;
+        Ok(f(&keypair))
+    }
+
+    /// Sign the data with the key pair
+    pub fn sign(&self, data: &[u8]) -> OsshResult<Vec<u8>> {
+        self.with_keypair(|keypair| keypair.sign(data))?
+    }
+}
+
+impl fmt::Debug for LockedKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LockedKeyPair")
+            .field("keytype", &self.keytype())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "ed25519"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locked_buffer_access() {
+        let buf = LockedBuffer::from_slice(b"secret data").unwrap();
+        assert_eq!(buf.len(), 11);
+        buf.with_bytes(|data| {
+            // Nested access keeps the pages readable
+            buf.with_bytes(|inner| assert_eq!(inner, data));
+            assert_eq!(data, b"secret data");
+        });
+
+        let empty = LockedBuffer::from_slice(&[]).unwrap();
+        assert!(empty.is_empty());
+        empty.with_bytes(|data| assert!(data.is_empty()));
+    }
+
+    #[test]
+    fn locked_keypair_set_comment() {
+        let keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+        let mut locked = LockedKeyPair::new(keypair).unwrap();
+        locked.set_comment("locked comment").unwrap();
+        assert_eq!(locked.public_key().comment(), "locked comment");
+        locked
+            .with_keypair(|keypair| assert_eq!(keypair.comment(), "locked comment"))
+            .unwrap();
+    }
+}
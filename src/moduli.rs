@@ -0,0 +1,455 @@
+use crate::error::*;
+use crate::keys::cert::civil_from_days;
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use rand_core::CryptoRngCore;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// The type of the modulus is unknown
+pub const MODULI_TYPE_UNKNOWN: u32 = 0;
+/// The modulus is a safe prime `p = 2q + 1`
+pub const MODULI_TYPE_SAFE: u32 = 2;
+/// The modulus is a Sophie Germain prime candidate `q`, where `2q + 1` may be a safe prime
+pub const MODULI_TYPE_SOPHIE_GERMAIN: u32 = 4;
+
+/// The modulus hasn't been tested
+pub const MODULI_TESTS_UNTESTED: u32 = 0x00;
+/// The modulus is known to be composite
+pub const MODULI_TESTS_COMPOSITE: u32 = 0x01;
+/// The modulus has passed the sieve of small primes
+pub const MODULI_TESTS_SIEVE: u32 = 0x02;
+/// The modulus has passed the Miller-Rabin primality test
+pub const MODULI_TESTS_MILLER_RABIN: u32 = 0x04;
+
+/// The default number of the primality test rounds used by `ssh-keygen -M screen`
+pub const DEFAULT_TRIALS: u32 = 100;
+
+const SMALL_PRIMES_LIMIT: u32 = 4096;
+
+/// An entry (line) of the `/etc/ssh/moduli` file
+///
+/// The line consists of the following space separated fields:
+/// `timestamp type tests trials size generator modulus`,
+/// where `size` is the bit length of the modulus minus one,
+/// and `modulus` is an uppercase hexadecimal number.
+#[derive(Debug, PartialEq)]
+pub struct ModuliEntry {
+    /// The UTC time of the entry in `YYYYMMDDHHMMSS` format
+    pub timestamp: String,
+    /// The type of the modulus, such as [`MODULI_TYPE_SAFE`]
+    pub modtype: u32,
+    /// The bit mask of the tests passed, such as [`MODULI_TESTS_MILLER_RABIN`]
+    pub tests: u32,
+    /// The number of the primality test rounds
+    pub trials: u32,
+    /// The bit length of the modulus minus one
+    pub size: u32,
+    /// The generator of the group, `0` if unknown
+    pub generator: u32,
+    /// The modulus
+    pub modulus: BigNum,
+}
+
+impl ModuliEntry {
+    fn new(
+        modtype: u32,
+        tests: u32,
+        trials: u32,
+        size: u32,
+        generator: u32,
+        modulus: BigNum,
+    ) -> Self {
+        ModuliEntry {
+            timestamp: utc_timestamp(),
+            modtype,
+            tests,
+            trials,
+            size,
+            generator,
+            modulus,
+        }
+    }
+}
+
+impl FromStr for ModuliEntry {
+    type Err = Error;
+
+    fn from_str(s: &str) -> OsshResult<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 7 {
+            return Err(ErrorKind::InvalidFormat.into());
+        }
+        let timestamp = fields[0];
+        if timestamp.len() != 14 || !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ErrorKind::InvalidFormat.into());
+        }
+        let number = |s: &str| {
+            s.parse::<u32>()
+                .map_err(|e| Error::with_error(ErrorKind::InvalidFormat, e))
+        };
+        if !fields[6].bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ErrorKind::InvalidFormat.into());
+        }
+
+        Ok(ModuliEntry {
+            timestamp: timestamp.to_owned(),
+            modtype: number(fields[1])?,
+            tests: number(fields[2])?,
+            trials: number(fields[3])?,
+            size: number(fields[4])?,
+            generator: number(fields[5])?,
+            modulus: BigNum::from_hex_str(fields[6])?,
+        })
+    }
+}
+
+impl fmt::Display for ModuliEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modulus = self.modulus.to_hex_str().map_err(|_| fmt::Error)?;
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            self.timestamp,
+            self.modtype,
+            self.tests,
+            self.trials,
+            self.size,
+            self.generator,
+            &*modulus
+        )
+    }
+}
+
+/// Parse the content of a moduli file
+///
+/// Empty lines and comments starting with `#` are skipped.
+pub fn parse_moduli(s: &str) -> OsshResult<Vec<ModuliEntry>> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ModuliEntry::from_str)
+        .collect()
+}
+
+/// Generate the candidates of the safe prime moduli with the given bit length, like `ssh-keygen -M generate`
+///
+/// The candidates are the Sophie Germain prime candidates `q` of `bits - 1` bits, where both `q` and
+/// `2q + 1` have no small prime factors. They should be screened by [`screen_candidates()`].
+///
+/// The bits should be at least `2048` for real use.
+pub fn generate_candidates<R: CryptoRngCore + ?Sized>(
+    bits: u32,
+    count: usize,
+    rng: &mut R,
+) -> OsshResult<Vec<ModuliEntry>> {
+    if !(64..=16384).contains(&bits) {
+        return Err(ErrorKind::InvalidKeySize.into());
+    }
+    let primes = small_primes(SMALL_PRIMES_LIMIT);
+    let qbits = bits - 1;
+    let mut candidates = Vec::with_capacity(count);
+
+    let mut q = random_odd(qbits, rng)?;
+    while candidates.len() < count {
+        if q.num_bits() as u32 != qbits {
+            q = random_odd(qbits, rng)?;
+        }
+        if sieve(&q, &primes)? {
+            candidates.push(ModuliEntry::new(
+                MODULI_TYPE_SOPHIE_GERMAIN,
+                MODULI_TESTS_SIEVE,
+                0,
+                qbits,
+                0,
+                q.to_owned()?,
+            ));
+        }
+        q.add_word(2)?;
+    }
+    Ok(candidates)
+}
+
+/// Screen the candidates to find the safe primes, like `ssh-keygen -M screen`
+///
+/// Both the Sophie Germain candidates and the safe prime entries are accepted.
+/// The entries which pass `trials` rounds of primality test and have a usable generator are returned.
+pub fn screen_candidates(candidates: &[ModuliEntry], trials: u32) -> OsshResult<Vec<ModuliEntry>> {
+    let mut ctx = BigNumContext::new()?;
+    let mut result = Vec::new();
+    for cand in candidates {
+        let (q, p) = match safe_prime_pair(cand)? {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let generator = choose_generator(&p)?;
+        if generator == 0 {
+            continue;
+        }
+        if !q.is_prime(trials as i32, &mut ctx)? || !p.is_prime(trials as i32, &mut ctx)? {
+            continue;
+        }
+        let size = p.num_bits() as u32 - 1;
+        result.push(ModuliEntry::new(
+            MODULI_TYPE_SAFE,
+            cand.tests | MODULI_TESTS_MILLER_RABIN,
+            trials,
+            size,
+            generator,
+            p,
+        ));
+    }
+    Ok(result)
+}
+
+/// The result of [`validate_entry()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuliCheck {
+    /// The entry is a usable Diffie-Hellman group
+    Valid,
+    /// The entry is not a safe prime entry, such as an unscreened candidate
+    NotSafeType,
+    /// The size field doesn't match the bit length of the modulus
+    SizeMismatch,
+    /// The modulus is not a prime
+    NotPrime,
+    /// The modulus is a prime, but `(p - 1) / 2` is not
+    NotSafePrime,
+    /// The generator is not in the range `(1, p - 1)`
+    BadGenerator,
+}
+
+/// Validate an entry of the moduli file
+///
+/// Check that the modulus is a safe prime using `trials` rounds of primality test,
+/// and the generator is not trivial.
+pub fn validate_entry(entry: &ModuliEntry, trials: u32) -> OsshResult<ModuliCheck> {
+    if entry.modtype != MODULI_TYPE_SAFE {
+        return Ok(ModuliCheck::NotSafeType);
+    }
+    let (q, p) = match safe_prime_pair(entry)? {
+        Some(pair) => pair,
+        None => return Ok(ModuliCheck::NotSafeType),
+    };
+    if p.num_bits() as u32 != entry.size.saturating_add(1) {
+        return Ok(ModuliCheck::SizeMismatch);
+    }
+
+    let g = BigNum::from_u32(entry.generator)?;
+    let mut pm1 = p.to_owned()?;
+    pm1.sub_word(1)?;
+    if g <= BigNum::from_u32(1)? || g >= pm1 {
+        return Ok(ModuliCheck::BadGenerator);
+    }
+
+    let mut ctx = BigNumContext::new()?;
+    if !p.is_prime(trials as i32, &mut ctx)? {
+        return Ok(ModuliCheck::NotPrime);
+    }
+    if !q.is_prime(trials as i32, &mut ctx)? {
+        return Ok(ModuliCheck::NotSafePrime);
+    }
+    Ok(ModuliCheck::Valid)
+}
+
+/// Remove the entries whose modulus is shorter than `min_bits` from the content of a moduli file
+///
+/// The comments and empty lines are preserved.
+/// This does the same thing as `awk '$5 >= min_bits - 1'` recommended by many hardening guides.
+pub fn filter_moduli(s: &str, min_bits: u32) -> OsshResult<String> {
+    let mut out = String::with_capacity(s.len());
+    for line in s.lines() {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            let entry = ModuliEntry::from_str(trimmed)?;
+            if entry.size.saturating_add(1) < min_bits {
+                continue;
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Get `(q, p)` where `p = 2q + 1` from the entry
+fn safe_prime_pair(entry: &ModuliEntry) -> OsshResult<Option<(BigNum, BigNum)>> {
+    match entry.modtype {
+        MODULI_TYPE_SOPHIE_GERMAIN => {
+            let q = entry.modulus.to_owned()?;
+            let mut p = BigNum::new()?;
+            p.lshift1(&q)?;
+            p.add_word(1)?;
+            Ok(Some((q, p)))
+        }
+        MODULI_TYPE_SAFE => {
+            let p = entry.modulus.to_owned()?;
+            let mut q = BigNum::new()?;
+            q.rshift1(&p)?;
+            Ok(Some((q, p)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Choose the generator in the same way as OpenSSH, return `0` if there is no suitable one
+fn choose_generator(p: &BigNumRef) -> OsshResult<u32> {
+    if p.mod_word(24)? == 11 {
+        return Ok(2);
+    }
+    match p.mod_word(10)? {
+        3 | 7 => Ok(5),
+        _ => Ok(0),
+    }
+}
+
+/// Check that both `q` and `2q + 1` aren't divisible by the small primes
+fn sieve(q: &BigNumRef, primes: &[u32]) -> OsshResult<bool> {
+    for &r in primes {
+        let m = q.mod_word(r)?;
+        // 2q + 1 = 0 (mod r) <=> q = (r - 1) / 2 (mod r)
+        if m == 0 || m == u64::from((r - 1) / 2) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn random_odd<R: CryptoRngCore + ?Sized>(bits: u32, rng: &mut R) -> OsshResult<BigNum> {
+    let mut buf = Zeroizing::new(vec![0u8; (bits as usize + 7) / 8]);
+    rng.fill_bytes(&mut buf);
+    buf[0] &= 0xff >> (buf.len() * 8 - bits as usize);
+    let mut n = BigNum::from_slice(&buf)?;
+    n.set_bit(bits as i32 - 1)?;
+    n.set_bit(0)?;
+    Ok(n)
+}
+
+/// The odd primes below the limit
+fn small_primes(limit: u32) -> Vec<u32> {
+    let mut composite = vec![false; limit as usize];
+    let mut primes = Vec::new();
+    for i in 2..limit {
+        if composite[i as usize] {
+            continue;
+        }
+        if i != 2 {
+            primes.push(i);
+        }
+        for j in (i * i..limit).step_by(i as usize) {
+            composite[j as usize] = true;
+        }
+    }
+    primes
+}
+
+/// The current UTC time in `YYYYMMDDHHMMSS` format
+fn utc_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn moduli_line_roundtrip() {
+        // p = 2 * 11 + 1 = 23
+        let entry: ModuliEntry = "20230101000000 2 6 100 4 5 17".parse().unwrap();
+        assert_eq!(entry.modtype, MODULI_TYPE_SAFE);
+        assert_eq!(entry.generator, 5);
+        assert_eq!(entry.modulus, BigNum::from_u32(23).unwrap());
+        assert_eq!(entry.to_string(), "20230101000000 2 6 100 4 5 17");
+
+        assert!("20230101000000 2 6 100 4 5".parse::<ModuliEntry>().is_err());
+        assert!("2023 2 6 100 4 5 17".parse::<ModuliEntry>().is_err());
+        assert!("20230101000000 2 6 100 4 5 XYZ"
+            .parse::<ModuliEntry>()
+            .is_err());
+    }
+
+    #[test]
+    fn moduli_generate_screen() {
+        let candidates = generate_candidates(128, 2000, &mut OsRng).unwrap();
+        assert_eq!(candidates.len(), 2000);
+        assert!(candidates.iter().all(|c| c.modulus.num_bits() == 127));
+
+        let safe = screen_candidates(&candidates, 20).unwrap();
+        assert!(!safe.is_empty());
+        let mut ctx = BigNumContext::new().unwrap();
+        for entry in safe {
+            assert_eq!(entry.modtype, MODULI_TYPE_SAFE);
+            assert_eq!(entry.size, 127);
+            assert!(entry.modulus.is_prime(20, &mut ctx).unwrap());
+            assert_ne!(entry.generator, 0);
+
+            let line = entry.to_string();
+            assert_eq!(line.parse::<ModuliEntry>().unwrap(), entry);
+        }
+    }
+
+    #[test]
+    fn moduli_validate() {
+        // 1019 = 2 * 509 + 1, both are primes
+        let entry: ModuliEntry = "20230101000000 2 6 100 9 2 3FB".parse().unwrap();
+        assert_eq!(validate_entry(&entry, 20).unwrap(), ModuliCheck::Valid);
+        // 1021 is a prime, but 510 is not
+        let entry: ModuliEntry = "20230101000000 2 6 100 9 2 3FD".parse().unwrap();
+        assert_eq!(
+            validate_entry(&entry, 20).unwrap(),
+            ModuliCheck::NotSafePrime
+        );
+        let entry: ModuliEntry = "20230101000000 2 6 100 9 2 3FF".parse().unwrap();
+        assert_eq!(validate_entry(&entry, 20).unwrap(), ModuliCheck::NotPrime);
+        let entry: ModuliEntry = "20230101000000 2 6 100 10 2 3FB".parse().unwrap();
+        assert_eq!(
+            validate_entry(&entry, 20).unwrap(),
+            ModuliCheck::SizeMismatch
+        );
+        let entry: ModuliEntry = "20230101000000 2 6 100 9 1018 3FB".parse().unwrap();
+        assert_eq!(
+            validate_entry(&entry, 20).unwrap(),
+            ModuliCheck::BadGenerator
+        );
+        let entry: ModuliEntry = "20230101000000 4 2 0 9 0 1FD".parse().unwrap();
+        assert_eq!(
+            validate_entry(&entry, 20).unwrap(),
+            ModuliCheck::NotSafeType
+        );
+    }
+
+    #[test]
+    fn moduli_filter() {
+        let content =
+            "# comment\n20230101000000 2 6 100 9 2 3FB\n\n20230101000000 2 6 100 4 5 17\n";
+        assert_eq!(
+            filter_moduli(content, 10).unwrap(),
+            "# comment\n20230101000000 2 6 100 9 2 3FB\n\n"
+        );
+        assert!(filter_moduli("invalid line", 10).is_err());
+    }
+
+    #[test]
+    fn timestamp_format() {
+        let ts = utc_timestamp();
+        assert_eq!(ts.len(), 14);
+        assert!(ts.starts_with("20"));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::cipher::Cipher;
+use crate::error::*;
+use crate::format::ossh_privkey::{ossh_priv_ciphername, ossh_priv_kdf_rounds};
+use crate::keys::{KeyType, LegacyAlgorithms};
+use std::str::FromStr;
+
+/// The restrictions on the keys, the key encryption and the signatures
+///
+/// The policy is passed to the `*_with_policy()` functions of [`KeyPair`](crate::KeyPair) and [`PublicKey`](crate::PublicKey),
+/// which fail with [`ErrorKind::PolicyViolation`] if the key or the signature is not allowed.
+/// The default policy allows everything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// The allowed key types, or `None` to allow all the types
+    pub key_types: Option<Vec<KeyType>>,
+    /// The minimum size in bits of the RSA keys
+    pub min_rsa_bits: usize,
+    /// The minimum size in bits of the EcDSA keys
+    pub min_ecdsa_bits: usize,
+    /// The allowed ciphers of the encrypted private keys, or `None` to allow all the ciphers
+    ///
+    /// The unencrypted keys are always allowed, and the encryption of the PKCS#8 keys is not checked.
+    pub ciphers: Option<Vec<Cipher>>,
+    /// The minimum bcrypt KDF rounds of the encrypted OpenSSH private keys
+    pub min_kdf_rounds: u32,
+    /// The allowed SSH signature algorithms such as `rsa-sha2-512`, or `None` to allow all the algorithms
+    pub signature_algorithms: Option<Vec<String>>,
+    /// Whether the `ssh-rsa` signatures and the DSA keys are allowed when signing and verifying
+    pub legacy: LegacyAlgorithms,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            key_types: None,
+            min_rsa_bits: 0,
+            min_ecdsa_bits: 0,
+            ciphers: None,
+            min_kdf_rounds: 0,
+            signature_algorithms: None,
+            legacy: LegacyAlgorithms::Global,
+        }
+    }
+}
+
+impl Policy {
+    /// The policy matching the defaults of OpenSSH 8.8 and later
+    ///
+    /// The DSA keys, the RSA keys smaller than 1024 bits and the `ssh-rsa` signatures are refused.
+    pub fn openssh() -> Self {
+        Policy {
+            key_types: Some(vec![
+                #[cfg(feature = "rsa")]
+                KeyType::RSA,
+                #[cfg(feature = "ecdsa")]
+                KeyType::ECDSA,
+                #[cfg(feature = "ed25519")]
+                KeyType::ED25519,
+            ]),
+            min_rsa_bits: 1024,
+            legacy: LegacyAlgorithms::Refuse,
+            ..Default::default()
+        }
+    }
+
+    /// Check the type of the key
+    pub fn check_key_type(&self, keytype: KeyType) -> OsshResult<()> {
+        match &self.key_types {
+            Some(key_types) if !key_types.contains(&keytype) => {
+                Err(violation(format!("{} key", keytype)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the type and the size of the key
+    pub fn check_key(&self, keytype: KeyType, bits: usize) -> OsshResult<()> {
+        self.check_key_type(keytype)?;
+        let min_bits = match keytype {
+            #[cfg(feature = "rsa")]
+            KeyType::RSA => self.min_rsa_bits,
+            #[cfg(feature = "ecdsa")]
+            KeyType::ECDSA => self.min_ecdsa_bits,
+            #[cfg(feature = "dsa")]
+            KeyType::DSA => 0,
+            #[cfg(feature = "ed25519")]
+            KeyType::ED25519 => 0,
+        };
+        if bits < min_bits {
+            return Err(violation(format!("{} key of {} bits", keytype, bits)));
+        }
+        Ok(())
+    }
+
+    /// Check the cipher of an encrypted private key
+    pub fn check_cipher(&self, cipher: Cipher) -> OsshResult<()> {
+        match &self.ciphers {
+            Some(ciphers) if cipher.is_some() && !ciphers.contains(&cipher) => {
+                Err(violation(format!("cipher {}", cipher.name())))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the bcrypt KDF rounds of an encrypted OpenSSH private key
+    pub fn check_kdf_rounds(&self, rounds: u32) -> OsshResult<()> {
+        if rounds < self.min_kdf_rounds {
+            return Err(violation(format!("{} KDF rounds", rounds)));
+        }
+        Ok(())
+    }
+
+    /// Check the SSH signature algorithm, such as `ssh-ed25519` or `rsa-sha2-256`
+    pub fn check_signature_algorithm(&self, algo: &str) -> OsshResult<()> {
+        if let Some(algos) = &self.signature_algorithms {
+            if !algos.iter().any(|a| a == algo) {
+                return Err(violation(format!("signature {}", algo)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the encryption of a private key string without decrypting it
+    #[cfg_attr(
+        not(any(feature = "aes-cbc", feature = "3des-cbc")),
+        allow(unused_variables, unreachable_code)
+    )]
+    pub fn check_keystr(&self, keystr: &str) -> OsshResult<()> {
+        let pemdata = ::pem::parse(keystr)?;
+        match pemdata.tag() {
+            "OPENSSH PRIVATE KEY" => {
+                let cipher = Cipher::from_str(&ossh_priv_ciphername(pemdata.contents())?)?;
+                self.check_cipher(cipher)?;
+                if let Some(rounds) = ossh_priv_kdf_rounds(pemdata.contents())? {
+                    self.check_kdf_rounds(rounds)?;
+                }
+            }
+            _ => {
+                if let Some(dekinfo) = pemdata.headers().get("DEK-Info") {
+                    let algo = dekinfo.split(',').next().unwrap_or_default();
+                    let cipher = match algo {
+                        #[cfg(feature = "3des-cbc")]
+                        "DES-EDE3-CBC" => Cipher::TDes_Cbc,
+                        #[cfg(feature = "aes-cbc")]
+                        "AES-128-CBC" => Cipher::Aes128_Cbc,
+                        #[cfg(feature = "aes-cbc")]
+                        "AES-192-CBC" => Cipher::Aes192_Cbc,
+                        #[cfg(feature = "aes-cbc")]
+                        "AES-256-CBC" => Cipher::Aes256_Cbc,
+                        _ if self.ciphers.is_none() => return Ok(()),
+                        _ => return Err(violation(format!("cipher {}", algo))),
+                    };
+                    self.check_cipher(cipher)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn violation(reason: String) -> Error {
+    ErrorKind::PolicyViolation(reason).into()
+}
+
+#[cfg(all(
+    test,
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    feature = "aes-cbc"
+))]
+mod test {
+    use super::*;
+    use crate::keys::rsa::RsaSignature;
+    use crate::{KeyPair, PublicKey};
+    use std::fs;
+
+    fn read(path: &str) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn assert_violation<T>(result: OsshResult<T>) {
+        assert!(matches!(
+            result.map(|_| ()).unwrap_err().kind(),
+            ErrorKind::PolicyViolation(_)
+        ));
+    }
+
+    #[test]
+    fn policy_keys() {
+        let policy = Policy {
+            min_rsa_bits: 3072,
+            ..Policy::openssh()
+        };
+        assert_violation(PublicKey::from_keystr_with_policy(
+            &read("assets/openssh_dsa.pub"),
+            &policy,
+        ));
+        assert_violation(PublicKey::from_keystr_with_policy(
+            &read("assets/openssh_rsa.pub"),
+            &policy,
+        ));
+        assert!(
+            PublicKey::from_keystr_with_policy(&read("assets/openssh_ed25519.pub"), &policy)
+                .is_ok()
+        );
+
+        assert_violation(KeyPair::generate_with_policy(KeyType::RSA, 2048, &policy));
+        assert_violation(KeyPair::generate_with_policy(KeyType::DSA, 0, &policy));
+        let keypair = KeyPair::generate_with_policy(KeyType::ECDSA, 0, &policy).unwrap();
+        assert_eq!(keypair.keytype(), KeyType::ECDSA);
+    }
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn policy_encryption() {
+        let policy = Policy {
+            ciphers: Some(vec![Cipher::Aes256_Ctr, Cipher::Aes256_Cbc]),
+            min_kdf_rounds: 16,
+            ..Default::default()
+        };
+        let keystr = read("assets/openssh_ecdsa_enc");
+        assert!(KeyPair::from_keystr_with_policy(&keystr, Some("12345678"), &policy).is_ok());
+        assert_violation(KeyPair::from_keystr_with_policy(
+            &read("assets/pem_ecdsa_enc"),
+            Some("12345678"),
+            &policy,
+        ));
+        assert!(KeyPair::from_keystr_with_policy(&read("assets/pem_ecdsa"), None, &policy).is_ok());
+
+        // The encryption is refused before checking the passphrase
+        let policy = Policy {
+            min_kdf_rounds: 100,
+            ..policy
+        };
+        assert_violation(KeyPair::from_keystr_with_policy(&keystr, None, &policy));
+    }
+
+    #[test]
+    fn policy_signatures() {
+        const DATA: &[u8] = b"8Kn9PPQV";
+        let policy = Policy {
+            signature_algorithms: Some(vec!["rsa-sha2-512".to_string()]),
+            ..Policy::openssh()
+        };
+        let keypair = KeyPair::from_keystr(&read("assets/openssh_rsa"), None).unwrap();
+        let pubkey = keypair.clone_public_key().unwrap();
+        assert_violation(keypair.sign_ssh_signature_with_policy(
+            DATA,
+            Some(RsaSignature::SHA2_256),
+            &policy,
+        ));
+        assert!(keypair
+            .sign_ssh_signature_with_policy(DATA, None, &policy)
+            .is_err());
+        let sig = keypair
+            .sign_ssh_signature_with_policy(DATA, Some(RsaSignature::SHA2_512), &policy)
+            .unwrap();
+        assert!(pubkey
+            .verify_ssh_signature_with_policy(DATA, &sig, &policy)
+            .unwrap());
+
+        let sig = keypair
+            .sign_ssh_signature_with(DATA, Some(RsaSignature::SHA2_256))
+            .unwrap();
+        assert_violation(pubkey.verify_ssh_signature_with_policy(DATA, &sig, &policy));
+    }
+}
@@ -0,0 +1,360 @@
+use crate::error::*;
+use crate::known_hosts::{match_pattern_list, SSH_DEFAULT_PORT};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The identity files which ssh tries if none is configured, limited to the key types of this crate
+const DEFAULT_IDENTITY_FILES: &[&str] = &[
+    "~/.ssh/id_rsa",
+    "~/.ssh/id_ecdsa",
+    "~/.ssh/id_ed25519",
+    "~/.ssh/id_dsa",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Criteria {
+    /// The directives before the first block, or `Match all`
+    All,
+    /// The patterns of `Host`
+    Host(Vec<String>),
+    /// `Match` with the other criteria, which is never applied
+    Unsupported,
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    criteria: Criteria,
+    directives: Vec<(String, Vec<String>)>,
+}
+
+impl Block {
+    fn new(criteria: Criteria) -> Self {
+        Block {
+            criteria,
+            directives: Vec::new(),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match &self.criteria {
+            Criteria::All => true,
+            Criteria::Host(patterns) => {
+                match_pattern_list(patterns.iter().map(String::as_str), host)
+            }
+            Criteria::Unsupported => false,
+        }
+    }
+}
+
+/// The keys and the agent which ssh uses for a host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostIdentities {
+    /// The `IdentityFile` paths in the order ssh tries them, or the default files if none is configured
+    pub identity_files: Vec<PathBuf>,
+    /// The `CertificateFile` paths
+    pub certificate_files: Vec<PathBuf>,
+    /// The `IdentityAgent` socket path, or `none` and `SSH_AUTH_SOCK` as they are
+    pub identity_agent: Option<String>,
+}
+
+/// The parsed ssh_config file, such as `~/.ssh/config`, to locate the keys ssh would use for a host
+///
+/// Only `Host`, `Match all`, `HostName`, `User`, `Port`, `IdentityFile`, `CertificateFile` and `IdentityAgent` are used.
+/// The blocks of `Match` with the other criteria are skipped, and `Include` is not followed.
+/// Like ssh, the first value of each directive wins, while all the `IdentityFile` and `CertificateFile` values are kept.
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    blocks: Vec<Block>,
+}
+
+impl SshConfig {
+    /// Parse the content of the ssh_config file
+    pub fn parse(s: &str) -> OsshResult<Self> {
+        let mut blocks = Vec::new();
+        let mut block = Block::new(Criteria::All);
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, args) = split_directive(line)?;
+            let criteria = match keyword.as_str() {
+                "host" if args.is_empty() => return Err(ErrorKind::InvalidFormat.into()),
+                "host" => Criteria::Host(args.iter().map(|p| p.to_ascii_lowercase()).collect()),
+                "match" => match args.as_slice() {
+                    [all] if all.eq_ignore_ascii_case("all") => Criteria::All,
+                    _ => Criteria::Unsupported,
+                },
+                _ => {
+                    block.directives.push((keyword, args));
+                    continue;
+                }
+            };
+            blocks.push(std::mem::replace(&mut block, Block::new(criteria)));
+        }
+        blocks.push(block);
+        Ok(SshConfig { blocks })
+    }
+
+    /// Resolve the identities of the host with the home directory and the user name of the current environment
+    ///
+    /// Fail with [`ErrorKind::InvalidArgument`] if the home directory is unknown.
+    pub fn identities(&self, host: &str) -> OsshResult<HostIdentities> {
+        let home = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .ok_or(ErrorKind::InvalidArgument)?;
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default();
+        self.identities_with(host, Path::new(&home), &user)
+    }
+
+    /// Resolve the identities of the host with the given home directory and local user name
+    ///
+    /// `~` at the beginning and the tokens `%%`, `%d`, `%h`, `%n`, `%p`, `%r` and `%u` are expanded like ssh,
+    /// and the other tokens fail with [`ErrorKind::InvalidFormat`].
+    pub fn identities_with(
+        &self,
+        host: &str,
+        home: &Path,
+        local_user: &str,
+    ) -> OsshResult<HostIdentities> {
+        let name = host.to_ascii_lowercase();
+        let (mut hostname, mut user, mut port, mut agent) = (None, None, None, None);
+        let mut identity_files = Vec::new();
+        let mut certificate_files = Vec::new();
+        for block in self.blocks.iter().filter(|b| b.matches(&name)) {
+            for (keyword, args) in &block.directives {
+                let value = match args.first() {
+                    Some(value) => value.as_str(),
+                    None => return Err(ErrorKind::InvalidFormat.into()),
+                };
+                match keyword.as_str() {
+                    "hostname" => hostname = hostname.or(Some(value)),
+                    "user" => user = user.or(Some(value)),
+                    "port" => port = port.or(Some(value)),
+                    "identityagent" => agent = agent.or(Some(value)),
+                    "identityfile" => identity_files.push(value),
+                    "certificatefile" => certificate_files.push(value),
+                    _ => {}
+                }
+            }
+        }
+        if identity_files.is_empty() {
+            identity_files.extend(DEFAULT_IDENTITY_FILES);
+        }
+
+        let mut tokens = Tokens {
+            home: &home.to_string_lossy(),
+            host: &name,
+            original_host: host,
+            port: &port.map_or_else(|| SSH_DEFAULT_PORT.to_string(), str::to_owned),
+            remote_user: user.unwrap_or(local_user),
+            local_user,
+        };
+        let hostname = match hostname {
+            Some(hostname) => tokens.expand(hostname)?,
+            None => name.clone(),
+        };
+        tokens.host = &hostname;
+        Ok(HostIdentities {
+            identity_files: tokens.expand_paths(&identity_files)?,
+            certificate_files: tokens.expand_paths(&certificate_files)?,
+            identity_agent: agent.map(|agent| tokens.expand(agent)).transpose()?,
+        })
+    }
+}
+
+impl FromStr for SshConfig {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+struct Tokens<'a> {
+    home: &'a str,
+    host: &'a str,
+    original_host: &'a str,
+    port: &'a str,
+    remote_user: &'a str,
+    local_user: &'a str,
+}
+
+impl Tokens<'_> {
+    fn expand(&self, value: &str) -> OsshResult<String> {
+        let mut expanded = String::new();
+        let mut rest = value;
+        if let Some(path) = value.strip_prefix('~') {
+            if path.is_empty() || path.starts_with('/') {
+                expanded.push_str(self.home);
+                rest = path;
+            }
+        }
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => expanded.push('%'),
+                Some('d') => expanded.push_str(self.home),
+                Some('h') => expanded.push_str(self.host),
+                Some('n') => expanded.push_str(self.original_host),
+                Some('p') => expanded.push_str(self.port),
+                Some('r') => expanded.push_str(self.remote_user),
+                Some('u') => expanded.push_str(self.local_user),
+                _ => return Err(ErrorKind::InvalidFormat.into()),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Expand the paths and remove the duplicates, like ssh does
+    fn expand_paths(&self, values: &[&str]) -> OsshResult<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for value in values {
+            let path = PathBuf::from(self.expand(value)?);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+}
+
+/// Split the line into the lowercase keyword and the arguments, like `Keyword arg` or `Keyword=arg`
+fn split_directive(line: &str) -> OsshResult<(String, Vec<String>)> {
+    let end = line
+        .find(|c: char| c.is_ascii_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+    Ok((line[..end].to_ascii_lowercase(), split_args(rest)?))
+}
+
+/// Split the arguments separated by the whitespaces, which may be quoted and end with a comment
+fn split_args(s: &str) -> OsshResult<Vec<String>> {
+    let mut args = Vec::new();
+    let mut arg = None;
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_with(String::new);
+            }
+            '#' if !quoted && arg.is_none() => break,
+            _ if c.is_ascii_whitespace() && !quoted => args.extend(arg.take()),
+            _ => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(ErrorKind::InvalidFormat.into());
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = "\
+IdentityAgent ~/.ssh/agent.sock
+
+Host github.com gitlab.*
+    User git
+    IdentityFile ~/.ssh/id_%h
+    IdentitiesOnly yes
+
+Host *.internal !bastion.internal
+    HostName %h.example.com
+    Port=2222
+    IdentityFile \"~/.ssh/keys/%r@%h:%p\"  # per host key
+    CertificateFile ~/.ssh/keys/%r@%h-cert.pub
+    IdentityAgent none
+
+Match exec \"test -f /nonexistent\"
+    IdentityFile ~/.ssh/never
+
+Host *
+    IdentityFile %d/.ssh/id_ed25519
+    IdentityFile ~/.ssh/id_ed25519
+";
+
+    #[test]
+    fn ssh_config_identities() {
+        let config = SshConfig::parse(CONFIG).unwrap();
+        let home = Path::new("/home/alice");
+
+        let ids = config.identities_with("GitHub.com", home, "alice").unwrap();
+        assert_eq!(
+            ids.identity_files,
+            [
+                PathBuf::from("/home/alice/.ssh/id_github.com"),
+                PathBuf::from("/home/alice/.ssh/id_ed25519")
+            ]
+        );
+        assert!(ids.certificate_files.is_empty());
+        assert_eq!(
+            ids.identity_agent.as_deref(),
+            Some("/home/alice/.ssh/agent.sock")
+        );
+
+        let ids = config
+            .identities_with("db.internal", home, "alice")
+            .unwrap();
+        assert_eq!(
+            ids.identity_files[0],
+            PathBuf::from("/home/alice/.ssh/keys/alice@db.internal.example.com:2222")
+        );
+        assert_eq!(
+            ids.certificate_files,
+            [PathBuf::from(
+                "/home/alice/.ssh/keys/alice@db.internal.example.com-cert.pub"
+            )]
+        );
+        // The first value wins
+        assert_eq!(
+            ids.identity_agent.as_deref(),
+            Some("/home/alice/.ssh/agent.sock")
+        );
+
+        let ids = config
+            .identities_with("bastion.internal", home, "alice")
+            .unwrap();
+        assert_eq!(
+            ids.identity_files,
+            [PathBuf::from("/home/alice/.ssh/id_ed25519")]
+        );
+    }
+
+    #[test]
+    fn ssh_config_defaults_and_errors() {
+        let config = SshConfig::parse("").unwrap();
+        let ids = config
+            .identities_with("example.com", Path::new("/root"), "root")
+            .unwrap();
+        assert_eq!(ids.identity_files.len(), DEFAULT_IDENTITY_FILES.len());
+        assert_eq!(ids.identity_files[0], PathBuf::from("/root/.ssh/id_rsa"));
+        assert!(ids.identity_agent.is_none());
+
+        assert!(SshConfig::parse("Host").is_err());
+        assert!(SshConfig::parse("IdentityFile \"~/.ssh/id_rsa").is_err());
+        let config = SshConfig::parse("IdentityFile ~/.ssh/%C").unwrap();
+        assert_eq!(
+            config
+                .identities_with("example.com", Path::new("/root"), "root")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidFormat
+        );
+        assert_eq!(
+            split_args("a \"b c\" \"\" d#e # comment").unwrap(),
+            ["a", "b c", "", "d#e"]
+        );
+    }
+}
@@ -1,16 +1,21 @@
+use crate::error::{Error, ErrorKind, OsshResult};
 use cryptovec::CryptoVec;
 pub use openssl::bn::{BigNum, BigNumRef};
+use openssl::error::ErrorStack;
+use std::fmt;
 use std::io;
 use std::io::{Read, Result, Write};
 use std::str;
 use zeroize::{Zeroize, Zeroizing};
 
 const MAX_BIGNUM: usize = 16384 / 8;
+/// The buffer size allocated before the string is read
+const MAX_PREALLOC: usize = 64 * 1024;
 
-/// A clear-on-drop vector based on `CryptoVec`
+/// A clear-on-drop buffer based on `CryptoVec`, which holds the decrypted private key sections
 ///
-/// This structure is designed for internal use only.
-/// It may disappear/breaking change at any version.
+/// It is written at the end like a `Vec<u8>` and read from its position like a `Cursor`,
+/// so [`SshReadExt`] and [`SshWriteExt`] work on it.
 #[derive(Debug, Default)]
 pub struct SshBuf {
     read_pos: usize,
@@ -18,6 +23,7 @@ pub struct SshBuf {
 }
 
 impl SshBuf {
+    /// Create an empty buffer
     pub fn new() -> SshBuf {
         SshBuf {
             read_pos: 0,
@@ -25,6 +31,7 @@ impl SshBuf {
         }
     }
 
+    /// Wrap the data, reading from its beginning
     pub fn with_vec(v: CryptoVec) -> SshBuf {
         SshBuf {
             read_pos: 0,
@@ -32,10 +39,15 @@ impl SshBuf {
         }
     }
 
+    /// Get the offset of the next read
     pub fn position(&self) -> usize {
         self.read_pos
     }
 
+    /// Move the offset of the next read
+    ///
+    /// # Panics
+    /// Panics if the offset is beyond the end of the data.
     pub fn set_position(&mut self, offset: usize) {
         if offset > self.buf.len() {
             panic!("Offset exceed length");
@@ -43,22 +55,27 @@ impl SshBuf {
         self.read_pos = offset;
     }
 
+    /// Unwrap the data
     pub fn into_inner(self) -> CryptoVec {
         self.buf
     }
 
+    /// Get a reference to the data
     pub fn get_ref(&self) -> &CryptoVec {
         &self.buf
     }
 
+    /// Get all the data, including the part already read
     pub fn as_slice(&self) -> &[u8] {
         &self.buf
     }
 
+    /// Get the length of all the data
     pub fn len(&self) -> usize {
         self.buf.len()
     }
 
+    /// Check if there is no data
     pub fn is_empty(&self) -> bool {
         self.buf.is_empty()
     }
@@ -86,6 +103,151 @@ impl Write for SshBuf {
     }
 }
 
+/// A cursor over the SSH encoded data, which reports where the decoding failed
+///
+/// Each read takes the name of the field, and the error carries the section, the field name and the offset,
+/// see [`Error::position()`](../error/struct.Error.html#method.position).
+/// The cursor doesn't move if a read fails, and the strings are borrowed from the data without copying.
+#[derive(Debug, Clone)]
+pub struct SshReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    base: usize,
+    section: &'static str,
+}
+
+impl<'a> SshReader<'a> {
+    /// Create a cursor at the beginning of the data, the offsets in the errors are relative to it
+    pub fn new(data: &'a [u8], section: &'static str) -> Self {
+        SshReader {
+            data,
+            pos: 0,
+            base: 0,
+            section,
+        }
+    }
+
+    /// Get the offset of the next field from the beginning of the section
+    pub fn position(&self) -> usize {
+        self.base + self.pos
+    }
+
+    /// Get the data which has not been read
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    /// Check if all the data has been read
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Check that all the data has been read, otherwise fail with [`ErrorKind::InvalidLength`]
+    pub fn finish(&self) -> OsshResult<()> {
+        if !self.is_empty() {
+            return Err(Error::from_kind(ErrorKind::InvalidLength).at(
+                self.section,
+                "trailing data",
+                self.position(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn read_field<T, F>(&mut self, field: &'static str, read: F) -> OsshResult<T>
+    where
+        F: FnOnce(&mut &'a [u8]) -> io::Result<T>,
+    {
+        let mut rest = self.remaining();
+        match read(&mut rest) {
+            Ok(value) => {
+                self.pos = self.data.len() - rest.len();
+                Ok(value)
+            }
+            Err(e) => Err(Error::from(e).at(self.section, field, self.position())),
+        }
+    }
+
+    pub fn read_bool(&mut self, field: &'static str) -> OsshResult<bool> {
+        self.read_field(field, |r| r.read_bool())
+    }
+
+    pub fn read_uint8(&mut self, field: &'static str) -> OsshResult<u8> {
+        self.read_field(field, |r| r.read_uint8())
+    }
+
+    pub fn read_uint32(&mut self, field: &'static str) -> OsshResult<u32> {
+        self.read_field(field, |r| r.read_uint32())
+    }
+
+    pub fn read_uint64(&mut self, field: &'static str) -> OsshResult<u64> {
+        self.read_field(field, |r| r.read_uint64())
+    }
+
+    /// Read the fixed number of bytes without the length prefix
+    pub fn read_bytes(&mut self, field: &'static str, len: usize) -> OsshResult<&'a [u8]> {
+        self.read_field(field, |r| {
+            if len > r.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Bytes exceed the data",
+                ));
+            }
+            let (data, rest) = r.split_at(len);
+            *r = rest;
+            Ok(data)
+        })
+    }
+
+    /// Read the binary string without copying it
+    pub fn read_string(&mut self, field: &'static str) -> OsshResult<&'a [u8]> {
+        self.read_field(field, read_slice)
+    }
+
+    /// Read the UTF-8 string without copying it
+    pub fn read_utf8(&mut self, field: &'static str) -> OsshResult<&'a str> {
+        self.read_field(field, |r| {
+            str::from_utf8(read_slice(r)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    pub fn read_mpint(&mut self, field: &'static str) -> OsshResult<BigNum> {
+        self.read_field(field, |r| r.read_mpint())
+    }
+
+    pub fn read_list<B: FromIterator<String>>(&mut self, field: &'static str) -> OsshResult<B> {
+        self.read_field(field, |r| r.read_list())
+    }
+
+    /// Read the binary string as the nested structure
+    ///
+    /// The offsets in the errors of the returned cursor are still relative to this section.
+    pub fn read_nested(&mut self, field: &'static str) -> OsshResult<SshReader<'a>> {
+        let offset = self.position();
+        let data = self.read_string(field)?;
+        Ok(SshReader {
+            data,
+            pos: 0,
+            base: offset + 4,
+            section: self.section,
+        })
+    }
+}
+
+fn read_slice<'a>(reader: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = reader.read_uint32()? as usize;
+    if len > reader.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "String exceeds the data",
+        ));
+    }
+    let (data, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(data)
+}
+
 /// [io::Read](https://doc.rust-lang.org/std/io/trait.Read.html) extension to read ssh data
 pub trait SshReadExt {
     /// Read a byte and convert it to boolean
@@ -120,12 +282,11 @@ pub trait SshReadExt {
     /// Integers which is longer than 16384 bits are also not supporting.
     fn read_mpint(&mut self) -> io::Result<BigNum>;
 
-    /*
     /// Read name-list
     ///
     /// It is a list representing in an ASCII string separated by the `,` charactor.
+    /// An empty string is an empty list.
     fn read_list<B: FromIterator<String>>(&mut self) -> io::Result<B>;
-    */
 }
 
 impl<R: io::Read + ?Sized> SshReadExt for R {
@@ -154,14 +315,18 @@ impl<R: io::Read + ?Sized> SshReadExt for R {
 
     fn read_string(&mut self) -> io::Result<Vec<u8>> {
         let len = self.read_uint32()? as usize;
-        let mut buf = vec![0u8; len];
-        match self.read_exact(buf.as_mut_slice()) {
-            Ok(_) => Ok(buf),
-            Err(e) => {
-                buf.zeroize();
-                Err(e)
-            }
+        // The length may be forged, so the buffer only grows with the data actually read
+        let mut buf = Zeroizing::new(vec![0u8; len.min(MAX_PREALLOC)]);
+        self.read_exact(&mut buf)?;
+        while buf.len() < len {
+            let filled = buf.len();
+            // The smaller buffer is wiped on drop
+            let mut larger = Zeroizing::new(vec![0u8; len.min(filled * 2)]);
+            larger[..filled].copy_from_slice(&buf);
+            buf = larger;
+            self.read_exact(&mut buf[filled..])?;
         }
+        Ok(std::mem::take(&mut *buf))
     }
 
     fn read_utf8(&mut self) -> io::Result<String> {
@@ -188,37 +353,40 @@ impl<R: io::Read + ?Sized> SshReadExt for R {
         let data = Zeroizing::new(self.read_string()?);
         to_bignum(&data)
     }
-    /*
+
     fn read_list<B: FromIterator<String>>(&mut self) -> io::Result<B> {
         let string = self.read_utf8()?;
+        if !string.is_ascii() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "List should only contain ascii characters",
+            ));
+        }
+        if string.is_empty() {
+            return Ok(std::iter::empty().collect());
+        }
+        if string.split(',').any(str::is_empty) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "List elements shouldn't be empty",
+            ));
+        }
         Ok(string.split(',').map(String::from).collect())
     }
-    */
 }
 
 // --------------------------
 // ---- Helper Functions ----
 // --------------------------
 fn to_bignum(data: &[u8]) -> io::Result<BigNum> {
-    if !data.is_empty() && data[0] & 0x80 != 0 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Negative Big Number",
-        ));
-    }
-    if (data.len() > MAX_BIGNUM + 1) || (data.len() == MAX_BIGNUM + 1 && data[0] != 0) {
+    let mpint = Mpint::from_ssh_bytes(data)?;
+    if mpint.as_be_bytes().len() > MAX_BIGNUM || data.len() > MAX_BIGNUM + 1 {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             "Big Number Too Long",
         ));
     }
-    // Remove Leading zeros
-    let mut i = 0;
-    let mut iter = data.iter();
-    while let Some(0) = iter.next() {
-        i += 1;
-    }
-    match BigNum::from_slice(&data[i..]) {
+    match BigNum::try_from(&mpint) {
         Ok(bn) => Ok(bn),
         Err(_) => Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -227,7 +395,7 @@ fn to_bignum(data: &[u8]) -> io::Result<BigNum> {
     }
 }
 
-/// [io::Write](https://doc.rust-lang.org/std/io/trait.Write.html) extension to read ssh data
+/// [io::Write](https://doc.rust-lang.org/std/io/trait.Write.html) extension to write ssh data
 pub trait SshWriteExt {
     /// Convert boolean to one byte and write it
     ///
@@ -259,7 +427,6 @@ pub trait SshWriteExt {
     /// Convert the integer into bytes array and write it.
     fn write_mpint(&mut self, value: &BigNumRef) -> io::Result<()>;
 
-    /*
     /// Write name-list
     ///
     /// Each entry must meets the following rules:
@@ -269,7 +436,6 @@ pub trait SshWriteExt {
     /// - being a valid ASCII string
     fn write_list<S: AsRef<str>, I: IntoIterator<Item = S>>(&mut self, values: I)
         -> io::Result<()>;
-        */
 }
 
 impl<W: io::Write + ?Sized> SshWriteExt for W {
@@ -308,22 +474,9 @@ impl<W: io::Write + ?Sized> SshWriteExt for W {
     }
 
     fn write_mpint(&mut self, value: &BigNumRef) -> io::Result<()> {
-        let mut buf = Zeroizing::new(vec![0x00u8]);
-        let bnbuf = Zeroizing::new(value.to_vec());
-        buf.reserve(bnbuf.len());
-        buf.extend(bnbuf.as_slice());
-
-        // Add a zero byte to make the intgeter unsigned
-        if (buf[1] & 0x80) > 0 {
-            self.write_string(&buf[..])
-        } else {
-            self.write_string(&buf[1..])
-        }
+        Mpint::from(value).write_to(self)
     }
 
-    /*
-    //TODO: Make list as a new struct to make it easiler for
-            implementing memory zeroizing
     fn write_list<S: AsRef<str>, I: IntoIterator<Item = S>>(
         &mut self,
         values: I,
@@ -350,12 +503,274 @@ impl<W: io::Write + ?Sized> SshWriteExt for W {
                 ));
             }
             if !list_str.is_empty() {
-                list_str.push_str(",");
+                list_str.push(',');
             }
             list_str.push_str(s);
         }
         self.write_utf8(&list_str)?;
         Ok(())
     }
-    */
+}
+
+/// A non-negative multiple precision integer of [RFC 4251](https://tools.ietf.org/html/rfc4251#section-5)
+///
+/// It holds the big-endian bytes without the leading zeros, which are wiped on drop.
+/// The conversions add and remove the zero byte which keeps the high bit of the SSH encoding unset,
+/// and zero is encoded as an empty string.
+///
+/// ```rust
+/// use osshkeys::sshbuf::Mpint;
+///
+/// let value = Mpint::from_be_bytes(&[0x00, 0x80]);
+/// assert_eq!(value.as_be_bytes(), [0x80]);
+/// assert_eq!(value.bits(), 8);
+/// assert_eq!(*value.to_ssh_bytes(), [0x00, 0x80]);
+///
+/// let mut buf = Vec::new();
+/// value.write_to(&mut buf).unwrap();
+/// assert_eq!(buf, [0x00, 0x00, 0x00, 0x02, 0x00, 0x80]);
+/// assert_eq!(Mpint::read_from(&mut buf.as_slice()).unwrap(), value);
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Mpint {
+    bytes: Zeroizing<Vec<u8>>,
+}
+
+impl Mpint {
+    /// Create the integer from its unsigned big-endian bytes, the leading zeros are allowed
+    pub fn from_be_bytes(bytes: &[u8]) -> Self {
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+        Mpint {
+            bytes: Zeroizing::new(bytes[start..].to_vec()),
+        }
+    }
+
+    /// Get the big-endian bytes without the leading zeros, zero is an empty slice
+    pub fn as_be_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Check if the integer is zero
+    pub fn is_zero(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Get the number of the significant bits
+    pub fn bits(&self) -> usize {
+        match self.bytes.first() {
+            Some(b) => self.bytes.len() * 8 - b.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    /// Parse the content of the mpint string, without the length prefix
+    ///
+    /// The negative integers are refused.
+    pub fn from_ssh_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.first().map_or(false, |b| b & 0x80 != 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Negative Big Number",
+            ));
+        }
+        Ok(Self::from_be_bytes(data))
+    }
+
+    /// Get the content of the mpint string, without the length prefix
+    pub fn to_ssh_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut buf = Zeroizing::new(Vec::with_capacity(self.bytes.len() + 1));
+        if self.bytes.first().map_or(false, |b| b & 0x80 != 0) {
+            buf.push(0);
+        }
+        buf.extend_from_slice(&self.bytes);
+        buf
+    }
+
+    /// Read the mpint string with its length prefix
+    pub fn read_from<R: io::Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        let data = Zeroizing::new(reader.read_string()?);
+        Self::from_ssh_bytes(&data)
+    }
+
+    /// Write the mpint string with its length prefix
+    pub fn write_to<W: io::Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_string(&self.to_ssh_bytes())
+    }
+}
+
+impl fmt::Debug for Mpint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Mpint(0x")?;
+        if self.bytes.is_empty() {
+            f.write_str("0")?;
+        }
+        for b in self.bytes.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl From<&BigNumRef> for Mpint {
+    /// Convert the magnitude of the `BigNum`
+    fn from(value: &BigNumRef) -> Self {
+        Mpint {
+            bytes: Zeroizing::new(value.to_vec()),
+        }
+    }
+}
+
+impl TryFrom<&Mpint> for BigNum {
+    type Error = ErrorStack;
+
+    fn try_from(value: &Mpint) -> std::result::Result<Self, Self::Error> {
+        BigNum::from_slice(&value.bytes)
+    }
+}
+
+#[cfg(feature = "num-bigint")]
+impl From<&num_bigint::BigUint> for Mpint {
+    fn from(value: &num_bigint::BigUint) -> Self {
+        Self::from_be_bytes(&Zeroizing::new(value.to_bytes_be()))
+    }
+}
+
+#[cfg(feature = "num-bigint")]
+impl From<&Mpint> for num_bigint::BigUint {
+    fn from(value: &Mpint) -> Self {
+        num_bigint::BigUint::from_bytes_be(&value.bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    // The examples of RFC 4251 section 5
+    const MPINT_VECTORS: [(&[u8], &[u8]); 3] = [
+        (&hex!(""), &hex!("00000000")),
+        (&hex!("09a378f9b2e332a7"), &hex!("0000000809a378f9b2e332a7")),
+        (&hex!("80"), &hex!("000000020080")),
+    ];
+
+    #[test]
+    fn mpint_vectors() {
+        for (value, encoded) in MPINT_VECTORS {
+            let bn = BigNum::from_slice(value).unwrap();
+            let mut buf = Vec::new();
+            buf.write_mpint(&bn).unwrap();
+            assert_eq!(buf, encoded);
+            assert_eq!(io::Cursor::new(encoded).read_mpint().unwrap(), bn);
+        }
+        assert!(io::Cursor::new(hex!("00000001ff")).read_mpint().is_err());
+    }
+
+    #[test]
+    fn mpint_type() {
+        for (value, encoded) in MPINT_VECTORS {
+            let mpint = Mpint::from_be_bytes(value);
+            let mut buf = Vec::new();
+            mpint.write_to(&mut buf).unwrap();
+            assert_eq!(buf, encoded);
+            assert_eq!(Mpint::read_from(&mut &buf[..]).unwrap(), mpint);
+
+            let bn = BigNum::try_from(&mpint).unwrap();
+            assert_eq!(bn, BigNum::from_slice(value).unwrap());
+            assert_eq!(Mpint::from(&*bn), mpint);
+            #[cfg(feature = "num-bigint")]
+            assert_eq!(Mpint::from(&num_bigint::BigUint::from(&mpint)), mpint);
+        }
+        let mpint = Mpint::from_be_bytes(&hex!("0000017f"));
+        assert_eq!(mpint.as_be_bytes(), hex!("017f"));
+        assert_eq!(mpint.bits(), 9);
+        assert_eq!(format!("{:?}", mpint), "Mpint(0x017f)");
+        assert!(Mpint::default().is_zero());
+        assert_eq!(Mpint::default().bits(), 0);
+        assert!(Mpint::from_ssh_bytes(&hex!("ff")).is_err());
+    }
+
+    #[test]
+    fn primitives_roundtrip() {
+        let mut buf = Vec::new();
+        buf.write_bool(true).unwrap();
+        buf.write_uint8(0x12).unwrap();
+        buf.write_uint32(0x29b7f4aa).unwrap();
+        buf.write_uint64(0x0102030405060708).unwrap();
+        buf.write_utf8("testing").unwrap();
+        assert_eq!(
+            buf,
+            hex!("01 12 29b7f4aa 0102030405060708 00000007 74657374696e67")
+        );
+
+        let mut reader = io::Cursor::new(&buf);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_uint8().unwrap(), 0x12);
+        assert_eq!(reader.read_uint32().unwrap(), 0x29b7f4aa);
+        assert_eq!(reader.read_uint64().unwrap(), 0x0102030405060708);
+        assert_eq!(reader.read_utf8().unwrap(), "testing");
+        assert!(reader.read_uint8().is_err());
+    }
+
+    #[test]
+    fn forged_string_length() {
+        let err = io::Cursor::new(hex!("ffffffff 0102"))
+            .read_string()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // The buffer grows beyond the preallocated size with the data
+        let data = vec![0x5a; MAX_PREALLOC * 3 + 1];
+        let mut buf = Vec::new();
+        buf.write_string(&data).unwrap();
+        assert_eq!(io::Cursor::new(&buf).read_string().unwrap(), data);
+        buf.pop();
+        assert!(io::Cursor::new(&buf).read_string().is_err());
+    }
+
+    #[test]
+    fn name_list() {
+        let mut buf = Vec::new();
+        buf.write_list(["zlib", "none"]).unwrap();
+        buf.write_list(Vec::<String>::new()).unwrap();
+        assert_eq!(buf, hex!("00000009 7a6c69622c6e6f6e65 00000000"));
+
+        let mut reader = io::Cursor::new(&buf);
+        let list: Vec<String> = reader.read_list().unwrap();
+        assert_eq!(list, ["zlib", "none"]);
+        let list: Vec<String> = reader.read_list().unwrap();
+        assert!(list.is_empty());
+
+        assert!(Vec::new().write_list(["a,b"]).is_err());
+        assert!(Vec::new().write_list([""]).is_err());
+        let list: io::Result<Vec<String>> = io::Cursor::new(hex!("00000002 612c")).read_list();
+        assert!(list.is_err());
+    }
+
+    #[test]
+    fn reader_positions() {
+        let data = hex!("00000003 616263 0000000a 00000001 61 00000001 ff 05");
+        let mut reader = SshReader::new(&data, "test");
+        assert_eq!(reader.read_utf8("name").unwrap(), "abc");
+
+        let mut nested = reader.read_nested("nested").unwrap();
+        assert_eq!(nested.read_string("first").unwrap(), b"a");
+        assert_eq!(nested.position(), 16);
+        let err = nested.read_utf8("second").unwrap_err();
+        let position = err.position().unwrap();
+        assert_eq!(
+            (position.section(), position.field(), position.offset()),
+            ("test", "second", 16)
+        );
+        // The cursor doesn't move on errors
+        assert_eq!(nested.read_string("second").unwrap(), hex!("ff"));
+        nested.finish().unwrap();
+
+        let err = reader.finish().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidLength);
+        assert_eq!(err.position().unwrap().offset(), 21);
+        assert_eq!(reader.read_uint8("last").unwrap(), 5);
+        reader.finish().unwrap();
+        assert!(reader.read_uint32("eof").is_err());
+    }
 }
@@ -0,0 +1,395 @@
+use crate::error::*;
+use crate::format::base64;
+use crate::keys::rsa::{RsaSignature, RSA_NAME};
+use crate::keys::{PublicKey, SshSigner};
+use crate::known_hosts::match_pattern_list;
+use crate::sshbuf::{SshReader, SshWriteExt};
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::str::FromStr;
+
+/// The magic preamble of the signature and the signed data
+pub const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+/// The version of the signature format
+pub const SSHSIG_VERSION: u32 = 1;
+
+const SIG_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+const SIG_END: &str = "-----END SSH SIGNATURE-----";
+const SIG_LINE_WIDTH: usize = 70;
+const SECTION_SSHSIG: &str = "sshsig";
+
+/// The hash algorithm applied to the message before signing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SshSigHash {
+    Sha256,
+    /// The default of `ssh-keygen -Y sign`
+    #[default]
+    Sha512,
+}
+
+impl SshSigHash {
+    /// Parse from the algorithm name, `sha256` or `sha512`
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "sha256" => Some(SshSigHash::Sha256),
+            "sha512" => Some(SshSigHash::Sha512),
+            _ => None,
+        }
+    }
+
+    /// The algorithm name written in the signature
+    pub fn name(self) -> &'static str {
+        match self {
+            SshSigHash::Sha256 => "sha256",
+            SshSigHash::Sha512 => "sha512",
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            SshSigHash::Sha256 => Sha256::digest(data).to_vec(),
+            SshSigHash::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+impl FromStr for SshSigHash {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s).ok_or_else(|| ErrorKind::UnsupportType(s.to_owned()).into())
+    }
+}
+
+impl fmt::Display for SshSigHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The checks applied by [`SshSig::verify_with()`] besides the signature itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// The namespace which must be equal to the one of the signature, or `None` to allow any namespace
+    pub namespace: Option<String>,
+    /// The allowed namespace patterns, or `None` to allow any namespace
+    ///
+    /// The patterns are matched like the `namespaces` option of the allowed_signers file,
+    /// with the `*` and `?` wildcards and the `!` negation.
+    pub namespaces: Option<Vec<String>>,
+    /// The allowed hash algorithms of the message
+    pub hashes: Vec<SshSigHash>,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        VerifyOptions {
+            namespace: None,
+            namespaces: None,
+            hashes: vec![SshSigHash::Sha256, SshSigHash::Sha512],
+        }
+    }
+}
+
+impl VerifyOptions {
+    /// Only allow the exact namespace, like `ssh-keygen -Y verify -n`
+    pub fn namespace(namespace: &str) -> Self {
+        VerifyOptions {
+            namespace: Some(namespace.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// Check the namespace and the hash algorithm of the signature
+    ///
+    /// Fail with [`ErrorKind::PolicyViolation`] if either is not allowed.
+    pub fn check(&self, sig: &SshSig) -> OsshResult<()> {
+        let exact = self
+            .namespace
+            .as_ref()
+            .map_or(true, |ns| *ns == sig.namespace);
+        let matched = self.namespaces.as_ref().map_or(true, |namespaces| {
+            match_pattern_list(namespaces.iter().map(String::as_str), &sig.namespace)
+        });
+        if !exact || !matched {
+            return Err(ErrorKind::PolicyViolation(format!(
+                "namespace {} is not allowed",
+                sig.namespace
+            ))
+            .into());
+        }
+        if !self.hashes.contains(&sig.hash) {
+            return Err(ErrorKind::PolicyViolation(format!(
+                "hash algorithm {} is not allowed",
+                sig.hash
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The signature of the data made by `ssh-keygen -Y sign`, see [PROTOCOL.sshsig](https://github.com/openssh/openssh-portable/blob/master/PROTOCOL.sshsig)
+///
+/// The namespace binds the signature to its purpose, such as `git` or `file`,
+/// so a signature made for one use can't be replayed for another.
+pub struct SshSig {
+    pubkey: PublicKey,
+    namespace: String,
+    hash: SshSigHash,
+    signature: Vec<u8>,
+}
+
+impl SshSig {
+    /// Sign the data in the namespace with SHA-512, like `ssh-keygen -Y sign -n <namespace>`
+    pub fn sign<S: SshSigner + ?Sized>(
+        signer: &S,
+        namespace: &str,
+        data: &[u8],
+    ) -> OsshResult<Self> {
+        Self::sign_with(signer, namespace, SshSigHash::Sha512, data)
+    }
+
+    /// Sign the data in the namespace with the given hash algorithm, like `ssh-keygen -Y sign -O hashalg=<hash>`
+    ///
+    /// The RSA keys are signed with `rsa-sha2-512`.
+    /// Fail with [`ErrorKind::InvalidArgument`] if the namespace is empty.
+    pub fn sign_with<S: SshSigner + ?Sized>(
+        signer: &S,
+        namespace: &str,
+        hash: SshSigHash,
+        data: &[u8],
+    ) -> OsshResult<Self> {
+        if namespace.is_empty() {
+            return Err(ErrorKind::InvalidArgument.into());
+        }
+        let signed = signed_data(namespace, hash, data)?;
+        Ok(SshSig {
+            pubkey: signer.clone_public_key()?,
+            namespace: namespace.to_owned(),
+            hash,
+            signature: signer.sign_ssh_signature_with(&signed, Some(RsaSignature::SHA2_512))?,
+        })
+    }
+
+    /// Parse the armored signature starting with `-----BEGIN SSH SIGNATURE-----`
+    pub fn from_armored(armored: &str) -> OsshResult<Self> {
+        let body = armored
+            .trim()
+            .strip_prefix(SIG_BEGIN)
+            .and_then(|s| s.strip_suffix(SIG_END))
+            .ok_or(ErrorKind::InvalidPemFormat)?;
+        let blob: String = body.split_whitespace().collect();
+        Self::from_blob(&base64::decode(blob)?)
+    }
+
+    /// Decode the binary signature
+    ///
+    /// The reserved field is ignored as the format requires.
+    pub fn from_blob(blob: &[u8]) -> OsshResult<Self> {
+        let mut reader = SshReader::new(blob, SECTION_SSHSIG);
+        if reader.read_bytes("magic", SSHSIG_MAGIC.len())? != SSHSIG_MAGIC {
+            return Err(Error::from_kind(ErrorKind::InvalidKeyFormat).at(
+                SECTION_SSHSIG,
+                "magic",
+                0,
+            ));
+        }
+        let version = reader.read_uint32("version")?;
+        if version != SSHSIG_VERSION {
+            return Err(ErrorKind::UnsupportType(format!("SSHSIG version {}", version)).into());
+        }
+        let pubkey = PublicKey::from_blob(reader.read_string("publickey")?)?;
+        let namespace_pos = reader.position();
+        let namespace = reader.read_utf8("namespace")?.to_owned();
+        if namespace.is_empty() {
+            return Err(Error::from_kind(ErrorKind::InvalidKeyFormat).at(
+                SECTION_SSHSIG,
+                "namespace",
+                namespace_pos,
+            ));
+        }
+        reader.read_string("reserved")?;
+        let hash = reader.read_utf8("hash_algorithm")?.parse()?;
+        let signature = reader.read_string("signature")?.to_vec();
+        reader.finish()?;
+        Ok(SshSig {
+            pubkey,
+            namespace,
+            hash,
+            signature,
+        })
+    }
+
+    /// Encode the binary signature
+    pub fn to_blob(&self) -> OsshResult<Vec<u8>> {
+        let mut buf = SSHSIG_MAGIC.to_vec();
+        buf.write_uint32(SSHSIG_VERSION)?;
+        buf.write_string(&self.pubkey.to_blob()?)?;
+        buf.write_utf8(&self.namespace)?;
+        buf.write_string(&[])?;
+        buf.write_utf8(self.hash.name())?;
+        buf.write_string(&self.signature)?;
+        Ok(buf)
+    }
+
+    /// Serialize the armored signature, the same as the `.sig` file written by `ssh-keygen -Y sign`
+    pub fn serialize(&self) -> OsshResult<String> {
+        let mut out = String::new();
+        out.push_str(SIG_BEGIN);
+        out.push('\n');
+        base64::write_encoded(&mut out, &self.to_blob()?, SIG_LINE_WIDTH)?;
+        out.push('\n');
+        out.push_str(SIG_END);
+        out.push('\n');
+        Ok(out)
+    }
+
+    /// Get the public key of the signer
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pubkey
+    }
+
+    /// Get the namespace of the signature
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Get the hash algorithm of the message
+    pub fn hash(&self) -> SshSigHash {
+        self.hash
+    }
+
+    /// Get the SSH signature in the wire encoding `string algorithm, string signature`
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Verify the signature of the data made in the namespace, like `ssh-keygen -Y verify -n <namespace>`
+    ///
+    /// The signer is not checked, compare [`SshSig::public_key()`] with the trusted keys.
+    pub fn verify(&self, data: &[u8], namespace: &str) -> OsshResult<bool> {
+        self.verify_with(data, &VerifyOptions::namespace(namespace))
+    }
+
+    /// Verify the signature of the data, after checking the namespace and the hash algorithm with the options
+    ///
+    /// Fail with [`ErrorKind::PolicyViolation`] if the options refuse the signature,
+    /// and with [`ErrorKind::LegacyAlgorithm`] for the `ssh-rsa` (SHA-1) signatures, which `ssh-keygen` never makes.
+    pub fn verify_with(&self, data: &[u8], options: &VerifyOptions) -> OsshResult<bool> {
+        options.check(self)?;
+        if SshReader::new(&self.signature, SECTION_SSHSIG).read_utf8("algorithm")? == RSA_NAME {
+            return Err(ErrorKind::LegacyAlgorithm(RSA_NAME.to_owned()).into());
+        }
+        let signed = signed_data(&self.namespace, self.hash, data)?;
+        self.pubkey.verify_ssh_signature(&signed, &self.signature)
+    }
+}
+
+impl FromStr for SshSig {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_armored(s)
+    }
+}
+
+/// Build the data which is actually signed, `byte[6] "SSHSIG", string namespace, string reserved, string hash_algorithm, string H(message)`
+fn signed_data(namespace: &str, hash: SshSigHash, data: &[u8]) -> OsshResult<Vec<u8>> {
+    let mut buf = SSHSIG_MAGIC.to_vec();
+    buf.write_utf8(namespace)?;
+    buf.write_string(&[])?;
+    buf.write_utf8(hash.name())?;
+    buf.write_string(&hash.digest(data))?;
+    Ok(buf)
+}
+
+#[cfg(all(test, feature = "rsa", feature = "ecdsa", feature = "ed25519"))]
+mod test {
+    use super::*;
+    use crate::keys::{KeyPair, KeyType};
+
+    const MESSAGE: &[u8] = b"Hello SSHSIG\n";
+
+    #[test]
+    #[cfg(not(feature = "fips"))]
+    fn sshsig_matches_ssh_keygen() {
+        // Made by `ssh-keygen -Y sign -n file` and `ssh-keygen -Y sign -n git -O hashalg=sha256`
+        for (key, sig, namespace, hash) in [
+            (
+                include_str!("../assets/openssh_ed25519"),
+                include_str!("../assets/sshsig_ed25519.sig"),
+                "file",
+                SshSigHash::Sha512,
+            ),
+            (
+                include_str!("../assets/openssh_rsa"),
+                include_str!("../assets/sshsig_rsa.sig"),
+                "git",
+                SshSigHash::Sha256,
+            ),
+        ] {
+            let keypair = KeyPair::from_keystr(key, None).unwrap();
+            let parsed: SshSig = sig.parse().unwrap();
+            assert_eq!(parsed.namespace(), namespace);
+            assert_eq!(parsed.hash(), hash);
+            assert!(parsed.public_key() == &keypair.clone_public_key().unwrap());
+            assert!(parsed.verify(MESSAGE, namespace).unwrap());
+            assert!(!parsed.verify(b"Hello SSHSIG", namespace).unwrap());
+
+            // Both signature algorithms are deterministic
+            let signed = SshSig::sign_with(&keypair, namespace, hash, MESSAGE).unwrap();
+            assert_eq!(signed.serialize().unwrap(), sig);
+        }
+    }
+
+    #[test]
+    fn sshsig_options() {
+        let keypair = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+        let sig = SshSig::sign(&keypair, "file", MESSAGE).unwrap();
+        assert_eq!(sig.hash(), SshSigHash::Sha512);
+        let sig = SshSig::from_armored(&sig.serialize().unwrap()).unwrap();
+        assert!(sig.verify(MESSAGE, "file").unwrap());
+        assert!(matches!(
+            sig.verify(MESSAGE, "git").unwrap_err().kind(),
+            ErrorKind::PolicyViolation(_)
+        ));
+
+        assert!(sig
+            .verify_with(MESSAGE, &VerifyOptions::namespace("fi*"))
+            .is_err());
+
+        let mut options = VerifyOptions {
+            namespaces: Some(vec!["fi*".into(), "!file".into()]),
+            ..Default::default()
+        };
+        assert!(sig.verify_with(MESSAGE, &options).is_err());
+        options.namespaces = Some(vec!["git".into(), "fi?e".into()]);
+        assert!(sig.verify_with(MESSAGE, &options).unwrap());
+        options.hashes = vec![SshSigHash::Sha256];
+        assert!(matches!(
+            sig.verify_with(MESSAGE, &options).unwrap_err().kind(),
+            ErrorKind::PolicyViolation(_)
+        ));
+        assert!(sig.verify_with(MESSAGE, &VerifyOptions::default()).unwrap());
+
+        assert_eq!(
+            SshSig::sign(&keypair, "", MESSAGE).err().unwrap().kind(),
+            ErrorKind::InvalidArgument
+        );
+        let mut blob = sig.to_blob().unwrap();
+        blob[SSHSIG_MAGIC.len() + 4..].fill(0);
+        assert!(SshSig::from_blob(&blob).is_err());
+        let mut empty_namespace = SshSig::sign(&keypair, "x", MESSAGE).unwrap();
+        empty_namespace.namespace.clear();
+        assert_eq!(
+            SshSig::from_blob(&empty_namespace.to_blob().unwrap())
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::InvalidKeyFormat
+        );
+        assert!(matches!(
+            "sha1".parse::<SshSigHash>().unwrap_err().kind(),
+            ErrorKind::UnsupportType(_)
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+extern crate osshkeys;
+
+use osshkeys::audit::*;
+use osshkeys::keys::*;
+use osshkeys::policy::Policy;
+use std::fs;
+use std::path::Path;
+
+fn copy_asset(dir: &Path, asset: &str, name: &str, mode: u32) {
+    let path = dir.join(name);
+    fs::copy(Path::new("assets").join(asset), &path).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+}
+
+#[test]
+fn scan_ssh_directory() {
+    let dir = std::env::temp_dir().join(format!("osshkeys-scan-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("old")).unwrap();
+    copy_asset(&dir, "openssh_ed25519", "id_ed25519", 0o600);
+    copy_asset(
+        &dir,
+        "openssh_ed25519-cert.pub",
+        "id_ed25519-cert.pub",
+        0o644,
+    );
+    copy_asset(&dir, "openssh_rsa_enc", "id_rsa", 0o644);
+    copy_asset(&dir, "openssh_dsa.pub", "id_dsa.pub", 0o644);
+    copy_asset(&dir, "openssh_ecdsa.pub", "id_ecdsa.pub", 0o644);
+    fs::write(
+        dir.join("authorized_keys"),
+        format!(
+            "# team\n{}\nnot a key\n",
+            fs::read_to_string("assets/openssh_rsa_enc.pub")
+                .unwrap()
+                .trim()
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("known_hosts"),
+        format!(
+            "example.com {}",
+            fs::read_to_string("assets/openssh_ecdsa.pub").unwrap()
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("config"), "Host *\n").unwrap();
+    fs::write(dir.join("old/id_ed25519.pub"), "ssh-ed25519 broken\n").unwrap();
+
+    let rsa = PublicKey::from_keystr(include_str!("../assets/openssh_rsa_enc.pub")).unwrap();
+    let options = ScanOptions {
+        policy: Some(Policy::openssh()),
+        blacklist: vec![Fingerprint::of(&rsa, FingerprintHash::MD5).unwrap()],
+        // 2099-12-01
+        time: Some(4099766400),
+        ..Default::default()
+    };
+    let mut scanner = Scanner::new(options.clone());
+    scanner.scan_path(&dir).unwrap();
+    let report = scanner.finish();
+    assert!(report.has_problems());
+
+    let names: Vec<_> = report
+        .keys
+        .iter()
+        .map(|k| (k.path.file_name().unwrap().to_str().unwrap(), k.line))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("authorized_keys", Some(2)),
+            ("id_dsa.pub", None),
+            ("id_ecdsa.pub", None),
+            ("id_ed25519", None),
+            ("id_ed25519-cert.pub", None),
+            ("id_rsa", None),
+            ("known_hosts", Some(1)),
+        ]
+    );
+    let authorized = &report.keys[0];
+    assert_eq!(authorized.source, KeySource::AuthorizedKeys);
+    assert_eq!(authorized.strength, KeyStrength::Acceptable);
+    assert_eq!(authorized.findings, [Finding::Blacklisted]);
+    assert_eq!(authorized.fingerprint.hash(), FingerprintHash::SHA256);
+
+    let dsa = &report.keys[1];
+    assert_eq!((dsa.keytype, dsa.size), (KeyType::DSA, 1024));
+    assert_eq!(dsa.findings[0], Finding::WeakKey(KeyStrength::Broken));
+    assert!(matches!(dsa.findings[1], Finding::PolicyViolation(_)));
+
+    assert!(report.keys[2].findings.is_empty());
+    assert_eq!(report.keys[3].source, KeySource::PrivateKey);
+    assert!(report.keys[3].findings.is_empty());
+
+    let cert = &report.keys[4];
+    assert_eq!(cert.source, KeySource::Certificate);
+    assert!(matches!(
+        cert.findings[..],
+        [Finding::CertificateExpiresSoon(secs)] if secs == 30 * 86400
+    ));
+    assert!(cert.valid_before.is_some());
+
+    // The public key is read from the encrypted private key without the passphrase
+    let encrypted = &report.keys[5];
+    assert_eq!(encrypted.source, KeySource::PrivateKey);
+    assert!(encrypted.fingerprint.matches(&rsa));
+    #[cfg(unix)]
+    assert_eq!(
+        encrypted.findings,
+        [Finding::Blacklisted, Finding::InsecurePermissions(0o644)]
+    );
+    assert_eq!(report.keys[6].source, KeySource::KnownHosts);
+    assert!(report.keys[6].to_string().contains("(ECDSA) strong"));
+
+    // The invalid line, and not the unrelated config file nor the skipped subdirectory
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, Some(3));
+
+    let mut scanner = Scanner::new(ScanOptions {
+        recursive: true,
+        ..options
+    });
+    scanner.scan_path(&dir).unwrap();
+    scanner.scan_known_hosts(dir.join("config"));
+    let report = scanner.finish();
+    assert_eq!(report.keys.len(), 7);
+    assert_eq!(report.errors.len(), 3);
+    assert!(report.errors[1].path.ends_with("old/id_ed25519.pub"));
+    assert_eq!(report.errors[2].line, Some(1));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fingerprint_key_files() {
+    let hashes = [FingerprintHash::SHA256, FingerprintHash::MD5];
+    let pubkey = PublicKey::from_keystr(include_str!("../assets/openssh_ed25519.pub")).unwrap();
+    let sha256 = Fingerprint::of(&pubkey, FingerprintHash::SHA256).unwrap();
+
+    for (path, source) in [
+        ("assets/openssh_ed25519", KeySource::PrivateKey),
+        ("assets/openssh_ed25519.pub", KeySource::PublicKey),
+        ("assets/openssh_ed25519-cert.pub", KeySource::Certificate),
+    ] {
+        let keys = fingerprint_file(path, &hashes).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].source, source);
+        assert_eq!((keys[0].keytype, keys[0].size), (KeyType::ED25519, 256));
+        assert_eq!(keys[0].fingerprints[0], sha256);
+        assert_eq!(keys[0].fingerprints[1].hash(), FingerprintHash::MD5);
+    }
+    let cert = &fingerprint_file("assets/openssh_ed25519-cert.pub", &hashes[..1]).unwrap()[0];
+    assert!(cert.to_string().ends_with(" (ED25519-CERT)"));
+    assert_eq!(
+        fingerprint_file("assets/openssh_ed25519.pub", &hashes[..1]).unwrap()[0].to_string(),
+        format!("256 {} no comment (ED25519)", sha256)
+    );
+
+    // The public key is read from the encrypted OpenSSH private key without the passphrase
+    let rsa = PublicKey::from_keystr(include_str!("../assets/openssh_rsa_enc.pub")).unwrap();
+    let keys = fingerprint_file("assets/openssh_rsa_enc", &hashes).unwrap();
+    assert!(keys[0].fingerprints[1].matches(&rsa));
+    assert!(fingerprint_file("assets/pem_rsa_enc", &hashes).is_err());
+
+    let authorized_keys = format!(
+        "# team\nno-pty {}not a key\n{}",
+        include_str!("../assets/openssh_ed25519.pub"),
+        include_str!("../assets/openssh_rsa_enc.pub")
+    );
+    let keys = fingerprint_keystr(&authorized_keys, &hashes[..1]).unwrap();
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[0].source, KeySource::AuthorizedKeys);
+    assert_eq!(keys[0].line, Some(2));
+    assert_eq!(keys[1].keytype, KeyType::RSA);
+    assert!(fingerprint_keystr("not a key\n", &hashes).is_err());
+
+    let dir = std::env::temp_dir().join(format!("osshkeys-fingerprint-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let known_hosts = dir.join("known_hosts");
+    fs::write(
+        &known_hosts,
+        format!(
+            "example.com,10.0.0.1 {}",
+            include_str!("../assets/openssh_ed25519.pub")
+        ),
+    )
+    .unwrap();
+    let keys = fingerprint_file(&known_hosts, &hashes[..1]).unwrap();
+    assert_eq!(keys[0].source, KeySource::KnownHosts);
+    assert_eq!(
+        keys[0].to_string(),
+        format!("256 {} example.com,10.0.0.1 (ED25519)", sha256)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lint_authorized_keys_file() {
+    let ed25519 = include_str!("../assets/openssh_ed25519.pub").trim();
+    let rsa = include_str!("../assets/openssh_rsa_enc.pub").trim();
+    let dsa = include_str!("../assets/openssh_dsa.pub").trim();
+    let file = format!(
+        "# team\n\
+         restrict,pty,command=\"echo \\\"a,b\\\"\" {ed25519}\n\
+         no-pty,PTY,no-agent-forwarding,agent-forwarding {rsa}\n\
+         command=\"a\",command=\"b\",no-pty=yes,from=10.0.0.1,foo {ed25519}\n\
+         {dsa}\n\
+         \n\
+         ssh-ed25519 broken\n\
+         from=\"{long}\" {rsa}\n",
+        long = "a".repeat(9000),
+    );
+    let findings = lint_authorized_keys(&file, &LintOptions::default());
+    let expected = [
+        (
+            3,
+            LintIssue::ConflictingOptions("pty".into(), "no-pty".into()),
+        ),
+        (
+            3,
+            LintIssue::ConflictingOptions("agent-forwarding".into(), "no-agent-forwarding".into()),
+        ),
+        (4, LintIssue::DuplicateOption("command".into())),
+        (4, LintIssue::MalformedOption("no-pty".into())),
+        (4, LintIssue::MalformedOption("from".into())),
+        (4, LintIssue::UnknownOption("foo".into())),
+        (4, LintIssue::DuplicateKey(2)),
+        (5, LintIssue::DeprecatedKeyType("ssh-dss".into())),
+        (
+            7,
+            LintIssue::SyntaxError(osshkeys::error::ErrorKind::Base64Error),
+        ),
+        (8, LintIssue::LineTooLong(9000 + 8 + rsa.len())),
+        (8, LintIssue::DuplicateKey(3)),
+    ];
+    let findings: Vec<_> = findings.into_iter().map(|f| (f.line, f.issue)).collect();
+    assert_eq!(findings, expected);
+    assert_eq!(
+        LintFinding {
+            line: 4,
+            issue: LintIssue::UnknownOption("foo".into())
+        }
+        .to_string(),
+        "line 4: unknown option foo"
+    );
+
+    let file = format!("{}\n", ed25519);
+    assert!(lint_authorized_keys(&file, &LintOptions::default()).is_empty());
+}
@@ -0,0 +1,70 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+extern crate osshkeys;
+
+use osshkeys::cipher::Cipher;
+use osshkeys::defaults::*;
+use osshkeys::format::{convert_key, ConvertOptions, OutputFormat};
+use osshkeys::keys::ecdsa::EcCurve;
+use osshkeys::keys::*;
+use osshkeys::policy::Policy;
+
+// The defaults are shared by the whole process, so they are only changed by this single test
+#[test]
+fn set_process_wide_defaults() {
+    assert_eq!(defaults(), Defaults::default());
+    assert_eq!(defaults().key_type, KeyType::ED25519);
+
+    set_defaults(Defaults {
+        key_type: KeyType::RSA,
+        rsa_bits: 3072,
+        ecdsa_curve: EcCurve::Nistp384,
+        cipher: Cipher::Aes128_Ctr,
+        kdf_rounds: 4,
+    });
+    assert_eq!(defaults().rsa_bits, 3072);
+
+    let keypair = KeyPair::generate(KeyType::ECDSA, 0).unwrap();
+    assert_eq!(keypair.size(), 384);
+    let keypair = KeyPair::generate(KeyType::ECDSA, 256).unwrap();
+    assert_eq!(keypair.size(), 256);
+    let keypair = defaults().generate().unwrap();
+    assert_eq!(keypair.keytype(), KeyType::RSA);
+    assert_eq!(keypair.size(), 3072);
+
+    // The KDF rounds apply to the keys encrypted with an explicit cipher
+    let keystr = keypair
+        .serialize_openssh(Some("12345678"), Cipher::Aes256_Ctr)
+        .unwrap();
+    let policy = Policy {
+        min_kdf_rounds: 5,
+        ..Policy::default()
+    };
+    assert!(policy.check_keystr(&keystr).is_err());
+
+    let converted = convert_key(
+        keystr.as_bytes(),
+        Some("12345678"),
+        OutputFormat::Openssh,
+        Some("87654321"),
+        &ConvertOptions::default(),
+    )
+    .unwrap();
+    let policy = Policy {
+        ciphers: Some(vec![Cipher::Aes128_Ctr]),
+        min_kdf_rounds: 4,
+        ..Policy::default()
+    };
+    policy
+        .check_keystr(std::str::from_utf8(&converted).unwrap())
+        .unwrap();
+
+    set_defaults(Defaults::default());
+    let keypair = KeyPair::generate(KeyType::ECDSA, 0).unwrap();
+    assert_eq!(keypair.size(), 256);
+}
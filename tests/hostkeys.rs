@@ -0,0 +1,197 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+extern crate osshkeys;
+
+use osshkeys::hostkeys::*;
+use osshkeys::keys::*;
+use std::fs;
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn generate_host_keys_set() {
+    let keys = generate_host_keys("root@localhost").unwrap();
+    assert_eq!(keys.len(), HOST_KEY_TYPES.len());
+    for (key, keytype) in keys.iter().zip(HOST_KEY_TYPES.iter()) {
+        assert_eq!(key.keypair.keytype(), *keytype);
+        assert_eq!(key.keypair.comment(), "root@localhost");
+    }
+    assert_eq!(keys[2].filename, "ssh_host_ed25519_key");
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn write_host_keys_skip_existing() {
+    let dir = std::env::temp_dir().join(format!("osshkeys-hostkeys-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("ssh_host_rsa_key"), "existing").unwrap();
+
+    let written = write_host_keys(&dir, "root@localhost").unwrap();
+    assert_eq!(written.len(), 2);
+    assert_eq!(
+        fs::read_to_string(dir.join("ssh_host_rsa_key")).unwrap(),
+        "existing"
+    );
+
+    let privkey = fs::read_to_string(dir.join("ssh_host_ed25519_key")).unwrap();
+    let keypair = KeyPair::from_keystr(&privkey, None).unwrap();
+    let pubkey = fs::read_to_string(dir.join("ssh_host_ed25519_key.pub")).unwrap();
+    assert_eq!(pubkey.trim_end(), keypair.serialize_publickey().unwrap());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let meta = fs::metadata(dir.join("ssh_host_ecdsa_key")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    }
+
+    assert!(write_host_keys(&dir, "").unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn hostkeys_prove_roundtrip() {
+    let keypairs: Vec<KeyPair> = generate_host_keys("")
+        .unwrap()
+        .into_iter()
+        .map(|key| key.keypair)
+        .collect();
+    let pubkeys: Vec<PublicKey> = keypairs
+        .iter()
+        .map(|keypair| keypair.clone_public_key().unwrap())
+        .collect();
+    let session_id = b"session identifier";
+
+    let announce = encode_hostkeys(&pubkeys).unwrap();
+    assert!(decode_hostkeys(&announce).unwrap() == pubkeys);
+
+    // The client asks to prove the last two keys
+    let request = encode_hostkeys(&pubkeys[1..]).unwrap();
+    let reply =
+        prove_hostkeys(&keypairs, session_id, &request, rsa::RsaSignature::SHA2_512).unwrap();
+    assert!(verify_hostkeys_prove(&pubkeys[1..], session_id, &reply).unwrap());
+    assert!(!verify_hostkeys_prove(&pubkeys[1..], b"another session", &reply).unwrap());
+    assert!(!verify_hostkeys_prove(&pubkeys, session_id, &reply).unwrap());
+    assert!(!verify_hostkeys_prove(&pubkeys[1..2], session_id, &reply).unwrap());
+
+    let rsa_reply = prove_hostkeys(
+        &keypairs,
+        session_id,
+        &encode_hostkeys(&pubkeys[..1]).unwrap(),
+        rsa::RsaSignature::SHA2_256,
+    )
+    .unwrap();
+    assert!(verify_hostkeys_prove(&pubkeys[..1], session_id, &rsa_reply).unwrap());
+
+    // The unknown key can't be proved
+    let other = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+    let request = encode_hostkeys(&[other.clone_public_key().unwrap()]).unwrap();
+    assert!(prove_hostkeys(&keypairs, session_id, &request, Default::default()).is_err());
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn hostkeys_skip_unsupported() {
+    let pubkey = KeyPair::generate(KeyType::ED25519, 0)
+        .unwrap()
+        .clone_public_key()
+        .unwrap();
+    let mut announce = vec![0, 0, 0, 11];
+    announce.extend_from_slice(&[0, 0, 0, 7]);
+    announce.extend_from_slice(b"unknown");
+    announce.extend(encode_hostkeys(std::slice::from_ref(&pubkey)).unwrap());
+    let decoded = decode_hostkeys(&announce).unwrap();
+    assert_eq!(decoded.len(), 1);
+    assert!(decoded[0] == pubkey);
+}
+
+#[test]
+fn sshfp_records_like_ssh_keygen() {
+    let pubkey =
+        PublicKey::from_keystr(&fs::read_to_string("assets/openssh_ed25519.pub").unwrap()).unwrap();
+    assert_eq!(
+        sshfp_records("example.com", &pubkey).unwrap(),
+        [
+            "example.com IN SSHFP 4 1 2e739136b2ee1af75fdf38a6ebfb3f9ee2f27ee5",
+            "example.com IN SSHFP 4 2 e2ee30f4aa14fb27ea72544c1be487fc0985b7cd0dc7ef2931756b598c36cd3d",
+        ]
+    );
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn rotate_host_keys_with_grace_period() {
+    let dir = std::env::temp_dir().join(format!("osshkeys-rotation-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    write_host_keys(&dir, "old").unwrap();
+    let old = fs::read_to_string(dir.join("ssh_host_ed25519_key.pub")).unwrap();
+
+    let rotation = rotate_host_keys(&dir, "new").unwrap();
+    assert_eq!(rotation.generated.len(), HOST_KEY_TYPES.len());
+    assert_eq!(rotation.active.len(), HOST_KEY_TYPES.len());
+    assert_eq!(rotation.retired.len(), HOST_KEY_TYPES.len());
+    assert_eq!(rotation.generated[2], dir.join("ssh_host_ed25519_key.next"));
+    // The old keys stay in service during the grace period
+    assert_eq!(
+        fs::read_to_string(dir.join("ssh_host_ed25519_key.pub")).unwrap(),
+        old
+    );
+    let new = fs::read_to_string(dir.join("ssh_host_ed25519_key.next.pub")).unwrap();
+    assert_ne!(old, new);
+    assert!(new.trim_end().ends_with(" new"));
+    assert!(rotate_host_keys(&dir, "new").is_err());
+
+    let retired = rotation
+        .retired
+        .iter()
+        .find(|key| key.pubkey.keytype() == KeyType::ED25519)
+        .unwrap();
+    assert_eq!(retired.path, dir.join("ssh_host_ed25519_key"));
+    assert!(retired.pubkey == PublicKey::from_keystr(&old).unwrap());
+    assert!(rotation.active.iter().all(|key| *key != retired.pubkey));
+
+    // Both the new and the retired keys are published
+    let lines = rotation.known_hosts_lines("example.com,192.0.2.1").unwrap();
+    assert_eq!(lines.len(), HOST_KEY_TYPES.len() * 2);
+    assert!(lines.contains(&format!(
+        "example.com,192.0.2.1 {}",
+        new.trim_end().trim_end_matches(" new")
+    )));
+    assert!(lines.contains(&format!(
+        "example.com,192.0.2.1 {}",
+        old.trim_end().trim_end_matches(" old")
+    )));
+    assert_eq!(
+        rotation.sshfp_records("example.com").unwrap().len(),
+        HOST_KEY_TYPES.len() * 4
+    );
+
+    // The retired keys are replaced once the grace period is over
+    let grace = std::time::Duration::from_secs(7 * 24 * 3600);
+    let retired_at = retired.retired_at;
+    assert!(prune_retired_host_keys(&dir, grace).unwrap().is_empty());
+    let replaced = prune_retired_host_keys_at(&dir, grace, retired_at + grace.as_secs()).unwrap();
+    assert_eq!(replaced.len(), HOST_KEY_TYPES.len());
+    assert!(retired_host_keys(&dir).unwrap().is_empty());
+    assert_eq!(
+        fs::read_to_string(dir.join("ssh_host_ed25519_key.pub")).unwrap(),
+        new
+    );
+    let privkey = fs::read_to_string(dir.join("ssh_host_ed25519_key")).unwrap();
+    assert_eq!(
+        KeyPair::from_keystr(&privkey, None)
+            .unwrap()
+            .serialize_publickey()
+            .unwrap(),
+        new.trim_end()
+    );
+    assert_eq!(
+        fs::read_dir(&dir).unwrap().count(),
+        HOST_KEY_TYPES.len() * 2
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+extern crate osshkeys;
+
+use osshkeys::hooks::*;
+use osshkeys::keys::*;
+use osshkeys::keystore::Keystore;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+// The hook is shared by the whole process, so it is only set by this single test
+#[test]
+fn report_key_usage() {
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&records);
+    set_key_usage_hook(move |record| sink.lock().unwrap().push(record.clone()));
+    let take = || std::mem::take(&mut *records.lock().unwrap());
+
+    // The unencrypted keys are not reported
+    let plain =
+        KeyPair::from_keystr(&fs::read_to_string("assets/openssh_ed25519").unwrap(), None).unwrap();
+    assert!(take().is_empty());
+
+    let keystr = fs::read_to_string("assets/openssh_ed25519_enc").unwrap();
+    let keypair = KeyPair::from_keystr(&keystr, Some("12345678")).unwrap();
+    let fingerprint = Fingerprint::of(&keypair, FingerprintHash::SHA256).unwrap();
+    let expected = KeyUsageRecord {
+        operation: KeyOperation::Decrypt,
+        fingerprint: fingerprint.clone(),
+        context: None,
+    };
+    assert_eq!(take(), vec![expected.clone()]);
+    KeyPair::from_keystr_exact(&keystr, Some("12345678")).unwrap();
+    assert_eq!(take(), [expected]);
+    assert!(KeyPair::from_keystr(&keystr, Some("wrong")).is_err());
+    assert!(take().is_empty());
+
+    let keystr = fs::read_to_string("assets/pem_rsa_enc").unwrap();
+    with_context("load rsa", || {
+        KeyPair::from_keystr(&keystr, Some("12345678"))
+    })
+    .unwrap();
+    let rsa_records = take();
+    assert_eq!(rsa_records.len(), 1);
+    assert_eq!(rsa_records[0].operation, KeyOperation::Decrypt);
+    assert_eq!(rsa_records[0].context.as_deref(), Some("load rsa"));
+
+    with_context("deploy job 42", || {
+        keypair.sign(b"data").unwrap();
+        keypair.sign_ssh_signature(b"data").unwrap();
+        keypair.sign_ed25519ctx(b"data", b"context").unwrap();
+        keypair.sign_ed25519ph(b"data", None).unwrap();
+    });
+    let expected = KeyUsageRecord {
+        operation: KeyOperation::Sign,
+        fingerprint: fingerprint.clone(),
+        context: Some("deploy job 42".to_string()),
+    };
+    assert_eq!(take(), vec![expected.clone(); 4]);
+    assert_eq!(
+        expected.to_string(),
+        format!("sign with {} context \"deploy job 42\"", fingerprint)
+    );
+
+    let mut keystore = Keystore::new();
+    keystore.add("plain", plain).unwrap();
+    let sealed = keystore.seal_with_rounds("12345678", 4).unwrap();
+    assert!(take().is_empty());
+    Keystore::open(&sealed, "12345678").unwrap();
+    let opened = take();
+    assert_eq!(opened.len(), 1);
+    assert_eq!(opened[0].operation, KeyOperation::Decrypt);
+
+    clear_key_usage_hook();
+    keypair.sign(b"data").unwrap();
+    assert!(take().is_empty());
+}
@@ -1,3 +1,9 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
 extern crate osshkeys;
 
 use osshkeys::keys::*;
@@ -26,6 +32,8 @@ fn verify_key<P: AsRef<Path>>(keyfile: P, passphrase: Option<&str>) {
 
     utils::fingerprint_assert(&privkey, &pubkey);
     utils::fingerprint_randomart_assert(&randomart, &pubkey);
+    assert!(privkey.matches_public(&pubkey));
+    assert!(privkey.clone_public_key().unwrap() == pubkey);
 
     // Make sure that privkey can be serialized
     // https://github.com/Leo1003/rust-osshkeys/issues/4
@@ -39,6 +47,116 @@ fn verify_key<P: AsRef<Path>>(keyfile: P, passphrase: Option<&str>) {
         .unwrap();
 }
 
+#[test]
+fn keyfile_randomart_md5() {
+    const RANDOMART: &str = "\
++--[ED25519 256]--+
+|        ...      |
+|     . o o .     |
+|      o + +   .  |
+|       . + .   + |
+|      . S   . . o|
+|      E..    . . |
+|       . . .  o  |
+|       .=.. o  o |
+|      .oo+ . ..  |
++------[MD5]------+";
+    let pubdata = fs::read(utils::locate_crate_files("assets/openssh_ed25519_enc.pub")).unwrap();
+    let pubkey = PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap();
+    assert_eq!(pubkey.randomart(FingerprintHash::MD5).unwrap(), RANDOMART);
+}
+
+#[test]
+fn keyfile_bubblebabble() {
+    let pubdata = fs::read(utils::locate_crate_files("assets/openssh_rsa.pub")).unwrap();
+    let pubkey = PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap();
+    assert_eq!(
+        pubkey.fingerprint_bubblebabble().unwrap(),
+        "xudib-gulov-puled-nipec-vecel-kupov-kyvin-mopyh-pegeh-bazig-zixix"
+    );
+}
+
+#[test]
+fn keyfile_fingerprint_matches() {
+    let pubdata = fs::read(utils::locate_crate_files("assets/openssh_ed25519_enc.pub")).unwrap();
+    let pubkey = PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap();
+    let fp: Fingerprint = "SHA512:83lGUBZjU6vpToCKhSERoQ7V4rjQiaXWkRPc8UnvQiuEMTY7UfVQNfSLlZHP0Q7X7oPisFR1GgtWLs3/m5vumw"
+        .parse()
+        .unwrap();
+    assert!(fp.matches(&pubkey));
+    assert_eq!(
+        Fingerprint::of(&pubkey, FingerprintHash::SHA512).unwrap(),
+        fp
+    );
+    let fp384: Fingerprint =
+        "SHA384:Wn8LOLRD8vlHxrwZHlIspijFsUEsVyiFGr2j3K2c6ysuTmZnMKSYc8JXZYOcTGx6"
+            .parse()
+            .unwrap();
+    assert!(fp384.matches(&pubkey));
+    assert!(pubkey
+        .randomart(FingerprintHash::SHA384)
+        .unwrap()
+        .ends_with("+----[SHA384]-----+"));
+
+    let pubdata = fs::read(utils::locate_crate_files("assets/openssh_rsa.pub")).unwrap();
+    let other = PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap();
+    assert!(!fp.matches(&other));
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn keyfile_publickey_hashset() {
+    let mut set = std::collections::HashSet::new();
+    for keyfile in ["assets/openssh_rsa.pub", "assets/openssh_ed25519.pub"] {
+        let pubdata = fs::read(utils::locate_crate_files(keyfile)).unwrap();
+        let pubkey = PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap();
+        assert!(set.insert(pubkey));
+    }
+    let privdata = fs::read(utils::locate_crate_files("assets/openssh_rsa")).unwrap();
+    let privkey = KeyPair::from_keystr(from_utf8(privdata.as_slice()).unwrap(), None).unwrap();
+    let mut pubkey = privkey.clone_public_key().unwrap();
+    pubkey.comment_mut().push_str("another comment");
+    assert!(set.contains(&pubkey));
+    assert!(!set.insert(pubkey));
+
+    let other = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+    assert!(!set.contains(&other.clone_public_key().unwrap()));
+    assert!(!set.iter().any(|k| other.matches_public(k)));
+}
+
+#[test]
+fn keyfile_publickey_sort() {
+    let mut keys: Vec<PublicKey> = [
+        "assets/openssh_ed25519.pub",
+        "assets/openssh_rsa.pub",
+        "assets/openssh_ecdsa.pub",
+        "assets/openssh_dsa.pub",
+        "assets/openssh_rsa_enc.pub",
+    ]
+    .iter()
+    .map(|keyfile| {
+        let pubdata = fs::read(utils::locate_crate_files(keyfile)).unwrap();
+        PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap()
+    })
+    .collect();
+    keys.sort();
+    let types: Vec<KeyType> = keys.iter().map(|k| k.keytype()).collect();
+    assert_eq!(
+        types,
+        [
+            KeyType::RSA,
+            KeyType::RSA,
+            KeyType::DSA,
+            KeyType::ECDSA,
+            KeyType::ED25519
+        ]
+    );
+    assert!(keys[0] < keys[1]);
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(keys.binary_search(key), Ok(i));
+    }
+}
+
 #[test]
 fn keyfile_pem_rsa() {
     verify_key("assets/pem_rsa", None);
@@ -49,6 +167,20 @@ fn keyfile_pem_rsa_enc() {
     verify_key("assets/pem_rsa_enc", Some(TEST_FILE_PASS));
 }
 
+#[cfg(feature = "legacy-des")]
+#[test]
+fn keyfile_pem_rsa_des() {
+    verify_key("assets/pem_rsa_des", Some(TEST_FILE_PASS));
+    let keystr = fs::read_to_string(utils::locate_crate_files("assets/pem_rsa_des")).unwrap();
+    assert_eq!(
+        KeyPair::from_keystr(&keystr, Some("wrong passphrase"))
+            .err()
+            .unwrap()
+            .kind(),
+        osshkeys::error::ErrorKind::IncorrectPass
+    );
+}
+
 #[test]
 fn keyfile_pem_dsa() {
     verify_key("assets/pem_dsa", None);
@@ -75,6 +207,7 @@ fn keyfile_openssh_rsa() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn keyfile_openssh_rsa_enc() {
     verify_key("assets/openssh_rsa_enc", Some(TEST_FILE_PASS));
 }
@@ -85,6 +218,7 @@ fn keyfile_openssh_dsa() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn keyfile_openssh_dsa_enc() {
     verify_key("assets/openssh_dsa_enc", Some(TEST_FILE_PASS));
 }
@@ -95,6 +229,7 @@ fn keyfile_openssh_ecdsa() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn keyfile_openssh_ecdsa_enc() {
     verify_key("assets/openssh_ecdsa_enc", Some(TEST_FILE_PASS));
 }
@@ -105,6 +240,7 @@ fn keyfile_openssh_ed25519() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn keyfile_openssh_ed25519_enc() {
     verify_key("assets/openssh_ed25519_enc", Some(TEST_FILE_PASS));
 }
@@ -142,3 +278,92 @@ fn keyfile_pem_ecdsa_wrong() {
 fn keyfile_pem_ed25519_wrong() {
     verify_key("assets/pem_ed25519_enc", Some("^&@#Y&G*"));
 }
+
+#[test]
+#[cfg(not(feature = "fips"))]
+#[cfg(feature = "aes-cbc")]
+fn keyfile_serialize_reproducible() {
+    use osshkeys::cipher::Cipher;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let read = |path: &str| {
+        let keystr = fs::read_to_string(utils::locate_crate_files(path)).unwrap();
+        KeyPair::from_keystr(&keystr, None).unwrap()
+    };
+    for keypair in [read("assets/openssh_ed25519"), read("assets/pem_rsa")] {
+        let serialize = |seed| {
+            keypair
+                .serialize_openssh_with_rng(
+                    Some(TEST_FILE_PASS),
+                    Cipher::Aes256_Ctr,
+                    &mut StdRng::seed_from_u64(seed),
+                )
+                .unwrap()
+        };
+        assert_eq!(serialize(1), serialize(1));
+        assert_ne!(serialize(1), serialize(2));
+        let parsed = KeyPair::from_keystr(&serialize(1), Some(TEST_FILE_PASS)).unwrap();
+        assert!(parsed.clone_public_key().unwrap() == keypair.clone_public_key().unwrap());
+    }
+
+    let keypair = read("assets/pem_ecdsa");
+    let serialize = |seed| {
+        keypair
+            .serialize_pem_with_rng(Some(TEST_FILE_PASS), &mut StdRng::seed_from_u64(seed))
+            .unwrap()
+    };
+    assert_eq!(serialize(1), serialize(1));
+    assert_ne!(serialize(1), serialize(2));
+    let parsed = KeyPair::from_keystr(&serialize(1), Some(TEST_FILE_PASS)).unwrap();
+    assert!(parsed.clone_public_key().unwrap() == keypair.clone_public_key().unwrap());
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn keyfile_openssh_exact_roundtrip() {
+    for name in ["rsa", "dsa", "ecdsa", "ed25519"] {
+        for (suffix, passphrase) in [("", None), ("_enc", Some(TEST_FILE_PASS))] {
+            let path = utils::locate_crate_files(format!("assets/openssh_{}{}", name, suffix));
+            let keystr = fs::read_to_string(path).unwrap();
+            let mut privkey = KeyPair::from_keystr_exact(&keystr, passphrase).unwrap();
+            assert_eq!(privkey.serialize_exact(passphrase).unwrap(), keystr);
+
+            // The changes are written with the same encoding
+            privkey.set_comment("changed");
+            let changed = privkey.serialize_exact(passphrase).unwrap();
+            let reparsed = KeyPair::from_keystr_exact(&changed, passphrase).unwrap();
+            assert_eq!(reparsed.comment(), "changed");
+            assert_eq!(reparsed.serialize_exact(passphrase).unwrap(), changed);
+        }
+    }
+
+    let keystr = fs::read_to_string(utils::locate_crate_files("assets/openssh_ed25519")).unwrap();
+    let crlf = keystr.replace('\n', "\r\n");
+    let privkey = KeyPair::from_keystr_exact(&crlf, None).unwrap();
+    assert_eq!(privkey.serialize_exact(None).unwrap(), crlf);
+    let no_newline = keystr.trim_end().to_owned();
+    let privkey = KeyPair::from_keystr_exact(&no_newline, None).unwrap();
+    assert_eq!(privkey.serialize_exact(None).unwrap(), no_newline);
+
+    let privkey = KeyPair::from_keystr(&keystr, None).unwrap();
+    assert!(privkey.serialize_exact(None).is_err());
+    let pem = fs::read_to_string(utils::locate_crate_files("assets/pem_rsa")).unwrap();
+    assert!(KeyPair::from_keystr_exact(&pem, None).is_err());
+    let irregular = keystr.replacen('\n', "", 2);
+    assert!(KeyPair::from_keystr_exact(&irregular, None).is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn parse_key_file_async() {
+    let keyfile = utils::locate_crate_files("assets/openssh_ed25519_enc");
+    let keystr = fs::read(&keyfile).unwrap();
+    let keypair = osshkeys::format::parse_keystr_reader_async(&keystr[..], Some(TEST_FILE_PASS))
+        .await
+        .unwrap();
+    let pubkey =
+        PublicKey::from_keystr(&fs::read_to_string(keyfile.with_extension("pub")).unwrap())
+            .unwrap();
+    assert!(keypair.matches_public(&pubkey));
+}
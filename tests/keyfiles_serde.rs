@@ -1,3 +1,12 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    feature = "aes-cbc",
+    feature = "3des-cbc",
+    not(feature = "fips")
+))]
 extern crate osshkeys;
 
 use osshkeys::cipher::Cipher;
@@ -1,3 +1,9 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
 extern crate osshkeys;
 
 use osshkeys::keys::*;
@@ -33,6 +39,7 @@ fn rsa_generate_strange() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn dsa_generate() {
     let key = KeyPair::generate(KeyType::DSA, 0).unwrap();
     println!("{}", key.clone_public_key().unwrap());
@@ -81,6 +88,7 @@ fn ecdsa_generate_invalid() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn ed25519_generate() {
     let key = KeyPair::generate(KeyType::ED25519, 0).unwrap();
     println!("{}", key.clone_public_key().unwrap());
@@ -94,3 +102,50 @@ fn ed25519_generate() {
 fn ed25519_generate_invalid() {
     KeyPair::generate(KeyType::ED25519, 512).unwrap();
 }
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn generate_with_rng_deterministic() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    for (keytype, bits) in [
+        (KeyType::RSA, 1024),
+        (KeyType::ECDSA, 256),
+        (KeyType::ECDSA, 521),
+        (KeyType::ED25519, 0),
+    ] {
+        let key1 =
+            KeyPair::generate_with_rng(keytype, bits, &mut StdRng::seed_from_u64(0x5eed)).unwrap();
+        let key2 =
+            KeyPair::generate_with_rng(keytype, bits, &mut StdRng::seed_from_u64(0x5eed)).unwrap();
+        assert_eq!(key1.keytype(), keytype);
+        assert_eq!(
+            key1.fingerprint(FingerprintHash::SHA256).unwrap(),
+            key2.fingerprint(FingerprintHash::SHA256).unwrap()
+        );
+    }
+}
+
+#[test]
+#[cfg(not(feature = "fips"))]
+fn dsa_generate_with_rng() {
+    let key = KeyPair::generate_with_rng(KeyType::DSA, 0, &mut rand::rngs::OsRng).unwrap();
+    assert_eq!(key.keytype(), KeyType::DSA);
+    assert_eq!(key.size(), 1024);
+}
+
+#[test]
+fn generate_in_thread() {
+    let handle = KeyPair::generate_in_thread(KeyType::ECDSA, 384);
+    let key = handle.join().unwrap();
+    assert_eq!(key.keytype(), KeyType::ECDSA);
+    assert_eq!(key.size(), 384);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn generate_async() {
+    let key = KeyPair::generate_async(KeyType::ED25519, 0).await.unwrap();
+    assert_eq!(key.keytype(), KeyType::ED25519);
+}
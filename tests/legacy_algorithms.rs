@@ -0,0 +1,68 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519",
+    not(feature = "fips")
+))]
+extern crate osshkeys;
+
+use osshkeys::error::ErrorKind;
+use osshkeys::keys::cert::*;
+use osshkeys::keys::rsa::RsaSignature;
+use osshkeys::keys::*;
+use std::fs;
+
+// The global switch is shared by the whole process, so it is only changed by this single test
+#[test]
+fn refuse_legacy_algorithms_globally() {
+    let ca = KeyPair::from_keystr(&fs::read_to_string("assets/ca_rsa").unwrap(), None).unwrap();
+    let key =
+        PublicKey::from_keystr(&fs::read_to_string("assets/openssh_ed25519.pub").unwrap()).unwrap();
+    let fields = CertificateFields {
+        cert_type: CertType::Host,
+        valid_principals: vec!["example.com".to_string()],
+        ..Default::default()
+    };
+    let cas = [ca.clone_public_key().unwrap()];
+    let legacy_cert = Certificate::sign(&key, &fields, &ca, RsaSignature::SHA1).unwrap();
+    assert!(legacy_cert
+        .verify(&cas, CertType::Host, "example.com")
+        .unwrap());
+    let dsa_key =
+        KeyPair::from_keystr(&fs::read_to_string("assets/openssh_dsa").unwrap(), None).unwrap();
+    let dsa_sig = dsa_key.sign_ssh_signature(b"data").unwrap();
+
+    assert!(legacy_algorithms_allowed());
+    set_legacy_algorithms(false);
+    assert!(!legacy_algorithms_allowed());
+
+    // The RSA key parsed from the OpenSSH format signs with `ssh-rsa` by default
+    let err = ca.sign_ssh_signature(b"data").unwrap_err();
+    assert_eq!(
+        err.kind(),
+        ErrorKind::LegacyAlgorithm("ssh-rsa".to_string())
+    );
+    assert!(Certificate::sign(&key, &fields, &ca, RsaSignature::SHA1).is_err());
+    let cert = Certificate::sign(&key, &fields, &ca, RsaSignature::SHA2_512).unwrap();
+    assert!(cert.verify(&cas, CertType::Host, "example.com").unwrap());
+
+    let err = legacy_cert
+        .verify(&cas, CertType::Host, "example.com")
+        .unwrap_err();
+    assert_eq!(
+        err.kind(),
+        ErrorKind::LegacyAlgorithm("ssh-rsa".to_string())
+    );
+    let dsa_pubkey = dsa_key.clone_public_key().unwrap();
+    assert!(dsa_key.sign_ssh_signature(b"data").is_err());
+    assert!(dsa_pubkey.verify_ssh_signature(b"data", &dsa_sig).is_err());
+
+    // The per-call setting overrides the global one
+    assert!(dsa_pubkey
+        .verify_ssh_signature_checked(b"data", &dsa_sig, LegacyAlgorithms::Allow)
+        .unwrap());
+
+    set_legacy_algorithms(true);
+    assert!(dsa_pubkey.verify_ssh_signature(b"data", &dsa_sig).unwrap());
+}
@@ -0,0 +1,61 @@
+#![cfg(all(
+    feature = "memsec",
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+extern crate osshkeys;
+
+use osshkeys::keys::*;
+use osshkeys::locked::LockedKeyPair;
+use std::fs;
+use std::str::from_utf8;
+use std::sync::Arc;
+
+mod utils;
+
+#[test]
+fn locked_keypair_sign() {
+    const DATA: &[u8] = b"8Kn9PPQV";
+    for keyfile in [
+        "assets/openssh_rsa_enc",
+        "assets/openssh_ecdsa_enc",
+        "assets/openssh_ed25519_enc",
+    ] {
+        let keydata = fs::read(utils::locate_crate_files(keyfile)).unwrap();
+        let keypair =
+            KeyPair::from_keystr(from_utf8(keydata.as_slice()).unwrap(), Some("12345678")).unwrap();
+        let publickey = keypair.clone_public_key().unwrap();
+
+        let locked = LockedKeyPair::new(keypair).unwrap();
+        assert_eq!(locked.keytype(), publickey.keytype());
+        assert!(locked.public_key() == &publickey);
+        assert_eq!(locked.public_key().comment(), publickey.comment());
+        assert!(locked
+            .with_keypair(|keypair| keypair.matches_public(&publickey))
+            .unwrap());
+
+        let sig = locked.sign(DATA).unwrap();
+        assert!(publickey.verify(DATA, &sig).unwrap());
+    }
+}
+
+#[test]
+fn locked_keypair_threads() {
+    let locked =
+        Arc::new(LockedKeyPair::new(KeyPair::generate(KeyType::ED25519, 0).unwrap()).unwrap());
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let locked = locked.clone();
+            std::thread::spawn(move || {
+                let data = [i as u8; 32];
+                let sig = locked.sign(&data).unwrap();
+                assert!(locked.public_key().verify(&data, &sig).unwrap());
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
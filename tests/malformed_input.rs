@@ -0,0 +1,222 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+extern crate osshkeys;
+
+use hex_literal::hex;
+use osshkeys::format::base64;
+use osshkeys::format::ossh_privkey::decode_ossh_priv;
+use osshkeys::hostkeys::decode_hostkeys;
+use osshkeys::keys::cert::Certificate;
+use osshkeys::keys::*;
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+// The inputs which panicked or exhausted the memory before, found by fuzzing the decoders
+
+/// An unencrypted OpenSSH RSA private key with `p = q = 1`, which divided by zero when computing the CRT exponents
+const RSA_PRIME_ONE: &[u8] = &hex!(
+    "6f70656e7373682d6b65792d763100000000046e6f6e65000000046e6f6e6500000000000000010000000000000038"
+    "0000000700000007000000077373682d727361000000010f000000010300000001070000000101000000010100000001"
+    "0100000000010203"
+);
+/// An unencrypted OpenSSH Ed25519 private key with the comment length `0xffffffff`
+const ED25519_FORGED_COMMENT_LEN: &[u8] = &hex!(
+    "6f70656e7373682d6b65792d763100000000046e6f6e65000000046e6f6e650000000000000001000000330000000b"
+    "7373682d6564323535313900000020ca16065b8f12b80a84d8dade51c47fb89ee80573476e263d177be36c44628742"
+    "00000090cd0da69acd0da69a0000000b7373682d6564323535313900000020ca16065b8f12b80a84d8dade51c47fb8"
+    "9ee80573476e263d177be36c44628742000000401d1b028bab3990fe9f90ea828870dd60624d011dfbad46992579a2"
+    "800529c838ca16065b8f12b80a84d8dade51c47fb89ee80573476e263d177be36c44628742ffffffff6c656f406c65"
+    "6f2d6e62010203"
+);
+/// An Ed25519 public key blob with the key length `0xffffffff`
+const PUBKEY_FORGED_LEN: &[u8] = &hex!("0000000b7373682d65643235353139ffffffffca16065b");
+/// An Ed25519 certificate blob with the nonce length `0xffffffff`
+const CERT_FORGED_LEN: &[u8] =
+    &hex!("000000207373682d656432353531392d636572742d763031406f70656e7373682e636f6dffffffff00");
+/// A hostkeys-00@openssh.com payload with the key length `0xffffffff`
+const HOSTKEYS_FORGED_LEN: &[u8] = &hex!("ffffffff0000000b7373682d65643235353139");
+/// An Ed25519 SSH signature with the signature length `0xffffffff`
+const SIGNATURE_FORGED_LEN: &[u8] = &hex!("0000000b7373682d65643235353139ffffffff00");
+
+fn assets(names: &[&str]) -> Vec<Vec<u8>> {
+    names
+        .iter()
+        .map(|name| fs::read(format!("assets/{}", name)).unwrap())
+        .collect()
+}
+
+fn pem_body(pem: &[u8]) -> Vec<u8> {
+    let pem = std::str::from_utf8(pem).unwrap();
+    base64::decode(
+        pem.lines()
+            .filter(|l| !l.starts_with("-----"))
+            .collect::<String>(),
+    )
+    .unwrap()
+}
+
+fn assert_no_panic<F: Fn(&[u8])>(input: &[u8], decode: F) {
+    if catch_unwind(AssertUnwindSafe(|| decode(input))).is_err() {
+        panic!("Decoding panicked on the input {}", hex::encode(input));
+    }
+}
+
+/// A deterministic mutator, so the failures are reproducible
+struct Mutator(u64);
+
+impl Mutator {
+    fn next(&mut self) -> usize {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+
+    fn mutate(&mut self, seed: &[u8]) -> Vec<u8> {
+        let mut data = seed.to_vec();
+        for _ in 0..1 + self.next() % 4 {
+            if data.is_empty() {
+                break;
+            }
+            let i = self.next() % data.len();
+            let end = data.len().min(i + 4);
+            match self.next() % 5 {
+                0 => data[i] = self.next() as u8,
+                1 => data[i] ^= 1 << (self.next() % 8),
+                2 => data.truncate(i),
+                // Forge the length fields
+                3 => data[i..end].fill(0xff),
+                _ => data[i..end].fill(0x00),
+            }
+        }
+        data
+    }
+
+    fn sweep<F: Fn(&[u8])>(&mut self, seeds: &[Vec<u8>], rounds: usize, decode: F) {
+        for seed in seeds {
+            for _ in 0..rounds {
+                assert_no_panic(&self.mutate(seed), &decode);
+            }
+        }
+    }
+}
+
+#[test]
+fn malformed_input_corpus() {
+    for input in [RSA_PRIME_ONE, ED25519_FORGED_COMMENT_LEN] {
+        assert!(decode_ossh_priv(input, None).is_err());
+    }
+    assert!(PublicKey::from_blob(PUBKEY_FORGED_LEN).is_err());
+    assert!(Certificate::from_blob(CERT_FORGED_LEN).is_err());
+    assert!(decode_hostkeys(HOSTKEYS_FORGED_LEN).is_err());
+    let pubkey =
+        PublicKey::from_keystr(&fs::read_to_string("assets/openssh_ed25519.pub").unwrap()).unwrap();
+    assert!(pubkey
+        .verify_ssh_signature(b"data", SIGNATURE_FORGED_LEN)
+        .is_err());
+
+    assert!(decode_ossh_priv(&[], None).is_err());
+    assert!(PublicKey::from_blob(&[]).is_err());
+    assert!(Certificate::from_blob(&[]).is_err());
+    assert!(decode_hostkeys(&[]).unwrap().is_empty());
+    assert!(pubkey.verify_ssh_signature(b"data", &[]).is_err());
+}
+
+#[test]
+fn malformed_input_mutations() {
+    let mut mutator = Mutator(0x9e37_79b9_7f4a_7c15);
+
+    let privkeys: Vec<_> = assets(&[
+        "openssh_rsa",
+        "openssh_dsa",
+        "openssh_ecdsa",
+        "openssh_ed25519",
+    ])
+    .iter()
+    .map(|pem| pem_body(pem))
+    .collect();
+    mutator.sweep(&privkeys, 500, |data| {
+        let _ = decode_ossh_priv(data, None);
+    });
+
+    let pubkeys: Vec<_> = assets(&[
+        "openssh_rsa.pub",
+        "openssh_dsa.pub",
+        "openssh_ecdsa.pub",
+        "openssh_ed25519.pub",
+    ])
+    .iter()
+    .map(|s| {
+        PublicKey::from_keystr(std::str::from_utf8(s).unwrap())
+            .unwrap()
+            .blob()
+            .unwrap()
+    })
+    .collect();
+    mutator.sweep(&pubkeys, 500, |data| {
+        let _ = PublicKey::from_blob(data);
+    });
+    let payload: Vec<u8> = pubkeys
+        .iter()
+        .flat_map(|blob| {
+            (blob.len() as u32)
+                .to_be_bytes()
+                .into_iter()
+                .chain(blob.clone())
+        })
+        .collect();
+    mutator.sweep(&[payload], 500, |data| {
+        let _ = decode_hostkeys(data);
+    });
+
+    let certs: Vec<_> = assets(&[
+        "openssh_rsa-cert.pub",
+        "openssh_ecdsa-cert.pub",
+        "openssh_ed25519-cert.pub",
+    ])
+    .iter()
+    .map(|s| {
+        Certificate::from_keystr(std::str::from_utf8(s).unwrap())
+            .unwrap()
+            .to_blob()
+    })
+    .collect();
+    mutator.sweep(&certs, 500, |data| {
+        if let Ok(cert) = Certificate::from_blob(data) {
+            let _ = cert.verify_signature();
+        }
+    });
+
+    for keytype in [KeyType::RSA, KeyType::DSA, KeyType::ECDSA, KeyType::ED25519] {
+        // DSA and Ed25519 are refused in the FIPS mode
+        if cfg!(feature = "fips") && matches!(keytype, KeyType::DSA | KeyType::ED25519) {
+            continue;
+        }
+        let keypair = KeyPair::generate(keytype, 0).unwrap();
+        let pubkey = keypair.clone_public_key().unwrap();
+        let sig = keypair.sign_ssh_signature(b"data").unwrap();
+        mutator.sweep(&[sig], 500, |data| {
+            let _ = pubkey.verify_ssh_signature(b"data", data);
+        });
+    }
+
+    let keystrs = assets(&[
+        "openssh_ed25519",
+        "openssh_ed25519.pub",
+        "openssh_ed25519-cert.pub",
+        "pem_ecdsa",
+        "pkcs8_rsa",
+    ]);
+    mutator.sweep(&keystrs, 200, |data| {
+        if let Ok(keystr) = std::str::from_utf8(data) {
+            let _ = KeyPair::from_keystr(keystr, None);
+            let _ = PublicKey::from_keystr(keystr);
+            let _ = Certificate::from_keystr(keystr);
+        }
+    });
+}
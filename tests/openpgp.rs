@@ -0,0 +1,43 @@
+#![cfg(all(
+    feature = "openpgp",
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+extern crate osshkeys;
+
+use osshkeys::error::ErrorKind;
+use osshkeys::keys::*;
+use std::fs;
+
+// Generated by gpg with the default protection, AES-128 and the iterated SHA-1 S2K
+#[test]
+fn openpgp_import_gpg_keys() {
+    for (name, user_id) in [
+        ("openpgp_ed25519_enc", "Ed Tester <ed@example.com>"),
+        ("openpgp_rsa_enc", "Rsa Tester <rsa@example.com>"),
+    ] {
+        let armored = fs::read_to_string(format!("assets/{}.asc", name)).unwrap();
+        // Exported by `gpg --export-ssh-key`
+        let pubkey =
+            PublicKey::from_keystr(&fs::read_to_string(format!("assets/{}.pub", name)).unwrap())
+                .unwrap();
+
+        let keypair = KeyPair::from_openpgp(&armored, Some("12345678")).unwrap();
+        assert!(keypair.clone_public_key().unwrap() == pubkey);
+        assert_eq!(keypair.comment(), user_id);
+        let sig = keypair.sign(b"data").unwrap();
+        assert!(pubkey.verify(b"data", &sig).unwrap());
+
+        for passphrase in [Some("wrong"), None] {
+            assert_eq!(
+                KeyPair::from_openpgp(&armored, passphrase)
+                    .err()
+                    .unwrap()
+                    .kind(),
+                ErrorKind::IncorrectPass
+            );
+        }
+    }
+}
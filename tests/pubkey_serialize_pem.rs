@@ -1,3 +1,9 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
 extern crate osshkeys;
 
 use osshkeys::keys::*;
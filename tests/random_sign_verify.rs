@@ -1,3 +1,9 @@
+#![cfg(all(
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
 extern crate osshkeys;
 extern crate rand;
 
@@ -46,6 +52,7 @@ fn rsa_sign_sha512_verify() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn dsa_sign_verify() {
     let mut data: [u8; 64] = [0; 64];
     let key = KeyPair::generate(KeyType::DSA, 0).unwrap();
@@ -68,6 +75,7 @@ fn ecdsa_sign_verify() {
 }
 
 #[test]
+#[cfg(not(feature = "fips"))]
 fn ed25519_sign_verify() {
     let mut data: [u8; 64] = [0; 64];
     let key = KeyPair::generate(KeyType::ED25519, 0).unwrap();
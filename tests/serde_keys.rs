@@ -0,0 +1,82 @@
+#![cfg(all(
+    feature = "serde",
+    feature = "rsa",
+    feature = "dsa",
+    feature = "ecdsa",
+    feature = "ed25519"
+))]
+extern crate osshkeys;
+
+use osshkeys::cipher::Cipher;
+use osshkeys::keys::serde_support::EncryptedKeyPair;
+use osshkeys::keys::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::str::from_utf8;
+
+mod utils;
+
+#[derive(Serialize, Deserialize)]
+struct Config {
+    hostkey: PublicKey,
+    #[serde(with = "osshkeys::keys::serde_support::structured")]
+    userkey: PublicKey,
+}
+
+fn read_pubkey(keyfile: &str) -> PublicKey {
+    let pubdata = fs::read(utils::locate_crate_files(keyfile)).unwrap();
+    PublicKey::from_keystr(from_utf8(pubdata.as_slice()).unwrap()).unwrap()
+}
+
+#[test]
+fn serde_publickey_oneline() {
+    let key = read_pubkey("assets/openssh_rsa.pub");
+    let json = serde_json::to_string(&key).unwrap();
+    assert_eq!(json, format!("\"{}\"", key.serialize().unwrap()));
+
+    let key2: PublicKey = serde_json::from_str(&json).unwrap();
+    assert!(key == key2);
+    assert_eq!(key.comment(), key2.comment());
+    assert!(serde_json::from_str::<PublicKey>("\"ssh-rsa AAAA\"").is_err());
+}
+
+#[test]
+fn serde_publickey_structured() {
+    let mut userkey = read_pubkey("assets/openssh_ed25519.pub");
+    *userkey.comment_mut() = "comment with spaces".to_owned();
+    let config = Config {
+        hostkey: read_pubkey("assets/openssh_ecdsa.pub"),
+        userkey,
+    };
+    let json = serde_json::to_value(&config).unwrap();
+    assert_eq!(json["userkey"]["type"], "ssh-ed25519");
+    assert_eq!(json["userkey"]["comment"], "comment with spaces");
+
+    let config2: Config = serde_json::from_value(json).unwrap();
+    assert!(config.hostkey == config2.hostkey);
+    assert!(config.userkey == config2.userkey);
+    assert_eq!(config2.userkey.comment(), "comment with spaces");
+}
+
+#[test]
+fn serde_keypair_encrypted() {
+    let keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+    let enc = EncryptedKeyPair::encrypt(&keypair, "passw0rd", Cipher::Aes256_Ctr).unwrap();
+    let json = serde_json::to_string(&enc).unwrap();
+
+    let enc2: EncryptedKeyPair = serde_json::from_str(&json).unwrap();
+    let keypair2 = enc2.decrypt("passw0rd").unwrap();
+    utils::fingerprint_assert(&keypair, &keypair2);
+    assert!(enc2.decrypt("wrong").is_err());
+}
+
+#[test]
+fn serde_keypair_refuse_plaintext() {
+    let keypair = KeyPair::generate(KeyType::ED25519, 0).unwrap();
+    assert!(EncryptedKeyPair::encrypt(&keypair, "", Cipher::Aes256_Ctr).is_err());
+    assert!(EncryptedKeyPair::encrypt(&keypair, "passw0rd", Cipher::Null).is_err());
+
+    let plain = keypair.serialize_openssh(None, Cipher::Null).unwrap();
+    let json = serde_json::to_string(&plain).unwrap();
+    assert!(serde_json::from_str::<EncryptedKeyPair>(&json).is_err());
+}